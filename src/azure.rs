@@ -0,0 +1,426 @@
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+
+use crate::context::Context;
+use crate::github::{self, CreatedPr, PullRequest};
+use crate::provider::{
+    self, ApiRequest, Credentials, MergeMethod, MergeOptions, MergeOutcome, NewPr, Provider,
+    ProviderKind,
+};
+
+const API_VERSION: &str = "7.1";
+
+/// A repository on Azure DevOps. Its pull requests go through the REST API, with the
+/// personal access token in `AZURE_DEVOPS_EXT_PAT`, the variable `az devops` reads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureDevops {
+    pub organization: String,
+    pub project: String,
+    pub repo: String,
+}
+
+impl AzureDevops {
+    /// From a remote or pull request URL: `https://dev.azure.com/org/project/_git/repo`,
+    /// `git@ssh.dev.azure.com:v3/org/project/repo` or the older
+    /// `https://org.visualstudio.com/project/_git/repo`
+    pub fn from_url(url: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Not an Azure DevOps repository URL: {}", url);
+        let (host, path) = provider::split_remote(url).ok_or_else(invalid)?;
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let (organization, project, repo) = match segments.iter().position(|&s| s == "_git") {
+            Some(git) => {
+                let repo = segments.get(git + 1).ok_or_else(invalid)?;
+                let before = &segments[..git];
+                let (organization, before) = match host.strip_suffix(".visualstudio.com") {
+                    Some(organization) => (organization, before),
+                    None => (*before.first().ok_or_else(invalid)?, &before[1..]),
+                };
+                // A repository named like its project can leave the project out
+                let project = before
+                    .last()
+                    .filter(|&&p| p != "DefaultCollection")
+                    .unwrap_or(repo);
+                (organization, *project, *repo)
+            }
+            None => match segments[..] {
+                ["v3", organization, project, repo] => (organization, project, repo),
+                _ => return Err(invalid()),
+            },
+        };
+        Ok(AzureDevops {
+            organization: organization.to_string(),
+            project: project.to_string(),
+            repo: repo.to_string(),
+        })
+    }
+
+    fn project_url(&self) -> String {
+        format!(
+            "https://dev.azure.com/{}/{}",
+            self.organization, self.project
+        )
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!(
+            "{}/_apis/git/repositories/{}/pullrequests{}?api-version={}",
+            self.project_url(),
+            self.repo,
+            path,
+            API_VERSION
+        )
+    }
+
+    /// Address of a pull request in the web UI; the API's own `url` is an API address
+    fn web_url(&self, id: u64) -> String {
+        format!(
+            "{}/_git/{}/pullrequest/{}",
+            self.project_url(),
+            self.repo,
+            id
+        )
+    }
+
+    fn create_request(&self, pr: &NewPr) -> ApiRequest {
+        ApiRequest::post(
+            self.api(""),
+            json!({
+                "sourceRefName": format!("refs/heads/{}", pr.branch),
+                "targetRefName": format!("refs/heads/{}", pr.base),
+                "title": pr.title,
+                "description": pr.body,
+                "isDraft": pr.draft,
+            }),
+        )
+    }
+
+    /// Pull requests of `branch` in any state, newest first
+    fn find_request(&self, branch: &str) -> Result<ApiRequest> {
+        let url = reqwest::Url::parse_with_params(
+            &self.api(""),
+            [
+                (
+                    "searchCriteria.sourceRefName",
+                    format!("refs/heads/{}", branch).as_str(),
+                ),
+                ("searchCriteria.status", "all"),
+            ],
+        )?;
+        Ok(ApiRequest::get(url.to_string()))
+    }
+
+    fn get_request(&self, id: u64) -> ApiRequest {
+        ApiRequest::get(self.api(&format!("/{}", id)))
+    }
+
+    /// Complete a pull request; Azure DevOps only does so for the source commit it last
+    /// merged, so a push after the lookup can't be merged unseen
+    fn merge_request(&self, id: u64, commit: &str, options: &MergeOptions) -> ApiRequest {
+        ApiRequest::patch(
+            self.api(&format!("/{}", id)),
+            json!({
+                "status": "completed",
+                "lastMergeSourceCommit": {"commitId": commit},
+                "completionOptions": {
+                    "mergeStrategy": merge_strategy(options.method),
+                    "deleteSourceBranch": options.delete_branch,
+                },
+            }),
+        )
+    }
+
+    fn comment_request(&self, id: u64, comment: &str) -> ApiRequest {
+        ApiRequest::post(
+            self.api(&format!("/{}/threads", id)),
+            json!({
+                "comments": [{"parentCommentId": 0, "content": comment, "commentType": 1}],
+                "status": "closed",
+            }),
+        )
+    }
+
+    fn abandon_request(&self, id: u64) -> ApiRequest {
+        ApiRequest::patch(
+            self.api(&format!("/{}", id)),
+            json!({"status": "abandoned"}),
+        )
+    }
+
+    /// Pull request in an Azure DevOps response, with completed ones as `MERGED` and
+    /// abandoned ones as `CLOSED`
+    fn parse_pr(&self, value: &Value) -> Option<PullRequest> {
+        let number = value["pullRequestId"].as_u64()?;
+        let state = match value["status"].as_str()? {
+            "active" => "OPEN",
+            "completed" => "MERGED",
+            _ => "CLOSED",
+        };
+        Some(PullRequest {
+            number,
+            url: self.web_url(number),
+            state: state.to_string(),
+        })
+    }
+
+    fn call(&self, ctx: &Context, request: &ApiRequest, what: &str) -> Result<Value> {
+        request.send(ctx, &credentials()?)?.json(what)
+    }
+}
+
+fn credentials() -> Result<Credentials> {
+    match std::env::var("AZURE_DEVOPS_EXT_PAT") {
+        // The token is the password; any user name is accepted
+        Ok(password) if !password.is_empty() => Ok(Credentials {
+            user: String::new(),
+            password,
+        }),
+        _ => anyhow::bail!(
+            "Set AZURE_DEVOPS_EXT_PAT (a personal access token with the Code (read & write) scope) for Azure DevOps pull requests"
+        ),
+    }
+}
+
+fn merge_strategy(method: MergeMethod) -> &'static str {
+    match method {
+        MergeMethod::Merge => "noFastForward",
+        MergeMethod::Squash => "squash",
+        MergeMethod::Rebase => "rebase",
+    }
+}
+
+/// Id of a pull request from its URL, e.g.
+/// `https://dev.azure.com/acme/web/_git/app/pullrequest/17`
+fn pr_id(pr_url: &str) -> Result<u64> {
+    pr_url
+        .rsplit_once("/pullrequest/")
+        .and_then(|(_, id)| id.split(['/', '?']).next()?.parse().ok())
+        .with_context(|| format!("Not an Azure DevOps pull request URL: {}", pr_url))
+}
+
+impl Provider for AzureDevops {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::AzureDevops
+    }
+
+    fn create_pr(&self, ctx: &Context, pr: &NewPr) -> Result<CreatedPr> {
+        if let Some(existing) = self.find_pr(ctx, pr.branch)? {
+            if existing.state == "OPEN" {
+                println!("PR already exists for branch '{}'", pr.branch);
+                println!("Existing PR URL: {}", existing.url);
+                return Ok(CreatedPr::Existing(existing.url));
+            }
+        }
+
+        println!(
+            "Creating PR for branch '{}' with title: '{}'",
+            pr.branch, pr.title
+        );
+        let created = self.call(ctx, &self.create_request(pr), "create PR")?;
+        let url = self
+            .parse_pr(&created)
+            .map(|pr| pr.url)
+            .context("Azure DevOps didn't return the created PR")?;
+        println!("PR created: {}", url);
+        Ok(CreatedPr::New(url))
+    }
+
+    fn find_pr(&self, ctx: &Context, branch: &str) -> Result<Option<PullRequest>> {
+        let list = self.call(ctx, &self.find_request(branch)?, "look up PR")?;
+        let prs = list["value"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|pr| self.parse_pr(pr))
+            .collect();
+        let repo = format!("{}/{}/{}", self.organization, self.project, self.repo);
+        github::pick_pr(prs, branch, &repo)
+    }
+
+    fn pr_state(&self, ctx: &Context, pr_url: &str) -> Result<String> {
+        let what = format!("check PR state for {}", pr_url);
+        let pr = self.call(ctx, &self.get_request(pr_id(pr_url)?), &what)?;
+        self.parse_pr(&pr)
+            .map(|pr| pr.state)
+            .with_context(|| format!("Failed to {}: no status in the response", what))
+    }
+
+    fn merge_pr(
+        &self,
+        ctx: &Context,
+        pr: &PullRequest,
+        options: &MergeOptions,
+    ) -> Result<MergeOutcome> {
+        let what = format!("merge PR {}", pr.url);
+        let current = self.call(ctx, &self.get_request(pr.number), &what)?;
+        let commit = current["lastMergeSourceCommit"]["commitId"]
+            .as_str()
+            .with_context(|| format!("Failed to {}: it has no merged source commit", what))?;
+
+        let response = self
+            .merge_request(pr.number, commit, options)
+            .send(ctx, &credentials()?)?;
+        match response.status {
+            200..=299 if response.body["status"] == "completed" => {
+                Ok(MergeOutcome::Merged(pr.clone()))
+            }
+            // Accepted, but left active until its merge goes through
+            200..=299 => Ok(MergeOutcome::Blocked {
+                pr: pr.clone(),
+                reason: format!(
+                    "not completed, merge status {}",
+                    response.body["mergeStatus"].as_str().unwrap_or("unknown")
+                ),
+            }),
+            // Required policies, a conflict or a draft
+            400 | 409 => Ok(MergeOutcome::Blocked {
+                pr: pr.clone(),
+                reason: response.error(),
+            }),
+            _ => anyhow::bail!("Failed to {}: {}", what, response.error()),
+        }
+    }
+
+    fn close_pr(&self, ctx: &Context, pr_url: &str, comment: &str) -> Result<()> {
+        let id = pr_id(pr_url)?;
+        let what = format!("close PR {}", pr_url);
+        self.call(ctx, &self.comment_request(id, comment), &what)?;
+        self.call(ctx, &self.abandon_request(id), &what)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ApiResponse;
+    use reqwest::Method;
+
+    const PULL_REQUEST: &str = include_str!("testdata/azure/pullrequest.json");
+    const PULL_REQUESTS: &str = include_str!("testdata/azure/pullrequests.json");
+    const MERGE_REFUSED: &str = include_str!("testdata/azure/merge_refused.json");
+
+    fn app() -> AzureDevops {
+        AzureDevops {
+            organization: "acme".to_string(),
+            project: "web".to_string(),
+            repo: "app".to_string(),
+        }
+    }
+
+    #[test]
+    fn repositories_are_read_from_remote_and_pull_request_urls() {
+        for url in [
+            "https://acme@dev.azure.com/acme/web/_git/app",
+            "git@ssh.dev.azure.com:v3/acme/web/app",
+            "https://acme.visualstudio.com/web/_git/app",
+            "https://acme.visualstudio.com/DefaultCollection/web/_git/app",
+            "acme@vs-ssh.visualstudio.com:v3/acme/web/app",
+            "https://dev.azure.com/acme/web/_git/app/pullrequest/17",
+        ] {
+            assert_eq!(AzureDevops::from_url(url).unwrap(), app(), "{}", url);
+        }
+        let named_like_project =
+            AzureDevops::from_url("https://dev.azure.com/acme/_git/web").unwrap();
+        assert_eq!(named_like_project.project, "web");
+        assert!(AzureDevops::from_url("https://dev.azure.com/acme/web").is_err());
+        assert_eq!(
+            pr_id("https://dev.azure.com/acme/web/_git/app/pullrequest/17?_a=files").unwrap(),
+            17
+        );
+    }
+
+    #[test]
+    fn api_calls_are_built_for_the_repository() {
+        let pr = NewPr {
+            branch: "update-lodash-4.17.21",
+            base: "main",
+            title: "chore(deps): update lodash to 4.17.21",
+            body: "Updates lodash",
+            draft: true,
+        };
+        assert_eq!(
+            app().create_request(&pr),
+            ApiRequest {
+                method: Method::POST,
+                url: "https://dev.azure.com/acme/web/_apis/git/repositories/app/pullrequests?api-version=7.1".to_string(),
+                body: Some(json!({
+                    "sourceRefName": "refs/heads/update-lodash-4.17.21",
+                    "targetRefName": "refs/heads/main",
+                    "title": "chore(deps): update lodash to 4.17.21",
+                    "description": "Updates lodash",
+                    "isDraft": true,
+                })),
+            }
+        );
+        assert_eq!(
+            app().find_request("update-lodash-4.17.21").unwrap().url,
+            "https://dev.azure.com/acme/web/_apis/git/repositories/app/pullrequests?api-version=7.1&searchCriteria.sourceRefName=refs%2Fheads%2Fupdate-lodash-4.17.21&searchCriteria.status=all"
+        );
+
+        let options = MergeOptions {
+            method: MergeMethod::Merge,
+            delete_branch: false,
+            admin: false,
+        };
+        let merge = app().merge_request(17, "9b2f1e4c", &options);
+        assert_eq!(merge.method, Method::PATCH);
+        assert_eq!(
+            merge.url,
+            "https://dev.azure.com/acme/web/_apis/git/repositories/app/pullrequests/17?api-version=7.1"
+        );
+        assert_eq!(
+            merge.body,
+            Some(json!({
+                "status": "completed",
+                "lastMergeSourceCommit": {"commitId": "9b2f1e4c"},
+                "completionOptions": {"mergeStrategy": "noFastForward", "deleteSourceBranch": false},
+            }))
+        );
+        assert_eq!(
+            app().abandon_request(17).body,
+            Some(json!({"status": "abandoned"}))
+        );
+        assert_eq!(
+            app().comment_request(17, "Superseded").url,
+            "https://dev.azure.com/acme/web/_apis/git/repositories/app/pullrequests/17/threads?api-version=7.1"
+        );
+    }
+
+    #[test]
+    fn responses_are_read_from_fixtures() {
+        let pr: Value = serde_json::from_str(PULL_REQUEST).unwrap();
+        assert_eq!(
+            app().parse_pr(&pr),
+            Some(PullRequest {
+                number: 17,
+                url: "https://dev.azure.com/acme/web/_git/app/pullrequest/17".to_string(),
+                state: "OPEN".to_string(),
+            })
+        );
+        assert_eq!(
+            pr["lastMergeSourceCommit"]["commitId"],
+            "9b2f1e4c7a30d5e8f1a2b3c4d5e6f7a8b9c0d1e2"
+        );
+
+        let list: Value = serde_json::from_str(PULL_REQUESTS).unwrap();
+        let prs: Vec<PullRequest> = list["value"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|pr| app().parse_pr(pr))
+            .collect();
+        assert_eq!(prs[0].state, "CLOSED");
+        let found = github::pick_pr(prs, "update-lodash-4.17.21", "acme/web/app").unwrap();
+        assert_eq!(found.map(|pr| pr.number), Some(17));
+
+        let refused = ApiResponse {
+            status: 400,
+            body: serde_json::from_str(MERGE_REFUSED).unwrap(),
+        };
+        assert_eq!(
+            refused.error(),
+            "HTTP 400: The pull request cannot be completed until its required policies are approved."
+        );
+    }
+}
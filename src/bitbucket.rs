@@ -0,0 +1,340 @@
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+
+use crate::context::Context;
+use crate::github::{self, CreatedPr, PullRequest};
+use crate::provider::{
+    self, ApiRequest, Credentials, MergeMethod, MergeOptions, MergeOutcome, NewPr, Provider,
+    ProviderKind,
+};
+
+const API: &str = "https://api.bitbucket.org/2.0";
+
+/// A repository on Bitbucket Cloud. Its pull requests go through the REST API, with
+/// the app password in `BITBUCKET_APP_PASSWORD` of the user in `BITBUCKET_USERNAME`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitbucket {
+    pub workspace: String,
+    pub repo: String,
+}
+
+impl Bitbucket {
+    /// From a remote or pull request URL such as `git@bitbucket.org:acme/app.git`. The
+    /// host isn't checked, so SSH host aliases work with `provider = "bitbucket"`
+    pub fn from_url(url: &str) -> Result<Self> {
+        let (_, path) = provider::split_remote(url)
+            .with_context(|| format!("Not a Bitbucket repository URL: {}", url))?;
+        let mut segments = path.split('/');
+        match (segments.next(), segments.next()) {
+            (Some(workspace), Some(repo)) if !workspace.is_empty() && !repo.is_empty() => {
+                Ok(Bitbucket {
+                    workspace: workspace.to_string(),
+                    repo: repo.to_string(),
+                })
+            }
+            _ => anyhow::bail!("Not a Bitbucket repository URL: {}", url),
+        }
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!(
+            "{}/repositories/{}/{}/pullrequests{}",
+            API, self.workspace, self.repo, path
+        )
+    }
+
+    fn create_request(&self, pr: &NewPr) -> ApiRequest {
+        ApiRequest::post(
+            self.api(""),
+            json!({
+                "title": pr.title,
+                "description": pr.body,
+                "source": {"branch": {"name": pr.branch}},
+                "destination": {"branch": {"name": pr.base}},
+                "draft": pr.draft,
+            }),
+        )
+    }
+
+    /// Pull requests of `branch` in any state, newest first
+    fn find_request(&self, branch: &str) -> Result<ApiRequest> {
+        let query = format!("source.branch.name={}", serde_json::to_string(branch)?);
+        let url = reqwest::Url::parse_with_params(
+            &self.api(""),
+            [
+                ("q", query.as_str()),
+                ("state", "OPEN"),
+                ("state", "MERGED"),
+                ("state", "DECLINED"),
+                ("state", "SUPERSEDED"),
+                ("sort", "-created_on"),
+            ],
+        )?;
+        Ok(ApiRequest::get(url.to_string()))
+    }
+
+    fn get_request(&self, id: u64) -> ApiRequest {
+        ApiRequest::get(self.api(&format!("/{}", id)))
+    }
+
+    fn merge_request(&self, id: u64, options: &MergeOptions) -> ApiRequest {
+        ApiRequest::post(
+            self.api(&format!("/{}/merge", id)),
+            json!({
+                "merge_strategy": merge_strategy(options.method),
+                "close_source_branch": options.delete_branch,
+            }),
+        )
+    }
+
+    fn comment_request(&self, id: u64, comment: &str) -> ApiRequest {
+        ApiRequest::post(
+            self.api(&format!("/{}/comments", id)),
+            json!({"content": {"raw": comment}}),
+        )
+    }
+
+    fn decline_request(&self, id: u64) -> ApiRequest {
+        ApiRequest::post(self.api(&format!("/{}/decline", id)), json!({}))
+    }
+
+    fn call(&self, ctx: &Context, request: &ApiRequest, what: &str) -> Result<Value> {
+        request.send(ctx, &credentials()?)?.json(what)
+    }
+}
+
+fn credentials() -> Result<Credentials> {
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    match (var("BITBUCKET_USERNAME"), var("BITBUCKET_APP_PASSWORD")) {
+        (Some(user), Some(password)) => Ok(Credentials { user, password }),
+        _ => anyhow::bail!(
+            "Set BITBUCKET_USERNAME and BITBUCKET_APP_PASSWORD (an app password with pull request write access) for Bitbucket pull requests"
+        ),
+    }
+}
+
+/// Bitbucket's strategy for a merge method; a fast-forward is its closest to a rebase,
+/// and fails unless the branch is up to date with the base
+fn merge_strategy(method: MergeMethod) -> &'static str {
+    match method {
+        MergeMethod::Merge => "merge_commit",
+        MergeMethod::Squash => "squash",
+        MergeMethod::Rebase => "fast_forward",
+    }
+}
+
+/// Pull request in a Bitbucket response, with declined and superseded ones as `CLOSED`
+fn parse_pr(value: &Value) -> Option<PullRequest> {
+    let state = match value["state"].as_str()? {
+        "OPEN" => "OPEN",
+        "MERGED" => "MERGED",
+        _ => "CLOSED",
+    };
+    Some(PullRequest {
+        number: value["id"].as_u64()?,
+        url: value["links"]["html"]["href"].as_str()?.to_string(),
+        state: state.to_string(),
+    })
+}
+
+/// Id of a pull request from its URL, e.g. `https://bitbucket.org/acme/app/pull-requests/7`
+fn pr_id(pr_url: &str) -> Result<u64> {
+    pr_url
+        .rsplit_once("/pull-requests/")
+        .and_then(|(_, id)| id.split('/').next()?.parse().ok())
+        .with_context(|| format!("Not a Bitbucket pull request URL: {}", pr_url))
+}
+
+impl Provider for Bitbucket {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Bitbucket
+    }
+
+    fn create_pr(&self, ctx: &Context, pr: &NewPr) -> Result<CreatedPr> {
+        if let Some(existing) = self.find_pr(ctx, pr.branch)? {
+            if existing.state == "OPEN" {
+                println!("PR already exists for branch '{}'", pr.branch);
+                println!("Existing PR URL: {}", existing.url);
+                return Ok(CreatedPr::Existing(existing.url));
+            }
+        }
+
+        println!(
+            "Creating PR for branch '{}' with title: '{}'",
+            pr.branch, pr.title
+        );
+        let created = self.call(ctx, &self.create_request(pr), "create PR")?;
+        let url = parse_pr(&created)
+            .map(|pr| pr.url)
+            .context("Bitbucket didn't return the created PR")?;
+        println!("PR created: {}", url);
+        Ok(CreatedPr::New(url))
+    }
+
+    fn find_pr(&self, ctx: &Context, branch: &str) -> Result<Option<PullRequest>> {
+        let page = self.call(ctx, &self.find_request(branch)?, "look up PR")?;
+        let prs = page["values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(parse_pr)
+            .collect();
+        let repo = format!("{}/{}", self.workspace, self.repo);
+        github::pick_pr(prs, branch, &repo)
+    }
+
+    fn pr_state(&self, ctx: &Context, pr_url: &str) -> Result<String> {
+        let what = format!("check PR state for {}", pr_url);
+        let pr = self.call(ctx, &self.get_request(pr_id(pr_url)?), &what)?;
+        parse_pr(&pr)
+            .map(|pr| pr.state)
+            .with_context(|| format!("Failed to {}: no state in the response", what))
+    }
+
+    fn merge_pr(
+        &self,
+        ctx: &Context,
+        pr: &PullRequest,
+        options: &MergeOptions,
+    ) -> Result<MergeOutcome> {
+        let response = self
+            .merge_request(pr.number, options)
+            .send(ctx, &credentials()?)?;
+        match response.status {
+            200..=299 => Ok(MergeOutcome::Merged(pr.clone())),
+            // Unresolved merge checks, or a fast-forward that isn't possible
+            400 | 409 => Ok(MergeOutcome::Blocked {
+                pr: pr.clone(),
+                reason: response.error(),
+            }),
+            _ => anyhow::bail!("Failed to merge PR {}: {}", pr.url, response.error()),
+        }
+    }
+
+    fn close_pr(&self, ctx: &Context, pr_url: &str, comment: &str) -> Result<()> {
+        let id = pr_id(pr_url)?;
+        let what = format!("close PR {}", pr_url);
+        self.call(ctx, &self.comment_request(id, comment), &what)?;
+        self.call(ctx, &self.decline_request(id), &what)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ApiResponse;
+    use reqwest::Method;
+
+    const PULL_REQUEST: &str = include_str!("testdata/bitbucket/pullrequest.json");
+    const PULL_REQUESTS: &str = include_str!("testdata/bitbucket/pullrequests.json");
+    const MERGE_REFUSED: &str = include_str!("testdata/bitbucket/merge_refused.json");
+
+    fn app() -> Bitbucket {
+        Bitbucket::from_url("git@bitbucket.org:acme/app.git").unwrap()
+    }
+
+    #[test]
+    fn repositories_are_read_from_remote_and_pull_request_urls() {
+        for url in [
+            "git@bitbucket.org:acme/app.git",
+            "https://release-bot@bitbucket.org/acme/app.git",
+            "ssh://git@bitbucket-work/acme/app",
+            "https://bitbucket.org/acme/app/pull-requests/42",
+        ] {
+            assert_eq!(Bitbucket::from_url(url).unwrap(), app(), "{}", url);
+        }
+        assert!(Bitbucket::from_url("git@bitbucket.org:acme").is_err());
+        assert_eq!(
+            pr_id("https://bitbucket.org/acme/app/pull-requests/42/overview").unwrap(),
+            42
+        );
+        assert!(pr_id("https://bitbucket.org/acme/app").is_err());
+    }
+
+    #[test]
+    fn api_calls_are_built_for_the_repository() {
+        let pr = NewPr {
+            branch: "update-lodash-4.17.21",
+            base: "main",
+            title: "chore(deps): update lodash to 4.17.21",
+            body: "Updates lodash",
+            draft: true,
+        };
+        assert_eq!(
+            app().create_request(&pr),
+            ApiRequest {
+                method: Method::POST,
+                url: "https://api.bitbucket.org/2.0/repositories/acme/app/pullrequests".to_string(),
+                body: Some(json!({
+                    "title": "chore(deps): update lodash to 4.17.21",
+                    "description": "Updates lodash",
+                    "source": {"branch": {"name": "update-lodash-4.17.21"}},
+                    "destination": {"branch": {"name": "main"}},
+                    "draft": true,
+                })),
+            }
+        );
+        assert_eq!(
+            app().find_request("update-lodash-4.17.21").unwrap().url,
+            "https://api.bitbucket.org/2.0/repositories/acme/app/pullrequests?q=source.branch.name%3D%22update-lodash-4.17.21%22&state=OPEN&state=MERGED&state=DECLINED&state=SUPERSEDED&sort=-created_on"
+        );
+
+        let options = MergeOptions {
+            method: MergeMethod::Rebase,
+            delete_branch: true,
+            admin: false,
+        };
+        let merge = app().merge_request(42, &options);
+        assert_eq!(
+            merge.url,
+            "https://api.bitbucket.org/2.0/repositories/acme/app/pullrequests/42/merge"
+        );
+        assert_eq!(
+            merge.body,
+            Some(json!({"merge_strategy": "fast_forward", "close_source_branch": true}))
+        );
+        assert_eq!(merge_strategy(MergeMethod::Merge), "merge_commit");
+        assert_eq!(
+            app().decline_request(42).url,
+            "https://api.bitbucket.org/2.0/repositories/acme/app/pullrequests/42/decline"
+        );
+        assert_eq!(
+            app().comment_request(42, "Superseded").body,
+            Some(json!({"content": {"raw": "Superseded"}}))
+        );
+    }
+
+    #[test]
+    fn responses_are_read_from_fixtures() {
+        let pr = parse_pr(&serde_json::from_str(PULL_REQUEST).unwrap()).unwrap();
+        assert_eq!(
+            pr,
+            PullRequest {
+                number: 42,
+                url: "https://bitbucket.org/acme/app/pull-requests/42".to_string(),
+                state: "OPEN".to_string(),
+            }
+        );
+
+        let page: Value = serde_json::from_str(PULL_REQUESTS).unwrap();
+        let prs: Vec<PullRequest> = page["values"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(parse_pr)
+            .collect();
+        assert_eq!(prs[1].state, "CLOSED");
+        let found = github::pick_pr(prs, "update-lodash-4.17.21", "acme/app").unwrap();
+        assert_eq!(found.map(|pr| pr.number), Some(42));
+
+        let refused = ApiResponse {
+            status: 400,
+            body: serde_json::from_str(MERGE_REFUSED).unwrap(),
+        };
+        assert_eq!(
+            refused.error(),
+            "HTTP 400: You can't merge until you resolve all merge checks."
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::package::{self, DEPENDENCY_SECTIONS};
+
+/// One package to update, from a line of `mru update --stdin`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bump {
+    pub package: String,
+    pub version: String,
+    /// Only repositories declaring the package in this dependency section
+    #[serde(default)]
+    pub section: Option<String>,
+    /// Only this repository, by alias, directory name or path
+    #[serde(default)]
+    pub repo: Option<String>,
+    /// Line of the input the bump was read from
+    #[serde(skip)]
+    pub line: usize,
+}
+
+impl Bump {
+    /// Configuration with only the repositories this bump applies to
+    pub fn config<'a>(&self, ctx: &Context, config: &'a Config) -> Result<Cow<'a, Config>> {
+        if self.repo.is_none() && self.section.is_none() {
+            return Ok(Cow::Borrowed(config));
+        }
+        let mut repositories = Vec::new();
+        for repo in &config.repositories {
+            if self.repo.as_ref().is_some_and(|name| !repo.is_named(name)) {
+                continue;
+            }
+            if let Some(section) = &self.section {
+                let entries = package::list_all_packages(ctx, &repo.path)?;
+                if !entries
+                    .iter()
+                    .any(|e| e.section == section && e.installs(&self.package))
+                {
+                    continue;
+                }
+            }
+            repositories.push(repo.clone());
+        }
+        if let (Some(name), true) = (&self.repo, repositories.is_empty()) {
+            if !config.repositories.iter().any(|r| r.is_named(name)) {
+                anyhow::bail!(
+                    "line {}: no configured repository named {}",
+                    self.line,
+                    name
+                );
+            }
+        }
+        let mut config = config.clone();
+        config.repositories = repositories;
+        Ok(Cow::Owned(config))
+    }
+}
+
+/// Bumps in `input`, one per line: `package version`, or a JSON object with `package`,
+/// `version` and optionally `section` and `repo`. Empty lines and `#` comments are skipped
+pub fn parse(input: &str) -> Result<Vec<Bump>> {
+    let mut bumps: Vec<Bump> = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut bump = if line.starts_with('{') {
+            serde_json::from_str::<Bump>(line)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", number, e))?
+        } else {
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [package, version] => Bump {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    section: None,
+                    repo: None,
+                    line: 0,
+                },
+                _ => anyhow::bail!(
+                    "line {}: expected `package version` or a JSON object, got `{}`",
+                    number,
+                    line
+                ),
+            }
+        };
+        bump.line = number;
+        if let Some(section) = &bump.section {
+            if !DEPENDENCY_SECTIONS.contains(&section.as_str()) {
+                anyhow::bail!(
+                    "line {}: unknown section {}; expected one of {}",
+                    number,
+                    section,
+                    DEPENDENCY_SECTIONS.join(", ")
+                );
+            }
+        }
+        if let Some(earlier) = bumps
+            .iter()
+            .find(|b| b.package == bump.package && b.repo == bump.repo)
+        {
+            anyhow::bail!(
+                "line {}: {} is already updated on line {}",
+                number,
+                bump.package,
+                earlier.line
+            );
+        }
+        bumps.push(bump);
+    }
+    if bumps.is_empty() {
+        anyhow::bail!("No packages to update on stdin");
+    }
+    Ok(bumps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_parsed_as_pairs_or_json() {
+        let bumps = parse(
+            "# from the weekly report\n\nlodash 4.17.21\n  {\"package\": \"react\", \"version\": \"18.3.1\", \"section\": \"peerDependencies\", \"repo\": \"web\"}\n",
+        )
+        .unwrap();
+        assert_eq!(bumps.len(), 2);
+        assert_eq!(
+            (
+                bumps[0].package.as_str(),
+                bumps[0].version.as_str(),
+                bumps[0].line
+            ),
+            ("lodash", "4.17.21", 3)
+        );
+        assert_eq!(bumps[1].section.as_deref(), Some("peerDependencies"));
+        assert_eq!(bumps[1].repo.as_deref(), Some("web"));
+        assert_eq!(bumps[1].line, 4);
+
+        let err = |input: &str| parse(input).unwrap_err().to_string();
+        assert_eq!(
+            err("lodash 4.17.21\nreact\n"),
+            "line 2: expected `package version` or a JSON object, got `react`"
+        );
+        assert!(err("{\"package\": \"react\"}").starts_with("line 1: missing field `version`"));
+        assert!(
+            err("{\"package\": \"a\", \"version\": \"1\", \"section\": \"deps\"}")
+                .starts_with("line 1: unknown section deps")
+        );
+        assert_eq!(
+            err("lodash 4\nlodash 5"),
+            "line 2: lodash is already updated on line 1"
+        );
+        assert_eq!(err("# nothing\n"), "No packages to update on stdin");
+    }
+}
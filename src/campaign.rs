@@ -0,0 +1,830 @@
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{state_dir, Repository};
+use crate::git::SubmoduleMode;
+use crate::preview::ResolvePreview;
+use crate::provenance::ProvenanceCheck;
+use crate::report::ReportFormat;
+use crate::summary::SummaryMode;
+use crate::template::TemplateContext;
+use crate::timing::StepTiming;
+
+/// Version of the campaign state file format
+pub const STATE_VERSION: u32 = 1;
+
+/// Pull request body when licenses are checked and no other body is set
+pub const DEFAULT_PR_BODY: &str = "Updates {package} from {old} to {version}.";
+
+/// Target version of a campaign that moves each repository to the newest version within
+/// its own range (`update --wanted`); each repository's is in its `target_version`
+pub const WANTED: &str = "wanted";
+
+/// Progress of a single repository within a campaign
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoStatus {
+    Pending,
+    Completed,
+    Skipped,
+    Failed,
+    /// Changes were rolled back by `mru undo`
+    Undone,
+    /// Skipped because a policy forbids the change
+    Blocked,
+    /// The update left nothing to push, so no branch or PR was created
+    NoChanges,
+    /// Left for a later batch (`--limit`, `--batch`); `mru resume --next-batch` runs it
+    Deferred,
+    /// Stopped by Ctrl-C while it was being updated; `mru resume` runs it again
+    Interrupted,
+}
+
+impl RepoStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            RepoStatus::Pending => "pending",
+            RepoStatus::Completed => "completed",
+            RepoStatus::Skipped => "skipped",
+            RepoStatus::Failed => "failed",
+            RepoStatus::Undone => "undone",
+            RepoStatus::Blocked => "blocked",
+            RepoStatus::NoChanges => "no changes",
+            RepoStatus::Deferred => "deferred",
+            RepoStatus::Interrupted => "interrupted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoState {
+    pub path: String,
+    pub status: RepoStatus,
+    pub error: Option<String>,
+    /// Raw output of the git command that failed, e.g. a rejected push
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub pr_url: Option<String>,
+    /// Version the package was at before the campaign changed it
+    #[serde(default)]
+    pub old_version: Option<String>,
+    /// Branch that was checked out before the campaign branch was created
+    #[serde(default)]
+    pub original_branch: Option<String>,
+    /// Commits the local base branch was fast-forwarded by before the update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fast_forwarded: Option<usize>,
+    /// Diffstat summary of the update commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diffstat: Option<String>,
+    /// Time the repository took to process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Time spent in each workflow step
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepTiming>,
+    /// Batch the repository belongs to, numbered from 1, when the campaign is split
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<usize>,
+    /// Problems that didn't stop the update, e.g. code owners no review was requested from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Milestone the pull request was put in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    /// Project the pull request was added to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// License of the package before and after the update, e.g. `MIT → MIT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Version this repository is updated to, where it differs from the campaign's
+    /// (`update --wanted`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_version: Option<String>,
+    /// Other packages the update changes in the lockfile (`--resolve-preview`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolve_preview: Option<ResolvePreview>,
+    /// Package managers the installs ran with, e.g. `pnpm 9.1.0 (corepack)`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_managers: Vec<String>,
+    /// Not in the config; given with `--repo-path` for this campaign only
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ad_hoc: bool,
+    /// Version the repository declared when the campaign started, updated or not
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned_version: Option<String>,
+}
+
+/// What `update` does in a repository where an update bot already has a pull request
+/// open for the package
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BotPrs {
+    /// Update and push the branch, but don't open another pull request
+    #[default]
+    NoPr,
+    /// Skip the repository before updating it (`--skip-if-bot-pr`)
+    Skip,
+    /// Don't look for bot pull requests (`--ignore-bot-prs`)
+    Ignore,
+}
+
+/// What a run does after a repository fails (`--on-error`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Record the failure and go on with the next repository
+    Continue,
+    /// Stop the run; the remaining repositories are left for `mru resume`
+    Abort,
+    /// Ask whether to go on
+    #[default]
+    Prompt,
+}
+
+impl OnError {
+    /// `prompt` on a terminal, `continue` when nobody is there to answer
+    pub fn detect() -> Self {
+        if std::io::stdin().is_terminal() {
+            OnError::Prompt
+        } else {
+            OnError::Continue
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OnError::Continue => "continue",
+            OnError::Abort => "abort",
+            OnError::Prompt => "prompt",
+        }
+    }
+}
+
+/// How a campaign is split into batches that are run one at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Batching {
+    /// Batches of this many repositories, starting with the first
+    Limit(usize),
+    /// `count` batches of about equal size, running batch `index` (from 1)
+    Batch { index: usize, count: usize },
+}
+
+impl Batching {
+    /// The repositories of each batch, in order; batches never reorder repositories
+    pub fn partition(self, paths: &[String]) -> Vec<Vec<String>> {
+        match self {
+            Batching::Limit(size) => paths.chunks(size.max(1)).map(<[String]>::to_vec).collect(),
+            Batching::Batch { count, .. } => (0..count)
+                .map(|i| paths[i * paths.len() / count..(i + 1) * paths.len() / count].to_vec())
+                .collect(),
+        }
+    }
+
+    /// Batch to run now, numbered from 1
+    pub fn current(self) -> usize {
+        match self {
+            Batching::Limit(_) => 1,
+            Batching::Batch { index, .. } => index,
+        }
+    }
+}
+
+/// A package update run across the configured repositories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Campaign {
+    pub version: u32,
+    pub id: String,
+    pub created_at: u64,
+    pub package: String,
+    pub target_version: String,
+    pub commit_message: String,
+    pub pull_request: bool,
+    /// Branch the campaign commits to; older state files derive it from the package
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Commits may go to the default or a protected branch (`--allow-protected`)
+    #[serde(default)]
+    pub allow_protected: bool,
+    /// Update repositories whose base branch diverged from origin (`--force-base`)
+    #[serde(default)]
+    pub force_base: bool,
+    /// Ref each repository branches from and whose versions are replaced (`--base`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// Add an entry to each repository's CHANGELOG.md (`--changelog`)
+    #[serde(default)]
+    pub changelog: bool,
+    /// Files the update may commit besides the configured ones (`--allow-extra-paths`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_extra_paths: Vec<String>,
+    /// Ask before pushing each repository (`--confirm-push` without `--yes`)
+    #[serde(default)]
+    pub confirm_push: bool,
+    /// Break the timing summary down by workflow step (`--timings`)
+    #[serde(default)]
+    pub timings: bool,
+    /// Pause between dependency waves (`--wait-between-waves`)
+    #[serde(default)]
+    pub wait_between_waves: bool,
+    /// Run the package manager's dedupe after installing (`--dedupe`)
+    #[serde(default)]
+    pub dedupe: bool,
+    /// Leave a failed update's changes in place instead of restoring them
+    /// (`--no-restore-on-failure`)
+    #[serde(default)]
+    pub keep_changes_on_failure: bool,
+    /// What to do with extra manifests in submodules (`--recurse-submodules`)
+    #[serde(default)]
+    pub submodules: SubmoduleMode,
+    /// Mark each pull request ready for review once created (`--ready`)
+    #[serde(default)]
+    pub ready: bool,
+    /// Comment on each pull request with the diff and command output (`--comment-details`)
+    #[serde(default)]
+    pub comment_details: bool,
+    /// Request reviews from the code owners of the changed files (`--codeowners`)
+    #[serde(default)]
+    pub codeowners: bool,
+    /// Milestone to put each pull request in (`--milestone`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    /// Project to add each pull request to (`--project`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Create the milestone where it is missing (`--create-missing-milestone`)
+    #[serde(default)]
+    pub create_missing_milestone: bool,
+    /// What to do where Renovate or Dependabot has a pull request open for the package
+    #[serde(default)]
+    pub bot_prs: BotPrs,
+    /// What the run does after a repository fails (`--on-error`)
+    #[serde(default)]
+    pub on_error: OnError,
+    /// Why the last run stopped before the remaining repositories, e.g. `--on-error abort`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<String>,
+    /// Resolve each update in a copy first and report the other packages it changes
+    /// (`--resolve-preview`)
+    #[serde(default)]
+    pub resolve_preview: bool,
+    /// Add `Mru-Campaign` and `Mru-Package` trailers to the commit message (`--trailers`)
+    #[serde(default)]
+    pub trailers: bool,
+    /// Attach the pull request URL to the commit as a note in `refs/notes/mru` (`--pr-note`)
+    #[serde(default)]
+    pub pr_note: bool,
+    /// Only warn when an install changes the lockfile's format or shrinks it
+    /// (`--allow-lockfile-format-change`)
+    #[serde(default)]
+    pub allow_lockfile_format_change: bool,
+    /// Pull request title template (`templates.pr_title`); the commit message if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pr_title: Option<String>,
+    /// Pull request body template; `{old}` is the repository's previous version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pr_body: Option<String>,
+    /// Template values that are the same in every repository, such as `{date}` and `{type}`
+    #[serde(default, skip_serializing_if = "TemplateContext::is_empty")]
+    pub template: TemplateContext,
+    /// What `--verify-provenance` found out about the target version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ProvenanceCheck>,
+    /// Open up to this many of the pull requests a run creates in the browser (`--web`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_in_browser: Option<usize>,
+    /// File the run report is written to (`--report`; `-` for stdout)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    #[serde(default)]
+    pub report_format: ReportFormat,
+    /// How much the end-of-run summary shows (`--summary`)
+    #[serde(default)]
+    pub summary: SummaryMode,
+    /// Batch being run, numbered from 1, when the campaign is split
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<usize>,
+    pub repos: Vec<RepoState>,
+}
+
+/// Id of a campaign on `branch`, which names its state file: the branch with `@` dropped
+/// and `/` replaced, so `update-@types/node-20.0.0` gives `update-types-node-20.0.0-<time>`
+fn campaign_id(branch: &str, created_at: u64) -> String {
+    format!("{}-{}", branch_slug(branch), created_at)
+}
+
+/// File-name-safe form of a branch name, as used in campaign ids
+pub fn branch_slug(branch: &str) -> String {
+    branch
+        .chars()
+        .filter(|&c| c != '@')
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+impl Campaign {
+    pub fn new(
+        package: &str,
+        target_version: &str,
+        commit_message: &str,
+        pull_request: bool,
+        branch_name: &str,
+        repo_paths: &[&str],
+    ) -> Self {
+        let created_at = now();
+        Campaign {
+            version: STATE_VERSION,
+            id: campaign_id(branch_name, created_at),
+            created_at,
+            package: package.to_string(),
+            target_version: target_version.to_string(),
+            commit_message: commit_message.to_string(),
+            pull_request,
+            branch: Some(branch_name.to_string()),
+            allow_protected: false,
+            force_base: false,
+            base: None,
+            changelog: false,
+            allow_extra_paths: Vec::new(),
+            confirm_push: false,
+            timings: false,
+            wait_between_waves: false,
+            dedupe: false,
+            keep_changes_on_failure: false,
+            submodules: SubmoduleMode::Skip,
+            ready: false,
+            comment_details: false,
+            codeowners: false,
+            milestone: None,
+            project: None,
+            create_missing_milestone: false,
+            resolve_preview: false,
+            bot_prs: BotPrs::NoPr,
+            on_error: OnError::Prompt,
+            stopped: None,
+            trailers: false,
+            pr_note: false,
+            allow_lockfile_format_change: false,
+            pr_title: None,
+            pr_body: None,
+            template: TemplateContext::default(),
+            provenance: None,
+            open_in_browser: None,
+            report: None,
+            report_format: ReportFormat::Json,
+            summary: SummaryMode::Full,
+            batch: None,
+            repos: repo_paths
+                .iter()
+                .map(|path| RepoState {
+                    path: path.to_string(),
+                    status: RepoStatus::Pending,
+                    error: None,
+                    output: None,
+                    pr_url: None,
+                    old_version: None,
+                    original_branch: None,
+                    fast_forwarded: None,
+                    diffstat: None,
+                    duration_ms: None,
+                    steps: Vec::new(),
+                    batch: None,
+                    warnings: Vec::new(),
+                    milestone: None,
+                    project: None,
+                    license: None,
+                    target_version: None,
+                    resolve_preview: None,
+                    package_managers: Vec::new(),
+                    ad_hoc: false,
+                    scanned_version: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Load a campaign by id
+    pub fn load(id: &str) -> Result<Self> {
+        let path = campaigns_dir()?.join(format!("{}.json", id));
+        if !path.exists() {
+            anyhow::bail!("Campaign not found: {}", id);
+        }
+        load_file(&path)
+    }
+
+    /// Most recent campaign
+    pub fn latest() -> Result<Option<Self>> {
+        Ok(list()?
+            .into_iter()
+            .filter_map(|(_, campaign)| campaign.ok())
+            .max_by_key(|c| c.created_at))
+    }
+
+    /// Most recent campaign that still has pending or failed repositories
+    pub fn latest_unfinished() -> Result<Option<Self>> {
+        let mut campaigns: Vec<Campaign> = list()?
+            .into_iter()
+            .filter_map(|(_, campaign)| campaign.ok())
+            .filter(|c| !c.is_finished())
+            .collect();
+        campaigns.sort_by_key(|c| c.created_at);
+        Ok(campaigns.pop())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = campaigns_dir()?;
+        fs::create_dir_all(&dir).context("Failed to create campaigns directory")?;
+
+        let path = dir.join(format!("{}.json", self.id));
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write campaign state: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn set_status(
+        &mut self,
+        repo_path: &str,
+        status: RepoStatus,
+        error: Option<String>,
+        pr_url: Option<String>,
+    ) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.status = status;
+            repo.error = error;
+            repo.output = None;
+            repo.pr_url = pr_url;
+        }
+    }
+
+    pub fn set_output(&mut self, repo_path: &str, output: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.output = Some(output.to_string());
+        }
+    }
+
+    pub fn set_old_version(&mut self, repo_path: &str, old_version: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.old_version = Some(old_version.to_string());
+        }
+    }
+
+    pub fn set_original_branch(&mut self, repo_path: &str, branch: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.original_branch = Some(branch.to_string());
+        }
+    }
+
+    pub fn set_timing(&mut self, repo_path: &str, duration_ms: u64, steps: Vec<StepTiming>) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.duration_ms = Some(duration_ms);
+            repo.steps = steps;
+        }
+    }
+
+    pub fn set_diffstat(&mut self, repo_path: &str, diffstat: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.diffstat = Some(diffstat.to_string());
+        }
+    }
+
+    pub fn set_warnings(&mut self, repo_path: &str, warnings: Vec<String>) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.warnings = warnings;
+        }
+    }
+
+    pub fn set_pr_metadata(
+        &mut self,
+        repo_path: &str,
+        milestone: Option<String>,
+        project: Option<String>,
+    ) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.milestone = milestone;
+            repo.project = project;
+        }
+    }
+
+    pub fn set_target_version(&mut self, repo_path: &str, version: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.target_version = Some(version.to_string());
+        }
+    }
+
+    /// Version a repository is updated to: its own, or else the campaign's
+    pub fn target_version_of(&self, repo_path: &str) -> &str {
+        self.repos
+            .iter()
+            .find(|r| r.path == repo_path)
+            .and_then(|r| r.target_version.as_deref())
+            .unwrap_or(&self.target_version)
+    }
+
+    pub fn set_package_managers(&mut self, repo_path: &str, package_managers: Vec<String>) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.package_managers = package_managers;
+        }
+    }
+
+    pub fn set_scanned_version(&mut self, repo_path: &str, version: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.scanned_version = Some(version.to_string());
+        }
+    }
+
+    pub fn set_ad_hoc(&mut self, repo_path: &str) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.ad_hoc = true;
+        }
+    }
+
+    pub fn set_resolve_preview(&mut self, repo_path: &str, preview: ResolvePreview) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.resolve_preview = Some(preview);
+        }
+    }
+
+    pub fn set_license(&mut self, repo_path: &str, license: String) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.license = Some(license);
+        }
+    }
+
+    /// Pull request body for a repository: `pr_body`, followed by the repository's
+    /// license change when licenses were checked
+    pub fn pr_body_for(&self, repo_path: &str) -> Option<String> {
+        let license = self
+            .repos
+            .iter()
+            .find(|r| r.path == repo_path)
+            .and_then(|r| r.license.as_deref());
+        let Some(license) = license else {
+            return self.pr_body.clone();
+        };
+        let body = self.pr_body.as_deref().unwrap_or(DEFAULT_PR_BODY);
+        Some(format!("{}\n\nlicense: {}", body, license))
+    }
+
+    /// Template values for `repo`, as far as they are known: the campaign's, the
+    /// repository's, and once it was updated `{old}` and `{base}`
+    pub fn template_for(&self, repo: &Repository) -> TemplateContext {
+        let mut vars = self
+            .template
+            .clone()
+            .with("package", &self.package)
+            .with("campaign", &self.id)
+            .with_version(self.target_version_of(&repo.path))
+            .with_repository(repo);
+        let state = self.repos.iter().find(|r| r.path == repo.path);
+        if let Some(old) = state.and_then(|s| s.old_version.as_deref()) {
+            vars = vars.with("old", &crate::version::normalize(old));
+        }
+        let base = self
+            .base
+            .as_deref()
+            .or(state.and_then(|s| s.original_branch.as_deref()));
+        if let Some(base) = base {
+            vars = vars.with("base", base);
+        }
+        vars
+    }
+
+    /// Commit message the campaign used in `repo`, once it was updated there
+    pub fn commit_message_for(&self, repo: &Repository) -> String {
+        self.template_for(repo).render(&self.commit_message)
+    }
+
+    /// Commit to `branch` instead of the branch the campaign was created with
+    pub fn set_branch(&mut self, branch: &str) {
+        self.id = campaign_id(branch, self.created_at);
+        self.branch = Some(branch.to_string());
+    }
+
+    pub fn set_fast_forwarded(&mut self, repo_path: &str, commits: usize) {
+        if let Some(repo) = self.repos.iter_mut().find(|r| r.path == repo_path) {
+            repo.fast_forwarded = Some(commits);
+        }
+    }
+
+    /// Split `eligible` into batches and defer every repository outside the current one
+    pub fn split(&mut self, eligible: &[String], batching: Batching) {
+        let current = batching.current();
+        for (i, batch) in batching.partition(eligible).iter().enumerate() {
+            for repo in self.repos.iter_mut().filter(|r| batch.contains(&r.path)) {
+                repo.batch = Some(i + 1);
+                if i + 1 != current {
+                    repo.status = RepoStatus::Deferred;
+                }
+            }
+        }
+        self.batch = Some(current);
+    }
+
+    /// Make the deferred batch after the current one pending again (wrapping around to
+    /// earlier batches skipped by `--batch`), returning its number
+    pub fn start_next_batch(&mut self) -> Option<usize> {
+        let deferred: Vec<usize> = self
+            .repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Deferred)
+            .filter_map(|r| r.batch)
+            .collect();
+        let current = self.batch.unwrap_or_default();
+        let next = deferred
+            .iter()
+            .filter(|&&batch| batch > current)
+            .min()
+            .or_else(|| deferred.iter().min())
+            .copied()?;
+        for repo in self.repos.iter_mut().filter(|r| r.batch == Some(next)) {
+            if repo.status == RepoStatus::Deferred {
+                repo.status = RepoStatus::Pending;
+            }
+        }
+        self.batch = Some(next);
+        Some(next)
+    }
+
+    /// Branch the campaign commits to in every repository
+    pub fn branch_name(&self) -> String {
+        self.branch
+            .clone()
+            .unwrap_or_else(|| crate::git::update_branch_name(&self.package, &self.target_version))
+    }
+
+    /// Repositories that still need to be processed
+    pub fn remaining(&self) -> Vec<String> {
+        self.repos
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r.status,
+                    RepoStatus::Pending | RepoStatus::Failed | RepoStatus::Interrupted
+                )
+            })
+            .map(|r| r.path.clone())
+            .collect()
+    }
+
+    pub fn count(&self, status: RepoStatus) -> usize {
+        self.repos.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Nothing is left to process, in this batch or a later one
+    pub fn is_finished(&self) -> bool {
+        self.remaining().is_empty() && self.count(RepoStatus::Deferred) == 0
+    }
+}
+
+/// Directory holding campaign state files
+pub fn campaigns_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("campaigns"))
+}
+
+/// All campaign state files, with a parse error for each corrupt one
+pub fn list() -> Result<Vec<(PathBuf, Result<Campaign>)>> {
+    let dir = campaigns_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut campaigns = Vec::new();
+    for entry in fs::read_dir(&dir).context("Failed to read campaigns directory")? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let campaign = load_file(&path);
+        campaigns.push((path, campaign));
+    }
+
+    Ok(campaigns)
+}
+
+fn load_file(path: &Path) -> Result<Campaign> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read campaign state: {}", path.display()))?;
+
+    let campaign: Campaign = serde_json::from_str(&content)
+        .with_context(|| format!("Corrupt campaign state file: {}", path.display()))?;
+
+    if campaign.version != STATE_VERSION {
+        anyhow::bail!(
+            "Unsupported campaign state version {} in {} (expected {})",
+            campaign.version,
+            path.display(),
+            STATE_VERSION
+        );
+    }
+
+    Ok(campaign)
+}
+
+/// Current time as seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DD HH:MM` (UTC)
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        (rem % 3_600) / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_defer_the_rest_and_run_in_turn() {
+        let paths = ["/w/a", "/w/b", "/w/c", "/w/d", "/w/e", "/w/unused"];
+        let mut campaign = Campaign::new("lodash", "4.17.21", "chore", true, "b", &paths);
+        let eligible: Vec<String> = paths[..5].iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            Batching::Batch { index: 1, count: 2 }.partition(&eligible),
+            vec![vec!["/w/a", "/w/b"], vec!["/w/c", "/w/d", "/w/e"]]
+        );
+
+        campaign.split(&eligible, Batching::Limit(2));
+        assert_eq!(campaign.remaining(), vec!["/w/a", "/w/b", "/w/unused"]);
+        assert_eq!(campaign.count(RepoStatus::Deferred), 3);
+
+        for path in campaign.remaining() {
+            campaign.set_status(&path, RepoStatus::Completed, None, None);
+        }
+        assert!(!campaign.is_finished());
+        assert_eq!(campaign.start_next_batch(), Some(2));
+        assert_eq!(campaign.remaining(), vec!["/w/c", "/w/d"]);
+        assert_eq!(campaign.repos[4].status, RepoStatus::Deferred);
+    }
+
+    #[test]
+    fn scoped_package_campaign_ids_are_file_names() {
+        let branch = "update-@types/node-20.0.0";
+        let mut campaign = Campaign::new("@types/node", "20.0.0", "chore", true, branch, &["/w/a"]);
+        let id = format!("update-types-node-20.0.0-{}", campaign.created_at);
+        assert_eq!(campaign.id, id);
+        assert_eq!(campaign.branch.as_deref(), Some(branch));
+        let path = Path::new("/state/campaigns").join(format!("{}.json", campaign.id));
+        assert_eq!(path.parent(), Some(Path::new("/state/campaigns")));
+
+        campaign.set_branch("deps/@scope/pkg");
+        assert!(campaign.id.starts_with("deps-scope-pkg-"));
+        assert_eq!(
+            branch_slug("update-lodash-4.17.21"),
+            "update-lodash-4.17.21"
+        );
+    }
+
+    #[test]
+    fn interrupted_repositories_are_redone_on_resume() {
+        let paths = ["/w/a", "/w/b", "/w/c"];
+        let mut campaign = Campaign::new("lodash", "4.17.21", "chore", true, "b", &paths);
+        campaign.set_status("/w/a", RepoStatus::Completed, None, None);
+        campaign.set_status(
+            "/w/b",
+            RepoStatus::Interrupted,
+            Some("'npm install' was killed".to_string()),
+            None,
+        );
+
+        assert_eq!(campaign.remaining(), vec!["/w/b", "/w/c"]);
+        assert!(!campaign.is_finished());
+        let state = serde_json::to_string(&campaign.repos[1]).unwrap();
+        assert!(state.contains(r#""status":"interrupted""#), "{}", state);
+    }
+}
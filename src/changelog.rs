@@ -0,0 +1,183 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::config::Repository;
+use crate::repo::expand_path;
+use crate::template::TemplateContext;
+
+/// Name of the changelog file `update --changelog` edits
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+const DEFAULT_HEADING: &str = "## Unreleased";
+const DEFAULT_TEMPLATE: &str = "- chore: update {package} from {old} to {new}";
+
+/// Where and how a repository's changelog gets its entry
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangelogConfig {
+    /// Heading the entry goes under (default: `## Unreleased`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// Entry line, a template such as `- Bump {package} to {new} (was {old})`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl ChangelogConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &ChangelogConfig::default()
+    }
+
+    fn heading(&self) -> &str {
+        self.heading.as_deref().unwrap_or(DEFAULT_HEADING)
+    }
+
+    fn entry(&self, vars: &TemplateContext) -> String {
+        vars.render(self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE))
+    }
+}
+
+/// Add the entry for an update to the repository's CHANGELOG.md.
+/// Returns whether the file changed: repositories without a changelog, and changelogs
+/// that already have the entry, are left alone.
+pub fn record_update(repo: &Repository, vars: &TemplateContext, dry_run: bool) -> Result<bool> {
+    let path = expand_path(&repo.path)?.join(CHANGELOG_FILE);
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let bytes = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = String::from_utf8(bytes)
+        .with_context(|| format!("{} is not UTF-8, leaving it alone", path.display()))?;
+
+    let entry = repo.changelog.entry(vars);
+    let Some(updated) = insert_entry(&text, repo.changelog.heading(), &entry) else {
+        return Ok(false);
+    };
+
+    if dry_run {
+        println!("Would add '{}' to {}", entry, CHANGELOG_FILE);
+    } else {
+        fs::write(&path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Added '{}' to {}", entry, CHANGELOG_FILE);
+    }
+    Ok(true)
+}
+
+/// Insert `entry` as the first item under `heading`, creating the heading above the first
+/// release heading if it is missing. Line endings and a byte order mark are kept.
+/// `None` if the changelog already has the entry.
+pub fn insert_entry(text: &str, heading: &str, entry: &str) -> Option<String> {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let content = |line: &str| line.trim_start_matches('\u{feff}').trim_end().to_string();
+
+    if lines.iter().any(|line| content(line) == entry) {
+        return None;
+    }
+
+    let mut out: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    if let Some(last) = out.last_mut() {
+        if !last.ends_with('\n') {
+            last.push_str(newline);
+        }
+    }
+
+    match lines.iter().position(|line| content(line) == heading) {
+        Some(at) => {
+            // First item goes after the blank lines following the heading
+            let mut pos = at + 1;
+            while pos < lines.len() && content(lines[pos]).is_empty() {
+                pos += 1;
+            }
+            let is_item = |line: &str| line.starts_with("- ") || line.starts_with("* ");
+            let mut insert = vec![format!("{}{}", entry, newline)];
+            if pos == at + 1 {
+                insert.insert(0, newline.to_string());
+            }
+            if pos < lines.len() && !is_item(&content(lines[pos])) {
+                insert.push(newline.to_string());
+            }
+            out.splice(pos..pos, insert);
+        }
+        None => {
+            let section = [
+                format!("{}{}", heading, newline),
+                newline.to_string(),
+                format!("{}{}", entry, newline),
+            ];
+            // Above the first release; otherwise after the title and its blank lines
+            let pos = match lines
+                .iter()
+                .position(|line| content(line).starts_with("## "))
+            {
+                Some(pos) => pos,
+                None => {
+                    let mut pos = lines
+                        .iter()
+                        .position(|line| content(line).starts_with("# "))
+                        .map_or(0, |title| title + 1);
+                    while pos < lines.len() && content(lines[pos]).is_empty() {
+                        pos += 1;
+                    }
+                    pos
+                }
+            };
+            let mut insert = section.to_vec();
+            if pos > 0 && !content(lines[pos - 1]).is_empty() {
+                insert.insert(0, newline.to_string());
+            }
+            if pos < lines.len() {
+                insert.push(newline.to_string());
+            }
+            out.splice(pos..pos, insert);
+        }
+    }
+
+    Some(out.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "- chore: update react from ^17.0.0 to ^18.2.0";
+
+    #[test]
+    fn insert_entry_adds_one_item_under_the_heading() {
+        let text = "# Changelog\n\n## Unreleased\n\n- fix: typo\n\n## 1.0.0\n\n- first\n";
+        let updated = insert_entry(text, "## Unreleased", ENTRY).unwrap();
+        assert_eq!(
+            updated,
+            format!(
+                "# Changelog\n\n## Unreleased\n\n{}\n- fix: typo\n\n## 1.0.0\n\n- first\n",
+                ENTRY
+            )
+        );
+        assert_eq!(insert_entry(&updated, "## Unreleased", ENTRY), None);
+
+        let empty_section = "## Unreleased\r\n\r\n## 1.0.0\r\n";
+        assert_eq!(
+            insert_entry(empty_section, "## Unreleased", ENTRY).unwrap(),
+            format!("## Unreleased\r\n\r\n{}\r\n\r\n## 1.0.0\r\n", ENTRY)
+        );
+    }
+
+    #[test]
+    fn insert_entry_creates_the_heading_above_the_first_release() {
+        let text = "\u{feff}# Changelog\n\nAll notable changes.\n\n## 1.0.0\n- first\n";
+        assert_eq!(
+            insert_entry(text, "## Unreleased", ENTRY).unwrap(),
+            format!(
+                "\u{feff}# Changelog\n\nAll notable changes.\n\n## Unreleased\n\n{}\n\n## 1.0.0\n- first\n",
+                ENTRY
+            )
+        );
+
+        assert_eq!(
+            insert_entry("# Changelog", "## Unreleased", ENTRY).unwrap(),
+            format!("# Changelog\n\n## Unreleased\n\n{}\n", ENTRY)
+        );
+    }
+}
@@ -1,16 +1,86 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context as _, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::time::Instant;
 
-use crate::config::Config;
-use crate::git;
+use crate::bumps;
+use crate::campaign::{self, Batching, BotPrs, Campaign, OnError, RepoStatus};
+use crate::config::{self, Config, Repository};
+use crate::context::{self, Context};
+use crate::git::{self, RepositoryLayout, SubmoduleMode, WorkflowOutcome};
 use crate::github;
+use crate::history::{self, HistoryRecord};
+use crate::interrupt;
+use crate::license::{self, LicenseChange};
+use crate::lint;
+use crate::lockfile::Lockfile;
+use crate::message::CommitStyle;
+use crate::node;
+use crate::notify;
+use crate::order;
 use crate::package;
+use crate::parallel;
+use crate::policy::{self, Policy};
+use crate::preflight::{self, Requirements};
+use crate::preview;
+use crate::provenance::{self, ProvenanceCheck};
+use crate::provider::{self, MergeMethod, MergeOptions, MergeOutcome};
+use crate::repo;
+use crate::report::{self, ReportFormat};
+use crate::runner::{CommandOutput, CommandSpec};
+use crate::scratch;
+use crate::share;
+use crate::suggest;
+use crate::summary::{self, SummaryMode};
+use crate::table;
+use crate::template::{self, Scope, TemplateContext};
+use crate::timing::{self, StepTimer};
+use crate::ui;
+use crate::version;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Number of attempts for network operations (default: 3)
+    #[arg(long, global = true)]
+    pub retries: Option<u32>,
+
+    /// Write a detailed log of every command and decision to this file
+    #[arg(long, global = true)]
+    pub log_file: Option<String>,
+
+    /// Don't stream output of git and package manager commands
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Accept comments and trailing commas in package.json (they are kept when updating)
+    #[arg(long, global = true)]
+    pub jsonc: bool,
+
+    /// Use this config file instead of ~/.config/mru/config.toml
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Use the config, campaigns and history of this profile
+    /// (default: $MRU_PROFILE, else default_profile)
+    #[arg(long, global = true, conflicts_with = "config")]
+    pub profile: Option<String>,
+
+    /// Wait for another running mru process to finish instead of failing
+    #[arg(long, global = true)]
+    pub wait: bool,
+
+    /// Don't send the configured notifications when a campaign finishes
+    #[arg(long, global = true)]
+    pub no_notify: bool,
 }
 
 #[derive(Subcommand)]
@@ -18,12 +88,29 @@ pub enum Commands {
     /// Update a package in all repositories
     Update {
         /// Package name to update
-        package: String,
+        #[arg(required_unless_present = "stdin")]
+        package: Option<String>,
 
         /// New version to set
-        version: String,
+        #[arg(required_unless_present_any = ["wanted", "stdin"])]
+        version: Option<String>,
+
+        /// Read the packages to update from stdin, one `package version` per line or JSON
+        /// objects with `package`, `version` and optionally `section` and `repo`
+        #[arg(long, conflicts_with_all = ["package", "version", "wanted"])]
+        stdin: bool,
+
+        /// Instead of a version, move each repository to the newest published version
+        /// within the range it already declares
+        #[arg(long, conflicts_with_all = ["version", "verify_provenance"])]
+        wanted: bool,
+
+        /// With --wanted, keep the range's operator, e.g. ^4.17.0 -> ^4.17.21, instead of
+        /// writing the exact version
+        #[arg(long, requires = "wanted")]
+        preserve_prefix: bool,
 
-        /// Commit message (optional)
+        /// Commit message; {package}, {version}, {type}, {scope} and {ticket} are replaced
         #[arg(short, long)]
         message: Option<String>,
 
@@ -34,12 +121,232 @@ pub enum Commands {
         /// Dry run (don't make any changes)
         #[arg(short, long)]
         dry_run: bool,
+
+        /// Don't check the version syntax (for git URLs, aliases and other specifiers)
+        #[arg(long)]
+        no_validate: bool,
+
+        /// Conventional-commit type of the commit message and branch (default: chore)
+        #[arg(long = "type")]
+        commit_type: Option<String>,
+
+        /// Conventional-commit scope, e.g. deps
+        #[arg(long)]
+        scope: Option<String>,
+
+        /// Ticket key to put in the commit subject, e.g. SEC-42
+        #[arg(long)]
+        ticket: Option<String>,
+
+        /// Skip repositories where a policy forbids the update, or abort the whole update
+        #[arg(long, value_enum, default_value_t = PolicyMode::Warn)]
+        policy: PolicyMode,
+
+        /// Allow committing to the default branch or a protected branch
+        #[arg(long)]
+        allow_protected: bool,
+
+        /// Update repositories whose base branch has diverged from origin instead of skipping them
+        #[arg(long)]
+        force_base: bool,
+
+        /// Branch from this ref (e.g. origin/main) instead of the checked-out branch, and
+        /// read the versions being replaced from it; the PR targets it if it names a branch
+        #[arg(long, value_name = "REF", conflicts_with = "wanted")]
+        base: Option<String>,
+
+        /// Add an entry for the update to CHANGELOG.md in repositories that have one
+        #[arg(long)]
+        changelog: bool,
+
+        /// Also allow committing files matching this glob, e.g. 'src/generated/*' (repeatable)
+        #[arg(long = "allow-extra-paths", value_name = "GLOB")]
+        allow_extra_paths: Vec<String>,
+
+        /// Show each repository's diffstat and ask before pushing it
+        #[arg(long, alias = "interactive")]
+        confirm_push: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Break the timing summary down by step and point out the slowest repositories
+        #[arg(long)]
+        timings: bool,
+
+        /// Pause after each wave of repositories that others depend on, e.g. to merge and publish
+        #[arg(long)]
+        wait_between_waves: bool,
+
+        /// Run the package manager's dedupe after installing (default: `dedupe` in the config)
+        #[arg(long, overrides_with = "no_dedupe")]
+        dedupe: bool,
+
+        /// Don't run dedupe, even if the config enables it
+        #[arg(long, overrides_with = "dedupe")]
+        no_dedupe: bool,
+
+        /// Put package.json and lockfiles back if installing or committing fails (default)
+        #[arg(long, overrides_with = "no_restore_on_failure")]
+        restore_on_failure: bool,
+
+        /// Leave a failed update's changes in the working tree, e.g. to debug the install
+        #[arg(long, overrides_with = "restore_on_failure")]
+        no_restore_on_failure: bool,
+
+        /// Update extra manifests inside git submodules in the submodule's repository
+        /// (its own branch, commit, push and PR) instead of skipping them
+        #[arg(long)]
+        recurse_submodules: bool,
+
+        /// Also commit the submodules' new commits in the parent repository
+        #[arg(long, requires = "recurse_submodules")]
+        commit_submodules: bool,
+
+        /// Mark each pull request ready for review right after creating it as a draft
+        #[arg(long, requires = "pull_request")]
+        ready: bool,
+
+        /// Comment on each pull request with the package.json diff, lockfile diffstat and
+        /// install output, editing the comment on re-runs
+        #[arg(long, requires = "pull_request")]
+        comment_details: bool,
+
+        /// Resolve each update in a temporary copy of the manifest and lockfile first and
+        /// report the other packages it adds, removes or changes; works with --dry-run, and
+        /// --comment-details includes the result
+        #[arg(long)]
+        resolve_preview: bool,
+
+        /// Skip repositories where Renovate or Dependabot already has a pull request open
+        /// for the package, instead of pushing the update without opening another
+        #[arg(long, conflicts_with = "ignore_bot_prs")]
+        skip_if_bot_pr: bool,
+
+        /// Open pull requests even where an update bot has one open for the package
+        #[arg(long)]
+        ignore_bot_prs: bool,
+
+        /// Only warn when an install rewrites a lockfile in another format or leaves less
+        /// than half of it, instead of failing the repository
+        #[arg(long)]
+        allow_lockfile_format_change: bool,
+
+        /// Update only this repository, configured or not, instead of the configured ones
+        #[arg(long, value_name = "PATH")]
+        repo_path: Option<String>,
+
+        /// Add `Mru-Campaign` and `Mru-Package` trailers to each commit message (default)
+        #[arg(long, overrides_with = "no_trailers")]
+        trailers: bool,
+
+        /// Leave the commit message as the template renders it
+        #[arg(long, overrides_with = "trailers")]
+        no_trailers: bool,
+
+        /// Attach each pull request's URL to its commit as a git note in refs/notes/mru,
+        /// and push the notes
+        #[arg(long, requires = "pull_request")]
+        pr_note: bool,
+
+        /// Request reviews from the code owners of the changed files, in repositories with
+        /// a CODEOWNERS file (default: `codeowners` in the config)
+        #[arg(long, requires = "pull_request")]
+        codeowners: bool,
+
+        /// Put each pull request in this milestone (default: `milestone` in the config)
+        #[arg(long, value_name = "NAME", requires = "pull_request")]
+        milestone: Option<String>,
+
+        /// Add each pull request to this project, by title or number (default: `project` in
+        /// the config)
+        #[arg(long, value_name = "NAME_OR_NUMBER", requires = "pull_request")]
+        project: Option<String>,
+
+        /// Create the milestone in repositories that don't have it, instead of warning
+        #[arg(long)]
+        create_missing_milestone: bool,
+
+        /// Check that the version was published with npm provenance and that its integrity
+        /// matches the public registry before updating anything
+        #[arg(long)]
+        verify_provenance: bool,
+
+        /// Abort the update when --verify-provenance finds a problem, or only warn
+        #[arg(long, value_enum, default_value_t = PolicyMode::Error)]
+        provenance: PolicyMode,
+
+        /// Compare the package's license before and after the update in every repository,
+        /// and warn or abort when it changes or is on `license_denylist` (default: warn
+        /// if the config has a deny-list)
+        #[arg(long, value_enum)]
+        license_policy: Option<PolicyMode>,
+
+        /// Only update the first N repositories using the package; the rest are deferred
+        /// to later batches of N (`mru resume --next-batch`)
+        #[arg(long, value_name = "N", conflicts_with = "batch")]
+        limit: Option<usize>,
+
+        /// Split the repositories using the package into N batches and update batch I
+        #[arg(long, value_name = "I/N", value_parser = parse_batch)]
+        batch: Option<Batching>,
+
+        /// Open the pull requests this run creates in the browser
+        #[arg(long)]
+        web: bool,
+
+        /// Open at most this many pull requests with --web
+        #[arg(long, value_name = "N", default_value_t = 10, requires = "web")]
+        web_limit: usize,
+
+        /// Write a report of the run to this file, or to stdout with `-`
+        #[arg(long, value_name = "PATH")]
+        report: Option<String>,
+
+        /// Format of the --report file
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json, requires = "report")]
+        report_format: ReportFormat,
+
+        /// How much the summary at the end of the run shows; the report always has
+        /// everything (default: `summary` in the config, or full)
+        #[arg(long, value_enum, value_name = "MODE")]
+        summary: Option<SummaryMode>,
+
+        /// What to do after a repository fails (default: `on_error` in the config, or
+        /// prompt on a terminal and continue otherwise)
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_error: Option<OnError>,
     },
 
+    /// Compose an update step by step: pick repositories, the version and options,
+    /// review the plan, then run it
+    Ui,
+
     /// Add a new repository to the config
     AddRepo {
         /// Local path to the repository
         path: String,
+
+        /// GitHub URL of the repository (detected from the origin remote if not specified)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Short name to refer to the repository by
+        #[arg(long)]
+        alias: Option<String>,
+
+        /// Add the top level of the repository when the path is a directory inside it
+        #[arg(long)]
+        use_toplevel: bool,
+
+        /// Refuse to add the repository if `doctor --repo` would warn about it
+        #[arg(long, conflicts_with = "force")]
+        strict: bool,
+
+        /// Add the repository without checking it
+        #[arg(long)]
+        force: bool,
     },
 
     /// Remove a repository from the config
@@ -51,299 +358,5947 @@ pub enum Commands {
     /// List all configured repositories
     ListRepos,
 
-    /// Compare package versions across repositories
-    Compare {
-        /// Package name to compare
-        package: String,
+    /// Show branch, working tree and upstream state of every repository
+    Status {
+        /// Fetch remote refs before comparing with upstream
+        #[arg(long)]
+        fetch: bool,
+
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Look up the pull request and checks of every update branch (one GitHub query per 50 branches)
+        #[arg(long)]
+        prs: bool,
+
+        /// Also list the pull requests Renovate and Dependabot have open (`bot_branches`)
+        #[arg(long)]
+        include_bots: bool,
     },
 
-    /// List all packages in a repository
-    ListPackages {
-        /// Repository path (optional, uses all repositories if not specified)
-        #[arg(short, long)]
+    /// Check that the tools mru runs are installed and git can commit and push in every repository
+    Doctor {
+        /// Only check this repository, which doesn't have to be configured yet
+        #[arg(long, value_name = "PATH")]
         repo: Option<String>,
     },
 
+    /// Compare package versions across repositories
+    Compare(CompareArgs),
+
+    /// Check version consistency and policies across repositories, for CI
+    Check(CheckArgs),
+
+    /// List all packages in a repository
+    ListPackages(ListPackagesArgs),
+
     /// Clone a repository
-    Clone {
-        /// GitHub URL of the repository
-        github_url: String,
+    Clone(CloneArgs),
 
-        /// Local path to clone to
-        #[arg(short, long)]
-        output: Option<String>,
+    /// Fork a repository, clone the fork and add the original as a remote
+    Fork(ForkArgs),
 
-        /// Add to config after cloning
-        #[arg(short, long)]
-        add: bool,
-    },
+    /// Clone configured repositories that are missing and check the remotes of the rest
+    Bootstrap(BootstrapArgs),
 
     /// Set default package manager
     SetPackageManager {
         /// Package manager name (npm, yarn, pnpm)
         name: String,
     },
-}
 
-/// Handle update command
-pub fn handle_update(
-    config: &Config,
-    package: &str,
-    version: &str,
-    message: Option<&str>,
-    pull_request: bool,
-    dry_run: bool,
-) -> Result<()> {
-    if config.repositories.is_empty() {
-        println!("No repositories configured. Use 'add-repo' command to add repositories.");
-        return Ok(());
-    }
+    /// Resume an interrupted update campaign
+    Resume {
+        /// Campaign id (defaults to the most recent unfinished campaign)
+        campaign_id: Option<String>,
 
-    let commit_message = message
-        .unwrap_or(&format!("chore: update {} to {}", package, version))
-        .to_string();
+        /// Also run the next batch of repositories deferred by `--limit` or `--batch`
+        #[arg(long)]
+        next_batch: bool,
 
-    if dry_run {
-        println!("DRY RUN MODE - No changes will be made");
-    }
+        /// What to do after a repository fails (default: what the campaign was started
+        /// with, but continue instead of prompting without a terminal)
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_error: Option<OnError>,
+    },
 
-    println!(
-        "Updating package '{}' to version '{}' in {} repositories",
-        package,
-        version,
-        config.repositories.len()
-    );
+    /// Roll back a campaign: close its PRs and delete its branches
+    Undo {
+        /// Campaign id (defaults to the most recent campaign)
+        campaign_id: Option<String>,
 
-    for repo in &config.repositories {
-        if let Err(e) = git::update_package_workflow(
-            repo,
-            package,
-            version,
-            &commit_message,
-            pull_request,
-            dry_run,
-            config,
-        ) {
-            eprintln!("Error processing repository {}: {}", repo.path, e);
+        /// Only list what would be done in each repository
+        #[arg(short, long)]
+        dry_run: bool,
 
-            // 사용자에게 계속할지 물어보기
-            if !prompt_continue() {
-                println!("Aborting update process");
-                break;
-            }
-        }
-    }
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
-    Ok(())
+    /// Mark the draft pull requests of a branch ready for review
+    Ready(ReadyArgs),
+
+    /// Show what mru has done on this machine
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+
+        /// Print raw history records as JSON
+        #[arg(long, global = true)]
+        json: bool,
+    },
+
+    /// Inspect update campaigns
+    Campaigns {
+        #[command(subcommand)]
+        command: CampaignCommands,
+    },
+
+    /// Run a command in every configured repository
+    Exec(ExecArgs),
+
+    /// Merge the pull requests of a branch, on GitHub, Bitbucket or Azure DevOps
+    Merge(MergeArgs),
+
+    /// Cherry-pick an update onto release branches and open PRs against them
+    Backport(BackportArgs),
+
+    /// Bump each repository's own package version, commit and tag it
+    Version(VersionArgs),
+
+    /// Update the repositories that consume a configured repository's package to its new version
+    Propagate(PropagateArgs),
+
+    /// Pin dependency ranges to the exact versions in the lockfile
+    PinAll(SpecifierArgs),
+
+    /// Turn exact dependency versions into ranges
+    Widen(WidenArgs),
+
+    /// Set the engines.node range, and the Volta pins to a release in it
+    Engines(EnginesArgs),
+
+    /// Report packages declared inconsistently in package.json and workspace members
+    LintManifests(LintManifestsArgs),
+
+    /// Manage policies that restrict automatic updates of packages
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+
+    /// Send the notifications for a finished run
+    Notify {
+        /// Run report: a campaign state file or a record from `mru history --json`
+        #[arg(long)]
+        report: String,
+    },
+
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Manage profiles: separate configs, campaigns and history selected with --profile
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+
+    /// Check the branch, commit, pull request and notification templates
+    Templates {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
+
+    /// Manage files mru keeps outside the repositories
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
 }
 
-/// Handle add repository command
-pub fn handle_add_repo(config: &mut Config, path: &str) -> Result<()> {
-    match config.add_repository(path.to_string()) {
-        Ok(_) => {
-            println!("Repository added successfully: {}", path);
-            Ok(())
-        }
-        Err(e) => {
-            eprintln!("Failed to add repository: {}", e);
-            Err(e)
+impl Commands {
+    /// Name recorded in the lock file for commands that change the config or
+    /// repositories; read-only commands run without taking the lock
+    pub fn lock_name(&self) -> Option<&'static str> {
+        match self {
+            Commands::Update { dry_run: false, .. } => Some("update"),
+            Commands::Ui => Some("ui"),
+            Commands::AddRepo { .. } => Some("add-repo"),
+            Commands::RemoveRepo { .. } => Some("remove-repo"),
+            Commands::Clone(_) => Some("clone"),
+            Commands::Fork(_) => Some("fork"),
+            Commands::Bootstrap(args) if !args.dry_run => Some("bootstrap"),
+            Commands::SetPackageManager { .. } => Some("set-package-manager"),
+            Commands::Resume { .. } => Some("resume"),
+            Commands::Undo { dry_run: false, .. } => Some("undo"),
+            Commands::Exec(_) => Some("exec"),
+            Commands::Backport(args) if !args.dry_run => Some("backport"),
+            Commands::Version(args) if !args.dry_run => Some("version"),
+            Commands::Propagate(args) if !args.dry_run => Some("propagate"),
+            Commands::PinAll(args) if !args.proposal.dry_run => Some("pin-all"),
+            Commands::Widen(args) if !args.specifiers.proposal.dry_run => Some("widen"),
+            Commands::Engines(args) if !args.proposal.dry_run => Some("engines"),
+            Commands::LintManifests(args) if args.fix && !args.proposal.dry_run => {
+                Some("lint-manifests")
+            }
+            Commands::Policy {
+                command: PolicyCommands::Add { .. } | PolicyCommands::Remove { .. },
+            } => Some("policy"),
+            Commands::Config {
+                command: ConfigCommands::Import { dry_run: false, .. },
+            } => Some("config-import"),
+            Commands::Profile {
+                command: ProfileCommands::Delete { .. },
+            } => Some("profile"),
+            _ => None,
         }
     }
 }
 
-/// Handle remove repository command
-pub fn handle_remove_repo(config: &mut Config, path: &str) -> Result<()> {
-    match config.remove_repository(path) {
-        Ok(_) => {
-            println!("Repository removed successfully: {}", path);
-            Ok(())
-        }
-        Err(e) => {
-            eprintln!("Failed to remove repository: {}", e);
-            Err(e)
+/// Parse `--batch 2/5`
+fn parse_batch(value: &str) -> Result<Batching, String> {
+    let parsed = value
+        .split_once('/')
+        .and_then(|(index, count)| Some((index.parse().ok()?, count.parse().ok()?)));
+    match parsed {
+        Some((index, count)) if index >= 1 && index <= count => {
+            Ok(Batching::Batch { index, count })
         }
+        _ => Err("expected I/N with 1 <= I <= N, e.g. 2/5".to_string()),
     }
 }
 
-/// Handle list repositories command
-pub fn handle_list_repos(config: &Config) -> Result<()> {
-    if config.repositories.is_empty() {
-        println!("No repositories configured");
-    } else {
-        println!("Configured repositories:");
-        for (i, repo) in config.repositories.iter().enumerate() {
-            println!("{}. Path: {}", i + 1, repo.path);
-
-            // Git 상태 확인
-            match git::check_status(&repo.path) {
-                Ok(has_changes) => {
-                    if has_changes {
-                        println!("   Status: Changes present");
-                    } else {
-                        println!("   Status: Clean");
-                    }
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PolicyMode {
+    Warn,
+    Error,
+}
 
-                    // 현재 브랜치 표시
-                    if let Ok(branch) = git::get_current_branch(&repo.path) {
-                        println!("   Branch: {}", branch);
-                    }
+/// Selects a subset of the configured repositories
+#[derive(Args, Debug, Default)]
+pub struct RepoFilter {
+    /// Only repositories with this name or path (repeatable)
+    #[arg(long)]
+    pub only: Vec<String>,
 
-                    // 패키지 매니저 감지
-                    if let Ok(pkg_manager) = package::detect_package_manager(&repo.path) {
-                        println!("   Package Manager: {}", pkg_manager);
-                    }
-                }
-                Err(e) => println!("   Status check failed: {}", e),
-            }
-        }
-    }
+    /// Skip repositories with this name or path (repeatable)
+    #[arg(long)]
+    pub exclude: Vec<String>,
 
-    Ok(())
+    /// Only repositories in this group
+    #[arg(long)]
+    pub group: Option<String>,
 }
 
-/// Handle package version comparison command
-pub fn handle_compare(config: &Config, package: &str) -> Result<()> {
-    if config.repositories.is_empty() {
-        println!("No repositories configured");
-        return Ok(());
+impl RepoFilter {
+    pub fn apply<'a>(&self, repositories: &'a [Repository]) -> Vec<&'a Repository> {
+        repositories.iter().filter(|r| self.matches(r)).collect()
     }
 
-    println!("Comparing package '{}' across repositories:", package);
+    fn matches(&self, repo: &Repository) -> bool {
+        let matches_any = |patterns: &[String]| patterns.iter().any(|p| repo.is_named(p));
 
-    let mut repo_paths = Vec::new();
-    for repo in &config.repositories {
-        repo_paths.push(repo.path.as_str());
+        (self.only.is_empty() || matches_any(&self.only))
+            && !matches_any(&self.exclude)
+            && self.group.as_ref().is_none_or(|g| repo.groups.contains(g))
     }
+}
 
-    let versions = package::compare_package_versions(&repo_paths, package)?;
+#[derive(Args)]
+pub struct CloneArgs {
+    /// Repository URL (HTTPS or SSH) or owner/repo shorthand
+    pub repository: String,
 
-    for (repo_path, version) in versions {
-        match version {
-            Some(v) => println!("{}: {}", repo_path, v),
-            None => println!("{}: Not found", repo_path),
-        }
-    }
+    /// Local path to clone to (defaults to the repository name)
+    #[arg(short, long)]
+    pub output: Option<String>,
 
-    Ok(())
-}
+    /// Add to config after cloning, with the repository name as its alias
+    #[arg(short, long)]
+    pub add: bool,
 
-/// Handle list packages command
-pub fn handle_list_packages(config: &Config, repo_path: Option<&str>) -> Result<()> {
-    if config.repositories.is_empty() && repo_path.is_none() {
-        println!("No repositories configured");
-        return Ok(());
-    }
+    /// Create a shallow clone with this many commits
+    #[arg(long)]
+    pub depth: Option<u32>,
 
-    let repositories = if let Some(path) = repo_path {
-        // Process specific repository only
-        let repo = config
-            .repositories
-            .iter()
-            .find(|r| r.path == path)
-            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", path))?;
+    /// Branch to check out instead of the default branch
+    #[arg(short, long)]
+    pub branch: Option<String>,
 
-        vec![repo]
-    } else {
-        // Process all repositories
-        config.repositories.iter().collect()
-    };
+    /// Clone over SSH, rewriting HTTPS URLs and shorthand
+    #[arg(long)]
+    pub ssh: bool,
 
-    for repo in repositories {
-        println!("Packages in {}:", repo.path);
+    /// Install dependencies after cloning
+    #[arg(long)]
+    pub install: bool,
+}
 
-        match package::list_all_packages(&repo.path) {
-            Ok(packages) => {
-                if packages.is_empty() {
-                    println!("  No packages found");
-                } else {
-                    // Group packages by type
-                    let mut deps = Vec::new();
-                    let mut dev_deps = Vec::new();
-                    let mut peer_deps = Vec::new();
-
-                    for (name, version, dep_type) in packages {
-                        match dep_type.as_str() {
-                            "dependencies" => deps.push((name, version)),
-                            "devDependencies" => dev_deps.push((name, version)),
-                            "peerDependencies" => peer_deps.push((name, version)),
-                            _ => {}
-                        }
-                    }
+#[derive(Args)]
+pub struct ForkArgs {
+    /// Repository URL (HTTPS or SSH) or owner/repo shorthand
+    pub repository: String,
 
-                    if !deps.is_empty() {
-                        println!("  Dependencies:");
-                        for (name, version) in deps {
-                            println!("    {}: {}", name, version);
-                        }
-                    }
+    /// Local path to clone the fork to (defaults to the repository name).
+    /// An existing clone of the repository is reused
+    #[arg(short, long)]
+    pub output: Option<String>,
 
-                    if !dev_deps.is_empty() {
-                        println!("  Dev Dependencies:");
-                        for (name, version) in dev_deps {
-                            println!("    {}: {}", name, version);
-                        }
-                    }
+    /// Add to config after cloning, marked as a fork so PRs go to the original repository
+    #[arg(short, long)]
+    pub add: bool,
 
-                    if !peer_deps.is_empty() {
-                        println!("  Peer Dependencies:");
-                        for (name, version) in peer_deps {
-                            println!("    {}: {}", name, version);
-                        }
-                    }
-                }
-            }
-            Err(e) => println!("  Error listing packages: {}", e),
-        }
-    }
+    /// Name of the remote pointing at the original repository
+    #[arg(long, default_value = "upstream")]
+    pub remote_name: String,
+}
 
-    Ok(())
+#[derive(Args)]
+pub struct BootstrapArgs {
+    /// Clone into <root>/<name> instead of the configured paths, and record the new paths
+    #[arg(long)]
+    pub root: Option<String>,
+
+    /// Number of repositories to clone at once
+    #[arg(short, long, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// List what would be cloned or flagged without cloning
+    #[arg(short, long)]
+    pub dry_run: bool,
 }
 
-/// 레포지토리 클론 명령 처리
-pub fn handle_clone(
-    config: &mut Config,
-    github_url: &str,
-    output: Option<&str>,
-    add: bool,
-) -> Result<()> {
-    // Determine output directory
-    let output_dir = if let Some(dir) = output {
-        dir.to_string()
-    } else {
-        // Extract repository name from URL
-        let repo_name = github_url
-            .split('/')
-            .last()
-            .map(|s| s.trim_end_matches(".git"))
-            .unwrap_or("repo")
-            .to_string();
+#[derive(Args)]
+pub struct CompareArgs {
+    /// Package names to compare
+    #[arg(required_unless_present_any = ["all", "campaign"])]
+    pub packages: Vec<String>,
 
-        repo_name
-    };
+    /// Report every package used by two or more repositories whose versions differ
+    #[arg(long, conflicts_with = "packages")]
+    pub all: bool,
 
-    // Clone repository
-    github::clone_repository(github_url, &output_dir)?;
+    /// Read only this repository, configured or not, instead of the configured ones
+    #[arg(long, value_name = "PATH")]
+    pub repo_path: Option<String>,
 
-    // Add to config
-    if add {
-        let path = std::fs::canonicalize(&output_dir)
-            .map_err(|e| anyhow::anyhow!("Failed to resolve path: {}", e))?
-            .to_string_lossy()
-            .to_string();
+    /// Exit with an error if repositories use different versions
+    #[arg(long)]
+    pub check: bool,
 
-        handle_add_repo(config, &path)?;
-    }
+    /// With --check, also fail if a repository doesn't depend on a package
+    #[arg(long, requires = "check")]
+    pub require_present: bool,
 
-    Ok(())
+    /// Treat specifiers naming the same version as equal (e.g. ^1.2.3 and 1.2.3)
+    #[arg(long)]
+    pub normalize: bool,
+
+    /// Read package.json as committed at this ref (e.g. origin/main) instead of the
+    /// working tree
+    #[arg(long, value_name = "REF")]
+    pub at_ref: Option<String>,
+
+    /// Show each repository's version before the campaign, on its branch and on the
+    /// base branch, and whether the update was merged
+    #[arg(long, value_name = "ID", conflicts_with_all = ["all", "at_ref", "check", "repo_path"])]
+    pub campaign: Option<String>,
+
+    /// Print the comparison as JSON
+    #[arg(long)]
+    pub json: bool,
 }
 
-/// Handle set package manager command
-pub fn handle_set_package_manager(config: &mut Config, name: &str) -> Result<()> {
-    let valid_managers = vec!["npm", "yarn", "pnpm"];
-    if !valid_managers.contains(&name) {
-        anyhow::bail!(
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Package that must have the same version in every repository using it (repeatable)
+    #[arg(long = "package", required_unless_present_any = ["all", "policies"])]
+    pub packages: Vec<String>,
+
+    /// Check every package used by two or more repositories
+    #[arg(long, conflicts_with = "packages")]
+    pub all: bool,
+
+    /// Check that current versions are within the ranges allowed by policies
+    #[arg(long)]
+    pub policies: bool,
+
+    /// Treat specifiers naming the same version as equal (e.g. ^1.2.3 and 1.2.3)
+    #[arg(long)]
+    pub normalize: bool,
+
+    /// Number of repositories to read at once
+    #[arg(short, long, default_value_t = 8)]
+    pub jobs: usize,
+
+    /// Write a JUnit XML report to this file
+    #[arg(long)]
+    pub junit: Option<String>,
+
+    /// Write a JSON report to this file
+    #[arg(long)]
+    pub json: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ListPackagesArgs {
+    /// Repository path (optional, uses all repositories if not specified)
+    #[arg(short, long)]
+    pub repo: Option<String>,
+
+    /// A repository that isn't configured, in place of the configured ones
+    #[arg(long, value_name = "PATH", conflicts_with = "repo")]
+    pub repo_path: Option<String>,
+
+    /// Only packages whose name matches this glob (e.g. '@babel/*')
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Only packages in this dependency section
+    #[arg(long, value_parser = package::DEPENDENCY_SECTIONS)]
+    pub section: Option<String>,
+
+    /// Order of the packages within each section
+    #[arg(long, value_enum, default_value_t = PackageSort::Name)]
+    pub sort: PackageSort,
+
+    /// Print only the number of packages in each section
+    #[arg(long)]
+    pub count: bool,
+
+    /// List each package once with the repositories and versions using it
+    #[arg(long, conflicts_with = "repo")]
+    pub aggregate: bool,
+
+    /// Show the license of each package's version in use, from node_modules or the registry
+    #[arg(long, conflicts_with_all = ["count", "aggregate"])]
+    pub licenses: bool,
+
+    /// Read package.json as committed at this ref (e.g. origin/main) instead of the
+    /// working tree
+    #[arg(long, value_name = "REF", conflicts_with = "licenses")]
+    pub at_ref: Option<String>,
+
+    /// Print the packages as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PackageSort {
+    Name,
+    Version,
+}
+
+#[derive(Args)]
+pub struct ExecArgs {
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Run in only this repository, configured or not
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["only", "exclude", "group"])]
+    pub repo_path: Option<String>,
+
+    /// Number of repositories to run the command in at once
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Stop starting the command in more repositories once it fails in one (`abort`),
+    /// or run it everywhere (`continue`; also what `prompt` does, since nothing is
+    /// asked while commands run; default: `on_error` in the config)
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub on_error: Option<OnError>,
+
+    /// Commit the changes made by the command on a new branch
+    #[arg(long)]
+    pub commit: bool,
+
+    /// Commit message (with --commit)
+    #[arg(short, long, requires = "commit")]
+    pub message: Option<String>,
+
+    /// Branch to commit to (with --commit, defaults to one derived from the message)
+    #[arg(long, requires = "commit")]
+    pub branch: Option<String>,
+
+    /// Push the branch (with --commit)
+    #[arg(long, requires = "commit")]
+    pub push: bool,
+
+    /// Create pull request (with --commit, implies --push)
+    #[arg(short, long, requires = "commit")]
+    pub pull_request: bool,
+
+    /// Command and arguments to run, after `--`
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct MergeArgs {
+    /// Branch whose pull requests to merge, e.g. update-react-18.2.0
+    pub branch: String,
+
+    /// How the commits end up on the base branch; on Bitbucket, rebase is a fast-forward
+    #[arg(long, value_enum, default_value_t = MergeMethod::Squash)]
+    pub method: MergeMethod,
+
+    /// Delete the branch once its pull request is merged
+    #[arg(long)]
+    pub delete_branch: bool,
+
+    /// Merge even if branch protection requirements are not met (GitHub only)
+    #[arg(long)]
+    pub admin: bool,
+
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Only show what would be merged
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct BackportArgs {
+    /// Campaign branch, e.g. update-lodash-4.17.21, or a commit
+    pub source: String,
+
+    /// Branch to backport onto, e.g. release/3.x (repeatable)
+    #[arg(long = "to", required = true)]
+    pub targets: Vec<String>,
+
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Dry run (don't make any changes)
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PropagateArgs {
+    /// Repository publishing the package (alias, directory name or path)
+    pub source: String,
+
+    /// Version to propagate (default: the version in the source's package.json)
+    #[arg(long)]
+    pub version: Option<String>,
+
+    /// Write the exact version instead of a caret range
+    #[arg(long)]
+    pub exact: bool,
+
+    /// Don't check that the version is published to the registry
+    #[arg(long)]
+    pub skip_publish_check: bool,
+
+    /// Create a pull request in each consumer, linking to the source's release
+    #[arg(short, long)]
+    pub pull_request: bool,
+
+    /// Dry run (don't make any changes)
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+/// Which repositories a manifest rewrite (`pin-all`, `widen`, `engines`) applies to,
+/// and how the change is proposed
+#[derive(Args, Debug)]
+pub struct ProposalArgs {
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Custom commit message
+    #[arg(short, long)]
+    pub message: Option<String>,
+
+    /// Create a pull request
+    #[arg(short, long)]
+    pub pull_request: bool,
+
+    /// List the changes without making them
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+/// Which specifiers `pin-all` and `widen` rewrite, and how the change is proposed
+#[derive(Args, Debug)]
+pub struct SpecifierArgs {
+    #[command(flatten)]
+    pub proposal: ProposalArgs,
+
+    /// Dependency section to rewrite (repeatable; default: dependencies and devDependencies)
+    #[arg(long = "section", value_parser = package::DEPENDENCY_SECTIONS)]
+    pub sections: Vec<String>,
+
+    /// Only packages whose name matches this glob (e.g. '@babel/*')
+    #[arg(long = "filter")]
+    pub packages: Option<String>,
+}
+
+impl SpecifierArgs {
+    fn sections(&self) -> Vec<String> {
+        if self.sections.is_empty() {
+            vec!["dependencies".to_string(), "devDependencies".to_string()]
+        } else {
+            self.sections.clone()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct WidenArgs {
+    /// Range operator put in front of exact versions
+    #[arg(long, default_value = "^", value_parser = ["^", "~"])]
+    pub prefix: String,
+
+    #[command(flatten)]
+    pub specifiers: SpecifierArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct EnginesArgs {
+    /// New `engines.node` range, e.g. '>=20'
+    pub range: String,
+
+    /// Node.js version for `volta.node` (default: the newest release in the range)
+    #[arg(long, value_name = "VERSION")]
+    pub volta: Option<String>,
+
+    /// Yarn version for `volta.yarn` (default: left alone)
+    #[arg(long, value_name = "VERSION")]
+    pub volta_yarn: Option<String>,
+
+    /// Leave `volta` blocks alone
+    #[arg(long, conflicts_with_all = ["volta", "volta_yarn"])]
+    pub no_volta: bool,
+
+    #[command(flatten)]
+    pub proposal: ProposalArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct LintManifestsArgs {
+    /// Give packages in both dependencies and devDependencies one specifier, and
+    /// propose that like pin-all
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Section whose specifier --fix keeps
+    #[arg(long, value_enum, default_value_t = lint::Prefer::Dependencies)]
+    pub prefer: lint::Prefer,
+
+    #[command(flatten)]
+    pub proposal: ProposalArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ReadyArgs {
+    /// Branch whose pull requests to mark ready, e.g. update-react-18.2.0
+    pub branch: String,
+
+    /// Leave pull requests whose checks aren't all passing as drafts
+    #[arg(long)]
+    pub only_green: bool,
+
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Only show what would be marked ready
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VersionArgs {
+    /// patch, minor, major or an explicit version such as 2.0.0
+    pub bump: String,
+
+    #[command(flatten)]
+    pub filter: RepoFilter,
+
+    /// Run the package manager's version command (e.g. npm version) instead of editing package.json
+    #[arg(long)]
+    pub native: bool,
+
+    /// Commit message; {version} is replaced
+    #[arg(short, long, default_value = "release: v{version}")]
+    pub message: String,
+
+    /// Create an annotated tag v<version>
+    #[arg(long)]
+    pub tag: bool,
+
+    /// Push the branch
+    #[arg(long)]
+    pub push: bool,
+
+    /// Push the branch and the tag
+    #[arg(long, requires = "tag")]
+    pub push_tags: bool,
+
+    /// Show the current and next version of each repository without changing anything
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// Show the per-repository detail of one run
+    Show {
+        /// Campaign id
+        id: String,
+
+        /// Print the run as a Markdown report, to share
+        #[arg(long, conflicts_with = "json")]
+        markdown: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CampaignCommands {
+    /// List past and ongoing campaigns
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Remove what is left over from runs that crashed
+    Clean {
+        /// Campaign directories in tmp_root whose run is no longer going
+        #[arg(long, required = true)]
+        tmp: bool,
+
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Show which config files were loaded
+    Which,
+
+    /// Write a config to share with a team, with paths relative to $WORKSPACE
+    Export {
+        /// File to write (default: stdout)
+        #[arg(long)]
+        out: Option<String>,
+
+        /// Directory the repository paths are made relative to (default: the deepest
+        /// directory all repositories are in)
+        #[arg(long)]
+        workspace: Option<String>,
+
+        /// Also leave out environment variables, retries, timeouts and GitHub concurrency
+        #[arg(long)]
+        strip_local: bool,
+    },
+
+    /// Add the repositories, policies and settings of a shared config to this one
+    Import {
+        /// Shared config, e.g. from `mru config export`
+        file: String,
+
+        /// Add to the config, keeping what it has (default)
+        #[arg(long, conflicts_with = "replace")]
+        merge: bool,
+
+        /// Replace the repositories, policies and settings with the shared ones
+        #[arg(long)]
+        replace: bool,
+
+        /// Directory the repositories live in on this machine, put in place of $WORKSPACE
+        #[arg(long)]
+        root: Option<String>,
+
+        /// Side to take when both configs set an entry differently (default: ask)
+        #[arg(long, value_enum)]
+        prefer: Option<share::Side>,
+
+        /// Clone the repositories that are missing, like `mru bootstrap`
+        #[arg(long)]
+        clone: bool,
+
+        /// Show what would change without saving
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// List profiles
+    List,
+
+    /// Create a profile with an empty config
+    Create {
+        /// Profile name
+        name: String,
+    },
+
+    /// Delete a profile with its campaigns and history
+    Delete {
+        /// Profile name
+        name: String,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Create a profile from the config of another, or of the default config ('default')
+    Copy {
+        /// Profile to copy, or 'default'
+        from: String,
+
+        /// New profile
+        to: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TemplateCommands {
+    /// Render the configured templates with sample values
+    Test {
+        /// Repository whose name, alias and groups to use, by name, alias or path
+        /// (default: the first configured)
+        #[arg(long)]
+        repo: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PolicyCommands {
+    /// List configured policies
+    List,
+
+    /// Add a policy
+    Add {
+        /// Policy name
+        name: String,
+
+        /// Package name or glob, e.g. '@babel/*'
+        #[arg(long)]
+        package: String,
+
+        /// Repository the policy applies to, by name, alias or path (repeatable; default: all)
+        #[arg(long = "repo")]
+        repos: Vec<String>,
+
+        /// Never update the package
+        #[arg(long, conflicts_with = "allowed", required_unless_present = "allowed")]
+        ignore: bool,
+
+        /// Range new versions must be in, e.g. '<5'
+        #[arg(long)]
+        allowed: Option<String>,
+
+        /// Why the policy exists, shown when it blocks an update
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// Remove a policy
+    Remove {
+        /// Policy name
+        name: String,
+    },
+}
+
+/// Handle `update --stdin`: read the packages to update from stdin, show the plan and
+/// run `update` for each, with prompts answered on the terminal
+pub fn handle_update_stdin(
+    ctx: &Context,
+    config: &Config,
+    yes: bool,
+    mut update: impl FnMut(&Config, &str, Option<&str>) -> Result<()>,
+) -> Result<()> {
+    use std::io::Read;
+
+    if std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "--stdin reads the packages from a pipe, e.g. `cat bumps.txt | mru update --stdin`"
+        );
+    }
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("Failed to read the packages from stdin")?;
+    let bumps = bumps::parse(&input)?;
+
+    let rows: Vec<Vec<String>> = bumps
+        .iter()
+        .map(|bump| {
+            vec![
+                bump.package.clone(),
+                bump.version.clone(),
+                bump.section.clone().unwrap_or_else(|| "-".to_string()),
+                bump.repo.clone().unwrap_or_else(|| "all".to_string()),
+            ]
+        })
+        .collect();
+    println!(
+        "{} packages to update:\n{}",
+        bumps.len(),
+        table::render(&["PACKAGE", "VERSION", "SECTION", "REPOSITORIES"], &rows)
+    );
+
+    // Stdin is the package list, so questions go to the terminal
+    PROMPTS_FROM_TTY.store(true, std::sync::atomic::Ordering::Relaxed);
+    if !yes {
+        if open_terminal().is_err() {
+            anyhow::bail!("No terminal to confirm the plan on; pass --yes to run it");
+        }
+        if !confirm("\nUpdate these packages?") {
+            println!("Aborted");
+            return Ok(());
+        }
+    }
+
+    for bump in &bumps {
+        let selected = bump.config(ctx, config)?;
+        if let (Some(section), true) = (&bump.section, selected.repositories.is_empty()) {
+            println!(
+                "\nSkipping {} (line {}): no repository declares it in {}",
+                bump.package, bump.line, section
+            );
+            continue;
+        }
+        println!("\n{} → {}", bump.package, bump.version);
+        update(&selected, &bump.package, Some(&bump.version))?;
+    }
+    Ok(())
+}
+
+/// Handle update command
+#[allow(clippy::too_many_arguments)]
+pub fn handle_update(
+    ctx: &Context,
+    config: &Config,
+    package: &str,
+    version: Option<&str>,
+    preserve_prefix: bool,
+    message: Option<&str>,
+    pull_request: bool,
+    dry_run: bool,
+    no_validate: bool,
+    style: &CommitStyle,
+    policy_mode: PolicyMode,
+    allow_protected: bool,
+    force_base: bool,
+    base: Option<&str>,
+    changelog: bool,
+    allow_extra_paths: &[String],
+    confirm_push: bool,
+    timings: bool,
+    wait_between_waves: bool,
+    dedupe: bool,
+    restore_on_failure: bool,
+    submodules: SubmoduleMode,
+    ready: bool,
+    comment_details: bool,
+    resolve_preview: bool,
+    bot_prs: BotPrs,
+    trailers: bool,
+    pr_note: bool,
+    allow_lockfile_format_change: bool,
+    summary: SummaryMode,
+    on_error: OnError,
+    codeowners: bool,
+    milestone: Option<&str>,
+    project: Option<&str>,
+    create_missing_milestone: bool,
+    verify_provenance: Option<PolicyMode>,
+    license_policy: Option<PolicyMode>,
+    batching: Option<Batching>,
+    web_limit: Option<usize>,
+    report: Option<(&str, ReportFormat)>,
+) -> Result<()> {
+    if config.repositories.is_empty() {
+        println!("No repositories configured. Use 'add-repo' command to add repositories.");
+        return Ok(());
+    }
+
+    // Reject typos before they are written into every package.json
+    if let (false, Some(version)) = (no_validate, version) {
+        version::validate(version)?;
+    }
+    style.validate(config.commit_types.as_deref())?;
+    template::check_config(config)?;
+    if let Some(message) = message {
+        template::validate("--message", message, Scope::Repository)?;
+    }
+
+    // Manifests are cached, so the workflow doesn't read them again
+    let manifest_paths = config.manifest_paths();
+    let repo_paths: Vec<&str> = manifest_paths.iter().map(String::as_str).collect();
+    let repo_versions = package::dependency_versions(ctx, &repo_paths, STATUS_JOBS, base);
+    if unused_everywhere(&repo_versions, package) {
+        anyhow::bail!(suggest::not_found_message(
+            package,
+            &suggest::known_names(&repo_versions)
+        ));
+    }
+
+    // Placeholders are filled in for each repository when it is committed
+    let commit_message = match message.or(config.templates.commit_message.as_deref()) {
+        Some(template) => template.to_string(),
+        None => style.subject("update {package} to {version}"),
+    };
+
+    if dry_run {
+        println!("DRY RUN MODE - No changes will be made");
+    }
+
+    let wanted = match version {
+        Some(version) => {
+            println!(
+                "Updating package '{}' to version '{}' in {} repositories",
+                package,
+                version,
+                config.repositories.len()
+            );
+            None
+        }
+        None => {
+            println!(
+                "Updating package '{}' to the newest version within each range in {} repositories",
+                package,
+                config.repositories.len()
+            );
+            let plan = plan_wanted(ctx, config, package, preserve_prefix);
+            print_wanted_plan(&plan);
+            Some(plan)
+        }
+    };
+    let targets = match (&wanted, version) {
+        (Some(plan), _) => plan
+            .iter()
+            .filter_map(|p| Some((p.repo, p.target.as_ref().ok()?.clone()?)))
+            .collect(),
+        (None, version) => update_targets(ctx, config, package, version.unwrap_or_default()),
+    };
+    let version = version.unwrap_or(campaign::WANTED);
+
+    let blocked = check_policies(config, package, &targets);
+    if let (PolicyMode::Error, false) = (policy_mode, blocked.is_empty()) {
+        let lines: Vec<String> = blocked
+            .iter()
+            .map(|(path, violation)| format!("  {}: {}", path, violation))
+            .collect();
+        anyhow::bail!(
+            "Update blocked by policy in {} repositories:\n{}\nUse --policy warn to skip them instead",
+            blocked.len(),
+            lines.join("\n")
+        );
+    }
+
+    let provenance = match verify_provenance {
+        Some(mode) => Some(check_provenance(ctx, config, package, version, mode)?),
+        None => None,
+    };
+    let licenses = match license_policy {
+        Some(mode) => check_licenses(ctx, config, package, &targets, mode)?,
+        None => Vec::new(),
+    };
+
+    let branch_name = style
+        .branch_name(package, version)
+        .unwrap_or_else(|| git::update_branch_name(package, version));
+    let waves = order::waves(&config.repositories)?;
+    let ordered = config
+        .repositories
+        .iter()
+        .any(|r| r.priority != 0 || !r.depends_on.is_empty());
+    if ordered || dry_run {
+        print_update_order(&waves);
+    }
+    let repo_paths: Vec<&str> = waves.iter().flatten().map(|r| r.path.as_str()).collect();
+    let mut campaign = Campaign::new(
+        package,
+        version,
+        &commit_message,
+        pull_request,
+        &branch_name,
+        &repo_paths,
+    );
+    let vars = TemplateContext::new(package, version)
+        .with_style(style)
+        .with(
+            "date",
+            &template::format_date(campaign.created_at, config.templates.date_format()),
+        )
+        .with(
+            "hash",
+            &template::short_hash(
+                &[package, version, &campaign.created_at.to_string()]
+                    .into_iter()
+                    .chain(repo_paths.iter().copied())
+                    .collect::<Vec<_>>(),
+            ),
+        );
+    if let Some(branch) = &config.templates.branch {
+        campaign.set_branch(&vars.render(branch));
+    }
+    campaign.template = vars;
+    for repo in config.repositories.iter().filter(|r| r.ad_hoc) {
+        campaign.set_ad_hoc(&repo.path);
+    }
+    // What each repository declared before the campaign, for `compare --campaign`
+    for (path, versions) in &repo_versions {
+        let declared = versions
+            .as_ref()
+            .ok()
+            .and_then(|versions| package::declared(versions, package));
+        if let Some((_, specifier)) = declared {
+            campaign.set_scanned_version(path, version::range_of(specifier));
+        }
+    }
+    campaign.pr_title = config.templates.pr_title.clone();
+    campaign.pr_body = config.templates.pr_body.clone();
+    campaign.allow_protected = allow_protected;
+    campaign.force_base = force_base;
+    campaign.base = base.map(str::to_string);
+    campaign.changelog = changelog;
+    campaign.allow_extra_paths = allow_extra_paths.to_vec();
+    campaign.confirm_push = confirm_push;
+    campaign.timings = timings;
+    campaign.wait_between_waves = wait_between_waves;
+    campaign.dedupe = dedupe;
+    campaign.keep_changes_on_failure = !restore_on_failure;
+    campaign.submodules = submodules;
+    campaign.ready = ready;
+    campaign.comment_details = comment_details;
+    campaign.resolve_preview = resolve_preview;
+    campaign.bot_prs = bot_prs;
+    campaign.trailers = trailers;
+    campaign.pr_note = pr_note;
+    campaign.allow_lockfile_format_change = allow_lockfile_format_change;
+    campaign.summary = summary;
+    campaign.on_error = on_error;
+    campaign.codeowners = codeowners;
+    campaign.milestone = milestone.map(str::to_string);
+    campaign.project = project.map(str::to_string);
+    campaign.create_missing_milestone = create_missing_milestone;
+    if let Some(check) = provenance {
+        campaign.pr_body = Some(format!(
+            "{}\n\n{}",
+            campaign
+                .pr_body
+                .as_deref()
+                .unwrap_or(campaign::DEFAULT_PR_BODY),
+            check.markdown()
+        ));
+        campaign.provenance = Some(check);
+    }
+    for (path, change) in licenses {
+        campaign.set_license(&path, change.describe());
+    }
+    for plan in wanted.into_iter().flatten() {
+        let path = &plan.repo.path;
+        match plan.target {
+            Ok(Some(target)) => campaign.set_target_version(path, &target),
+            Ok(None) => {
+                let reason = format!("no newer version of {} within {}", package, plan.current);
+                campaign.set_status(path, RepoStatus::Skipped, Some(reason), None);
+            }
+            Err(e) => campaign.set_status(path, RepoStatus::Failed, Some(format!("{:#}", e)), None),
+        }
+    }
+    if version == campaign::WANTED {
+        // Only repositories with a plan have a version to move to
+        let unplanned: Vec<String> = campaign
+            .repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Pending && r.target_version.is_none())
+            .map(|r| r.path.clone())
+            .collect();
+        for path in unplanned {
+            let reason = format!("doesn't depend on {}", package);
+            campaign.set_status(&path, RepoStatus::Skipped, Some(reason), None);
+        }
+    }
+    campaign.open_in_browser = web_limit;
+    if let Some((path, format)) = report {
+        campaign.report = Some(path.to_string());
+        campaign.report_format = format;
+    }
+
+    for (path, violation) in blocked {
+        println!("⛔ Skipping {}: blocked by {}", path, violation);
+        ctx.log.event(
+            "policy_blocked",
+            &[
+                ("repo", &path),
+                ("package", package),
+                ("policy", &violation),
+            ],
+        );
+        campaign.set_status(&path, RepoStatus::Blocked, Some(violation), None);
+    }
+
+    if let Some(batching) = batching {
+        // Only repositories that will actually be updated count towards a batch
+        let eligible: Vec<String> = campaign
+            .repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Pending)
+            .filter(|r| {
+                repo_versions.iter().any(|(path, versions)| {
+                    *path == r.path && versions.as_ref().is_ok_and(|v| v.contains_key(package))
+                })
+            })
+            .map(|r| r.path.clone())
+            .collect();
+        campaign.split(&eligible, batching);
+        print_batch_plan(&campaign);
+    }
+
+    preflight::check(ctx, &campaign_requirements(ctx, config, &campaign, dry_run))?;
+
+    if !dry_run {
+        campaign.save()?;
+        println!("Campaign: {}", campaign.id);
+    }
+
+    run_campaign(ctx, config, &mut campaign, "update", dry_run)
+}
+
+/// Look up the provenance and integrity of `package@version` in the registry of the
+/// first repository using it, failing in `PolicyMode::Error` if it falls short
+fn check_provenance(
+    ctx: &Context,
+    config: &Config,
+    package: &str,
+    version: &str,
+    mode: PolicyMode,
+) -> Result<ProvenanceCheck> {
+    let repo = config
+        .repositories
+        .iter()
+        .find(|repo| depends_on(ctx, repo, package))
+        .unwrap_or(&config.repositories[0]);
+    let check = provenance::check(ctx, &repo.path, package, version)?;
+
+    println!("Provenance of {}@{}:", package, version);
+    for line in check.lines() {
+        println!("  {}", line);
+    }
+    let problems = check.problems();
+    ctx.log.event(
+        "provenance_checked",
+        &[
+            ("package", package),
+            ("version", version),
+            ("problems", &problems.join("; ")),
+        ],
+    );
+    if problems.is_empty() {
+        return Ok(check);
+    }
+
+    match mode {
+        PolicyMode::Error => anyhow::bail!(
+            "{}@{} fails the provenance check: {}\nUse --provenance warn to update anyway",
+            package,
+            version,
+            problems.join("; ")
+        ),
+        PolicyMode::Warn => eprintln!("Warning: {}@{} {}", package, version, problems.join("; ")),
+    }
+    Ok(check)
+}
+
+/// License of `package` in each repository of `targets`, before and after updating it
+/// to the repository's target, failing in `PolicyMode::Error` if one changes or is on
+/// the deny-list
+fn check_licenses(
+    ctx: &Context,
+    config: &Config,
+    package: &str,
+    targets: &[(&Repository, String)],
+    mode: PolicyMode,
+) -> Result<Vec<(String, LicenseChange)>> {
+    if targets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    println!("License of {}:", package);
+    let mut changes = Vec::new();
+    let mut problems = Vec::new();
+    for (repo, target) in targets {
+        let new = license::published(ctx, &repo.path, package, target)?;
+        let specifier = package::get_package_version(ctx, &repo.path, package)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let lockfile = Lockfile::load(&repo.path).ok().flatten();
+        let old = license::current(ctx, &repo.path, package, &specifier, lockfile.as_ref())
+            .unwrap_or_else(|e| {
+                eprintln!("Warning: {:#}", e);
+                license::UNKNOWN.to_string()
+            });
+        let change = LicenseChange { old, new };
+        println!(
+            "  {}: {}",
+            context::repo_name(&repo.path),
+            change.describe()
+        );
+        for problem in change.problems(&config.license_denylist) {
+            ctx.log.event(
+                "license_problem",
+                &[
+                    ("repo", &repo.path),
+                    ("package", package),
+                    ("problem", &problem),
+                ],
+            );
+            problems.push(format!("{}: {}", repo.path, problem));
+        }
+        changes.push((repo.path.clone(), change));
+    }
+    if problems.is_empty() {
+        return Ok(changes);
+    }
+
+    match mode {
+        PolicyMode::Error => anyhow::bail!(
+            "Update blocked by license policy:\n{}\nUse --license-policy warn to update anyway",
+            problems
+                .iter()
+                .map(|p| format!("  {}", p))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+        PolicyMode::Warn => {
+            for problem in &problems {
+                eprintln!("Warning: {}", problem);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Print which repositories the current batch updates and which are left for later ones
+fn print_batch_plan(campaign: &Campaign) {
+    let batches = campaign
+        .repos
+        .iter()
+        .filter_map(|r| r.batch)
+        .max()
+        .unwrap_or(1);
+    let Some(current) = campaign.batch else {
+        return;
+    };
+    let in_batch: Vec<&str> = campaign
+        .repos
+        .iter()
+        .filter(|r| r.batch == Some(current) && r.status != RepoStatus::Deferred)
+        .map(|r| r.path.as_str())
+        .collect();
+    println!("Batch {} of {}: {}", current, batches, in_batch.join(", "));
+
+    let deferred: Vec<String> = campaign
+        .repos
+        .iter()
+        .filter(|r| r.status == RepoStatus::Deferred)
+        .map(|r| format!("{} (batch {})", r.path, r.batch.unwrap_or_default()))
+        .collect();
+    if !deferred.is_empty() {
+        println!("Deferred: {}", deferred.join(", "));
+    }
+}
+
+/// Print the order repositories are updated in, one line per wave
+fn print_update_order(waves: &[Vec<&Repository>]) {
+    println!("Update order:");
+    for (i, wave) in waves.iter().enumerate() {
+        let names: Vec<String> = wave.iter().map(|r| order::display_name(r)).collect();
+        println!("  Wave {}: {}", i + 1, names.join(", "));
+    }
+}
+
+/// Handle ui command: prompts for what `update` takes as arguments, shows the
+/// dry run, and runs the same update once confirmed
+pub fn handle_ui(ctx: &Context, config: &Config) -> Result<()> {
+    ui::require_terminal()?;
+    if config.repositories.is_empty() {
+        println!("No repositories configured. Use 'add-repo' command to add repositories.");
+        return Ok(());
+    }
+
+    let package = loop {
+        let package = ui::ask("Package to update", None)?;
+        if !package.is_empty() {
+            break package;
+        }
+    };
+
+    let versions = parallel::map(&config.repositories, STATUS_JOBS, |repo| {
+        package::get_package_version(ctx, &repo.path, &package)
+            .ok()
+            .flatten()
+    });
+    let rows: Vec<Vec<String>> = config
+        .repositories
+        .iter()
+        .zip(&versions)
+        .enumerate()
+        .map(|(i, (repo, version))| {
+            vec![
+                (i + 1).to_string(),
+                order::display_name(repo),
+                version.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    println!(
+        "\n{}",
+        table::render(&["#", "REPOSITORY", "CURRENT"], &rows)
+    );
+
+    let using: Vec<String> = versions
+        .iter()
+        .enumerate()
+        .filter(|(_, version)| version.is_some())
+        .map(|(i, _)| (i + 1).to_string())
+        .collect();
+    let default_selection = if using.is_empty() {
+        "all".to_string()
+    } else {
+        using.join(",")
+    };
+    let selected = loop {
+        let input = ui::ask("Repositories (e.g. 1,3-5 or all)", Some(&default_selection))?;
+        match ui::parse_selection(&input, config.repositories.len()) {
+            Ok(selected) => break selected,
+            Err(e) => println!("{}", e),
+        }
+    };
+
+    let selected_config = Config {
+        repositories: selected
+            .iter()
+            .map(|&i| config.repositories[i].clone())
+            .collect(),
+        ..config.clone()
+    };
+    let latest = match package::latest_version(ctx, &selected_config.repositories[0].path, &package)
+    {
+        Ok(latest) => latest,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+    let version = loop {
+        let version = ui::ask("Target version", latest.as_deref())?;
+        if !version.is_empty() {
+            break version;
+        }
+    };
+    let pull_request = ui::ask_yes_no("Open pull requests", true)?;
+
+    let run = |dry_run: bool| {
+        handle_update(
+            ctx,
+            &selected_config,
+            &package,
+            Some(&version),
+            false,
+            None,
+            pull_request,
+            dry_run,
+            false,
+            &CommitStyle::default(),
+            PolicyMode::Warn,
+            false,
+            false,
+            None,
+            false,
+            &[],
+            false,
+            false,
+            false,
+            config.dedupe.unwrap_or(false),
+            true,
+            SubmoduleMode::Skip,
+            false,
+            false,
+            false,
+            BotPrs::NoPr,
+            true,
+            false,
+            false,
+            config.summary.unwrap_or_default(),
+            config.on_error.unwrap_or(OnError::Prompt),
+            false,
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    println!("\nPlan:");
+    run(true)?;
+    if !ui::ask_yes_no("\nStart the update", false)? {
+        println!("Nothing was changed");
+        return Ok(());
+    }
+    run(false)
+}
+
+/// Programs the remaining repositories of a campaign need. Package managers are
+/// only needed where the package will actually change.
+fn campaign_requirements(
+    ctx: &Context,
+    config: &Config,
+    campaign: &Campaign,
+    dry_run: bool,
+) -> Requirements {
+    let mut requirements = Requirements::git().with_gh(campaign.pull_request && !dry_run);
+    if dry_run {
+        return requirements;
+    }
+
+    for repo_path in campaign.remaining() {
+        let manifest_paths = match config.find_repository(&repo_path) {
+            Some(repo) => std::iter::once(repo.path.clone())
+                .chain(repo.extra_manifest_paths())
+                .collect(),
+            None => vec![repo_path.clone()],
+        };
+        for path in manifest_paths {
+            let current = package::get_package_version(ctx, &path, &campaign.package);
+            let manager = config
+                .package_manager(ctx, &path)
+                .map(|detection| detection.manager);
+            if let (Ok(Some(current)), Ok(manager)) = (current, manager) {
+                if current != campaign.target_version_of(&repo_path) {
+                    requirements.package_manager(&manager, &path);
+                    requirements.push_to(&repo_path);
+                }
+            }
+        }
+    }
+    requirements
+}
+
+/// Whether the repository's package.json or one of its extra manifests declares `package`
+fn depends_on(ctx: &Context, repo: &Repository, package: &str) -> bool {
+    std::iter::once(repo.path.clone())
+        .chain(repo.extra_manifest_paths())
+        .any(|path| package::get_package_version(ctx, &path, package).is_ok_and(|v| v.is_some()))
+}
+
+/// Repositories depending on `package`, each with `version` as its target
+fn update_targets<'a>(
+    ctx: &Context,
+    config: &'a Config,
+    package: &str,
+    version: &str,
+) -> Vec<(&'a Repository, String)> {
+    config
+        .repositories
+        .iter()
+        .filter(|repo| depends_on(ctx, repo, package))
+        .map(|repo| (repo, version.to_string()))
+        .collect()
+}
+
+/// Repositories of `targets` where a policy forbids updating `package` to their target,
+/// with the violation
+fn check_policies(
+    config: &Config,
+    package: &str,
+    targets: &[(&Repository, String)],
+) -> Vec<(String, String)> {
+    targets
+        .iter()
+        .filter_map(|(repo, version)| {
+            policy::find_violation(&config.policies, repo, package, version)
+                .map(|violation| (repo.path.clone(), violation))
+        })
+        .collect()
+}
+
+/// What `update --wanted` does in one repository using the package
+struct WantedPlan<'a> {
+    repo: &'a Repository,
+    /// Specifier the repository declares
+    current: String,
+    /// Specifier to move to; `None` if the range has no newer version
+    target: Result<Option<String>>,
+}
+
+/// For each repository using `package`, the newest published version within the range
+/// it declares, if that is newer than the range's own version
+fn plan_wanted<'a>(
+    ctx: &Context,
+    config: &'a Config,
+    package: &str,
+    preserve_prefix: bool,
+) -> Vec<WantedPlan<'a>> {
+    let repos: Vec<&Repository> = config.repositories.iter().collect();
+    let plans = parallel::map(&repos, STATUS_JOBS, |&repo| {
+        let current = std::iter::once(repo.path.clone())
+            .chain(repo.extra_manifest_paths())
+            .find_map(|path| {
+                package::get_package_version(ctx, &path, package)
+                    .ok()
+                    .flatten()
+            })?;
+        let range = version::range_of(&current);
+        let target = package::newest_in_range(ctx, &repo.path, package, range).map(|newest| {
+            newest
+                .filter(|v| version::compare_specifiers(v, range) == Ordering::Greater)
+                .map(|v| version::move_to(&current, &v, preserve_prefix))
+        });
+        Some(WantedPlan {
+            repo,
+            current,
+            target,
+        })
+    });
+    plans.into_iter().flatten().collect()
+}
+
+/// Print the specifier each repository moves from and to with `--wanted`
+fn print_wanted_plan(plan: &[WantedPlan]) {
+    println!("Plan:");
+    for entry in plan {
+        let name = order::display_name(entry.repo);
+        match &entry.target {
+            Ok(Some(target)) => println!("  {}: {} → {}", name, entry.current, target),
+            Ok(None) => println!("  {}: {} (no newer version in range)", name, entry.current),
+            Err(e) => println!("  {}: {} ({:#})", name, entry.current, e),
+        }
+    }
+}
+
+/// Run the update workflow for every remaining repository of a campaign,
+/// saving its state after each repository
+fn run_campaign(
+    ctx: &Context,
+    config: &Config,
+    campaign: &mut Campaign,
+    command: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let package = campaign.package.clone();
+    let pull_request = campaign.pull_request;
+    let branch_name = campaign.branch_name();
+    let pr_metadata = github::PrMetadata {
+        reviewers: config.reviewers.clone(),
+        milestone: campaign.milestone.clone(),
+        project: campaign.project.clone(),
+        create_missing_milestone: campaign.create_missing_milestone,
+    };
+    let run_start = Instant::now();
+
+    // Wave of each repository, to pause before consumers of what was just updated
+    let waves = if campaign.wait_between_waves {
+        order::waves(&config.repositories)?
+    } else {
+        Vec::new()
+    };
+    let wave_of = |path: &str| waves.iter().position(|w| w.iter().any(|r| r.path == path));
+    let mut current_wave = None;
+    let mut created_prs = Vec::new();
+    // Temporary copies of repositories; removed when the run ends, however it ends
+    let scratch = if campaign.resolve_preview {
+        let root = scratch::root(config)?;
+        scratch::check_space(config, &root, campaign.remaining().len())?;
+        Some(scratch::ScratchDir::create(&root, &campaign.id)?)
+    } else {
+        None
+    };
+    interrupt::install();
+    campaign.stopped = None;
+
+    for repo_path in campaign.remaining() {
+        if interrupt::requested() {
+            break;
+        }
+        let wave = wave_of(&repo_path);
+        if let (Some(done), Some(next)) = (current_wave, wave) {
+            if next > done && !confirm_next_wave(&waves[next], next) {
+                println!("Stopping before wave {}", next + 1);
+                break;
+            }
+        }
+        current_wave = wave.or(current_wave);
+
+        let repo_start = Instant::now();
+        let mut repo = config
+            .find_repository(&repo_path)
+            .cloned()
+            .unwrap_or(Repository {
+                path: repo_path.clone(),
+                ..Default::default()
+            });
+        repo.allowed_paths
+            .extend(campaign.allow_extra_paths.iter().cloned());
+
+        // Branching from a base that is behind origin would propose changes to stale code
+        let sync = if dry_run {
+            Ok(git::BaseSync::UpToDate)
+        } else if campaign.base.is_some() {
+            // The branch starts from the given ref, so only the remote refs need refreshing
+            git::fetch(ctx, &repo.path).map(|()| git::BaseSync::UpToDate)
+        } else {
+            git::sync_base(ctx, &repo.path)
+        };
+        if let Ok(git::BaseSync::FastForwarded(commits)) = sync {
+            campaign.set_fast_forwarded(&repo_path, commits);
+        }
+        let problem = sync.as_ref().ok().and_then(|sync| sync.problem());
+        if let Some(problem) = problem.filter(|_| !campaign.force_base) {
+            println!(
+                "⚠ Skipping {}: {}. Use --force-base to update it anyway",
+                repo.path, problem
+            );
+            ctx.log.event(
+                "base_diverged",
+                &[("repo", &repo.path), ("reason", &problem)],
+            );
+            campaign.set_status(&repo_path, RepoStatus::Skipped, Some(problem), None);
+            if let Err(e) = campaign.save() {
+                eprintln!("Warning: Failed to save campaign state: {}", e);
+            }
+            continue;
+        }
+
+        let check_bots = match campaign.bot_prs {
+            BotPrs::Skip => true,
+            BotPrs::NoPr => pull_request,
+            BotPrs::Ignore => false,
+        };
+        let bot_pr = if check_bots {
+            match open_bot_prs(ctx, config, &repo, Some(&package)) {
+                Ok(prs) => prs.into_iter().next(),
+                Err(e) => {
+                    eprintln!("Warning: {}: {:#}", repo.path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(pr) = &bot_pr {
+            ctx.log.event(
+                "bot_pr",
+                &[
+                    ("repo", &repo.path),
+                    ("branch", &pr.branch),
+                    ("url", &pr.url),
+                ],
+            );
+            if campaign.bot_prs == BotPrs::Skip {
+                let reason = format!("{} already has a pull request open: {}", pr.bot(), pr.url);
+                println!("⚠ Skipping {}: {}", repo.path, reason);
+                campaign.set_status(&repo_path, RepoStatus::Skipped, Some(reason), None);
+                if !dry_run {
+                    if let Err(e) = campaign.save() {
+                        eprintln!("Warning: Failed to save campaign state: {}", e);
+                    }
+                }
+                continue;
+            }
+            println!(
+                "{} already has a pull request open in {} ({}); pushing the update without opening another",
+                pr.bot(),
+                repo.path,
+                pr.url
+            );
+        }
+
+        if let Some(scratch) = &scratch {
+            let version = campaign.target_version_of(&repo_path).to_string();
+            if let Some(preview) =
+                preview_resolution(ctx, config, &repo, &package, &version, scratch.path())
+            {
+                campaign.set_resolve_preview(&repo_path, preview);
+            }
+        }
+
+        let ask = |path: &str| confirm(&format!("Push {}?", path));
+        let confirm_push = campaign
+            .confirm_push
+            .then_some(&ask as &dyn Fn(&str) -> bool);
+        let mut timer = StepTimer::default();
+        let version = campaign.target_version_of(&repo_path).to_string();
+        let vars = campaign.template_for(&repo);
+        let pr_body = campaign.pr_body_for(&repo_path);
+        let commit_message = if campaign.trailers {
+            git::with_trailers(
+                &campaign.commit_message,
+                &[
+                    (git::CAMPAIGN_TRAILER, &campaign.id),
+                    (git::PACKAGE_TRAILER, &format!("{}@{}", package, version)),
+                ],
+            )
+        } else {
+            campaign.commit_message.clone()
+        };
+        let result = sync.and_then(|_| {
+            git::update_package_workflow(
+                ctx,
+                &repo,
+                &package,
+                &version,
+                &branch_name,
+                campaign.base.as_deref(),
+                &commit_message,
+                pull_request && bot_pr.is_none(),
+                pr_body.as_deref(),
+                // Without the trailers
+                Some(
+                    campaign
+                        .pr_title
+                        .as_ref()
+                        .unwrap_or(&campaign.commit_message),
+                ),
+                campaign.allow_protected,
+                campaign.changelog,
+                campaign.dedupe,
+                !campaign.keep_changes_on_failure,
+                campaign.allow_lockfile_format_change,
+                confirm_push,
+                &mut timer,
+                dry_run,
+                config,
+                campaign.submodules,
+                campaign.codeowners,
+                &pr_metadata,
+                &vars,
+            )
+        });
+        campaign.set_timing(
+            &repo_path,
+            repo_start.elapsed().as_millis() as u64,
+            timer.finish(),
+        );
+
+        let failed = result.is_err();
+        match result {
+            Ok(WorkflowOutcome::Updated {
+                changes,
+                pr_url,
+                diffstat,
+                original_branch,
+                outputs,
+                warnings,
+                milestone,
+                project,
+                package_managers,
+            }) => {
+                if let Some(url) = pr_url.as_deref().filter(|_| campaign.ready) {
+                    if dry_run {
+                        println!("Would mark the pull request ready for review");
+                    } else if let Err(e) = github::mark_pr_ready(ctx, url) {
+                        eprintln!("Warning: {:#}", e);
+                    } else {
+                        println!("Marked {} ready for review", url);
+                    }
+                }
+                if let Some(url) = pr_url.as_deref().filter(|_| campaign.comment_details) {
+                    if dry_run {
+                        println!("Would comment the update details on the pull request");
+                    } else if let Err(e) = comment_details(
+                        ctx,
+                        &repo_path,
+                        url,
+                        &branch_name,
+                        &campaign.package,
+                        &changes,
+                        &outputs,
+                        campaign
+                            .repos
+                            .iter()
+                            .find(|r| r.path == repo_path)
+                            .and_then(|r| r.resolve_preview.as_ref()),
+                    ) {
+                        eprintln!("Warning: {:#}", e);
+                    } else {
+                        println!("Commented the update details on {}", url);
+                    }
+                }
+                if let Some(url) = pr_url.as_deref().filter(|_| campaign.pr_note) {
+                    if let Err(e) = git::add_note(ctx, &repo_path, &branch_name, url, dry_run) {
+                        eprintln!("Warning: {}: {:#}", repo_path, e);
+                    }
+                }
+                created_prs.extend(pr_url.clone());
+                campaign.set_status(&repo_path, RepoStatus::Completed, None, pr_url);
+                if let Some(diffstat) = diffstat {
+                    campaign.set_diffstat(&repo_path, &diffstat);
+                }
+                campaign.set_old_version(&repo_path, &changes[0].old_version);
+                campaign.set_original_branch(&repo_path, &original_branch.to_string());
+                let mut warnings = warnings;
+                if let Some(pr) = &bot_pr {
+                    warnings.push(format!(
+                        "no pull request opened, since {} has one open: {} (--ignore-bot-prs opens one anyway)",
+                        pr.bot(),
+                        pr.url
+                    ));
+                }
+                campaign.set_warnings(&repo_path, warnings);
+                campaign.set_pr_metadata(&repo_path, milestone, project);
+                campaign.set_package_managers(&repo_path, package_managers);
+            }
+            Ok(WorkflowOutcome::Skipped) => {
+                campaign.set_status(&repo_path, RepoStatus::Skipped, None, None)
+            }
+            Ok(WorkflowOutcome::NoChanges) => {
+                campaign.set_status(&repo_path, RepoStatus::NoChanges, None, None)
+            }
+            Ok(WorkflowOutcome::PushDeclined { diffstat }) => {
+                let reason = "push declined after reviewing the diffstat".to_string();
+                campaign.set_status(&repo_path, RepoStatus::Skipped, Some(reason), None);
+                campaign.set_diffstat(&repo_path, &diffstat);
+            }
+            Err(e) if interrupt::forced() => {
+                eprintln!("Interrupted while updating {}: {}", repo.path, e);
+                campaign.set_status(
+                    &repo_path,
+                    RepoStatus::Interrupted,
+                    Some(e.to_string()),
+                    None,
+                );
+            }
+            Err(e) => {
+                eprintln!("Error processing repository {}: {}", repo.path, e);
+                campaign.set_status(&repo_path, RepoStatus::Failed, Some(e.to_string()), None);
+                if let Some(rejection) = e.downcast_ref::<git::GitRejection>() {
+                    campaign.set_output(&repo_path, &rejection.output);
+                }
+            }
+        }
+
+        if !dry_run {
+            if let Err(e) = campaign.save() {
+                eprintln!("Warning: Failed to save campaign state: {}", e);
+            }
+        }
+
+        // 사용자에게 계속할지 물어보기
+        if failed && !interrupt::requested() {
+            let stop = match campaign.on_error {
+                OnError::Continue => false,
+                OnError::Abort => true,
+                OnError::Prompt => !prompt_continue(),
+            };
+            if stop {
+                println!("Aborting update process");
+                campaign.stopped = Some(format!(
+                    "after {} failed (--on-error {})",
+                    repo.path,
+                    campaign.on_error.label()
+                ));
+                if !dry_run {
+                    if let Err(e) = campaign.save() {
+                        eprintln!("Warning: Failed to save campaign state: {}", e);
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    if !dry_run {
+        println!(
+            "\n{}",
+            summary::render(&campaign.id, &campaign.repos, campaign.summary)
+        );
+
+        let run_millis = run_start.elapsed().as_millis() as u64;
+        if campaign.summary == SummaryMode::Full {
+            println!(
+                "\n{}",
+                timing::render(&campaign.repos, run_millis, campaign.timings)
+            );
+        }
+
+        if !campaign.remaining().is_empty() {
+            println!("Resume with: mru resume {}", campaign.id);
+        } else if !campaign.is_finished() {
+            println!(
+                "Run the next batch with: mru resume {} --next-batch",
+                campaign.id
+            );
+        }
+
+        if campaign.count(RepoStatus::Failed) > 0 {
+            if let Some(path) = ctx.log.path() {
+                println!("Detailed log: {}", path.display());
+            }
+        }
+
+        let mut record = HistoryRecord::from_campaign(command, campaign);
+        record.duration_ms = Some(run_millis);
+        print_pr_urls(&record);
+        if let Some(limit) = campaign.open_in_browser {
+            open_prs(ctx, &created_prs, limit);
+        }
+        if let Some(path) = &campaign.report {
+            if let Err(e) = report::write(&record, campaign.report_format, path) {
+                eprintln!("Warning: {:#}", e);
+            }
+        }
+        history::record(&record);
+        if ctx.notify {
+            notify::send(&config.notifications, &config.templates, &record);
+        }
+    }
+
+    if interrupt::requested() {
+        return Err(interrupt::Interrupted.into());
+    }
+    Ok(())
+}
+
+/// Open pull requests of update bots in the repository pull requests go to (its upstream
+/// for a fork), for `package` if given; none without a GitHub remote
+fn open_bot_prs(
+    ctx: &Context,
+    config: &Config,
+    repo: &Repository,
+    package: Option<&str>,
+) -> Result<Vec<github::BotPr>> {
+    let url = repo
+        .upstream
+        .clone()
+        .or_else(|| repo.github_url.clone())
+        .or_else(|| github::detect_github_url(ctx, &repo.path));
+    let Some(url) = url else {
+        return Ok(Vec::new());
+    };
+    let source = github::CloneSource::parse(&url, &ctx.github.host)?;
+    github::bot_prs(ctx, &source.repo_arg(), &config.bot_branches(), package)
+        .context("Failed to look up bot pull requests")
+}
+
+/// Packages listed under a resolution preview before the rest are counted
+const PREVIEW_LINES: usize = 10;
+
+/// Resolve the update in a copy of the repository and print the other packages it changes.
+/// A failure is a warning, since the update itself may still work.
+fn preview_resolution(
+    ctx: &Context,
+    config: &Config,
+    repo: &Repository,
+    package: &str,
+    version: &str,
+    tmp: &std::path::Path,
+) -> Option<preview::ResolvePreview> {
+    let result = config
+        .package_manager(ctx, &repo.path)
+        .and_then(|detection| {
+            preview::resolve(
+                ctx,
+                &repo.path,
+                &detection.manager,
+                package,
+                version,
+                &config.env_for(Some(repo))?,
+                tmp,
+            )
+        });
+    match result {
+        Ok(preview) => {
+            println!(
+                "Resolution preview for {}: {}",
+                repo.path,
+                preview.summary()
+            );
+            for line in preview.lines().iter().take(PREVIEW_LINES) {
+                println!("  {}", line);
+            }
+            let hidden = preview.lines().len().saturating_sub(PREVIEW_LINES);
+            if hidden > 0 {
+                println!("  … and {} more", hidden);
+            }
+            Some(preview)
+        }
+        Err(e) => {
+            eprintln!(
+                "Warning: Could not preview the resolution in {}: {:#}",
+                repo.path, e
+            );
+            None
+        }
+    }
+}
+
+/// The run's pull requests as bare URLs, one per line, to paste elsewhere
+fn print_pr_urls(record: &HistoryRecord) {
+    let urls = record.pr_urls();
+    if !urls.is_empty() {
+        println!("\nPull requests:\n{}", urls.join("\n"));
+    }
+}
+
+/// Post or refresh the comment with the update's diff and command output on its pull request
+#[allow(clippy::too_many_arguments)]
+fn comment_details(
+    ctx: &Context,
+    repo_path: &str,
+    pr_url: &str,
+    branch: &str,
+    package: &str,
+    changes: &[package::UpdateOutcome],
+    outputs: &[(String, String)],
+    resolve_preview: Option<&preview::ResolvePreview>,
+) -> Result<()> {
+    let (manifest_diff, lockfile_stat) = git::manifest_diff(ctx, repo_path, branch)?;
+    let body = github::PrDetails {
+        package,
+        changes,
+        manifest_diff: &manifest_diff,
+        lockfile_stat: &lockfile_stat,
+        outputs,
+        resolve_preview,
+    }
+    .render();
+    github::upsert_details_comment(ctx, pr_url, &body)
+}
+
+/// Open up to `limit` pull requests in the browser, so a large run doesn't open dozens of tabs
+fn open_prs(ctx: &Context, urls: &[String], limit: usize) {
+    for url in urls.iter().take(limit) {
+        if let Err(e) = github::open_in_browser(ctx, url) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+    if urls.len() > limit {
+        println!(
+            "Opened {} of {} pull requests in the browser (--web-limit {})",
+            limit,
+            urls.len(),
+            limit
+        );
+    }
+}
+
+/// Ask whether to go on with the next wave once the previous one is merged and published
+fn confirm_next_wave(wave: &[&Repository], index: usize) -> bool {
+    let names: Vec<String> = wave.iter().map(|r| order::display_name(r)).collect();
+    println!(
+        "\nWave {} is done. Merge and publish its changes before the repositories depending on it are updated.",
+        index
+    );
+    confirm(&format!(
+        "Continue with wave {} ({})?",
+        index + 1,
+        names.join(", ")
+    ))
+}
+
+/// Handle resume command
+pub fn handle_resume(
+    ctx: &Context,
+    config: &Config,
+    campaign_id: Option<&str>,
+    next_batch: bool,
+    on_error: Option<OnError>,
+) -> Result<()> {
+    let campaign = match campaign_id {
+        Some(id) => Some(Campaign::load(id)?),
+        None => Campaign::latest_unfinished()?,
+    };
+
+    let Some(mut campaign) = campaign else {
+        println!("No unfinished campaigns to resume");
+        return Ok(());
+    };
+
+    if next_batch {
+        match campaign.start_next_batch() {
+            Some(_) => print_batch_plan(&campaign),
+            None => println!("Campaign {} has no deferred repositories", campaign.id),
+        }
+    }
+
+    let remaining = campaign.remaining();
+    if remaining.is_empty() {
+        if campaign.is_finished() {
+            println!("Campaign {} is already finished", campaign.id);
+        } else {
+            println!(
+                "Batch {} of campaign {} is done; run the next one with: mru resume {} --next-batch",
+                campaign.batch.unwrap_or_default(),
+                campaign.id,
+                campaign.id
+            );
+        }
+        return Ok(());
+    }
+
+    println!(
+        "Resuming campaign {}: updating '{}' to '{}' in {} remaining repositories",
+        campaign.id,
+        campaign.package,
+        campaign.target_version,
+        remaining.len()
+    );
+
+    // Nobody may be there to answer this time
+    campaign.on_error = on_error.unwrap_or(match campaign.on_error {
+        OnError::Prompt => OnError::detect(),
+        policy => policy,
+    });
+    preflight::check(ctx, &campaign_requirements(ctx, config, &campaign, false))?;
+    run_campaign(ctx, config, &mut campaign, "resume", false)
+}
+
+/// What `undo` does in one repository
+struct UndoPlan {
+    repo_path: String,
+    /// Open PR to close
+    close_pr: Option<String>,
+    /// Branch to check out because the campaign branch is checked out
+    checkout: Option<String>,
+    delete_local_branch: bool,
+}
+
+/// Handle undo command
+pub fn handle_undo(
+    ctx: &Context,
+    config: &Config,
+    campaign_id: Option<&str>,
+    dry_run: bool,
+    yes: bool,
+) -> Result<()> {
+    let campaign = match campaign_id {
+        Some(id) => match Campaign::load(id) {
+            Ok(campaign) => Some(campaign),
+            Err(e) => Some(rediscover_campaign(ctx, config, id)?.ok_or(e)?),
+        },
+        None => Campaign::latest()?,
+    };
+
+    let Some(mut campaign) = campaign else {
+        println!("No campaigns to undo");
+        return Ok(());
+    };
+
+    // Pull requests on Bitbucket and Azure DevOps are closed without gh
+    let github_prs = campaign
+        .repos
+        .iter()
+        .filter_map(|r| r.pr_url.as_deref())
+        .any(|url| provider::ProviderKind::detect(url).is_none());
+    preflight::check(ctx, &Requirements::git().with_gh(github_prs))?;
+
+    let branch = campaign.branch_name();
+    println!(
+        "Undoing campaign {}: {}@{} on branch '{}'",
+        campaign.id, campaign.package, campaign.target_version, branch
+    );
+
+    let mut plans = Vec::new();
+    let mut merged = Vec::new();
+    let mut failed = Vec::new();
+
+    for repo in campaign
+        .repos
+        .iter()
+        .filter(|r| r.status == RepoStatus::Completed)
+    {
+        match plan_undo(ctx, repo, &branch) {
+            Ok(Some(plan)) => plans.push(plan),
+            Ok(None) => merged.push(repo.path.clone()),
+            Err(e) => failed.push((repo.path.clone(), e.to_string())),
+        }
+    }
+
+    if plans.is_empty() && merged.is_empty() && failed.is_empty() {
+        println!("Nothing to undo: no repository was changed by this campaign");
+        return Ok(());
+    }
+
+    for plan in &plans {
+        println!("\n{}:", plan.repo_path);
+        if let Some(url) = &plan.close_pr {
+            println!("  close PR {} with a comment", url);
+        }
+        println!("  delete remote branch origin/{}", branch);
+        if let Some(original) = &plan.checkout {
+            println!("  check out {}", original);
+        }
+        if plan.delete_local_branch {
+            println!("  delete local branch {}", branch);
+        }
+    }
+
+    if !merged.is_empty() {
+        println!("\nCannot undo, already merged:");
+        for path in &merged {
+            println!("  {}", path);
+        }
+    }
+
+    if dry_run || plans.is_empty() {
+        report_undo_failures(&failed);
+        return Ok(());
+    }
+
+    if !yes && !confirm("\nApply these changes?") {
+        println!("Undo cancelled");
+        return Ok(());
+    }
+
+    let comment = format!("Rolled back by mru undo (campaign {})", campaign.id);
+    for plan in &plans {
+        match apply_undo(ctx, plan, &branch, &comment) {
+            Ok(()) => {
+                println!("Undone: {}", plan.repo_path);
+                campaign.set_status(&plan.repo_path, RepoStatus::Undone, None, None);
+            }
+            Err(e) => failed.push((plan.repo_path.clone(), e.to_string())),
+        }
+    }
+
+    campaign.save()?;
+    history::record(&HistoryRecord::from_campaign("undo", &campaign));
+    report_undo_failures(&failed);
+
+    Ok(())
+}
+
+/// Rebuild a campaign whose state file is gone from the commits with its trailers in the
+/// configured repositories, and the pull request URLs in their notes
+fn rediscover_campaign(ctx: &Context, config: &Config, id: &str) -> Result<Option<Campaign>> {
+    let found = campaign_commits(ctx, config, Some(id));
+    let Some((_, first)) = found.first() else {
+        return Ok(None);
+    };
+    let Some((package, version)) = first
+        .package
+        .as_deref()
+        .and_then(|p| p.rsplit_once('@'))
+        .filter(|(name, _)| !name.is_empty())
+    else {
+        anyhow::bail!(
+            "Commit {} of campaign {} has no {} trailer",
+            first.sha,
+            id,
+            git::PACKAGE_TRAILER
+        );
+    };
+    // Campaign ids are the branch name, made file-name-safe, and a timestamp
+    let branch = first
+        .branches
+        .iter()
+        .map(|b| b.strip_prefix("origin/").unwrap_or(b))
+        .find(|b| id.starts_with(&format!("{}-", campaign::branch_slug(b))))
+        .map(str::to_string)
+        .unwrap_or_else(|| id.rsplit_once('-').map_or(id, |(b, _)| b).to_string());
+
+    let paths: Vec<&str> = found.iter().map(|(path, _)| path.as_str()).collect();
+    let mut campaign = Campaign::new(package, version, "", true, &branch, &paths);
+    campaign.id = id.to_string();
+    for (path, commit) in &found {
+        campaign.set_status(path, RepoStatus::Completed, None, commit.pr_url.clone());
+    }
+    println!(
+        "No state file for campaign {}; found its commits in {} repositories",
+        id,
+        found.len()
+    );
+    Ok(Some(campaign))
+}
+
+/// The newest commit with a campaign trailer in each configured repository
+fn campaign_commits(
+    ctx: &Context,
+    config: &Config,
+    campaign_id: Option<&str>,
+) -> Vec<(String, git::CampaignCommit)> {
+    let mut found = Vec::new();
+    for repo in &config.repositories {
+        match git::campaign_commits(ctx, &repo.path, campaign_id) {
+            Ok(commits) => found.extend(commits.into_iter().next().map(|c| (repo.path.clone(), c))),
+            Err(e) => eprintln!("Warning: {}: {:#}", repo.path, e),
+        }
+    }
+    found
+}
+
+/// Actions needed to undo a campaign in one repository, or `None` if its PR was merged
+fn plan_undo(ctx: &Context, repo: &campaign::RepoState, branch: &str) -> Result<Option<UndoPlan>> {
+    let mut close_pr = None;
+    if let Some(url) = &repo.pr_url {
+        let state = match provider::for_pr_url(url)? {
+            Some(provider) => provider.pr_state(ctx, url)?,
+            None => github::pr_state(ctx, url)?,
+        };
+        match state.as_str() {
+            "MERGED" => return Ok(None),
+            "OPEN" => close_pr = Some(url.clone()),
+            _ => {}
+        }
+    }
+
+    let checkout = if git::get_current_branch(ctx, &repo.path)? == branch {
+        Some(match &repo.original_branch {
+            Some(original) => original.clone(),
+            None => git::default_branch(ctx, &repo.path)?,
+        })
+    } else {
+        None
+    };
+
+    Ok(Some(UndoPlan {
+        repo_path: repo.path.clone(),
+        close_pr,
+        checkout,
+        delete_local_branch: git::local_branch_exists(ctx, &repo.path, branch)?,
+    }))
+}
+
+fn apply_undo(ctx: &Context, plan: &UndoPlan, branch: &str, comment: &str) -> Result<()> {
+    if let Some(url) = &plan.close_pr {
+        match provider::for_pr_url(url)? {
+            Some(provider) => provider.close_pr(ctx, url, comment)?,
+            None => github::close_pr(ctx, url, comment)?,
+        }
+    }
+    git::delete_remote_branch(ctx, &plan.repo_path, branch)?;
+    if let Some(original) = &plan.checkout {
+        git::checkout_branch(ctx, &plan.repo_path, original, false)?;
+    }
+    if plan.delete_local_branch {
+        git::delete_local_branch(ctx, &plan.repo_path, branch)?;
+    }
+    Ok(())
+}
+
+fn report_undo_failures(failed: &[(String, String)]) {
+    if failed.is_empty() {
+        return;
+    }
+    eprintln!("\nFailed to undo:");
+    for (path, error) in failed {
+        eprintln!("  {}: {}", path, error);
+    }
+}
+
+/// Handle history command
+pub fn handle_history(json: bool) -> Result<()> {
+    let records = history::load()?;
+
+    if json {
+        for record in &records {
+            println!("{}", serde_json::to_string(record)?);
+        }
+        return Ok(());
+    }
+
+    if records.is_empty() {
+        println!("No history recorded yet");
+        return Ok(());
+    }
+
+    println!("History:");
+    for record in &records {
+        println!(
+            "{}  {}  {} {}@{}  {} repos touched, {} PRs opened, {}",
+            campaign::format_timestamp(record.timestamp),
+            record.id,
+            record.command,
+            record.package,
+            record.version,
+            record.count(RepoStatus::Completed),
+            record.pr_count(),
+            record.outcome()
+        );
+    }
+
+    Ok(())
+}
+
+/// Handle history show command
+pub fn handle_history_show(
+    ctx: &Context,
+    config: &Config,
+    id: &str,
+    json: bool,
+    markdown: bool,
+) -> Result<()> {
+    let records: Vec<HistoryRecord> = history::load()?
+        .into_iter()
+        .filter(|r| r.id == id)
+        .collect();
+
+    if records.is_empty() {
+        let found = campaign_commits(ctx, config, Some(id));
+        if found.is_empty() {
+            anyhow::bail!("No history found for: {}", id);
+        }
+        println!("No history recorded for {}; commits with its trailers:", id);
+        for (path, commit) in &found {
+            println!(
+                "  {}: {} {}",
+                path,
+                &commit.sha[..commit.sha.len().min(12)],
+                commit.package.as_deref().unwrap_or("")
+            );
+            if !commit.branches.is_empty() {
+                println!("    branches: {}", commit.branches.join(", "));
+            }
+            if let Some(url) = &commit.pr_url {
+                println!("    PR: {}", url);
+            }
+        }
+        return Ok(());
+    }
+
+    for record in &records {
+        if json {
+            println!("{}", serde_json::to_string_pretty(record)?);
+            continue;
+        }
+        if markdown {
+            println!("{}\n", report::markdown(record));
+            continue;
+        }
+
+        println!(
+            "{} {} {}@{} ({}, {})",
+            campaign::format_timestamp(record.timestamp),
+            record.command,
+            record.package,
+            record.version,
+            record.id,
+            record.outcome()
+        );
+        for repo in &record.repos {
+            let ad_hoc = if repo.ad_hoc { " (ad hoc)" } else { "" };
+            match &repo.old_version {
+                Some(old) => println!(
+                    "  {}{}: {:?} ({} -> {})",
+                    repo.path, ad_hoc, repo.status, old, record.version
+                ),
+                None => println!("  {}{}: {:?}", repo.path, ad_hoc, repo.status),
+            }
+            if let Some(url) = &repo.pr_url {
+                println!("    PR: {}", url);
+            }
+            if let Some(diffstat) = &repo.diffstat {
+                println!("    Diffstat: {}", diffstat);
+            }
+            if let Some(error) = &repo.error {
+                println!("    Error: {}", error);
+                print_git_output(repo);
+            }
+        }
+        print_pr_urls(record);
+    }
+
+    Ok(())
+}
+
+/// Raw output of the git command that failed in a repository, if it was recorded
+fn print_git_output(repo: &campaign::RepoState) {
+    for line in repo.output.iter().flat_map(|o| o.lines()) {
+        println!("      | {}", line);
+    }
+}
+
+/// Handle cache clean command: remove the temporary directories of runs that crashed
+pub fn handle_cache_clean(config: &Config, dry_run: bool) -> Result<()> {
+    let root = scratch::root(config)?;
+    let leftovers = scratch::leftovers(&root);
+    if leftovers.is_empty() {
+        println!("No leftover temporary directories in {}", root.display());
+        return Ok(());
+    }
+
+    for dir in &leftovers {
+        if dry_run {
+            println!("Would remove {}", dir.display());
+        } else {
+            std::fs::remove_dir_all(dir)
+                .with_context(|| format!("Failed to remove {}", dir.display()))?;
+            println!("Removed {}", dir.display());
+        }
+    }
+    Ok(())
+}
+
+/// Handle campaigns list command
+pub fn handle_campaigns_list() -> Result<()> {
+    let mut campaigns = Vec::new();
+    for (path, campaign) in campaign::list()? {
+        match campaign {
+            Ok(campaign) => campaigns.push(campaign),
+            Err(e) => eprintln!("Skipping {}: {:#}", path.display(), e),
+        }
+    }
+
+    if campaigns.is_empty() {
+        println!("No campaigns found");
+        return Ok(());
+    }
+
+    campaigns.sort_by_key(|c| c.created_at);
+
+    println!("Campaigns:");
+    for campaign in campaigns {
+        let done = campaign.count(RepoStatus::Completed)
+            + campaign.count(RepoStatus::Skipped)
+            + campaign.count(RepoStatus::NoChanges)
+            + campaign.count(RepoStatus::Blocked);
+        let state = if campaign.is_finished() {
+            "finished"
+        } else {
+            "unfinished"
+        };
+        println!(
+            "{}  {}  {}@{}  {}/{} done, {} failed ({})",
+            campaign.id,
+            campaign::format_timestamp(campaign.created_at),
+            campaign.package,
+            campaign.target_version,
+            done,
+            campaign.repos.len(),
+            campaign.count(RepoStatus::Failed),
+            state
+        );
+    }
+
+    Ok(())
+}
+
+/// Handle exec command
+pub fn handle_exec(ctx: &Context, config: &Config, args: &ExecArgs) -> Result<()> {
+    let repos = args.filter.apply(&config.repositories);
+    if repos.is_empty() {
+        println!("No repositories match");
+        return Ok(());
+    }
+
+    if args.commit {
+        preflight::check(ctx, &Requirements::git().with_gh(args.pull_request))?;
+    }
+
+    let command_line = args.command.join(" ");
+    let message = args
+        .message
+        .clone()
+        .unwrap_or_else(|| format!("chore: run {}", command_line));
+    let branch = args
+        .branch
+        .clone()
+        .unwrap_or_else(|| format!("mru-exec-{}", slugify(&message)));
+
+    // Result line for each repository, and whether it failed
+    let mut outcomes: Vec<Option<(String, bool)>> = vec![None; repos.len()];
+
+    // Committing would sweep up unrelated local changes
+    if args.commit {
+        for (i, repo) in repos.iter().enumerate() {
+            if git::check_status(ctx, &repo.path).unwrap_or(true) {
+                outcomes[i] = Some(("skipped: uncommitted changes".to_string(), true));
+            }
+        }
+    }
+
+    let pending: Vec<usize> = (0..repos.len())
+        .filter(|&i| outcomes[i].is_none())
+        .collect();
+    let abort = args.on_error.or(config.on_error) == Some(OnError::Abort);
+    let results = run_in_parallel(
+        ctx,
+        config,
+        &repos,
+        &pending,
+        &args.command,
+        args.jobs,
+        abort,
+    );
+
+    for (i, result) in results {
+        let repo = repos[i];
+        let Some(result) = result else {
+            continue;
+        };
+        outcomes[i] = Some(match result {
+            Ok(output) if output.success() && args.commit => {
+                match commit_exec_changes(ctx, repo, &branch, &message, args) {
+                    Ok(summary) => (summary, false),
+                    Err(e) => (format!("commit failed: {}", e), true),
+                }
+            }
+            Ok(output) if output.success() => ("exit 0".to_string(), false),
+            Ok(output) => match output.code {
+                Some(code) => (format!("exit {}", code), true),
+                None => ("killed by signal".to_string(), true),
+            },
+            Err(e) => (format!("error: {}", e), true),
+        });
+    }
+
+    println!("\nSummary for '{}':", command_line);
+    let mut failed = 0;
+    for (repo, outcome) in repos.iter().zip(outcomes) {
+        let Some((summary, is_failure)) = outcome else {
+            println!(
+                "  - {}: not run, since an earlier repository failed (--on-error abort)",
+                repo.path
+            );
+            continue;
+        };
+        if is_failure {
+            failed += 1;
+        }
+        let mark = if is_failure { "✗" } else { "✓" };
+        println!("  {} {}: {}", mark, repo.path, summary);
+    }
+
+    if failed > 0 {
+        anyhow::bail!(
+            "Command failed in {} of {} repositories",
+            failed,
+            repos.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Handle backport command
+pub fn handle_backport(ctx: &Context, config: &Config, args: &BackportArgs) -> Result<()> {
+    let campaigns: Vec<Campaign> = campaign::list()?
+        .into_iter()
+        .filter_map(|(_, campaign)| campaign.ok())
+        .collect();
+    let source_campaign = campaigns.iter().find(|c| c.branch_name() == args.source);
+
+    // A campaign branch is backported where the campaign completed
+    let repos: Vec<&Repository> = args
+        .filter
+        .apply(&config.repositories)
+        .into_iter()
+        .filter(|repo| {
+            source_campaign.is_none_or(|c| {
+                c.repos.iter().any(|r| {
+                    repo::same_path(&r.path, &repo.path) && r.status == RepoStatus::Completed
+                })
+            })
+        })
+        .collect();
+    if repos.is_empty() {
+        println!("No repositories to backport");
+        return Ok(());
+    }
+
+    if !args.dry_run {
+        preflight::check(ctx, &Requirements::git().with_gh(true))?;
+    }
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for repo in &repos {
+        let outcomes = backport_repo(ctx, repo, args, source_campaign, &campaigns);
+        let mut row = vec![repo.path.clone()];
+        for outcome in outcomes {
+            let cell = match outcome {
+                Ok(git::BackportOutcome::Pushed { pr_url: Some(url) }) => format!("✓ {}", url),
+                Ok(git::BackportOutcome::Pushed { pr_url: None }) if args.dry_run => {
+                    "would backport".to_string()
+                }
+                Ok(git::BackportOutcome::Pushed { pr_url: None }) => "✓ pushed".to_string(),
+                Ok(git::BackportOutcome::Conflict(files)) => {
+                    failed += 1;
+                    format!("✗ conflict in {}", files.join(", "))
+                }
+                Err(e) => {
+                    failed += 1;
+                    format!("✗ {}", e)
+                }
+            };
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+
+    println!("\nBackport of {}:", args.source);
+    let mut header = vec!["REPOSITORY"];
+    header.extend(args.targets.iter().map(String::as_str));
+    table::print(&header, &rows);
+
+    if failed > 0 {
+        anyhow::bail!(
+            "Backport failed for {} of {} branches",
+            failed,
+            repos.len() * args.targets.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Backport the source commit onto every target branch of one repository,
+/// returning to the original branch afterwards
+fn backport_repo(
+    ctx: &Context,
+    repo: &Repository,
+    args: &BackportArgs,
+    source_campaign: Option<&Campaign>,
+    campaigns: &[Campaign],
+) -> Vec<Result<git::BackportOutcome>> {
+    let fail_all = |e: anyhow::Error| {
+        args.targets
+            .iter()
+            .map(|_| Err(anyhow::anyhow!("{}", e)))
+            .collect()
+    };
+
+    // Checking out release branches would carry local changes along
+    if git::check_status(ctx, &repo.path).unwrap_or(true) {
+        return fail_all(anyhow::anyhow!("uncommitted changes"));
+    }
+    if !args.dry_run {
+        if let Err(e) = git::fetch(ctx, &repo.path) {
+            return fail_all(e);
+        }
+    }
+
+    let commit = match find_backport_commit(ctx, repo, &args.source, source_campaign) {
+        Ok(Some(commit)) => commit,
+        Ok(None) => return fail_all(anyhow::anyhow!("commit not found")),
+        Err(e) => return fail_all(e),
+    };
+    let subject = match git::commit_subject(ctx, &repo.path, &commit) {
+        Ok(subject) => subject,
+        Err(e) => return fail_all(e),
+    };
+    let original_branch = match git::get_current_branch(ctx, &repo.path) {
+        Ok(branch) => branch,
+        Err(e) => return fail_all(e),
+    };
+
+    // The campaign knows the package edit that resolves a package.json conflict
+    let campaign = source_campaign.or_else(|| {
+        campaigns
+            .iter()
+            .find(|c| c.commit_message_for(repo).lines().next() == Some(subject.as_str()))
+    });
+    let update = campaign.map(|c| (c.package.as_str(), c.target_version.as_str()));
+    let source_branch = match campaign {
+        Some(c) => c.branch_name(),
+        None => format!("backport-{}", &commit[..commit.len().min(7)]),
+    };
+
+    let outcomes = args
+        .targets
+        .iter()
+        .map(|target| {
+            git::backport_commit(
+                ctx,
+                repo,
+                &commit,
+                target,
+                &format!("{}-{}", source_branch, slugify(target)),
+                &format!("[{}] {}", target, subject),
+                update,
+                args.dry_run,
+            )
+        })
+        .collect();
+
+    if !args.dry_run {
+        if let Err(e) = git::checkout_branch(ctx, &repo.path, &original_branch, false) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    outcomes
+}
+
+/// Commit to backport: the tip of the campaign branch, the campaign's commit found by
+/// its message, or `source` itself as a commit
+fn find_backport_commit(
+    ctx: &Context,
+    repo: &Repository,
+    source: &str,
+    campaign: Option<&Campaign>,
+) -> Result<Option<String>> {
+    let repo_path = repo.path.as_str();
+    let Some(campaign) = campaign else {
+        return git::resolve_commit(ctx, repo_path, source);
+    };
+
+    for rev in [
+        format!("refs/heads/{}", source),
+        format!("refs/remotes/origin/{}", source),
+    ] {
+        if let Some(commit) = git::resolve_commit(ctx, repo_path, &rev)? {
+            return Ok(Some(commit));
+        }
+    }
+    let message = campaign.commit_message_for(repo);
+    let subject = message.lines().next().unwrap_or_default();
+    git::find_commit_by_message(ctx, repo_path, subject)
+}
+
+/// Handle ready command
+pub fn handle_ready(ctx: &Context, config: &Config, args: &ReadyArgs) -> Result<()> {
+    // PRs of forks are in the repository they were forked from
+    let targets: Vec<(&Repository, &String)> = args
+        .filter
+        .apply(&config.repositories)
+        .into_iter()
+        .filter_map(|repo| Some((repo, repo.upstream.as_ref().or(repo.github_url.as_ref())?)))
+        .collect();
+    if targets.is_empty() {
+        println!("No repositories with a GitHub remote match");
+        return Ok(());
+    }
+
+    preflight::check(ctx, &Requirements::default().with_gh(true))?;
+    let heads: Vec<(String, String)> = targets
+        .iter()
+        .map(|(_, url)| (url.to_string(), args.branch.clone()))
+        .collect();
+    let summaries = github::pr_summaries(ctx, &heads)?;
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for ((repo, _), summary) in targets.iter().zip(summaries) {
+        let (pr, result) = match summary {
+            Some(pr) => {
+                let result = match mark_ready(ctx, &pr, args) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        failed += 1;
+                        format!("✗ {}", e)
+                    }
+                };
+                (format!("#{}", pr.number), result)
+            }
+            None => (String::new(), "no pull request".to_string()),
+        };
+        rows.push(vec![repo.path.clone(), pr, result]);
+    }
+
+    table::print(&["REPOSITORY", "PR", "RESULT"], &rows);
+    if failed > 0 {
+        anyhow::bail!("Failed to mark {} pull requests ready", failed);
+    }
+    Ok(())
+}
+
+/// Mark one pull request ready unless it is closed, merged, already ready or, with
+/// `--only-green`, its checks aren't passing; returns what happened, for the table
+fn mark_ready(ctx: &Context, pr: &github::PrSummary, args: &ReadyArgs) -> Result<String> {
+    if pr.state != "OPEN" {
+        return Ok(pr.state.to_lowercase());
+    }
+    if !pr.draft {
+        return Ok("already ready".to_string());
+    }
+    if args.only_green && pr.checks.as_deref() != Some("SUCCESS") {
+        let checks = match pr.checks.as_deref() {
+            Some("FAILURE" | "ERROR") => "checks failing",
+            Some(_) => "checks pending",
+            None => "no checks",
+        };
+        return Ok(format!("left as draft: {}", checks));
+    }
+    if args.dry_run {
+        return Ok("would mark ready".to_string());
+    }
+    github::mark_pr_ready(ctx, &pr.url)?;
+    Ok("✓ draft → ready".to_string())
+}
+
+/// Handle version command
+pub fn handle_version(ctx: &Context, config: &Config, args: &VersionArgs) -> Result<()> {
+    let repos = args.filter.apply(&config.repositories);
+    if repos.is_empty() {
+        println!("No repositories match");
+        return Ok(());
+    }
+
+    // Current and next version of each repository, or why it is skipped
+    let plans: Vec<Result<(String, String)>> = repos
+        .iter()
+        .map(|repo| plan_release(ctx, repo, &args.bump, args.dry_run))
+        .collect();
+
+    if !args.dry_run {
+        let mut requirements = Requirements::git();
+        if args.native {
+            for (repo, _) in repos.iter().zip(&plans).filter(|(_, plan)| plan.is_ok()) {
+                let manager = config.package_manager(ctx, &repo.path)?.manager;
+                requirements.package_manager(&manager, &repo.path);
+            }
+        }
+        preflight::check(ctx, &requirements)?;
+    }
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for (repo, plan) in repos.iter().zip(plans) {
+        let (change, tag, result) = match plan {
+            Ok((current, next)) => {
+                let tag = format!("v{}", next);
+                let result = if args.dry_run {
+                    "would release".to_string()
+                } else {
+                    match release_repo(ctx, config, repo, &next, &tag, args) {
+                        Ok(()) => "✓ released".to_string(),
+                        Err(e) => {
+                            failed += 1;
+                            format!("✗ {}", e)
+                        }
+                    }
+                };
+                (format!("{} → {}", current, next), tag, result)
+            }
+            Err(e) => (String::new(), String::new(), format!("skipped: {}", e)),
+        };
+        rows.push(vec![repo.path.clone(), change, tag, result]);
+    }
+
+    println!();
+    table::print(&["REPOSITORY", "VERSION", "TAG", "RESULT"], &rows);
+
+    if failed > 0 {
+        anyhow::bail!(
+            "Release failed in {} of {} repositories",
+            failed,
+            repos.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Current and next version of a repository, or why it can't be released
+fn plan_release(
+    ctx: &Context,
+    repo: &Repository,
+    bump: &str,
+    dry_run: bool,
+) -> Result<(String, String)> {
+    if repo.skip_version {
+        anyhow::bail!("released by other tooling");
+    }
+    let current = package::own_version(ctx, &repo.path)?
+        .ok_or_else(|| anyhow::anyhow!("no version in package.json"))?;
+    let next = version::next_release(&current, bump)?;
+
+    let tag = format!("v{}", next);
+    if git::resolve_commit(ctx, &repo.path, &format!("refs/tags/{}", tag))?.is_some() {
+        anyhow::bail!("tag {} already exists", tag);
+    }
+    // Committing would sweep up unrelated local changes
+    if !dry_run && git::check_status(ctx, &repo.path)? {
+        anyhow::bail!("uncommitted changes");
+    }
+
+    Ok((current, next))
+}
+
+/// Set the version, commit it to the current branch, tag and push as requested
+fn release_repo(
+    ctx: &Context,
+    config: &Config,
+    repo: &Repository,
+    next: &str,
+    tag: &str,
+    args: &VersionArgs,
+) -> Result<()> {
+    if args.native {
+        let manager = config.package_manager(ctx, &repo.path)?.manager;
+        package::run_version_command(ctx, &repo.path, &manager, next)?;
+    } else {
+        package::set_own_version(ctx, &repo.path, next)?;
+    }
+
+    git::stage_changes(ctx, &repo::expand_path(&repo.path)?, &[], false)?;
+    git::commit_changes(
+        ctx,
+        &repo.path,
+        &args.message.replace("{version}", next),
+        false,
+    )?;
+    if args.tag {
+        git::create_tag(ctx, &repo.path, tag, tag)?;
+    }
+
+    if args.push || args.push_tags {
+        let branch = git::get_current_branch(ctx, &repo.path)?;
+        git::push_branch(ctx, &repo.path, branch.to_str()?, false)?;
+    }
+    if args.push_tags {
+        git::push_tag(ctx, &repo.path, tag)?;
+    }
+
+    Ok(())
+}
+
+/// Run `command` in the repositories at `indices`, at most `jobs` at a time. With
+/// `abort`, repositories not started when the command fails somewhere get `None`
+fn run_in_parallel(
+    ctx: &Context,
+    config: &Config,
+    repos: &[&Repository],
+    indices: &[usize],
+    command: &[String],
+    jobs: usize,
+    abort: bool,
+) -> Vec<(usize, Option<Result<CommandOutput>>)> {
+    let failed = std::sync::atomic::AtomicBool::new(false);
+    let results = parallel::map(indices, jobs, |&i| {
+        if abort && failed.load(std::sync::atomic::Ordering::SeqCst) {
+            return None;
+        }
+        let repo = repos[i];
+        let result = repo::expand_path(&repo.path).and_then(|path| {
+            let name = context::repo_name(&repo.path);
+            let cmd = CommandSpec::new(&command[0])
+                .args(&command[1..])
+                .current_dir(&path)
+                .envs(&config.env_for(Some(repo))?)
+                .env("MRU_REPO_PATH", &path.to_string_lossy())
+                .env("MRU_REPO_NAME", &name);
+            ctx.stream(&cmd, &repo.path)
+        });
+        if !result.as_ref().is_ok_and(CommandOutput::success) {
+            failed.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+        Some(result)
+    });
+    indices.iter().copied().zip(results).collect()
+}
+
+/// Commit what an exec command changed, returning a one-line summary
+fn commit_exec_changes(
+    ctx: &Context,
+    repo: &Repository,
+    branch: &str,
+    message: &str,
+    args: &ExecArgs,
+) -> Result<String> {
+    if !git::check_status(ctx, &repo.path)? {
+        return Ok("exit 0, no changes to commit".to_string());
+    }
+
+    let pr_url = git::commit_to_branch(ctx, repo, branch, message, args.push, args.pull_request)?;
+    Ok(match pr_url {
+        Some(url) => format!("exit 0, committed to {}, PR: {}", branch, url),
+        None => format!("exit 0, committed to {}", branch),
+    })
+}
+
+/// Lowercase branch-name-safe form of a message
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(40);
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Handle merge command
+pub fn handle_merge(ctx: &Context, config: &Config, args: &MergeArgs) -> Result<()> {
+    let repos = args.filter.apply(&config.repositories);
+    if repos.is_empty() {
+        println!("No repositories match");
+        return Ok(());
+    }
+
+    let providers = repos
+        .iter()
+        .map(|repo| provider::for_repo(ctx, repo))
+        .collect::<Result<Vec<_>>>()?;
+    let github_repos = providers.iter().any(Option::is_none);
+    preflight::check(ctx, &Requirements::git().with_gh(github_repos))?;
+
+    let options = MergeOptions {
+        method: args.method,
+        delete_branch: args.delete_branch,
+        admin: args.admin,
+    };
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for (repo, provider) in repos.iter().zip(&providers) {
+        let (pr, result) = match merge_branch(ctx, repo, provider.as_deref(), args, &options) {
+            Ok(result) => result,
+            Err(e) => {
+                failed += 1;
+                (String::new(), format!("✗ {:#}", e))
+            }
+        };
+        rows.push(vec![repo.path.clone(), pr, result]);
+    }
+
+    table::print(&["REPOSITORY", "PR", "RESULT"], &rows);
+    if failed > 0 {
+        anyhow::bail!("Failed to merge {} pull requests", failed);
+    }
+    Ok(())
+}
+
+/// Merge the open PR of the branch in one repository; returns its number and
+/// what happened, for the table
+fn merge_branch(
+    ctx: &Context,
+    repo: &Repository,
+    provider: Option<&dyn provider::Provider>,
+    args: &MergeArgs,
+    options: &MergeOptions,
+) -> Result<(String, String)> {
+    let outcome = match provider {
+        Some(provider) => {
+            if options.admin {
+                anyhow::bail!("--admin only applies to GitHub pull requests");
+            }
+            let pr = provider.find_pr(ctx, &args.branch)?;
+            if args.dry_run {
+                return Ok(dry_run_row(pr));
+            }
+            match pr {
+                Some(pr) if pr.state == "OPEN" => provider.merge_pr(ctx, &pr, options)?,
+                Some(pr) if pr.state == "MERGED" => MergeOutcome::AlreadyMerged(pr),
+                _ => anyhow::bail!("No open PR for branch '{}'", args.branch),
+            }
+        }
+        None => {
+            // PRs of forks are in the repository they were forked from
+            let url = repo
+                .upstream
+                .clone()
+                .or_else(|| repo.github_url.clone())
+                .or_else(|| github::detect_github_url(ctx, &repo.path))
+                .context("no GitHub, Bitbucket or Azure DevOps remote")?;
+            let github_repo = github::CloneSource::parse(&url, &ctx.github.host)?.repo_arg();
+            if args.dry_run {
+                return Ok(dry_run_row(github::find_pr(
+                    ctx,
+                    &github_repo,
+                    &args.branch,
+                )?));
+            }
+            github::merge_pr(ctx, &github_repo, &args.branch, options)?
+        }
+    };
+
+    Ok(match outcome {
+        MergeOutcome::Merged(pr) => (format!("#{}", pr.number), "✓ merged".to_string()),
+        MergeOutcome::AlreadyMerged(pr) => (format!("#{}", pr.number), "merged".to_string()),
+        MergeOutcome::Blocked { pr, reason } => {
+            (format!("#{}", pr.number), format!("blocked: {}", reason))
+        }
+    })
+}
+
+/// Table row of the PR a dry run would merge
+fn dry_run_row(pr: Option<github::PullRequest>) -> (String, String) {
+    match pr {
+        Some(pr) if pr.state == "OPEN" => (format!("#{}", pr.number), "would merge".to_string()),
+        Some(pr) => (format!("#{}", pr.number), pr.state.to_lowercase()),
+        None => (String::new(), "no pull request".to_string()),
+    }
+}
+
+/// Handle add repository command
+#[allow(clippy::too_many_arguments)]
+pub fn handle_add_repo(
+    ctx: &Context,
+    config: &mut Config,
+    path: &str,
+    url: Option<&str>,
+    alias: Option<&str>,
+    use_toplevel: bool,
+    strict: bool,
+    force: bool,
+) -> Result<()> {
+    let path = &repository_toplevel(ctx, path, use_toplevel)?;
+    let github_url = match url {
+        Some(url) => Some(github::normalize_github_url(url).unwrap_or_else(|| url.to_string())),
+        None => github::detect_github_url(ctx, path),
+    };
+
+    if !force {
+        let warnings = preflight::repository_warnings(ctx, path, github_url.as_deref());
+        for warning in &warnings {
+            eprintln!("Warning: {}: {}", path, warning);
+        }
+        if strict && !warnings.is_empty() {
+            anyhow::bail!(
+                "Not adding {} (--strict): fix the problems above, or pass --force to add it anyway",
+                path
+            );
+        }
+    }
+
+    let repository = Repository {
+        path: path.to_string(),
+        github_url: github_url.clone(),
+        alias: alias.map(|a| a.to_string()),
+        ..Default::default()
+    };
+    match config.add_repository(repository) {
+        Ok(_) => {
+            println!("Repository added successfully: {}", path);
+            if let Some(url) = github_url {
+                println!("GitHub: {}", url);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Failed to add repository: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// The config with `repo_path` (`--repo-path`) as its only repository, if given. The path
+/// is checked like `add-repo` checks it; one that isn't configured is marked ad hoc.
+pub fn ad_hoc_config<'a>(
+    ctx: &Context,
+    config: &'a Config,
+    repo_path: Option<&str>,
+) -> Result<Cow<'a, Config>> {
+    let Some(path) = repo_path else {
+        return Ok(Cow::Borrowed(config));
+    };
+    let path = repo::expand_path(path)?.to_string_lossy().to_string();
+    let repository = match config.find_repository(&path) {
+        Some(repository) => repository.clone(),
+        None => {
+            if !matches!(
+                git::repository_layout(ctx, &path)?,
+                RepositoryLayout::WorkTree { ref toplevel } if repo::same_path(toplevel, &path)
+            ) {
+                anyhow::bail!("{} is not the top level of a git working tree", path);
+            }
+            if !Path::new(&path).join("package.json").is_file() {
+                anyhow::bail!("{} has no package.json", path);
+            }
+            let github_url = github::detect_github_url(ctx, &path);
+            for warning in preflight::repository_warnings(ctx, &path, github_url.as_deref()) {
+                eprintln!("Warning: {}: {}", path, warning);
+            }
+            Repository {
+                path,
+                github_url,
+                ad_hoc: true,
+                ..Default::default()
+            }
+        }
+    };
+    Ok(Cow::Owned(Config {
+        repositories: vec![repository],
+        ..config.clone()
+    }))
+}
+
+/// Path to configure for the working tree at `path`: the path itself when it is the
+/// top level, or the top level if `use_toplevel` or the user agrees
+fn repository_toplevel(ctx: &Context, path: &str, use_toplevel: bool) -> Result<String> {
+    let toplevel = match git::repository_layout(ctx, path)? {
+        RepositoryLayout::Bare => anyhow::bail!(
+            "{} is a bare repository, which has no working tree to update; add a clone of it instead",
+            path
+        ),
+        RepositoryLayout::GitDir => anyhow::bail!(
+            "{} is inside a .git directory; add the working tree it belongs to instead",
+            path
+        ),
+        RepositoryLayout::WorkTree { toplevel } => toplevel,
+    };
+    if repo::same_path(&toplevel, path) {
+        return Ok(path.to_string());
+    }
+
+    println!("{} is inside the repository at {}", path, toplevel);
+    if use_toplevel
+        || (std::io::stdin().is_terminal() && confirm(&format!("Add {} instead?", toplevel)))
+    {
+        return Ok(toplevel);
+    }
+    anyhow::bail!(
+        "{} is not the top level of its repository; add {} instead, or pass --use-toplevel",
+        path,
+        toplevel
+    )
+}
+
+/// Handle remove repository command
+pub fn handle_remove_repo(config: &mut Config, path: &str) -> Result<()> {
+    match config.remove_repository(path) {
+        Ok(_) => {
+            println!("Repository removed successfully: {}", path);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Failed to remove repository: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Handle propagate command
+pub fn handle_propagate(ctx: &Context, config: &Config, args: &PropagateArgs) -> Result<()> {
+    let source = config
+        .repositories
+        .iter()
+        .find(|r| r.is_named(&args.source))
+        .ok_or_else(|| anyhow::anyhow!("No configured repository named '{}'", args.source))?;
+
+    let package = package::own_name(ctx, &source.path)?
+        .ok_or_else(|| anyhow::anyhow!("No package name in {}/package.json", source.path))?;
+    let version = match &args.version {
+        Some(version) => version::normalize(version),
+        None => package::own_version(ctx, &source.path)?.ok_or_else(|| {
+            anyhow::anyhow!("No version in {}/package.json; pass --version", source.path)
+        })?,
+    };
+
+    // Consumers would fail to install a version that isn't in the registry yet
+    if !args.skip_publish_check {
+        preflight::check(ctx, &{
+            let mut requirements = Requirements::default();
+            requirements.package_manager("npm", &source.path);
+            requirements
+        })?;
+        if !package::is_published(ctx, &source.path, &package, &version)? {
+            anyhow::bail!(
+                "{}@{} is not published to the registry yet. Publish it first, or use --skip-publish-check",
+                package,
+                version
+            );
+        }
+    }
+
+    let consumers: Vec<&Repository> = order::waves(&config.repositories)?
+        .into_iter()
+        .flatten()
+        .filter(|repo| !repo::same_path(&repo.path, &source.path))
+        .filter(|repo| depends_on(ctx, repo, &package))
+        .collect();
+    if consumers.is_empty() {
+        println!("No other configured repository depends on {}", package);
+        return Ok(());
+    }
+
+    let specifier = if args.exact {
+        version.clone()
+    } else {
+        format!("^{}", version)
+    };
+    println!(
+        "Propagating {}@{} from {} to {} repositories",
+        package,
+        specifier,
+        order::display_name(source),
+        consumers.len()
+    );
+    if args.dry_run {
+        println!("DRY RUN MODE - No changes will be made");
+    }
+
+    let style = CommitStyle::default();
+    let commit_message = style.subject(&format!("update {} to {}", package, specifier));
+    let branch_name = git::update_branch_name(&package, &specifier);
+    let repo_paths: Vec<&str> = consumers.iter().map(|r| r.path.as_str()).collect();
+    let mut campaign = Campaign::new(
+        &package,
+        &specifier,
+        &commit_message,
+        args.pull_request,
+        &branch_name,
+        &repo_paths,
+    );
+    campaign.pr_body = Some(propagation_body(ctx, source, &package, &version));
+    campaign.dedupe = config.dedupe.unwrap_or(false);
+
+    let targets = update_targets(ctx, config, &package, &specifier);
+    for (path, violation) in check_policies(config, &package, &targets) {
+        println!("⛔ Skipping {}: blocked by {}", path, violation);
+        campaign.set_status(&path, RepoStatus::Blocked, Some(violation), None);
+    }
+
+    preflight::check(
+        ctx,
+        &campaign_requirements(ctx, config, &campaign, args.dry_run),
+    )?;
+
+    if !args.dry_run {
+        campaign.save()?;
+        println!("Campaign: {}", campaign.id);
+    }
+
+    run_campaign(ctx, config, &mut campaign, "propagate", args.dry_run)
+}
+
+/// PR body for consumers of a propagated package, linking to the source's release and
+/// to what changed since the version each consumer was at (`{old}`)
+fn propagation_body(ctx: &Context, source: &Repository, package: &str, version: &str) -> String {
+    let mut body = format!(
+        "Updates {} to {}, published from {}.",
+        package,
+        version,
+        order::display_name(source)
+    );
+    let url = source
+        .github_url
+        .clone()
+        .or_else(|| github::detect_github_url(ctx, &source.path));
+    if let Some(Ok(source)) = url.map(|url| github::CloneSource::parse(&url, &ctx.github.host)) {
+        body.push_str(&format!(
+            "\n\nRelease: {}/releases/tag/v{}\nChanges: {}/compare/v{{old}}...v{}",
+            source.web_url(),
+            version,
+            source.web_url(),
+            version
+        ));
+    }
+    body
+}
+
+/// Handle pin-all command
+pub fn handle_pin_all(ctx: &Context, config: &Config, args: &SpecifierArgs) -> Result<()> {
+    let sections = args.sections();
+    rewrite_specifiers(
+        ctx,
+        config,
+        &args.proposal,
+        "pin-dependencies",
+        "pin dependencies to exact versions",
+        |repo| {
+            let lockfile = Lockfile::load(&repo.path)?;
+            package::plan_specifier_changes(
+                ctx,
+                &repo.path,
+                &sections,
+                args.packages.as_deref(),
+                |entry| {
+                    let resolved = lockfile
+                        .as_ref()
+                        .and_then(|lock| lock.resolved(&entry.name, &entry.specifier));
+                    version::pin(&entry.specifier, resolved.as_deref())
+                },
+            )
+        },
+    )
+}
+
+/// Handle widen command
+pub fn handle_widen(ctx: &Context, config: &Config, args: &WidenArgs) -> Result<()> {
+    let specifiers = &args.specifiers;
+    let sections = specifiers.sections();
+    rewrite_specifiers(
+        ctx,
+        config,
+        &specifiers.proposal,
+        "widen-dependencies",
+        &format!("widen pinned dependencies to {} ranges", args.prefix),
+        |repo| {
+            package::plan_specifier_changes(
+                ctx,
+                &repo.path,
+                &sections,
+                specifiers.packages.as_deref(),
+                |entry| version::widen(&entry.specifier, &args.prefix),
+            )
+        },
+    )
+}
+
+/// Handle engines command
+pub fn handle_engines(ctx: &Context, config: &Config, args: &EnginesArgs) -> Result<()> {
+    version::validate(&args.range)?;
+    let pins = if args.no_volta {
+        package::VoltaPins::default()
+    } else {
+        volta_pins(ctx, config, args)?
+    };
+
+    rewrite_specifiers(
+        ctx,
+        config,
+        &args.proposal,
+        "update-engines",
+        &format!("require node {}", args.range),
+        |repo| package::plan_engine_changes(ctx, &repo.path, &args.range, &pins),
+    )
+}
+
+/// Versions for the Volta pins of the selected repositories. The Node.js release
+/// index is only fetched when a repository pins node or npm.
+fn volta_pins(ctx: &Context, config: &Config, args: &EnginesArgs) -> Result<package::VoltaPins> {
+    let tools: std::collections::BTreeSet<String> = args
+        .proposal
+        .filter
+        .apply(&config.repositories)
+        .iter()
+        .filter_map(|repo| package::volta_tools(ctx, &repo.path).ok())
+        .flatten()
+        .collect();
+
+    let pins_node = tools.contains("node");
+    let pins_npm = tools.contains("npm");
+    let release = if pins_node || pins_npm {
+        let releases = if pins_npm || args.volta.is_none() {
+            node::releases()?
+        } else {
+            Vec::new()
+        };
+        let release = node::pick(&releases, &args.range, args.volta.as_deref())?;
+        match &release.npm {
+            Some(npm) => println!("Volta: node {} with npm {}", release.number(), npm),
+            None => println!("Volta: node {}", release.number()),
+        }
+        Some(release)
+    } else {
+        None
+    };
+
+    Ok(package::VoltaPins {
+        node: release.as_ref().map(|r| r.number().to_string()),
+        npm: release.and_then(|r| r.npm),
+        yarn: args.volta_yarn.clone(),
+    })
+}
+
+/// Report the manifest issues of each selected repository. With `--fix`, the issues
+/// that can be fixed safely are proposed like `pin-all`; otherwise, or if some need
+/// fixing by hand, finding any issue is an error.
+pub fn handle_lint_manifests(
+    ctx: &Context,
+    config: &Config,
+    args: &LintManifestsArgs,
+) -> Result<()> {
+    let repos = args.proposal.filter.apply(&config.repositories);
+    let mut fixes: BTreeMap<String, Vec<package::SpecifierChange>> = BTreeMap::new();
+    let (mut found, mut by_hand, mut unreadable) = (0, 0, 0);
+    for repo in &repos {
+        let issues = match lint::load(ctx, &repo.path) {
+            Ok(manifests) => lint::check(&manifests, args.prefer),
+            Err(e) => {
+                println!("\n{}:\n  ✗ {:#}", repo.path, e);
+                unreadable += 1;
+                continue;
+            }
+        };
+        if issues.is_empty() {
+            continue;
+        }
+
+        println!("\n{}:", repo.path);
+        for issue in &issues {
+            println!("  ✗ {}: {}", issue.package, issue.kind);
+            for location in &issue.locations {
+                println!("      {}", location);
+            }
+            if let Some(note) = &issue.note {
+                println!("      ({})", note);
+            }
+        }
+        found += issues.len();
+        by_hand += issues.iter().filter(|i| i.fix.is_none()).count();
+        fixes.insert(
+            repo.path.clone(),
+            issues.into_iter().filter_map(|i| i.fix).collect(),
+        );
+    }
+
+    if unreadable > 0 {
+        anyhow::bail!(
+            "Failed to read the manifests of {} repositories",
+            unreadable
+        );
+    }
+    if found == 0 {
+        println!("No manifest issues in {} repositories", repos.len());
+        return Ok(());
+    }
+    if !args.fix {
+        anyhow::bail!(
+            "Found {} manifest issues ({} can be fixed with --fix)",
+            found,
+            found - by_hand
+        );
+    }
+
+    rewrite_specifiers(
+        ctx,
+        config,
+        &args.proposal,
+        "lint-manifests",
+        "align dependency specifiers",
+        |repo| Ok(fixes.get(&repo.path).cloned().unwrap_or_default()),
+    )?;
+    if by_hand > 0 {
+        anyhow::bail!("{} manifest issues need fixing by hand", by_hand);
+    }
+    Ok(())
+}
+
+/// List the specifier changes `plan` finds in each selected repository, then propose
+/// them on `branch_name` unless this is a dry run
+fn rewrite_specifiers(
+    ctx: &Context,
+    config: &Config,
+    args: &ProposalArgs,
+    branch_name: &str,
+    description: &str,
+    plan: impl Fn(&Repository) -> Result<Vec<package::SpecifierChange>>,
+) -> Result<()> {
+    let repos = args.filter.apply(&config.repositories);
+    if repos.is_empty() {
+        println!("No repositories match");
+        return Ok(());
+    }
+
+    let plans: Vec<Result<Vec<package::SpecifierChange>>> =
+        repos.iter().map(|repo| plan(repo)).collect();
+    for (repo, plan) in repos.iter().zip(&plans) {
+        let Ok(changes) = plan else { continue };
+        if changes.is_empty() {
+            continue;
+        }
+        println!("\n{}:", repo.path);
+        let rows: Vec<Vec<String>> = changes
+            .iter()
+            .map(|c| {
+                let section = match &c.file {
+                    Some(file) => format!("{} ({})", c.section, file),
+                    None => c.section.to_string(),
+                };
+                vec![section, c.name.clone(), format!("{} → {}", c.old, c.new)]
+            })
+            .collect();
+        for line in table::render(&["SECTION", "PACKAGE", "CHANGE"], &rows).lines() {
+            println!("  {}", line);
+        }
+    }
+
+    if !args.dry_run {
+        let mut requirements = Requirements::git().with_gh(args.pull_request);
+        let pending = repos
+            .iter()
+            .zip(&plans)
+            .filter(|(_, plan)| plan.as_ref().is_ok_and(|changes| !changes.is_empty()));
+        for (repo, _) in pending {
+            let manager = config.package_manager(ctx, &repo.path)?.manager;
+            requirements.package_manager(&manager, &repo.path);
+        }
+        preflight::check(ctx, &requirements)?;
+    }
+
+    let commit_message = match &args.message {
+        Some(message) => message.clone(),
+        None => CommitStyle::default().subject(description),
+    };
+    let mut rows = Vec::new();
+    let mut failed = 0;
+    for (repo, plan) in repos.iter().zip(&plans) {
+        let (count, result) = match plan {
+            Ok(changes) if changes.is_empty() => (0, "nothing to change".to_string()),
+            Ok(changes) if args.dry_run => (changes.len(), "would push".to_string()),
+            Ok(changes) => {
+                let result = git::rewrite_specifiers_workflow(
+                    ctx,
+                    repo,
+                    changes,
+                    branch_name,
+                    &commit_message,
+                    args.pull_request,
+                    config,
+                );
+                let result = match result {
+                    Ok(Some(pr_url)) => format!("✓ {}", pr_url),
+                    Ok(None) => "✓ pushed".to_string(),
+                    Err(e) => {
+                        failed += 1;
+                        format!("✗ {}", e)
+                    }
+                };
+                (changes.len(), result)
+            }
+            Err(e) => {
+                failed += 1;
+                (0, format!("✗ {}", e))
+            }
+        };
+        rows.push(vec![repo.path.clone(), count.to_string(), result]);
+    }
+
+    println!();
+    table::print(&["REPOSITORY", "CHANGES", "RESULT"], &rows);
+
+    if failed > 0 {
+        anyhow::bail!("Failed in {} of {} repositories", failed, repos.len());
+    }
+    Ok(())
+}
+
+/// Handle list repositories command
+pub fn handle_list_repos(ctx: &Context, config: &Config) -> Result<()> {
+    if config.repositories.is_empty() {
+        println!("No repositories configured");
+    } else {
+        let records = history::load().unwrap_or_else(|e| {
+            eprintln!("Warning: Failed to read history: {:#}", e);
+            Vec::new()
+        });
+        println!("Configured repositories:");
+        for (i, repo) in config.repositories.iter().enumerate() {
+            println!("{}. Path: {}", i + 1, repo.path);
+            if let Some(alias) = &repo.alias {
+                println!("   Alias: {}", alias);
+            }
+            if let Some(url) = &repo.github_url {
+                println!("   GitHub: {}", url);
+            }
+            if repo.priority != 0 {
+                println!("   Priority: {}", repo.priority);
+            }
+            if !repo.depends_on.is_empty() {
+                println!("   Depends on: {}", repo.depends_on.join(", "));
+            }
+
+            // Git 상태 확인
+            match git::check_status(ctx, &repo.path) {
+                Ok(has_changes) => {
+                    if has_changes {
+                        println!("   Status: Changes present");
+                    } else {
+                        println!("   Status: Clean");
+                    }
+
+                    // 현재 브랜치 표시
+                    if let Ok(branch) = git::get_current_branch(ctx, &repo.path) {
+                        println!("   Branch: {}", branch);
+                    }
+
+                    // 패키지 매니저 감지
+                    if let Ok(detection) = config.package_manager(ctx, &repo.path) {
+                        println!(
+                            "   Package Manager: {} ({})",
+                            detection.manager, detection.source
+                        );
+                        if let Some(warning) = detection.warning {
+                            println!("   Warning: {}", warning);
+                        }
+                    }
+                }
+                Err(e) => println!("   Status check failed: {}", e),
+            }
+            match git::last_commit(ctx, &repo.path) {
+                Ok(Some((date, subject))) => println!("   Last commit: {} {}", date, subject),
+                Ok(None) => println!("   Last commit: none"),
+                Err(_) => {}
+            }
+            println!(
+                "   Last campaign: {}",
+                LastCampaign::describe(LastCampaign::from_history(&records, &repo.path).as_ref())
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of repositories probed at once by `status`
+const STATUS_JOBS: usize = 8;
+
+/// One row of the `status` table. Probes that failed are `None` and explained in `warnings`.
+#[derive(Debug, Default, Serialize)]
+struct StatusRow {
+    path: String,
+    branch: Option<String>,
+    dirty: Option<bool>,
+    behind: Option<usize>,
+    ahead: Option<usize>,
+    package_manager: Option<String>,
+    update_branches: Vec<String>,
+    /// PRs of the update branches, with `--prs`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pull_requests: Vec<github::PrSummary>,
+    /// Open pull requests of update bots, with `--include-bots`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bot_prs: Vec<github::BotPr>,
+    /// Last commit on the base branch; `None` without commits
+    last_commit: Option<LastCommit>,
+    /// Latest mru run in the repository; `None` if mru never ran there
+    last_campaign: Option<LastCampaign>,
+    warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LastCommit {
+    /// `YYYY-MM-DD`
+    date: String,
+    subject: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LastCampaign {
+    id: String,
+    command: String,
+    /// Start time, `YYYY-MM-DD HH:MM` in UTC
+    date: String,
+    package: String,
+    version: String,
+    /// The repository's status in the run
+    status: &'static str,
+}
+
+impl LastCampaign {
+    fn from_history(records: &[HistoryRecord], repo_path: &str) -> Option<Self> {
+        let (record, repo) = history::last_run(records, repo_path)?;
+        Some(LastCampaign {
+            id: record.id.clone(),
+            command: record.command.clone(),
+            date: campaign::format_timestamp(record.timestamp),
+            package: record.package.clone(),
+            version: record.version.clone(),
+            status: repo.status.label(),
+        })
+    }
+
+    /// `2024-03-01 update react@18.3.1: completed`, or `never`
+    fn describe(campaign: Option<&Self>) -> String {
+        match campaign {
+            Some(c) => format!(
+                "{} {} {}@{}: {}",
+                c.date.split(' ').next().unwrap_or_default(),
+                c.command,
+                c.package,
+                c.version,
+                c.status
+            ),
+            None => "never".to_string(),
+        }
+    }
+}
+
+/// `2024-03-01 fix: subject`, shortened for a table cell
+fn describe_commit(commit: Option<&LastCommit>) -> String {
+    const MAX_SUBJECT: usize = 40;
+    match commit {
+        Some(commit) if commit.subject.chars().count() > MAX_SUBJECT => {
+            let subject: String = commit.subject.chars().take(MAX_SUBJECT - 1).collect();
+            format!("{} {}…", commit.date, subject)
+        }
+        Some(commit) => format!("{} {}", commit.date, commit.subject),
+        None => "no commits".to_string(),
+    }
+}
+
+/// Handle doctor command
+pub fn handle_doctor(ctx: &Context, config: &Config, repo: Option<&str>) -> Result<()> {
+    let repositories = match repo {
+        Some(path) => vec![config.find_repository(path).cloned().unwrap_or(Repository {
+            path: path.to_string(),
+            ..Default::default()
+        })],
+        None => config.repositories.clone(),
+    };
+
+    let mut requirements = Requirements::git().with_gh(true);
+    for repo in &repositories {
+        match config
+            .package_manager(ctx, &repo.path)
+            .map(|detection| detection.manager)
+        {
+            Ok(manager) => requirements.package_manager(&manager, &repo.path),
+            Err(e) => eprintln!("Warning: {}: {}", repo.path, e),
+        }
+        requirements.push_to(&repo.path);
+    }
+
+    preflight::check(ctx, &requirements)?;
+    println!(
+        "✓ git, gh and package managers are installed, and git can commit and push in {} repositories",
+        repositories.len()
+    );
+
+    let mut warned = 0;
+    for repo in &repositories {
+        let warnings = preflight::repository_warnings(ctx, &repo.path, repo.github_url.as_deref());
+        for warning in &warnings {
+            println!("⚠ {}: {}", repo.path, warning);
+        }
+        warned += usize::from(!warnings.is_empty());
+    }
+    if warned == 0 {
+        println!("✓ No problems found in the repositories");
+    }
+    Ok(())
+}
+
+/// Handle status command
+pub fn handle_status(
+    ctx: &Context,
+    config: &Config,
+    fetch: bool,
+    json: bool,
+    prs: bool,
+    include_bots: bool,
+) -> Result<()> {
+    if config.repositories.is_empty() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+
+    let mut rows = parallel::map(&config.repositories, STATUS_JOBS, |repo| {
+        probe_status(ctx, config, &repo.path, fetch)
+    });
+    if prs {
+        attach_pull_requests(ctx, &config.repositories, &mut rows);
+    }
+    if include_bots {
+        let bot_prs = parallel::map(&config.repositories, STATUS_JOBS, |repo| {
+            open_bot_prs(ctx, config, repo, None)
+        });
+        for (row, prs) in rows.iter_mut().zip(bot_prs) {
+            match prs {
+                Ok(prs) => row.bot_prs = prs,
+                Err(e) => row.warnings.push(format!("{:#}", e)),
+            }
+        }
+    }
+    let records = history::load().unwrap_or_else(|e| {
+        eprintln!("Warning: Failed to read history: {:#}", e);
+        Vec::new()
+    });
+    for row in &mut rows {
+        row.last_campaign = LastCampaign::from_history(&records, &row.path);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            let unknown = || "?".to_string();
+            vec![
+                row.path.clone(),
+                row.branch.clone().unwrap_or_else(unknown),
+                match row.dirty {
+                    Some(true) => "dirty".to_string(),
+                    Some(false) => "clean".to_string(),
+                    None => unknown(),
+                },
+                match (row.behind, row.ahead) {
+                    (Some(behind), Some(ahead)) => format!("↓{} ↑{}", behind, ahead),
+                    _ => "-".to_string(),
+                },
+                row.package_manager
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                if row.update_branches.is_empty() {
+                    "-".to_string()
+                } else {
+                    row.update_branches
+                        .iter()
+                        .map(|branch| {
+                            match row.pull_requests.iter().find(|pr| &pr.branch == branch) {
+                                Some(pr) => format!("{} ({})", branch, describe_pr(pr)),
+                                None => branch.clone(),
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+                describe_commit(row.last_commit.as_ref()),
+                LastCampaign::describe(row.last_campaign.as_ref()),
+            ]
+        })
+        .collect();
+
+    table::print(
+        &[
+            "REPOSITORY",
+            "BRANCH",
+            "STATE",
+            "UPSTREAM",
+            "PM",
+            "UPDATE BRANCHES",
+            "LAST COMMIT",
+            "LAST CAMPAIGN",
+        ],
+        &cells,
+    );
+
+    if rows.iter().any(|row| !row.bot_prs.is_empty()) {
+        println!("\nBot pull requests:");
+        for row in &rows {
+            for pr in &row.bot_prs {
+                println!("  {}: {} ({})", row.path, pr.title, pr.url);
+            }
+        }
+    }
+
+    let warnings: Vec<(&str, &String)> = rows
+        .iter()
+        .flat_map(|row| row.warnings.iter().map(|w| (row.path.as_str(), w)))
+        .collect();
+    if !warnings.is_empty() {
+        println!();
+        for (path, warning) in warnings {
+            println!("Warning: {}: {}", path, warning);
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up the PRs of all update branches in batched GitHub queries; PRs of forks
+/// are looked up in the upstream repository
+fn attach_pull_requests(ctx: &Context, repos: &[Repository], rows: &mut [StatusRow]) {
+    let mut heads = Vec::new();
+    let mut owners = Vec::new();
+    for (i, (repo, row)) in repos.iter().zip(rows.iter()).enumerate() {
+        let Some(url) = repo.upstream.as_ref().or(repo.github_url.as_ref()) else {
+            continue;
+        };
+        for branch in &row.update_branches {
+            heads.push((url.clone(), branch.clone()));
+            owners.push(i);
+        }
+    }
+    if heads.is_empty() {
+        return;
+    }
+
+    match github::pr_summaries(ctx, &heads) {
+        Ok(summaries) => {
+            for (i, summary) in owners.into_iter().zip(summaries) {
+                rows[i].pull_requests.extend(summary);
+            }
+        }
+        Err(e) => eprintln!("Warning: {:#}", e),
+    }
+}
+
+/// `#12 open, checks failing` (or `#12 draft, ...`) for the status table
+fn describe_pr(pr: &github::PrSummary) -> String {
+    let checks = match pr.checks.as_deref() {
+        Some("SUCCESS") => ", checks passing",
+        Some("FAILURE" | "ERROR") => ", checks failing",
+        Some("PENDING" | "EXPECTED") => ", checks pending",
+        _ => "",
+    };
+    let state = if pr.draft && pr.state == "OPEN" {
+        "draft".to_string()
+    } else {
+        pr.state.to_lowercase()
+    };
+    format!("#{} {}{}", pr.number, state, checks)
+}
+
+/// Run the independent git probes for one repository, turning failures into warnings
+fn probe_status(ctx: &Context, config: &Config, repo_path: &str, fetch: bool) -> StatusRow {
+    let mut row = StatusRow {
+        path: repo_path.to_string(),
+        ..Default::default()
+    };
+
+    match repo::expand_path(repo_path) {
+        Ok(path) if path.is_dir() => {}
+        _ => {
+            row.warnings.push("path does not exist".to_string());
+            return row;
+        }
+    }
+
+    if fetch {
+        if let Err(e) = git::fetch(ctx, repo_path) {
+            row.warnings.push(e.to_string());
+        }
+    }
+
+    match git::current_branch(ctx, repo_path) {
+        Ok(branch) => {
+            row.branch = Some(branch.map_or_else(|| "(detached)".to_string(), |b| b.to_string()))
+        }
+        Err(e) => row.warnings.push(e.to_string()),
+    }
+    match git::check_status(ctx, repo_path) {
+        Ok(dirty) => row.dirty = Some(dirty),
+        Err(e) => row.warnings.push(e.to_string()),
+    }
+    match git::ahead_behind(ctx, repo_path) {
+        Ok(Some((behind, ahead))) => {
+            row.behind = Some(behind);
+            row.ahead = Some(ahead);
+        }
+        Ok(None) => row.warnings.push("no upstream branch".to_string()),
+        Err(e) => row.warnings.push(e.to_string()),
+    }
+    match git::list_branches(ctx, repo_path, "update-*") {
+        Ok(branches) => row.update_branches = branches,
+        Err(e) => row.warnings.push(e.to_string()),
+    }
+    row.package_manager = config
+        .package_manager(ctx, repo_path)
+        .ok()
+        .map(|detection| detection.manager);
+    match git::last_commit(ctx, repo_path) {
+        Ok(commit) => row.last_commit = commit.map(|(date, subject)| LastCommit { date, subject }),
+        Err(e) => row.warnings.push(e.to_string()),
+    }
+
+    row
+}
+
+/// Version of a package in one repository
+#[derive(Debug, Serialize)]
+struct CompareEntry {
+    path: String,
+    /// `None` if the repository doesn't depend on the package
+    version: Option<String>,
+    /// Set if the repository's package.json couldn't be read
+    error: Option<String>,
+    /// `lodash-es → lodash` if the package is declared as an npm alias
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+}
+
+/// Repositories sharing one version; `version` is `None` for "not found"
+#[derive(Debug, Serialize)]
+struct VersionGroup {
+    version: Option<String>,
+    repos: Vec<String>,
+    /// npm aliases the package is declared as, by repository
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompareReport {
+    package: String,
+    /// Found versions in version order, then "not found"
+    versions: Vec<VersionGroup>,
+    errors: Vec<CompareEntry>,
+}
+
+impl CompareReport {
+    fn new(entries: Vec<CompareEntry>, package: &str, normalize: bool) -> Self {
+        let mut versions: Vec<VersionGroup> = Vec::new();
+        let mut not_found = Vec::new();
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            match (&entry.error, &entry.version) {
+                (Some(_), _) => errors.push(entry),
+                (None, None) => not_found.push(entry.path),
+                (None, Some(version)) => {
+                    let key = if normalize {
+                        version::normalize(version)
+                    } else {
+                        version.clone()
+                    };
+                    let index = match versions
+                        .iter()
+                        .position(|g| g.version.as_deref() == Some(&key))
+                    {
+                        Some(index) => index,
+                        None => {
+                            versions.push(VersionGroup {
+                                version: Some(key),
+                                repos: Vec::new(),
+                                aliases: BTreeMap::new(),
+                            });
+                            versions.len() - 1
+                        }
+                    };
+                    let group = &mut versions[index];
+                    if let Some(alias) = entry.alias {
+                        group.aliases.insert(entry.path.clone(), alias);
+                    }
+                    group.repos.push(entry.path);
+                }
+            }
+        }
+
+        versions.sort_by(|a, b| {
+            version::compare_specifiers(
+                a.version.as_deref().unwrap_or_default(),
+                b.version.as_deref().unwrap_or_default(),
+            )
+        });
+        if !not_found.is_empty() {
+            versions.push(VersionGroup {
+                version: None,
+                repos: not_found,
+                aliases: BTreeMap::new(),
+            });
+        }
+
+        CompareReport {
+            package: package.to_string(),
+            versions,
+            errors,
+        }
+    }
+
+    fn found_versions(&self) -> usize {
+        self.versions.iter().filter(|g| g.version.is_some()).count()
+    }
+
+    /// Repositories not on the most common version
+    fn disagreeing(&self) -> usize {
+        let found = self.versions.iter().filter(|g| g.version.is_some());
+        let total: usize = found.clone().map(|g| g.repos.len()).sum();
+        let largest = found.map(|g| g.repos.len()).max().unwrap_or(0);
+        total - largest
+    }
+
+    fn has_missing(&self) -> bool {
+        self.versions.iter().any(|g| g.version.is_none())
+    }
+
+    /// e.g. `^4.17.0 ×5, 4.17.21 ×2, not found ×1`
+    fn counts(&self) -> String {
+        self.versions
+            .iter()
+            .map(|g| {
+                format!(
+                    "{} ×{}",
+                    g.version.as_deref().unwrap_or("not found"),
+                    g.repos.len()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// e.g. `3 distinct versions found (^4.17.0 ×5, 4.17.21 ×2, not found ×1)`
+    fn verdict(&self) -> String {
+        let noun = if self.versions.len() == 1 {
+            "version"
+        } else {
+            "versions"
+        };
+        let mut verdict = format!(
+            "{} distinct {} found ({})",
+            self.versions.len(),
+            noun,
+            self.counts()
+        );
+        if !self.errors.is_empty() {
+            verdict.push_str(&format!(
+                ", {} repositories could not be read",
+                self.errors.len()
+            ));
+        }
+        verdict
+    }
+
+    /// Reason the comparison fails `--check`, if it does
+    fn check(&self, require_present: bool) -> Option<String> {
+        if self.found_versions() > 1 {
+            Some(format!(
+                "Repositories use {} different versions of '{}'",
+                self.found_versions(),
+                self.package
+            ))
+        } else if require_present && self.has_missing() {
+            Some(format!(
+                "Some repositories don't depend on '{}'",
+                self.package
+            ))
+        } else if !self.errors.is_empty() {
+            Some(format!(
+                "Could not read package.json in {} repositories",
+                self.errors.len()
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn print(&self) {
+        let mut rows = Vec::new();
+        for group in &self.versions {
+            let label = group.version.as_deref().unwrap_or("not found");
+            for (i, path) in group.repos.iter().enumerate() {
+                let label = if i == 0 { label } else { "" };
+                let repo = match group.aliases.get(path) {
+                    Some(alias) => format!("{} ({})", path, alias),
+                    None => path.clone(),
+                };
+                rows.push(vec![label.to_string(), repo]);
+            }
+        }
+        for entry in &self.errors {
+            rows.push(vec![
+                "error".to_string(),
+                format!(
+                    "{} ({})",
+                    entry.path,
+                    entry.error.as_deref().unwrap_or_default()
+                ),
+            ]);
+        }
+
+        table::print(&["VERSION", "REPOSITORY"], &rows);
+        println!("\n{}", self.verdict());
+    }
+}
+
+/// Entries for one package, from versions read once per repository
+fn compare_entries(
+    repo_versions: &[(String, Result<BTreeMap<String, String>>)],
+    package: &str,
+) -> Vec<CompareEntry> {
+    repo_versions
+        .iter()
+        .map(|(path, versions)| match versions {
+            Ok(versions) => {
+                let declared = package::declared(versions, package);
+                CompareEntry {
+                    path: path.clone(),
+                    version: declared.map(|(_, spec)| version::range_of(spec).to_string()),
+                    error: None,
+                    alias: declared.and_then(|(name, spec)| {
+                        let (target, _) = version::npm_alias(spec)?;
+                        Some(format!("{} → {}", name, target))
+                    }),
+                }
+            }
+            Err(e) => CompareEntry {
+                path: path.clone(),
+                version: None,
+                error: Some(format!("{:#}", e)),
+                alias: None,
+            },
+        })
+        .collect()
+}
+
+/// Packages used by two or more repositories, by name
+fn shared_packages(repo_versions: &[(String, Result<BTreeMap<String, String>>)]) -> Vec<&str> {
+    let mut usage: BTreeMap<&str, usize> = BTreeMap::new();
+    for versions in repo_versions.iter().filter_map(|(_, v)| v.as_ref().ok()) {
+        for name in versions.keys() {
+            *usage.entry(name).or_default() += 1;
+        }
+    }
+    usage
+        .into_iter()
+        .filter(|&(_, repos)| repos >= 2)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Packages used by two or more repositories whose versions differ,
+/// most disagreeing first
+fn drift_reports(
+    repo_versions: &[(String, Result<BTreeMap<String, String>>)],
+    normalize: bool,
+) -> Vec<CompareReport> {
+    let mut reports: Vec<CompareReport> = shared_packages(repo_versions)
+        .into_iter()
+        .map(|name| CompareReport::new(compare_entries(repo_versions, name), name, normalize))
+        .filter(|report| report.found_versions() > 1)
+        .collect();
+    reports.sort_by(|a, b| {
+        b.disagreeing()
+            .cmp(&a.disagreeing())
+            .then_with(|| a.package.cmp(&b.package))
+    });
+    reports
+}
+
+fn print_drift(
+    reports: &[CompareReport],
+    repo_versions: &[(String, Result<BTreeMap<String, String>>)],
+) {
+    for (path, versions) in repo_versions {
+        if let Err(e) = versions {
+            eprintln!("Warning: Skipping {}: {:#}", path, e);
+        }
+    }
+
+    if reports.is_empty() {
+        println!("No version drift found across repositories");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = reports
+        .iter()
+        .map(|r| vec![r.package.clone(), r.disagreeing().to_string(), r.counts()])
+        .collect();
+    table::print(&["PACKAGE", "DISAGREEING", "VERSIONS"], &rows);
+    let noun = if reports.len() == 1 {
+        "package"
+    } else {
+        "packages"
+    };
+    println!("\n{} {} with version drift", reports.len(), noun);
+}
+
+/// Outcome of one check run by `mru check`
+#[derive(Debug, Serialize)]
+struct CheckResult {
+    /// Kind of check: `repo reachable`, `version consistency` or `policy`
+    check: &'static str,
+    /// Repository, package or policy checked
+    name: String,
+    /// Why the check failed; `None` if it passed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure: Option<String>,
+}
+
+/// Run the checks selected by `args` against versions read from every repository
+fn check_results(
+    config: &Config,
+    repo_versions: &[(String, Result<BTreeMap<String, String>>)],
+    args: &CheckArgs,
+) -> Vec<CheckResult> {
+    let mut results: Vec<CheckResult> = repo_versions
+        .iter()
+        .map(|(path, versions)| CheckResult {
+            check: "repo reachable",
+            name: path.clone(),
+            failure: versions.as_ref().err().map(|e| format!("{:#}", e)),
+        })
+        .collect();
+
+    let packages: Vec<&str> = if args.all {
+        shared_packages(repo_versions)
+    } else {
+        args.packages.iter().map(String::as_str).collect()
+    };
+    for package in packages {
+        let report = CompareReport::new(
+            compare_entries(repo_versions, package),
+            package,
+            args.normalize,
+        );
+        results.push(CheckResult {
+            check: "version consistency",
+            name: package.to_string(),
+            failure: (report.found_versions() > 1).then(|| {
+                format!(
+                    "{} different versions ({})",
+                    report.found_versions(),
+                    report.counts()
+                )
+            }),
+        });
+    }
+
+    if args.policies {
+        for policy in &config.policies {
+            let mut violations = Vec::new();
+            for (path, versions) in repo_versions {
+                let (Some(repo), Ok(versions)) = (config.find_repository(path), versions) else {
+                    continue;
+                };
+                for (package, version) in versions {
+                    if let Some(violation) = policy.current_violation(repo, package, version) {
+                        violations.push(format!("{} in {}: {}", version, path, violation));
+                    }
+                }
+            }
+            results.push(CheckResult {
+                check: "policy",
+                name: policy.name.clone(),
+                failure: (!violations.is_empty()).then(|| violations.join("\n")),
+            });
+        }
+    }
+
+    results
+}
+
+/// JUnit XML report with one test case per check
+fn junit_report(results: &[CheckResult]) -> String {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"mru check\" tests=\"{}\" failures=\"{}\">\n",
+        results.len(),
+        failures
+    ));
+    for result in results {
+        let case = format!(
+            "  <testcase classname=\"{}\" name=\"{}\"",
+            escape(result.check),
+            escape(&result.name)
+        );
+        match &result.failure {
+            Some(failure) => xml.push_str(&format!(
+                "{}>\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+                case,
+                escape(failure.lines().next().unwrap_or_default()),
+                escape(failure)
+            )),
+            None => xml.push_str(&format!("{}/>\n", case)),
+        }
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+/// Handle check command. Never changes anything; fails iff a check fails.
+pub fn handle_check(ctx: &Context, config: &Config, args: &CheckArgs) -> Result<()> {
+    let repo_paths: Vec<&str> = config
+        .repositories
+        .iter()
+        .map(|r| r.path.as_str())
+        .collect();
+    let repo_versions = package::dependency_versions(ctx, &repo_paths, args.jobs, None);
+    let results = check_results(config, &repo_versions, args);
+
+    if let Some(path) = &args.junit {
+        std::fs::write(repo::expand_path(path)?, junit_report(&results))
+            .with_context(|| format!("Failed to write JUnit report: {}", path))?;
+    }
+    if let Some(path) = &args.json {
+        std::fs::write(
+            repo::expand_path(path)?,
+            serde_json::to_string_pretty(&results)?,
+        )
+        .with_context(|| format!("Failed to write JSON report: {}", path))?;
+    }
+
+    let failed: Vec<&CheckResult> = results.iter().filter(|r| r.failure.is_some()).collect();
+    for result in &failed {
+        let failure = result.failure.as_deref().unwrap_or_default();
+        println!("✗ {} [{}]", result.name, result.check);
+        for line in failure.lines() {
+            println!("    {}", line);
+        }
+    }
+
+    if failed.is_empty() {
+        println!("✓ All {} checks passed", results.len());
+        Ok(())
+    } else {
+        anyhow::bail!("{} of {} checks failed", failed.len(), results.len())
+    }
+}
+
+/// Handle package version comparison command
+pub fn handle_compare(ctx: &Context, config: &Config, args: &CompareArgs) -> Result<()> {
+    if config.repositories.is_empty() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+    if let Some(id) = &args.campaign {
+        return compare_campaign(ctx, config, args, id);
+    }
+
+    // Extra manifests are compared as if they were repositories of their own
+    let manifest_paths = config.manifest_paths();
+    let repo_paths: Vec<&str> = manifest_paths.iter().map(String::as_str).collect();
+    let repo_versions = package::dependency_versions(ctx, &repo_paths, 1, args.at_ref.as_deref());
+
+    let reports: Vec<CompareReport> = if args.all {
+        drift_reports(&repo_versions, args.normalize)
+    } else {
+        args.packages
+            .iter()
+            .map(|package| {
+                CompareReport::new(
+                    compare_entries(&repo_versions, package),
+                    package,
+                    args.normalize,
+                )
+            })
+            .collect()
+    };
+
+    if args.json {
+        match reports.as_slice() {
+            [report] if !args.all => println!("{}", serde_json::to_string_pretty(report)?),
+            _ => println!("{}", serde_json::to_string_pretty(&reports)?),
+        }
+    } else if args.all {
+        print_drift(&reports, &repo_versions);
+    } else {
+        for (i, report) in reports.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!(
+                "Comparing package '{}' across repositories:\n",
+                report.package
+            );
+            report.print();
+        }
+    }
+
+    if args.check {
+        let failures: Vec<String> = reports
+            .iter()
+            .filter_map(|report| report.check(args.require_present))
+            .collect();
+        if !failures.is_empty() {
+            anyhow::bail!(failures.join("\n"));
+        }
+    }
+
+    // A package no readable manifest has is most likely a typo
+    if !args.all {
+        let known = suggest::known_names(&repo_versions);
+        let missing: Vec<String> = args
+            .packages
+            .iter()
+            .filter(|package| unused_everywhere(&repo_versions, package))
+            .map(|package| suggest::not_found_message(package, &known))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!(missing.join("\n"));
+        }
+    }
+
+    Ok(())
+}
+
+/// A repository's version of a campaign's package before the campaign, on the campaign
+/// branch and on the base branch
+#[derive(Debug, Serialize)]
+struct CampaignVersions {
+    path: String,
+    before: Option<String>,
+    branch: Option<String>,
+    base: Option<String>,
+    base_ref: Option<String>,
+    state: String,
+}
+
+/// Handle `compare --campaign`
+fn compare_campaign(ctx: &Context, config: &Config, args: &CompareArgs, id: &str) -> Result<()> {
+    let campaign = Campaign::load(id)?;
+    if let Some(other) = args.packages.iter().find(|p| **p != campaign.package) {
+        anyhow::bail!(
+            "Campaign {} updated {}, not {}",
+            campaign.id,
+            campaign.package,
+            other
+        );
+    }
+    let branch = campaign.branch_name();
+
+    // Repositories the campaign ran in that have since left the config are shown too
+    let mut paths: Vec<String> = config.repositories.iter().map(|r| r.path.clone()).collect();
+    for repo in &campaign.repos {
+        if !paths.iter().any(|p| repo::same_path(p, &repo.path)) {
+            paths.push(repo.path.clone());
+        }
+    }
+
+    let package = &campaign.package;
+    let rows: Vec<CampaignVersions> = paths
+        .into_iter()
+        .map(|path| {
+            let state = campaign
+                .repos
+                .iter()
+                .find(|r| repo::same_path(&r.path, &path));
+            let base_ref = campaign
+                .base
+                .clone()
+                .or_else(|| state.and_then(|s| s.original_branch.clone()))
+                .or_else(|| git::default_branch(ctx, &path).ok());
+            let base = base_ref.as_deref().and_then(|git_ref| {
+                package::get_package_version_at(ctx, &path, package, git_ref)
+                    .ok()
+                    .flatten()
+            });
+            let Some(state) = state else {
+                return CampaignVersions {
+                    path,
+                    before: None,
+                    branch: None,
+                    base,
+                    base_ref,
+                    state: "not in campaign".to_string(),
+                };
+            };
+            let before = state
+                .scanned_version
+                .clone()
+                .or_else(|| state.old_version.clone());
+            // A missing branch was never pushed, or was deleted after merging
+            let branch = [branch.clone(), format!("origin/{}", branch)]
+                .iter()
+                .find_map(|git_ref| {
+                    package::get_package_version_at(ctx, &path, package, git_ref).ok()
+                })
+                .flatten();
+            CampaignVersions {
+                state: campaign_progress(state.status, &before, &branch, &base),
+                path,
+                before,
+                branch,
+                base,
+                base_ref,
+            }
+        })
+        .collect();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    println!(
+        "Comparing package '{}' with campaign {} (branch {}):\n",
+        package, campaign.id, branch
+    );
+    let dash = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+    let table_rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            vec![
+                row.path.clone(),
+                dash(&row.before),
+                dash(&row.branch),
+                match (&row.base, &row.base_ref) {
+                    (Some(version), Some(git_ref)) => format!("{} ({})", version, git_ref),
+                    _ => "-".to_string(),
+                },
+                row.state.clone(),
+            ]
+        })
+        .collect();
+    println!(
+        "{}",
+        table::render(
+            &["REPOSITORY", "BEFORE", "CAMPAIGN BRANCH", "BASE", "STATE"],
+            &table_rows
+        )
+    );
+    Ok(())
+}
+
+/// Where a repository of a campaign stands: `merged` once the base branch has the
+/// branch's version, `pending` while it doesn't, or else the campaign's status
+fn campaign_progress(
+    status: RepoStatus,
+    before: &Option<String>,
+    branch: &Option<String>,
+    base: &Option<String>,
+) -> String {
+    match branch {
+        Some(_) if base == branch => "merged".to_string(),
+        Some(_) => "pending".to_string(),
+        None if status == RepoStatus::Completed && base.is_some() && base != before => {
+            "merged".to_string()
+        }
+        None => status.label().to_string(),
+    }
+}
+
+/// Whether at least one manifest was read and none of them has the package
+fn unused_everywhere(
+    repo_versions: &[(String, Result<BTreeMap<String, String>>)],
+    package: &str,
+) -> bool {
+    let read: Vec<&BTreeMap<String, String>> = repo_versions
+        .iter()
+        .filter_map(|(_, versions)| versions.as_ref().ok())
+        .collect();
+    !read.is_empty()
+        && read
+            .iter()
+            .all(|versions| package::declared(versions, package).is_none())
+}
+
+/// Packages of one repository after filtering and sorting
+#[derive(Serialize)]
+struct RepoPackages {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packages: Option<Vec<package::DependencyEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// One package across all repositories
+#[derive(Serialize)]
+struct AggregatedPackage {
+    name: String,
+    sections: Vec<&'static str>,
+    /// Repositories using each version of the package
+    versions: Vec<VersionGroup>,
+}
+
+impl ListPackagesArgs {
+    fn select(&self, mut packages: Vec<package::DependencyEntry>) -> Vec<package::DependencyEntry> {
+        packages.retain(|entry| {
+            self.section.as_ref().is_none_or(|s| entry.section == s)
+                && self
+                    .filter
+                    .as_ref()
+                    .is_none_or(|f| package::matches_glob(f, &entry.name))
+        });
+        // Stable sort keeps the section order from package.json
+        match self.sort {
+            PackageSort::Name => packages.sort_by(|a, b| a.name.cmp(&b.name)),
+            PackageSort::Version => packages.sort_by(|a, b| {
+                version::compare_specifiers(&a.specifier, &b.specifier)
+                    .then_with(|| a.name.cmp(&b.name))
+            }),
+        }
+        packages.sort_by_key(|entry| section_index(entry.section));
+        packages
+    }
+}
+
+fn section_index(section: &str) -> usize {
+    package::DEPENDENCY_SECTIONS
+        .iter()
+        .position(|s| *s == section)
+        .unwrap_or(usize::MAX)
+}
+
+fn section_title(section: &str) -> &'static str {
+    match section {
+        "devDependencies" => "Dev Dependencies",
+        "peerDependencies" => "Peer Dependencies",
+        "optionalDependencies" => "Optional Dependencies",
+        _ => "Dependencies",
+    }
+}
+
+/// Number of packages in each section
+fn section_counts(
+    sections: impl IntoIterator<Item = &'static str>,
+) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for section in sections {
+        *counts.entry(section).or_default() += 1;
+    }
+    counts
+}
+
+fn print_counts(counts: &BTreeMap<&'static str, usize>, indent: &str) {
+    for section in package::DEPENDENCY_SECTIONS {
+        if let Some(count) = counts.get(section) {
+            println!("{}{}: {}", indent, section_title(section), count);
+        }
+    }
+}
+
+/// Merge the packages of every repository, listing each package once
+fn aggregate_packages(repos: &[RepoPackages], sort: PackageSort) -> Vec<AggregatedPackage> {
+    let mut by_name: BTreeMap<&str, AggregatedPackage> = BTreeMap::new();
+    for repo in repos {
+        for entry in repo.packages.iter().flatten() {
+            let aggregated = by_name
+                .entry(&entry.name)
+                .or_insert_with(|| AggregatedPackage {
+                    name: entry.name.clone(),
+                    sections: Vec::new(),
+                    versions: Vec::new(),
+                });
+            if !aggregated.sections.contains(&entry.section) {
+                aggregated.sections.push(entry.section);
+            }
+            let version = Some(entry.specifier.clone());
+            match aggregated
+                .versions
+                .iter_mut()
+                .find(|g| g.version == version)
+            {
+                Some(group) if !group.repos.contains(&repo.path) => {
+                    group.repos.push(repo.path.clone())
+                }
+                Some(_) => {}
+                None => aggregated.versions.push(VersionGroup {
+                    version,
+                    repos: vec![repo.path.clone()],
+                    aliases: BTreeMap::new(),
+                }),
+            }
+        }
+    }
+
+    let mut packages: Vec<AggregatedPackage> = by_name.into_values().collect();
+    for package in &mut packages {
+        package.sections.sort_by_key(|s| section_index(s));
+        package.versions.sort_by(|a, b| {
+            version::compare_specifiers(
+                a.version.as_deref().unwrap_or_default(),
+                b.version.as_deref().unwrap_or_default(),
+            )
+        });
+    }
+    if let PackageSort::Version = sort {
+        let newest = |p: &AggregatedPackage| {
+            p.versions
+                .last()
+                .and_then(|g| g.version.clone())
+                .unwrap_or_default()
+        };
+        packages.sort_by(|a, b| version::compare_specifiers(&newest(a), &newest(b)));
+    }
+    packages
+}
+
+fn print_aggregated(packages: &[AggregatedPackage]) {
+    if packages.is_empty() {
+        println!("No packages found");
+        return;
+    }
+
+    let mut rows = Vec::new();
+    for package in packages {
+        for (i, group) in package.versions.iter().enumerate() {
+            let name = if i == 0 { package.name.as_str() } else { "" };
+            let repos: Vec<String> = group.repos.iter().map(|r| context::repo_name(r)).collect();
+            rows.push(vec![
+                name.to_string(),
+                group.version.clone().unwrap_or_default(),
+                repos.join(", "),
+            ]);
+        }
+    }
+    table::print(&["PACKAGE", "VERSION", "REPOSITORIES"], &rows);
+}
+
+/// Look up the license of the version of each package the repository uses, the way
+/// `update --license-policy` does
+fn add_licenses(ctx: &Context, repo_path: &str, packages: &mut [package::DependencyEntry]) {
+    let lockfile = Lockfile::load(repo_path).ok().flatten();
+    for entry in packages {
+        let license = license::current(
+            ctx,
+            repo_path,
+            &entry.name,
+            &entry.specifier,
+            lockfile.as_ref(),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}", e);
+            license::UNKNOWN.to_string()
+        });
+        entry.license = Some(license);
+    }
+}
+
+/// Handle list packages command
+pub fn handle_list_packages(ctx: &Context, config: &Config, args: &ListPackagesArgs) -> Result<()> {
+    if config.repositories.is_empty() && args.repo.is_none() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+
+    let repositories = if let Some(path) = &args.repo {
+        // Process specific repository only
+        let repo = config
+            .find_repository(path)
+            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", path))?;
+
+        vec![repo]
+    } else {
+        // Process all repositories
+        config.repositories.iter().collect()
+    };
+
+    // Extra manifests are listed after their repository, under their own path
+    let repos: Vec<RepoPackages> = repositories
+        .iter()
+        .flat_map(|repo| std::iter::once(repo.path.clone()).chain(repo.extra_manifest_paths()))
+        .map(|path| {
+            let packages = match &args.at_ref {
+                Some(git_ref) => package::list_packages_at(ctx, &path, git_ref),
+                None => package::list_all_packages(ctx, &path),
+            };
+            (path, packages)
+        })
+        .map(|(path, packages)| match packages {
+            Ok(packages) => {
+                let mut packages = args.select(packages);
+                if args.licenses {
+                    add_licenses(ctx, &path, &mut packages);
+                }
+                RepoPackages {
+                    path,
+                    packages: Some(packages),
+                    error: None,
+                }
+            }
+            Err(e) => RepoPackages {
+                path,
+                packages: None,
+                error: Some(format!("{:#}", e)),
+            },
+        })
+        .collect();
+
+    if args.aggregate {
+        for repo in &repos {
+            if let Some(error) = &repo.error {
+                eprintln!("Warning: Skipping {}: {}", repo.path, error);
+            }
+        }
+        let packages = aggregate_packages(&repos, args.sort);
+
+        if args.count {
+            // Each package counts once in every section it appears in
+            let counts = section_counts(packages.iter().flat_map(|p| p.sections.iter().copied()));
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&counts)?);
+            } else {
+                print_counts(&counts, "");
+            }
+        } else if args.json {
+            println!("{}", serde_json::to_string_pretty(&packages)?);
+        } else {
+            print_aggregated(&packages);
+        }
+        return Ok(());
+    }
+
+    if args.json {
+        if args.count {
+            let counts: Vec<serde_json::Value> = repos
+                .iter()
+                .map(|repo| {
+                    serde_json::json!({
+                        "path": repo.path,
+                        "counts": repo.packages.as_ref().map(|p| section_counts(p.iter().map(|e| e.section))),
+                        "error": repo.error,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&counts)?);
+        } else {
+            println!("{}", serde_json::to_string_pretty(&repos)?);
+        }
+        return Ok(());
+    }
+
+    for repo in &repos {
+        println!("Packages in {}:", repo.path);
+
+        let packages = match (&repo.packages, &repo.error) {
+            (Some(packages), _) => packages,
+            (None, error) => {
+                println!(
+                    "  Error listing packages: {}",
+                    error.as_deref().unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        if args.count {
+            print_counts(&section_counts(packages.iter().map(|e| e.section)), "  ");
+        } else if packages.is_empty() {
+            println!("  No packages found");
+        } else {
+            let mut current = None;
+            for entry in packages {
+                if current != Some(entry.section) {
+                    println!("  {}:", section_title(entry.section));
+                    current = Some(entry.section);
+                }
+                match &entry.license {
+                    Some(license) => {
+                        println!("    {}: {} ({})", entry.name, entry.specifier, license)
+                    }
+                    None => println!("    {}: {}", entry.name, entry.specifier),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 레포지토리 클론 명령 처리
+pub fn handle_clone(ctx: &Context, config: &mut Config, args: &CloneArgs) -> Result<()> {
+    let source = github::CloneSource::parse(&args.repository, config.github_host())?;
+    let url = if args.ssh {
+        source.ssh_url()
+    } else {
+        source.url.clone()
+    };
+
+    // Determine output directory
+    let output_dir = args.output.clone().unwrap_or_else(|| source.name.clone());
+    let target = repo::expand_path(&output_dir)?;
+    let occupied = match std::fs::read_dir(&target) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => target.exists(),
+    };
+    if occupied {
+        anyhow::bail!(
+            "Destination already exists and is not empty: {}. Choose another path with --output",
+            output_dir
+        );
+    }
+
+    preflight::check(ctx, &Requirements::git())?;
+
+    // Clone repository
+    github::clone_repository(
+        ctx,
+        &url,
+        &target.to_string_lossy(),
+        args.depth,
+        args.branch.as_deref(),
+    )?;
+
+    let path = repo::canonical_path(&output_dir)
+        .map_err(|e| anyhow::anyhow!("Failed to resolve path: {}", e))?
+        .to_string_lossy()
+        .to_string();
+
+    if args.install {
+        let pkg_manager = config.package_manager(ctx, &path)?.manager;
+        let repository = config.find_repository(&path);
+        let env = config.env_for(repository)?;
+        let pkg_manager = package::check_manager(
+            ctx,
+            &path,
+            &pkg_manager,
+            config.package_manager_version(repository, &pkg_manager),
+            &env,
+        )?;
+        package::run_install_with_manager(ctx, &path, &pkg_manager, &env, false)?;
+    }
+
+    // Add to config
+    if args.add {
+        handle_add_repo(
+            ctx,
+            config,
+            &path,
+            Some(&source.web_url()),
+            Some(&source.name),
+            false,
+            false,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Handle fork command
+pub fn handle_fork(ctx: &Context, config: &mut Config, args: &ForkArgs) -> Result<()> {
+    let source = github::CloneSource::parse(&args.repository, config.github_host())?;
+    preflight::check(ctx, &Requirements::git().with_gh(true))?;
+    let fork_url = github::fork_repository(ctx, &source)?;
+
+    let output_dir = args.output.clone().unwrap_or_else(|| source.name.clone());
+    let target = repo::expand_path(&output_dir)?;
+    let target_str = target.to_string_lossy().to_string();
+
+    if target.join(".git").exists() {
+        println!("Using existing clone: {}", output_dir);
+
+        // A clone of the original gets the fork as origin and the original as upstream
+        let origin = git::remote_url(ctx, &target_str, "origin");
+        let origin_is_source = origin
+            .as_deref()
+            .and_then(github::normalize_github_url)
+            .is_some_and(|url| url == source.web_url());
+        if origin_is_source && git::remote_url(ctx, &target_str, &args.remote_name).is_none() {
+            git::rename_remote(ctx, &target_str, "origin", &args.remote_name)?;
+        }
+        git::set_remote(ctx, &target_str, "origin", &format!("{}.git", fork_url))?;
+    } else {
+        let occupied = match std::fs::read_dir(&target) {
+            Ok(mut entries) => entries.next().is_some(),
+            Err(_) => target.exists(),
+        };
+        if occupied {
+            anyhow::bail!(
+                "Destination already exists and is not a git repository: {}. Choose another path with --output",
+                output_dir
+            );
+        }
+        github::clone_repository(ctx, &format!("{}.git", fork_url), &target_str, None, None)?;
+    }
+
+    git::set_remote(ctx, &target_str, &args.remote_name, &source.url)?;
+    println!(
+        "Remote '{}' points to {}",
+        args.remote_name,
+        source.web_url()
+    );
+
+    if args.add {
+        let path = repo::canonical_path(&output_dir)
+            .map_err(|e| anyhow::anyhow!("Failed to resolve path: {}", e))?
+            .to_string_lossy()
+            .to_string();
+        let repository = Repository {
+            path: path.clone(),
+            github_url: Some(fork_url),
+            alias: Some(source.name.clone()),
+            upstream: Some(source.web_url()),
+            ..Default::default()
+        };
+        config.add_repository(repository)?;
+        println!("Repository added successfully: {}", path);
+    }
+
+    Ok(())
+}
+
+/// Handle notify command
+pub fn handle_notify(config: &Config, report: &str) -> Result<()> {
+    if config.notifications.is_empty() {
+        anyhow::bail!("No notifications configured. Add a [notifications] section to the config");
+    }
+
+    let record = notify::load_report(&repo::expand_path(report)?)?;
+    println!("{}", notify::message(&record, &config.templates));
+    notify::send(&config.notifications, &config.templates, &record);
+    Ok(())
+}
+
+/// Handle config which command
+pub fn handle_config_which(config: &Config) -> Result<()> {
+    println!(
+        "Precedence: --config > {} > global config",
+        config::LOCAL_CONFIG_FILE
+    );
+
+    if let Some(file) = &config.file {
+        println!("--config: {} (used on its own)", file.display());
+        return Ok(());
+    }
+
+    match config::active_profile() {
+        Some(name) => println!(
+            "Profile: {} ({})",
+            name,
+            config::get_config_path()?.display()
+        ),
+        None => println!("Global: {}", config::get_config_path()?.display()),
+    }
+    match &config.local_file {
+        Some(layer) => println!("Local:  {} (changes are saved here)", layer.path.display()),
+        None => println!("Local:  no {} found", config::LOCAL_CONFIG_FILE),
+    }
+    Ok(())
+}
+
+/// Handle config export command
+pub fn handle_config_export(
+    config: &Config,
+    out: Option<&str>,
+    workspace: Option<&str>,
+    strip_local: bool,
+) -> Result<()> {
+    let workspace = match workspace {
+        Some(dir) => repo::expand_path(dir)?,
+        None => share::common_root(config)
+            .ok_or_else(|| anyhow::anyhow!("No repositories configured to export"))?,
+    };
+    let export = share::export(config, &workspace, strip_local);
+    for path in &export.skipped {
+        eprintln!(
+            "Warning: left out {}: it is outside {} and has no github_url to clone it from",
+            path,
+            workspace.display()
+        );
+    }
+
+    let toml = format!(
+        "# Shared mru config; repository paths are relative to ${}\n\
+         # Import with: mru config import <file> --root <directory with the repositories>\n\n{}",
+        share::WORKSPACE_VAR,
+        toml::to_string(&export.config)?
+    );
+    match out {
+        Some(out) => {
+            let path = repo::expand_path(out)?;
+            std::fs::write(&path, toml)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "Exported {} repositories relative to {} to {}",
+                export.config.repositories.len(),
+                workspace.display(),
+                path.display()
+            );
+        }
+        None => print!("{}", toml),
+    }
+    Ok(())
+}
+
+/// Handle config import command
+#[allow(clippy::too_many_arguments)]
+pub fn handle_config_import(
+    ctx: &Context,
+    config: &mut Config,
+    file: &str,
+    replace: bool,
+    root: Option<&str>,
+    prefer: Option<share::Side>,
+    clone: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let path = repo::expand_path(file)?;
+    let mut shared = config::read_file(&path)?;
+    shared
+        .validate()
+        .with_context(|| format!("Invalid config file: {}", path.display()))?;
+    share::resolve_paths(&mut shared, root)?;
+
+    if replace {
+        let count = shared.repositories.len();
+        share::replace(config, shared);
+        println!(
+            "{} the config with {} ({} repositories)",
+            if dry_run { "Would replace" } else { "Replaced" },
+            path.display(),
+            count
+        );
+    } else {
+        let interactive = std::io::stdin().is_terminal();
+        let mut resolve = |conflict: &share::Conflict| -> Result<share::Side> {
+            if let Some(side) = prefer {
+                return Ok(side);
+            }
+            if !interactive && !dry_run {
+                anyhow::bail!(
+                    "{} differs between the configs. Pass --prefer local or --prefer imported",
+                    conflict.what
+                );
+            }
+            println!(
+                "\n{} differs:\n  local:    {}\n  imported: {}",
+                conflict.what,
+                conflict.local.replace('\n', "\n            "),
+                conflict.imported.replace('\n', "\n            ")
+            );
+            // A dry run shows the conflicts without asking
+            Ok(if !dry_run && confirm("Take the imported one?") {
+                share::Side::Imported
+            } else {
+                share::Side::Local
+            })
+        };
+        let summary = share::merge(config, shared, &mut resolve)?;
+        let verb = |done: &'static str, would: &'static str| if dry_run { would } else { done };
+        for what in &summary.added {
+            println!("+ {} {}", verb("Added", "Would add"), what);
+        }
+        for what in &summary.updated {
+            println!(
+                "~ {} {}",
+                verb("Took the imported", "Would take the imported"),
+                what
+            );
+        }
+        for what in &summary.kept {
+            println!("= Kept the local {}", what);
+        }
+        if summary.added.is_empty() && summary.updated.is_empty() {
+            println!("Nothing to import");
+        }
+    }
+    config.validate()?;
+
+    // Clones recorded at their expected path, e.g. `~/work/api`, are made by bootstrap
+    let missing: Vec<&Repository> = config
+        .repositories
+        .iter()
+        .filter(|r| !repo::expand_path(&r.path).is_ok_and(|p| p.exists()))
+        .collect();
+    if !missing.is_empty() && !clone {
+        println!(
+            "\n{} repositories aren't cloned yet; run 'mru bootstrap' or pass --clone:",
+            missing.len()
+        );
+        for repo in &missing {
+            println!("  {}", repo.path);
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    config.save()?;
+    if clone && !missing.is_empty() {
+        handle_bootstrap(
+            ctx,
+            config,
+            &BootstrapArgs {
+                root: None,
+                jobs: 4,
+                dry_run: false,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Handle profile list command
+pub fn handle_profile_list(config: &Config) -> Result<()> {
+    let default = config::default_profile().ok().flatten();
+    let mut names: Vec<String> = match std::fs::read_dir(config::profiles_dir()?) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_stem()?.to_str()?.to_string();
+                (path.extension()? == "toml").then_some(name)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    if names.is_empty() {
+        println!("No profiles. Create one with 'mru profile create <name>' or '--profile <name>'");
+        return Ok(());
+    }
+
+    let active = config::active_profile();
+    let rows: Vec<Vec<String>> = std::iter::once(None)
+        .chain(names.iter().map(Some))
+        .map(|name| {
+            let (label, path) = match name {
+                Some(name) => (name.as_str(), config::profile_path(name)),
+                None => ("default", config::default_config_path()),
+            };
+            let repositories = match path.as_ref().map(|p| config::read_file(p)) {
+                Ok(Ok(profile)) => profile.repositories.len().to_string(),
+                _ if name.is_none() && active.is_none() => config.repositories.len().to_string(),
+                _ => "invalid".to_string(),
+            };
+            let marker = if active == name.map(String::as_str) {
+                "*"
+            } else {
+                ""
+            };
+            let note = if name.is_some() && default.as_ref() == name {
+                "default_profile"
+            } else {
+                ""
+            };
+            vec![
+                marker.to_string(),
+                label.to_string(),
+                repositories,
+                note.to_string(),
+            ]
+        })
+        .collect();
+    table::print(&["", "PROFILE", "REPOSITORIES", ""], &rows);
+    Ok(())
+}
+
+/// Handle profile create command
+pub fn handle_profile_create(name: &str) -> Result<()> {
+    config::validate_profile_name(name)?;
+    let path = config::profile_path(name)?;
+    if path.exists() {
+        anyhow::bail!("Profile '{}' already exists: {}", name, path.display());
+    }
+    std::fs::create_dir_all(config::profiles_dir()?)?;
+    std::fs::write(&path, toml::to_string(&Config::initial())?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Created profile '{}' at {}", name, path.display());
+    Ok(())
+}
+
+/// Handle profile delete command
+pub fn handle_profile_delete(name: &str, yes: bool) -> Result<()> {
+    config::validate_profile_name(name)?;
+    if config::active_profile() == Some(name) {
+        anyhow::bail!("Profile '{}' is in use; select another to delete it", name);
+    }
+    let path = config::profile_path(name)?;
+    if !path.exists() {
+        anyhow::bail!("No profile named '{}'", name);
+    }
+    let state = config::profiles_dir()?.join(name);
+    if !yes
+        && !confirm(&format!(
+            "Delete profile '{}' with its campaigns and history?",
+            name
+        ))
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+    std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+    if state.exists() {
+        std::fs::remove_dir_all(&state)
+            .with_context(|| format!("Failed to delete {}", state.display()))?;
+    }
+    println!("Deleted profile '{}'", name);
+    Ok(())
+}
+
+/// Handle profile copy command
+pub fn handle_profile_copy(from: &str, to: &str) -> Result<()> {
+    let source = match from {
+        "default" => config::default_config_path()?,
+        name => {
+            config::validate_profile_name(name)?;
+            config::profile_path(name)?
+        }
+    };
+    if !source.exists() {
+        anyhow::bail!("No profile named '{}'", from);
+    }
+    config::validate_profile_name(to)?;
+    let target = config::profile_path(to)?;
+    if target.exists() {
+        anyhow::bail!("Profile '{}' already exists: {}", to, target.display());
+    }
+    // Parse it, so a broken config isn't copied
+    let mut copy = config::read_file(&source)?;
+    copy.default_profile = None;
+    std::fs::create_dir_all(config::profiles_dir()?)?;
+    std::fs::write(&target, toml::to_string(&copy)?)
+        .with_context(|| format!("Failed to write {}", target.display()))?;
+    println!(
+        "Copied '{}' to profile '{}' at {}",
+        from,
+        to,
+        target.display()
+    );
+    Ok(())
+}
+
+/// Handle templates test command
+pub fn handle_templates_test(config: &Config, repo: Option<&str>) -> Result<()> {
+    let sample = match repo {
+        Some(name) => config
+            .repositories
+            .iter()
+            .find(|r| r.is_named(name))
+            .ok_or_else(|| anyhow::anyhow!("No configured repository named '{}'", name))?
+            .clone(),
+        None => config.repositories.first().cloned().unwrap_or(Repository {
+            path: "~/src/web-app".to_string(),
+            ..Default::default()
+        }),
+    };
+    let (package, old, version) = ("lodash", "4.17.20", "4.17.21");
+    let created_at = campaign::now();
+    let vars = TemplateContext::new(package, version)
+        .with_style(&CommitStyle::default())
+        .with(
+            "date",
+            &template::format_date(created_at, config.templates.date_format()),
+        )
+        .with(
+            "hash",
+            &template::short_hash(&[package, version, &created_at.to_string()]),
+        )
+        .with(
+            "campaign",
+            &format!(
+                "{}-{}",
+                git::update_branch_name(package, version),
+                created_at
+            ),
+        )
+        .with_repository(&sample)
+        .with("old", old)
+        .with("base", "main")
+        .with("summary", "1 PR opened");
+    println!(
+        "Sample: {} {} → {} in {}\n",
+        package, old, version, sample.path
+    );
+
+    let templates = template::configured(config);
+    if templates.is_empty() {
+        println!("No templates configured; add a [templates] section to the config");
+    }
+    let mut problems = 0;
+    for (label, text, scope) in &templates {
+        match template::validate(label, text, *scope) {
+            Ok(()) => println!("✓ {}\n    {}", label, vars.render(text)),
+            Err(e) => {
+                problems += 1;
+                println!("✗ {}", e);
+            }
+        }
+    }
+    if let Err(e) = template::validate_date_format(config.templates.date_format()) {
+        problems += 1;
+        println!("✗ {}", e);
+    }
+
+    println!("\nVariables:");
+    let rows: Vec<Vec<String>> = template::VARIABLES
+        .iter()
+        .map(|v| vec![format!("{{{}}}", v.name), v.description.to_string()])
+        .collect();
+    table::print(&["NAME", "VALUE"], &rows);
+
+    if problems > 0 {
+        anyhow::bail!(
+            "{} template setting{} need{} fixing",
+            problems,
+            if problems == 1 { "" } else { "s" },
+            if problems == 1 { "s" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/// Handle policy list command
+pub fn handle_policy_list(config: &Config) -> Result<()> {
+    if config.policies.is_empty() {
+        println!("No policies configured");
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = config
+        .policies
+        .iter()
+        .map(|p| {
+            let repos = if p.repos.is_empty() {
+                "all".to_string()
+            } else {
+                p.repos.join(", ")
+            };
+            vec![
+                p.name.clone(),
+                p.package.clone(),
+                repos,
+                p.rule(),
+                p.reason.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    table::print(
+        &["NAME", "PACKAGE", "REPOSITORIES", "RULE", "REASON"],
+        &rows,
+    );
+    Ok(())
+}
+
+/// Handle policy add command
+pub fn handle_policy_add(config: &mut Config, mut policy: Policy) -> Result<()> {
+    policy.validate()?;
+    policy.local = config.local_file.is_some();
+    if config.policies.iter().any(|p| p.name == policy.name) {
+        anyhow::bail!("Policy already exists: {}", policy.name);
+    }
+
+    println!(
+        "Policy added: {} ({} {})",
+        policy.name,
+        policy.package,
+        policy.rule()
+    );
+    config.policies.push(policy);
+    config.save()
+}
+
+/// Handle policy remove command
+pub fn handle_policy_remove(config: &mut Config, name: &str) -> Result<()> {
+    if let Some(policy) = config.policies.iter().find(|p| p.name == name) {
+        config.check_writable(policy.local, &format!("Policy '{}'", name))?;
+    }
+    let initial_len = config.policies.len();
+    config.policies.retain(|p| p.name != name);
+    if config.policies.len() == initial_len {
+        anyhow::bail!("Policy not found: {}", name);
+    }
+
+    config.save()?;
+    println!("Policy removed: {}", name);
+    Ok(())
+}
+
+/// What bootstrap found or did for one repository
+#[derive(Debug, PartialEq, Eq)]
+enum BootstrapResult {
+    /// Exists and points at the configured remote
+    Ok,
+    Cloned,
+    WouldClone,
+    /// Exists but doesn't match the configured remote
+    Mismatch(String),
+    /// Missing and can't be cloned
+    Missing(String),
+    Failed(String),
+}
+
+/// Clone a missing repository, or check the remote of an existing one
+fn bootstrap_repository(
+    ctx: &Context,
+    repo: &Repository,
+    target: &str,
+    dry_run: bool,
+) -> BootstrapResult {
+    let expected = repo.github_url.as_deref().map(|url| {
+        github::normalize_github_url(url).unwrap_or_else(|| url.trim_end_matches('/').to_string())
+    });
+
+    let path = match repo::expand_path(target) {
+        Ok(path) => path,
+        Err(e) => return BootstrapResult::Failed(format!("{:#}", e)),
+    };
+
+    if path.exists() {
+        if !path.join(".git").exists() {
+            return BootstrapResult::Mismatch("exists but is not a git repository".to_string());
+        }
+        let Some(expected) = expected else {
+            return BootstrapResult::Ok;
+        };
+        return match git::remote_url(ctx, target, "origin") {
+            Some(origin) if github::normalize_github_url(&origin).as_ref() == Some(&expected) => {
+                BootstrapResult::Ok
+            }
+            Some(origin) => {
+                BootstrapResult::Mismatch(format!("origin is {}, expected {}", origin, expected))
+            }
+            None => BootstrapResult::Mismatch(format!("no origin remote, expected {}", expected)),
+        };
+    }
+
+    let Some(url) = expected else {
+        return BootstrapResult::Missing("no github_url to clone from".to_string());
+    };
+    if dry_run {
+        return BootstrapResult::WouldClone;
+    }
+
+    match github::clone_repository(
+        ctx,
+        &format!("{}.git", url),
+        &path.to_string_lossy(),
+        None,
+        None,
+    ) {
+        Ok(()) => BootstrapResult::Cloned,
+        Err(e) => BootstrapResult::Failed(format!("{:#}", e)),
+    }
+}
+
+/// Handle bootstrap command
+pub fn handle_bootstrap(ctx: &Context, config: &mut Config, args: &BootstrapArgs) -> Result<()> {
+    if config.repositories.is_empty() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+
+    if !args.dry_run {
+        preflight::check(ctx, &Requirements::git())?;
+    }
+
+    // Where each repository should live on this machine
+    let targets: Vec<(Repository, String)> = config
+        .repositories
+        .iter()
+        .map(|repo| {
+            let target = match &args.root {
+                Some(root) => std::path::Path::new(root)
+                    .join(context::repo_name(&repo.path))
+                    .to_string_lossy()
+                    .to_string(),
+                None => repo.path.clone(),
+            };
+            (repo.clone(), target)
+        })
+        .collect();
+
+    let results = parallel::map(&targets, args.jobs, |(repo, target)| {
+        bootstrap_repository(ctx, repo, target, args.dry_run)
+    });
+
+    let mut problems = 0;
+    let mut rows = Vec::new();
+    for ((_, target), result) in targets.iter().zip(&results) {
+        let (status, detail) = match result {
+            BootstrapResult::Ok => ("ok", String::new()),
+            BootstrapResult::Cloned => ("cloned", String::new()),
+            BootstrapResult::WouldClone => ("would clone", String::new()),
+            BootstrapResult::Mismatch(detail) => ("mismatch", detail.clone()),
+            BootstrapResult::Missing(detail) => ("missing", detail.clone()),
+            BootstrapResult::Failed(detail) => ("failed", detail.clone()),
+        };
+        if !matches!(
+            result,
+            BootstrapResult::Ok | BootstrapResult::Cloned | BootstrapResult::WouldClone
+        ) {
+            problems += 1;
+        }
+        rows.push(vec![target.clone(), status.to_string(), detail]);
+    }
+    println!();
+    table::print(&["REPOSITORY", "STATUS", "DETAIL"], &rows);
+
+    // Record where the repositories now live
+    if args.root.is_some() && !args.dry_run {
+        let mut moved = false;
+        for (repo, ((_, target), result)) in config
+            .repositories
+            .iter_mut()
+            .zip(targets.iter().zip(&results))
+        {
+            if matches!(result, BootstrapResult::Ok | BootstrapResult::Cloned)
+                && !repo::same_path(&repo.path, target)
+                && (repo.local || config.local_file.is_none())
+            {
+                repo.path = target.clone();
+                moved = true;
+            }
+        }
+        if moved {
+            config.save()?;
+            println!("\nUpdated repository paths in the config");
+        }
+    }
+
+    if problems > 0 {
+        anyhow::bail!("{} repositories need attention", problems);
+    }
+
+    Ok(())
+}
+
+/// Handle set package manager command
+pub fn handle_set_package_manager(config: &mut Config, name: &str) -> Result<()> {
+    if !package::PACKAGE_MANAGERS.contains(&name) {
+        anyhow::bail!(
             "Invalid package manager. Must be one of: {:?}",
-            valid_managers
+            package::PACKAGE_MANAGERS
         );
     }
 
@@ -355,15 +6310,776 @@ pub fn handle_set_package_manager(config: &mut Config, name: &str) -> Result<()>
 
 /// Ask user if they want to continue
 fn prompt_continue() -> bool {
-    use std::io::{self, Write};
+    confirm("Continue with remaining repositories?")
+}
+
+/// Set once stdin has been read for something other than answers (`update --stdin`)
+static PROMPTS_FROM_TTY: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// The terminal, to read answers from when stdin is taken
+#[cfg(unix)]
+fn open_terminal() -> std::io::Result<std::fs::File> {
+    std::fs::File::open("/dev/tty")
+}
+
+/// The console's input, to read answers from when stdin is taken; Windows only lets it
+/// be opened for reading and writing
+#[cfg(not(unix))]
+fn open_terminal() -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("CONIN$")
+}
+
+/// Ask a yes/no question, defaulting to no
+fn confirm(question: &str) -> bool {
+    use std::io::{self, BufRead, Write};
 
-    print!("Continue with remaining repositories? [y/N]: ");
+    print!("{} [y/N]: ", question);
     io::stdout().flush().unwrap();
 
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_err() {
+    let read = if PROMPTS_FROM_TTY.load(std::sync::atomic::Ordering::Relaxed) {
+        open_terminal().and_then(|tty| io::BufReader::new(tty).read_line(&mut input))
+    } else {
+        io::stdin().read_line(&mut input)
+    };
+    if read.is_err() {
         return false;
     }
 
     input.trim().eq_ignore_ascii_case("y")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::RepoState;
+    use crate::runner::MockRunner;
+    use std::sync::Arc;
+
+    fn repository(path: &str, groups: &[&str]) -> Repository {
+        Repository {
+            path: path.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn propagation_body_links_the_release_and_changes() {
+        let ctx = Context::new();
+        let source = Repository {
+            path: "/w/design-system".to_string(),
+            github_url: Some("https://github.com/corp/design-system".to_string()),
+            alias: Some("ds".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            propagation_body(&ctx, &source, "@corp/ds", "2.3.0"),
+            "Updates @corp/ds to 2.3.0, published from ds.\n\n\
+             Release: https://github.com/corp/design-system/releases/tag/v2.3.0\n\
+             Changes: https://github.com/corp/design-system/compare/v{old}...v2.3.0"
+        );
+    }
+
+    #[test]
+    fn repo_filter_selects_by_name_path_and_group() {
+        let repos = vec![
+            repository("/mru-test/web", &["frontend"]),
+            repository("/mru-test/api", &["backend"]),
+            repository("/mru-test/admin", &["frontend"]),
+        ];
+        let paths = |filter: RepoFilter| -> Vec<String> {
+            filter
+                .apply(&repos)
+                .iter()
+                .map(|r| r.path.clone())
+                .collect()
+        };
+
+        assert_eq!(paths(RepoFilter::default()).len(), 3);
+        assert_eq!(
+            paths(RepoFilter {
+                only: vec!["api".to_string(), "/mru-test/web/".to_string()],
+                ..Default::default()
+            }),
+            vec!["/mru-test/web", "/mru-test/api"]
+        );
+        assert_eq!(
+            paths(RepoFilter {
+                exclude: vec!["admin".to_string()],
+                group: Some("frontend".to_string()),
+                ..Default::default()
+            }),
+            vec!["/mru-test/web"]
+        );
+    }
+
+    #[test]
+    fn exec_on_error_abort_starts_no_more_repositories_after_a_failure() {
+        let runner =
+            Arc::new(MockRunner::new().respond_once("make check", CommandOutput::failed("boom")));
+        let ctx = Context::with_runner(runner.clone());
+        let config = Config::default();
+        let (web, api) = (repository("/work/web", &[]), repository("/work/api", &[]));
+        let repos = [&web, &api];
+        let command = ["make".to_string(), "check".to_string()];
+
+        let results = run_in_parallel(&ctx, &config, &repos, &[0, 1], &command, 1, true);
+        assert!(results[0]
+            .1
+            .as_ref()
+            .unwrap()
+            .as_ref()
+            .is_ok_and(|o| !o.success()));
+        assert!(results[1].1.is_none());
+        assert_eq!(runner.calls().len(), 1);
+
+        let results = run_in_parallel(&ctx, &config, &repos, &[0, 1], &command, 1, false);
+        assert!(results.iter().all(|(_, result)| result.is_some()));
+    }
+
+    #[test]
+    fn campaign_progress_tells_merged_from_pending() {
+        let v = |s: &str| Some(s.to_string());
+        assert_eq!(
+            campaign_progress(
+                RepoStatus::Completed,
+                &v("^4.17.20"),
+                &v("4.17.21"),
+                &v("4.17.21")
+            ),
+            "merged"
+        );
+        assert_eq!(
+            campaign_progress(
+                RepoStatus::Completed,
+                &v("^4.17.20"),
+                &v("4.17.21"),
+                &v("^4.17.20")
+            ),
+            "pending"
+        );
+        // The branch was deleted after merging
+        assert_eq!(
+            campaign_progress(RepoStatus::Completed, &v("^4.17.20"), &None, &v("4.17.21")),
+            "merged"
+        );
+        assert_eq!(
+            campaign_progress(RepoStatus::Failed, &v("^4.17.20"), &None, &v("^4.17.20")),
+            "failed"
+        );
+    }
+
+    #[test]
+    fn repo_path_replaces_the_configured_repositories_after_checking_it() {
+        let ctx = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let config = Config {
+            repositories: vec![repository("/mru-test/web", &["frontend"])],
+            ..Default::default()
+        };
+        assert_eq!(
+            ad_hoc_config(&ctx, &config, None)
+                .unwrap()
+                .repositories
+                .len(),
+            1
+        );
+
+        let err = ad_hoc_config(&ctx, &config, Some(&path)).unwrap_err();
+        assert!(err.to_string().contains("is not a git repository"));
+        ctx.runner
+            .output(
+                &git::git_command()
+                    .current_dir(dir.path())
+                    .args(["init", "--quiet"]),
+            )
+            .unwrap();
+        let err = ad_hoc_config(&ctx, &config, Some(&path)).unwrap_err();
+        assert_eq!(err.to_string(), format!("{} has no package.json", path));
+
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        let ad_hoc = ad_hoc_config(&ctx, &config, Some(&path)).unwrap();
+        assert_eq!(ad_hoc.repositories.len(), 1);
+        assert_eq!(ad_hoc.repositories[0].path, path);
+        assert!(ad_hoc.repositories[0].ad_hoc);
+
+        // A configured repository keeps its settings and isn't ad hoc
+        let config = Config {
+            repositories: vec![repository(&path, &["frontend"])],
+            ..Default::default()
+        };
+        let configured = ad_hoc_config(&ctx, &config, Some(&path)).unwrap();
+        assert!(!configured.repositories[0].ad_hoc);
+        assert_eq!(configured.repositories[0].groups, vec!["frontend"]);
+    }
+
+    fn completed_repo(pr_url: &str) -> RepoState {
+        RepoState {
+            path: "/mru-test/web".to_string(),
+            status: RepoStatus::Completed,
+            error: None,
+            output: None,
+            pr_url: Some(pr_url.to_string()),
+            old_version: Some("^17.0.2".to_string()),
+            original_branch: Some("develop".to_string()),
+            fast_forwarded: None,
+            diffstat: None,
+            duration_ms: None,
+            steps: Vec::new(),
+            batch: None,
+            warnings: Vec::new(),
+            milestone: None,
+            project: None,
+            license: None,
+            target_version: None,
+            resolve_preview: None,
+            package_managers: Vec::new(),
+            ad_hoc: false,
+            scanned_version: None,
+        }
+    }
+
+    #[test]
+    fn undo_leaves_merged_prs_alone_and_restores_original_branch() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "gh pr view https://github.com/o/r/pull/1",
+                    CommandOutput::ok("MERGED\n"),
+                )
+                .respond(
+                    "gh pr view https://github.com/o/r/pull/2",
+                    CommandOutput::ok("OPEN\n"),
+                )
+                .respond(
+                    "git symbolic-ref --quiet --short HEAD",
+                    CommandOutput::ok("update-react-18.2.0\n"),
+                )
+                .respond(
+                    "git for-each-ref --format=%(refname) refs/heads/",
+                    CommandOutput::ok("* update-react-18.2.0\n"),
+                ),
+        );
+        let ctx = Context::with_runner(runner.clone());
+
+        let merged = completed_repo("https://github.com/o/r/pull/1");
+        assert!(plan_undo(&ctx, &merged, "update-react-18.2.0")
+            .unwrap()
+            .is_none());
+
+        let open = completed_repo("https://github.com/o/r/pull/2");
+        let plan = plan_undo(&ctx, &open, "update-react-18.2.0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            plan.close_pr.as_deref(),
+            Some("https://github.com/o/r/pull/2")
+        );
+        assert_eq!(plan.checkout.as_deref(), Some("develop"));
+        assert!(plan.delete_local_branch);
+
+        apply_undo(&ctx, &plan, "update-react-18.2.0", "Rolled back").unwrap();
+        let calls = runner.calls();
+        assert_eq!(
+            calls[calls.len() - 4..],
+            [
+                "gh pr close https://github.com/o/r/pull/2 --comment Rolled back",
+                "git push origin --delete update-react-18.2.0",
+                "git checkout develop",
+                "git branch -D update-react-18.2.0",
+            ]
+        );
+    }
+
+    fn entry(path: &str, version: Option<&str>) -> CompareEntry {
+        CompareEntry {
+            path: path.to_string(),
+            version: version.map(|v| v.to_string()),
+            error: None,
+            alias: None,
+        }
+    }
+
+    #[test]
+    fn compare_report_groups_versions_and_checks_consistency() {
+        let entries = || {
+            vec![
+                entry("/a", Some("4.17.21")),
+                entry("/b", Some("^4.17.0")),
+                entry("/c", None),
+                entry("/d", Some("^4.17.0")),
+            ]
+        };
+
+        let report = CompareReport::new(entries(), "lodash", false);
+        assert_eq!(
+            report.verdict(),
+            "3 distinct versions found (^4.17.0 ×2, 4.17.21 ×1, not found ×1)"
+        );
+        assert!(report.check(false).is_some());
+
+        let consistent = vec![entry("/a", Some("^4.17.0")), entry("/b", None)];
+        let report = CompareReport::new(consistent, "lodash", false);
+        assert!(report.check(false).is_none());
+        assert!(report.check(true).is_some());
+
+        let report = CompareReport::new(
+            vec![entry("/a", Some("^1.2.3")), entry("/b", Some("1.2.3"))],
+            "zod",
+            true,
+        );
+        assert_eq!(report.found_versions(), 1);
+    }
+
+    #[test]
+    fn compare_matches_npm_aliases_of_the_package() {
+        let repo_versions: Vec<(String, Result<BTreeMap<String, String>>)> = vec![
+            (
+                "/a".to_string(),
+                Ok(BTreeMap::from([(
+                    "@scope/pkg".to_string(),
+                    "^1.0.0".to_string(),
+                )])),
+            ),
+            (
+                "/b".to_string(),
+                Ok(BTreeMap::from([(
+                    "pkg-next".to_string(),
+                    "npm:@scope/pkg@^1.0.0".to_string(),
+                )])),
+            ),
+        ];
+
+        let report = CompareReport::new(
+            compare_entries(&repo_versions, "@scope/pkg"),
+            "@scope/pkg",
+            false,
+        );
+        assert_eq!(report.found_versions(), 1);
+        assert_eq!(report.versions[0].repos, ["/a", "/b"]);
+        assert_eq!(
+            report.versions[0].aliases.get("/b").map(String::as_str),
+            Some("pkg-next → @scope/pkg")
+        );
+
+        let entries = compare_entries(&repo_versions, "pkg-next");
+        assert_eq!(entries[1].version.as_deref(), Some("^1.0.0"));
+        assert!(!unused_everywhere(&repo_versions, "@scope/pkg"));
+    }
+
+    #[test]
+    fn drift_report_lists_only_differing_packages_most_disagreeing_first() {
+        let repo = |deps: &[(&str, &str)]| -> Result<BTreeMap<String, String>> {
+            Ok(deps
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect())
+        };
+        let repo_versions = vec![
+            (
+                "/a".to_string(),
+                repo(&[("react", "^18.0.0"), ("zod", "^3.0.0"), ("ts", "5.0.0")]),
+            ),
+            (
+                "/b".to_string(),
+                repo(&[("react", "^17.0.0"), ("zod", "^3.1.0"), ("ts", "5.0.0")]),
+            ),
+            (
+                "/c".to_string(),
+                repo(&[("react", "^16.0.0"), ("zod", "^3.1.0"), ("only", "1.0.0")]),
+            ),
+        ];
+
+        let reports = drift_reports(&repo_versions, false);
+        let summary: Vec<(&str, usize)> = reports
+            .iter()
+            .map(|r| (r.package.as_str(), r.disagreeing()))
+            .collect();
+        assert_eq!(summary, vec![("react", 2), ("zod", 1)]);
+    }
+
+    #[test]
+    fn check_reports_unreachable_repos_drift_and_policy_violations() {
+        let repo_versions: Vec<(String, Result<BTreeMap<String, String>>)> = vec![
+            (
+                "/a".to_string(),
+                Ok(BTreeMap::from([(
+                    "react".to_string(),
+                    "^18.0.0".to_string(),
+                )])),
+            ),
+            (
+                "/b".to_string(),
+                Ok(BTreeMap::from([(
+                    "react".to_string(),
+                    "^17.0.0".to_string(),
+                )])),
+            ),
+            (
+                "/gone".to_string(),
+                Err(anyhow::anyhow!("No such directory")),
+            ),
+        ];
+        let config = Config {
+            repositories: ["/a", "/b", "/gone"]
+                .map(|path| Repository {
+                    path: path.to_string(),
+                    ..Default::default()
+                })
+                .to_vec(),
+            policies: vec![Policy {
+                name: "react-17".to_string(),
+                package: "react".to_string(),
+                allowed: Some("<18".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let args = CheckArgs {
+            packages: Vec::new(),
+            all: true,
+            policies: true,
+            normalize: false,
+            jobs: 1,
+            junit: None,
+            json: None,
+        };
+
+        let results = check_results(&config, &repo_versions, &args);
+        let failed: Vec<(&str, &str)> = results
+            .iter()
+            .filter(|r| r.failure.is_some())
+            .map(|r| (r.check, r.name.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("repo reachable", "/gone"),
+                ("version consistency", "react"),
+                ("policy", "react-17"),
+            ]
+        );
+
+        let xml = junit_report(&results);
+        assert!(xml.contains(r#"tests="5" failures="3""#));
+        assert!(xml.contains(r#"<testcase classname="repo reachable" name="/a"/>"#));
+    }
+
+    #[test]
+    fn fork_reuses_existing_clone_of_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "gh api --hostname github.com user",
+                    CommandOutput::ok("me\n"),
+                )
+                .respond(
+                    "git remote get-url origin",
+                    CommandOutput::ok("git@github.com:acme/app.git\n"),
+                )
+                .respond(
+                    "git remote get-url upstream",
+                    CommandOutput::failed("error: No such remote 'upstream'"),
+                ),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let args = ForkArgs {
+            repository: "acme/app".to_string(),
+            output: Some(dir.path().to_string_lossy().to_string()),
+            add: false,
+            remote_name: "upstream".to_string(),
+        };
+
+        handle_fork(&ctx, &mut Config::default(), &args).unwrap();
+
+        let calls = runner.calls();
+        assert!(!calls.iter().any(|c| c.starts_with("git clone")));
+        for expected in [
+            "git remote rename origin upstream",
+            "git remote set-url origin https://github.com/me/app.git",
+            "git remote add upstream https://github.com/acme/app.git",
+        ] {
+            assert!(calls.iter().any(|c| c == expected), "{:?}", calls);
+        }
+    }
+
+    #[test]
+    fn ready_marks_only_open_drafts_with_passing_checks() {
+        let pr = |number: u64, state: &str, draft: bool, checks: &str| {
+            format!(
+                r#"{{"pullRequests":{{"nodes":[{{"number":{},"url":"https://github.com/acme/r{}/pull/{}","state":"{}","isDraft":{},"commits":{{"nodes":[{{"commit":{{"statusCheckRollup":{}}}}}]}}}}]}}}}"#,
+                number, number, number, state, draft, checks
+            )
+        };
+        let response = format!(
+            r#"{{"data":{{"pr0":{},"pr1":{},"pr2":{},"pr3":{},"pr4":{{"pullRequests":{{"nodes":[]}}}}}}}}"#,
+            pr(1, "OPEN", true, r#"{"state":"SUCCESS"}"#),
+            pr(2, "OPEN", true, r#"{"state":"PENDING"}"#),
+            pr(3, "OPEN", false, r#"{"state":"SUCCESS"}"#),
+            pr(4, "CLOSED", true, "null"),
+        );
+        let runner =
+            Arc::new(MockRunner::new().respond("gh api graphql", CommandOutput::ok(&response)));
+        let ctx = Context::with_runner(runner.clone());
+        let config = Config {
+            repositories: (0..5)
+                .map(|i| Repository {
+                    path: format!("/work/r{}", i),
+                    github_url: Some(format!("https://github.com/acme/r{}", i)),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let args = ReadyArgs {
+            branch: "update-react-18.2.0".to_string(),
+            only_green: true,
+            filter: RepoFilter::default(),
+            dry_run: false,
+        };
+
+        handle_ready(&ctx, &config, &args).unwrap();
+
+        let readied: Vec<String> = runner
+            .calls()
+            .into_iter()
+            .filter(|c| c.starts_with("gh pr ready"))
+            .collect();
+        assert_eq!(readied, ["gh pr ready https://github.com/acme/r1/pull/1"]);
+
+        // Failing checks keep a draft as it is, and a merged PR is only reported
+        let failing = github::PrSummary {
+            branch: args.branch.clone(),
+            number: 5,
+            url: "https://github.com/acme/r5/pull/5".to_string(),
+            state: "OPEN".to_string(),
+            draft: true,
+            checks: Some("FAILURE".to_string()),
+        };
+        assert_eq!(
+            mark_ready(&ctx, &failing, &args).unwrap(),
+            "left as draft: checks failing"
+        );
+        let merged = github::PrSummary {
+            state: "MERGED".to_string(),
+            ..failing.clone()
+        };
+        assert_eq!(mark_ready(&ctx, &merged, &args).unwrap(), "merged");
+    }
+
+    #[test]
+    fn add_repo_checks_the_repository_layout_with_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("app");
+        std::fs::create_dir_all(root.join("packages/ui")).unwrap();
+        // A linked worktree or submodule checkout has a .git file
+        std::fs::write(root.join(".git"), "gitdir: /srv/app.git/worktrees/app\n").unwrap();
+        let root = root.to_string_lossy().to_string();
+        let nested = format!("{}/packages/ui", root);
+        let layout = |answers: &str| {
+            MockRunner::new()
+                .respond(
+                    "git rev-parse --is-bare-repository --is-inside-work-tree",
+                    CommandOutput::ok(answers),
+                )
+                .respond(
+                    "git rev-parse --show-toplevel",
+                    CommandOutput::ok(&format!("{}\n", root)),
+                )
+                .respond("git remote get-url origin", CommandOutput::failed(""))
+        };
+        let mut config = Config {
+            file: Some(dir.path().join("config.toml")),
+            ..Default::default()
+        };
+
+        let ctx = Context::with_runner(Arc::new(layout("true\nfalse\n")));
+        let err =
+            handle_add_repo(&ctx, &mut config, &root, None, None, false, false, false).unwrap_err();
+        assert!(err.to_string().contains("is a bare repository"));
+
+        let ctx = Context::with_runner(Arc::new(layout("false\nfalse\n")));
+        let err =
+            handle_add_repo(&ctx, &mut config, &root, None, None, false, false, false).unwrap_err();
+        assert!(err.to_string().contains("inside a .git directory"));
+
+        let ctx = Context::with_runner(Arc::new(layout("false\ntrue\n")));
+        let err = handle_add_repo(&ctx, &mut config, &nested, None, None, false, false, false)
+            .unwrap_err();
+        assert!(err.to_string().contains("pass --use-toplevel"));
+        assert!(config.repositories.is_empty());
+
+        // No package.json and no origin remote
+        let err =
+            handle_add_repo(&ctx, &mut config, &root, None, None, false, true, false).unwrap_err();
+        assert!(err.to_string().contains("(--strict)"));
+        assert!(config.repositories.is_empty());
+
+        handle_add_repo(&ctx, &mut config, &nested, None, None, true, false, false).unwrap();
+        handle_add_repo(&ctx, &mut config, &root, None, None, false, false, false).unwrap_err();
+        let paths: Vec<&str> = config
+            .repositories
+            .iter()
+            .map(|r| r.path.as_str())
+            .collect();
+        assert_eq!(paths, vec![root.as_str()]);
+
+        let err = handle_add_repo(
+            &ctx,
+            &mut config,
+            &format!("{}/missing", root),
+            None,
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not a directory"));
+    }
+
+    #[test]
+    fn bootstrap_clones_missing_repositories_and_flags_wrong_remotes() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing");
+        std::fs::create_dir_all(existing.join(".git")).unwrap();
+        let runner = Arc::new(MockRunner::new().respond(
+            "git remote get-url origin",
+            CommandOutput::ok("git@github.com:someone/else.git\n"),
+        ));
+        let ctx = Context::with_runner(runner.clone());
+        let repo = |url: Option<&str>| Repository {
+            github_url: url.map(|u| u.to_string()),
+            ..Default::default()
+        };
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+
+        assert_eq!(
+            bootstrap_repository(
+                &ctx,
+                &repo(Some("https://github.com/acme/app")),
+                &existing.to_string_lossy(),
+                false
+            ),
+            BootstrapResult::Mismatch(
+                "origin is git@github.com:someone/else.git, expected https://github.com/acme/app"
+                    .to_string()
+            )
+        );
+        assert!(matches!(
+            bootstrap_repository(&ctx, &repo(None), &missing, false),
+            BootstrapResult::Missing(_)
+        ));
+        assert_eq!(
+            bootstrap_repository(
+                &ctx,
+                &repo(Some("https://github.com/acme/app")),
+                &missing,
+                true
+            ),
+            BootstrapResult::WouldClone
+        );
+        assert_eq!(
+            bootstrap_repository(
+                &ctx,
+                &repo(Some("https://github.com/acme/app")),
+                &missing,
+                false
+            ),
+            BootstrapResult::Cloned
+        );
+        assert_eq!(
+            runner.calls().last().unwrap(),
+            &format!("git clone -- https://github.com/acme/app.git {}", missing)
+        );
+    }
+
+    #[test]
+    fn merge_merges_only_open_pull_requests() {
+        let listed = |number: u64, state: &str| {
+            CommandOutput::ok(&format!(
+                r#"[{{"number":{},"url":"https://github.com/acme/r{}/pull/{}","state":"{}"}}]"#,
+                number, number, number, state
+            ))
+        };
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond("gh pr list --repo acme/r0", listed(1, "OPEN"))
+                .respond("gh pr list --repo acme/r1", listed(2, "MERGED"))
+                .respond("gh pr list --repo acme/r2", CommandOutput::ok("[]")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let config = Config {
+            repositories: (0..3)
+                .map(|i| Repository {
+                    path: format!("/work/r{}", i),
+                    github_url: Some(format!("https://github.com/acme/r{}", i)),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut args = MergeArgs {
+            branch: "update-react-18.2.0".to_string(),
+            method: MergeMethod::Rebase,
+            delete_branch: true,
+            admin: false,
+            filter: RepoFilter::default(),
+            dry_run: true,
+        };
+        let options = MergeOptions {
+            method: args.method,
+            delete_branch: args.delete_branch,
+            admin: args.admin,
+        };
+        let results: Vec<(String, String)> = config
+            .repositories
+            .iter()
+            .map(|repo| merge_branch(&ctx, repo, None, &args, &options).unwrap())
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("#1".to_string(), "would merge".to_string()),
+                ("#2".to_string(), "merged".to_string()),
+                (String::new(), "no pull request".to_string()),
+            ]
+        );
+
+        // The repository without a PR fails the run, after the others were merged
+        args.dry_run = false;
+        assert!(handle_merge(&ctx, &config, &args).is_err());
+        let merged: Vec<String> = runner
+            .calls()
+            .into_iter()
+            .filter(|c| c.starts_with("gh pr merge"))
+            .collect();
+        assert_eq!(
+            merged,
+            ["gh pr merge 1 --repo acme/r0 --rebase --delete-branch"]
+        );
+    }
+
+    #[test]
+    fn open_prs_stops_at_the_limit() {
+        let runner = Arc::new(MockRunner::new());
+        let ctx = Context::with_runner(runner.clone());
+        let urls: Vec<String> = (1..=4)
+            .map(|n| format!("https://github.com/acme/app/pull/{}", n))
+            .collect();
+
+        open_prs(&ctx, &urls, 2);
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[0].ends_with(" https://github.com/acme/app/pull/1"));
+        assert!(calls[1].ends_with(" https://github.com/acme/app/pull/2"));
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context as _, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::package::matches_glob;
+
+/// Where GitHub looks for a CODEOWNERS file, in order; the first one found is used
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Ownership rules of a CODEOWNERS file
+#[derive(Debug, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// Pattern split on `/`, starting with `**` unless it is anchored to the root
+    segments: Vec<String>,
+    /// Pattern ended with `/`, so it only matches files inside a directory
+    dir_only: bool,
+    /// Owners as written, e.g. `@octocat`, `@acme/web` or an email address
+    owners: Vec<String>,
+}
+
+impl Rule {
+    fn parse(pattern: &str, owners: Vec<String>) -> Self {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        // Like gitignore, a slash anywhere but the end anchors the pattern to the root
+        let anchored = pattern.contains('/');
+        let mut segments: Vec<String> = pattern
+            .trim_start_matches('/')
+            .split('/')
+            .map(str::to_string)
+            .collect();
+        if !anchored {
+            segments.insert(0, "**".to_string());
+        }
+        Rule {
+            segments,
+            dir_only,
+            owners,
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let path: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        let last = self.segments.last().map(String::as_str).unwrap_or_default();
+        // A pattern naming a directory covers everything below it, but unlike gitignore,
+        // `docs/*` only matches the files directly in `docs`
+        let covers_directories = self.dir_only || !last.contains(['*', '?']);
+        (1..=path.len())
+            .filter(|&len| len < path.len() || !self.dir_only)
+            .filter(|&len| len == path.len() || covers_directories)
+            .any(|len| segments_match(&self.segments, &path[..len]))
+    }
+}
+
+/// Whether `path` matches `pattern` segment by segment; `**` matches any number of segments
+fn segments_match(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| segments_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| matches_glob(first, name) && segments_match(rest, path)),
+    }
+}
+
+impl CodeOwners {
+    /// The repository's CODEOWNERS file, or `None` if it has none
+    pub fn load(repo_path: &Path) -> Result<Option<Self>> {
+        let Some(path) = LOCATIONS
+            .iter()
+            .map(|location| repo_path.join(location))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(Self::parse(&text)))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let rules = text
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    return None;
+                }
+                let mut fields = line.split_whitespace();
+                // `\#` starts a pattern for files whose name begins with `#`
+                let pattern = fields.next()?.replace("\\#", "#");
+                let owners = fields
+                    .take_while(|field| !field.starts_with('#'))
+                    .map(str::to_string)
+                    .collect();
+                Some(Rule::parse(&pattern, owners))
+            })
+            .collect();
+        CodeOwners { rules }
+    }
+
+    /// Owners of `path`, from the last rule matching it; a rule without owners leaves the
+    /// path unowned
+    pub fn owners_of(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path))
+            .map_or(&[], |rule| &rule.owners)
+    }
+
+    /// Users and teams (`org/team`) to request reviews from for changes to `files`, and
+    /// warnings about owners that can't be requested
+    pub fn reviewers(&self, files: &[String]) -> (Vec<String>, Vec<String>) {
+        let mut reviewers: Vec<String> = Vec::new();
+        let mut warnings = Vec::new();
+        let mut unowned = Vec::new();
+        for file in files {
+            let owners = self.owners_of(file);
+            if owners.is_empty() {
+                unowned.push(file.as_str());
+            }
+            for owner in owners {
+                match owner.strip_prefix('@') {
+                    Some(handle) => {
+                        if !reviewers.iter().any(|r| r.eq_ignore_ascii_case(handle)) {
+                            reviewers.push(handle.to_string());
+                        }
+                    }
+                    None => {
+                        let warning = format!(
+                            "code owner {} of {} is not a GitHub user or team, so no review was requested",
+                            owner, file
+                        );
+                        if !warnings.contains(&warning) {
+                            warnings.push(warning);
+                        }
+                    }
+                }
+            }
+        }
+        if !unowned.is_empty() {
+            warnings.push(format!("no code owners for {}", unowned.join(", ")));
+        }
+        (reviewers, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owners(codeowners: &CodeOwners, path: &str) -> Vec<String> {
+        codeowners.owners_of(path).to_vec()
+    }
+
+    #[test]
+    fn matches_paths_like_github() {
+        let codeowners = CodeOwners::parse(
+            r"
+# Default owners
+*                   @acme/platform
+*.json              @json-owner    # inline comment
+/package.json       @acme/deps
+apps/               @apps-owner
+/docs/*             @docs-owner
+/legal/             @legal-owner
+/vendor/lib
+**/functions/package.json  @functions dev@example.com
+\#notes             @hash
+",
+        );
+
+        // The last matching rule wins
+        assert_eq!(owners(&codeowners, "package.json"), ["@acme/deps"]);
+        assert_eq!(owners(&codeowners, "web/package.json"), ["@json-owner"]);
+        assert_eq!(owners(&codeowners, "yarn.lock"), ["@acme/platform"]);
+
+        // Unanchored directory patterns match at any depth, and everything below
+        assert_eq!(owners(&codeowners, "apps/web/yarn.lock"), ["@apps-owner"]);
+        assert_eq!(owners(&codeowners, "packages/apps/x.lock"), ["@apps-owner"]);
+        assert_eq!(owners(&codeowners, "packages/apps"), ["@acme/platform"]);
+
+        // `/docs/*` covers the files directly in docs, not nested ones
+        assert_eq!(owners(&codeowners, "docs/package.json"), ["@docs-owner"]);
+        assert_eq!(
+            owners(&codeowners, "docs/site/yarn.lock"),
+            ["@acme/platform"]
+        );
+        assert_eq!(owners(&codeowners, "legal/a/b.lock"), ["@legal-owner"]);
+        assert_eq!(owners(&codeowners, "x/legal/b.lock"), ["@acme/platform"]);
+
+        // A rule without owners leaves its files unowned
+        assert!(owners(&codeowners, "vendor/lib/package.json").is_empty());
+        assert_eq!(
+            owners(&codeowners, "a/b/functions/package.json"),
+            ["@functions", "dev@example.com"]
+        );
+        assert_eq!(owners(&codeowners, "#notes"), ["@hash"]);
+    }
+
+    #[test]
+    fn reviewers_are_users_and_teams_with_warnings_for_the_rest() {
+        let codeowners = CodeOwners::parse(
+            "/package.json @acme/deps @Octocat\n\
+             package-lock.json @octocat dev@example.com\n\
+             /functions/ \n",
+        );
+        let files = [
+            "package.json",
+            "package-lock.json",
+            "functions/package.json",
+        ]
+        .map(str::to_string);
+
+        let (reviewers, warnings) = codeowners.reviewers(&files);
+        assert_eq!(reviewers, ["acme/deps", "Octocat"]);
+        assert_eq!(
+            warnings,
+            [
+                "code owner dev@example.com of package-lock.json is not a GitHub user or team, so no review was requested",
+                "no code owners for functions/package.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn load_uses_the_first_location_github_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(CodeOwners::load(dir.path()).unwrap().is_none());
+
+        fs::create_dir_all(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/CODEOWNERS"), "* @docs\n").unwrap();
+        fs::write(dir.path().join("CODEOWNERS"), "* @root\n").unwrap();
+        let codeowners = CodeOwners::load(dir.path()).unwrap().unwrap();
+        assert_eq!(owners(&codeowners, "package.json"), ["@root"]);
+    }
+}
@@ -1,60 +1,611 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::campaign::OnError;
+use crate::changelog::ChangelogConfig;
+use crate::hooks::HookFailure;
+use crate::notify::NotificationConfig;
+use crate::package::{self, ManagerDetection, PACKAGE_MANAGERS};
+use crate::policy::Policy;
+use crate::provider::ProviderKind;
+use crate::repo::same_path;
+use crate::summary::SummaryMode;
+
+/// Name of the project-local config file
+pub const LOCAL_CONFIG_FILE: &str = ".mru.toml";
+
+/// Environment variable selecting a profile when `--profile` isn't given
+pub const PROFILE_ENV: &str = "MRU_PROFILE";
+
+/// Profile chosen for this invocation; unset for the default config
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub default_commit_message: String,
+    /// Profile used when neither `--profile` nor `MRU_PROFILE` is given; only read
+    /// from the default config
+    pub default_profile: Option<String>,
     pub repositories: Vec<Repository>,
     pub default_package_manager: Option<String>,
+    /// Number of attempts for network operations (push, pull, clone, gh)
+    pub retries: Option<u32>,
+    /// Default path for the detailed JSONL log
+    pub log_file: Option<String>,
+    /// Directory campaigns keep their temporary copies of repositories in (default: the
+    /// system temp directory)
+    pub tmp_root: Option<String>,
+    /// Disk space in MB a campaign expects to use in `tmp_root` per repository (default: 100)
+    pub tmp_budget_mb: Option<u64>,
+    /// Timeout in seconds for package installs (default: 600)
+    pub install_timeout: Option<u64>,
+    /// Timeout in seconds for git network operations (default: 120)
+    pub git_timeout: Option<u64>,
+    /// Timeout in seconds for gh commands (default: 120)
+    pub gh_timeout: Option<u64>,
+    /// Timeout in seconds for npm registry lookups (default: 120)
+    pub registry_timeout: Option<u64>,
+    /// Host used to expand `owner/repo` shorthand (default: github.com)
+    pub github_host: Option<String>,
+    /// Most GitHub CLI calls running at once, whatever `--jobs` is (default: 4)
+    pub github_concurrency: Option<usize>,
+    /// Commit types allowed for `--type` (default: the conventional-commit types)
+    pub commit_types: Option<Vec<String>>,
+    /// Shell commands run in every repository before an update is staged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_update_hooks: Vec<String>,
+    /// Shell commands run in every repository after an update is pushed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_update_hooks: Vec<String>,
+    /// What a failing pre-update hook does: `fail` (default) or `warn`
+    pub pre_update_hook_failure: Option<HookFailure>,
+    /// What a failing post-update hook does: `warn` (default) or `fail`
+    pub post_update_hook_failure: Option<HookFailure>,
+    /// Branches `update` never commits to, besides the default branch, e.g. `release/*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected_branches: Vec<String>,
+    /// Files besides package.json, lockfiles and changelogs an update may commit (globs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_paths: Vec<String>,
+    /// Most files an update may commit in one repository (default: 50)
+    pub max_staged_files: Option<usize>,
+    /// Delete the remote update branch when it turns out to have nothing over the base branch
+    pub delete_empty_branches: Option<bool>,
+    /// Run the package manager's dedupe after installing an update (default: false)
+    pub dedupe: Option<bool>,
+    /// Users and teams (`org/team`) to request reviews from on update pull requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviewers: Vec<String>,
+    /// Request reviews from the code owners of the changed files instead, in repositories
+    /// with a CODEOWNERS file (default: false)
+    pub codeowners: Option<bool>,
+    /// Milestone to put update pull requests in, e.g. `deps-2026-10`
+    pub milestone: Option<String>,
+    /// Project (title or number) to add update pull requests to
+    pub project: Option<String>,
+    /// Head branches of update-bot pull requests, e.g. `renovate/*` (default: Renovate's
+    /// and Dependabot's npm branches)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bot_branches: Vec<String>,
+    /// How much the summary at the end of `update` and `resume` shows: `full` (default),
+    /// `failures` or `compact`
+    pub summary: Option<SummaryMode>,
+    /// What `update` does after a repository fails: `continue`, `abort` or `prompt`
+    /// (default: prompt on a terminal, continue otherwise)
+    pub on_error: Option<OnError>,
+    /// Webhooks announcing finished campaigns
+    #[serde(default, skip_serializing_if = "NotificationConfig::is_empty")]
+    pub notifications: NotificationConfig,
+    /// Branch, commit, pull request and notification templates for `update`
+    #[serde(default, skip_serializing_if = "TemplateConfig::is_empty")]
+    pub templates: TemplateConfig,
+    /// Environment variables for installs, hooks and `exec` in every repository;
+    /// values may reference the environment as `${VAR}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Versions of each package manager installs may run with, e.g. `pnpm = ">=9"`;
+    /// a repository's `packageManager` field takes precedence
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub package_manager_versions: BTreeMap<String, String>,
+    /// Licenses an update must not move a package to, e.g. `AGPL-*`; checked with
+    /// `--license-policy` (default: warn)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub license_denylist: Vec<String>,
+    /// Packages that must not be updated automatically, or only within a range
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+    /// In a `.mru.toml`, use only its repositories instead of adding them to the global ones
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub replace_repositories: bool,
+    /// File given with `--config`; `None` for the default location
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+    /// `.mru.toml` merged over the global config, if one was found
+    #[serde(skip)]
+    pub local_file: Option<LocalLayer>,
+}
+
+/// Templates for what `update` names and writes; see `mru templates test`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateConfig {
+    /// Update branch, e.g. `deps/{package}-{date}` (default: `update-{package}-{version}`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Commit message when `--message` isn't given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_message: Option<String>,
+    /// Pull request title (default: the commit message)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pr_title: Option<String>,
+    /// Pull request body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pr_body: Option<String>,
+    /// Notification message (default: `mru updated {package} to {version} — {summary}`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification: Option<String>,
+    /// Format of `{date}`, with %Y, %m, %d, %H and %M (default: `%Y-%m-%d`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_format: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl TemplateConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &TemplateConfig::default()
+    }
+
+    /// Settings of this (local) config where set, else those of `global`
+    fn or(self, global: TemplateConfig) -> TemplateConfig {
+        TemplateConfig {
+            branch: self.branch.or(global.branch),
+            commit_message: self.commit_message.or(global.commit_message),
+            pr_title: self.pr_title.or(global.pr_title),
+            pr_body: self.pr_body.or(global.pr_body),
+            notification: self.notification.or(global.notification),
+            date_format: self.date_format.or(global.date_format),
+        }
+    }
+
+    pub fn date_format(&self) -> &str {
+        self.date_format
+            .as_deref()
+            .unwrap_or(crate::template::DEFAULT_DATE_FORMAT)
+    }
+}
+
+/// Project-local config file and what is needed to write changes back to it
+#[derive(Debug, Clone)]
+pub struct LocalLayer {
+    pub path: PathBuf,
+    /// The local file as loaded
+    local: Box<Config>,
+    /// Merged settings as loaded, to tell which ones a command changed
+    loaded: Box<Config>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Repository {
     pub path: String,
+    /// GitHub repository the local clone belongs to, e.g. `https://github.com/owner/repo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_url: Option<String>,
+    /// Groups for selecting repositories with `--group`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Short name that can be used in place of the path, e.g. with `--only`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Repository this one is a fork of; pull requests are opened against it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// Service pull requests are opened on (`github`, `bitbucket` or `azure-devops`), for
+    /// when the host of `origin` doesn't tell, e.g. an SSH host alias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderKind>,
+    /// Shell commands run before an update is staged, after the global ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_update_hooks: Vec<String>,
+    /// Shell commands run after an update is pushed, after the global ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_update_hooks: Vec<String>,
+    /// Released by other tooling such as changesets or semantic-release;
+    /// `mru version` leaves it alone
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_version: bool,
+    /// Files an update may commit in this repository, besides the global `allowed_paths`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_paths: Vec<String>,
+    /// Changelog heading and entry format for `update --changelog`
+    #[serde(default, skip_serializing_if = "ChangelogConfig::is_empty")]
+    pub changelog: ChangelogConfig,
+    /// Repositories with a higher priority are updated first (default: 0)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Repositories (by alias, directory name or path) that must be updated before this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Package manager to use, over the `packageManager` field and lockfiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<String>,
+    /// Directories with a package.json deployed on its own, outside any workspace,
+    /// e.g. `functions`; updated, installed and compared like the root one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_manifests: Vec<String>,
+    /// Environment variables for installs, hooks and `exec` in this repository, over
+    /// the global ones; values may reference the environment as `${VAR}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Package manager versions for this repository, over the global ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub package_manager_versions: BTreeMap<String, String>,
+    /// Defined in the project-local config file
+    #[serde(skip)]
+    pub local: bool,
+    /// Given with `--repo-path` for this run only, not configured anywhere
+    #[serde(skip)]
+    pub ad_hoc: bool,
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+impl Repository {
+    /// Whether `name` refers to this repository by directory name, alias or path
+    pub fn is_named(&self, name: &str) -> bool {
+        crate::context::repo_name(&self.path) == name
+            || self.alias.as_deref() == Some(name)
+            || same_path(&self.path, name)
+    }
+
+    /// Path of each extra manifest's directory, e.g. `~/work/app/functions`
+    pub fn extra_manifest_paths(&self) -> Vec<String> {
+        self.extra_manifests
+            .iter()
+            .map(|dir| format!("{}/{}", self.path.trim_end_matches('/'), dir))
+            .collect()
+    }
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
+    /// Load the config. Precedence: a file given with `--config` is used on its own;
+    /// otherwise the nearest `.mru.toml` in the current directory or an ancestor is
+    /// merged over the global config, which is created if missing.
+    pub fn load(file: Option<&Path>) -> Result<Self> {
+        if let Some(file) = file {
+            let mut config = read_file(file)?;
+            config
+                .validate()
+                .with_context(|| format!("Invalid config file: {}", file.display()))?;
+            config.file = Some(file.to_path_buf());
+            return Ok(config);
+        }
+
+        let global = Self::load_global()?;
+        let Some(local_path) = find_local_config(&std::env::current_dir()?) else {
+            return Ok(global);
+        };
+
+        let config = global.merge_local(read_file(&local_path)?, local_path.clone());
+        config
+            .validate()
+            .with_context(|| format!("Invalid config file: {}", local_path.display()))?;
+        Ok(config)
+    }
+
+    /// Merge a project-local config over this one: settings from the local file win,
+    /// repositories and policies are added (a local entry replaces a global one for the
+    /// same path or name), or repositories are replaced with `replace_repositories`
+    fn merge_local(self, mut local: Config, path: PathBuf) -> Self {
+        for repo in &mut local.repositories {
+            repo.local = true;
+        }
+        for policy in &mut local.policies {
+            policy.local = true;
+        }
+
+        let mut merged = self;
+        if !local.default_commit_message.is_empty() {
+            merged.default_commit_message = local.default_commit_message.clone();
+        }
+        merged.default_package_manager = local
+            .default_package_manager
+            .clone()
+            .or(merged.default_package_manager);
+        merged.retries = local.retries.or(merged.retries);
+        merged.log_file = local.log_file.clone().or(merged.log_file);
+        merged.tmp_root = local.tmp_root.clone().or(merged.tmp_root);
+        merged.tmp_budget_mb = local.tmp_budget_mb.or(merged.tmp_budget_mb);
+        merged.install_timeout = local.install_timeout.or(merged.install_timeout);
+        merged.git_timeout = local.git_timeout.or(merged.git_timeout);
+        merged.gh_timeout = local.gh_timeout.or(merged.gh_timeout);
+        merged.registry_timeout = local.registry_timeout.or(merged.registry_timeout);
+        merged.github_concurrency = local.github_concurrency.or(merged.github_concurrency);
+        merged.github_host = local.github_host.clone().or(merged.github_host);
+        merged.commit_types = local.commit_types.clone().or(merged.commit_types);
+        if !local.pre_update_hooks.is_empty() {
+            merged.pre_update_hooks = local.pre_update_hooks.clone();
+        }
+        if !local.post_update_hooks.is_empty() {
+            merged.post_update_hooks = local.post_update_hooks.clone();
+        }
+        merged.pre_update_hook_failure = local
+            .pre_update_hook_failure
+            .or(merged.pre_update_hook_failure);
+        merged.post_update_hook_failure = local
+            .post_update_hook_failure
+            .or(merged.post_update_hook_failure);
+        if !local.protected_branches.is_empty() {
+            merged.protected_branches = local.protected_branches.clone();
+        }
+        if !local.allowed_paths.is_empty() {
+            merged.allowed_paths = local.allowed_paths.clone();
+        }
+        merged.max_staged_files = local.max_staged_files.or(merged.max_staged_files);
+        merged.delete_empty_branches = local.delete_empty_branches.or(merged.delete_empty_branches);
+        merged.dedupe = local.dedupe.or(merged.dedupe);
+        if !local.reviewers.is_empty() {
+            merged.reviewers = local.reviewers.clone();
+        }
+        merged.codeowners = local.codeowners.or(merged.codeowners);
+        merged.milestone = local.milestone.clone().or(merged.milestone);
+        merged.project = local.project.clone().or(merged.project);
+        merged.summary = local.summary.or(merged.summary);
+        merged.on_error = local.on_error.or(merged.on_error);
+        if !local.bot_branches.is_empty() {
+            merged.bot_branches = local.bot_branches.clone();
+        }
+        if !local.license_denylist.is_empty() {
+            merged.license_denylist = local.license_denylist.clone();
+        }
+        merged.env.extend(local.env.clone());
+        merged
+            .package_manager_versions
+            .extend(local.package_manager_versions.clone());
+        merged.templates = local.templates.clone().or(merged.templates);
+        merged.notifications = NotificationConfig {
+            webhook_url: local
+                .notifications
+                .webhook_url
+                .clone()
+                .or(merged.notifications.webhook_url),
+            slack_webhook_url: local
+                .notifications
+                .slack_webhook_url
+                .clone()
+                .or(merged.notifications.slack_webhook_url),
+        };
+
+        if local.replace_repositories {
+            merged.repositories.clear();
+        }
+        merged.repositories.retain(|g| {
+            !local
+                .repositories
+                .iter()
+                .any(|l| same_path(&l.path, &g.path))
+        });
+        merged
+            .repositories
+            .extend(local.repositories.iter().cloned());
+
+        merged
+            .policies
+            .retain(|g| !local.policies.iter().any(|l| l.name == g.name));
+        merged.policies.extend(local.policies.iter().cloned());
+
+        merged.local_file = Some(LocalLayer {
+            path,
+            local: Box::new(local),
+            loaded: Box::new(merged.clone()),
+        });
+        merged
+    }
+
+    fn load_global() -> Result<Self> {
         let config_path = get_config_path()?;
         let config_dir = config_path.parent().unwrap();
 
         if !config_path.exists() {
             fs::create_dir_all(config_dir)?;
-            let default_config = Config {
-                default_commit_message: "chore: update dependencies".to_string(),
-                repositories: Vec::new(),
-                default_package_manager: Some("npm".to_string()),
-            };
+            let default_config = Config::initial();
             let toml = toml::to_string(&default_config)?;
             fs::write(&config_path, toml)?;
+            if let Some(name) = active_profile() {
+                eprintln!("Created profile '{}' at {}", name, config_path.display());
+            }
             return Ok(default_config);
         }
 
         let content = fs::read_to_string(&config_path).context("Failed to read config file")?;
         let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid config file: {}", config_path.display()))?;
 
-        let mut expanded_repos = Vec::new();
-        for repo in &config.repositories {
-            let expanded_path = expand_tilde(&repo.path)?;
-            expanded_repos.push(Repository {
-                path: expanded_path,
-            });
+        // Paths are kept as written (e.g. with `~`) so saving doesn't rewrite them
+        Ok(config)
+    }
+
+    /// Config written when there is none yet
+    pub fn initial() -> Self {
+        Config {
+            default_commit_message: "chore: update dependencies".to_string(),
+            repositories: Vec::new(),
+            default_package_manager: Some("npm".to_string()),
+            ..Default::default()
         }
+    }
+
+    /// Environment for commands run in `repo` (or in no configured repository): the
+    /// global `env` with the repository's own over it, `${VAR}` references resolved
+    pub fn env_for(&self, repo: Option<&Repository>) -> Result<Vec<(String, String)>> {
+        let mut env = self.env.clone();
+        if let Some(repo) = repo {
+            env.extend(repo.env.clone());
+        }
+        env.into_iter()
+            .map(|(name, value)| {
+                let value = crate::repo::substitute_env_vars(&value, &format!("env.{}", name))?;
+                Ok((name, value))
+            })
+            .collect()
+    }
 
-        Ok(Config {
-            default_commit_message: config.default_commit_message,
-            repositories: expanded_repos,
-            default_package_manager: config.default_package_manager,
-        })
+    /// Path of every repository, each followed by its extra manifests' directories
+    pub fn manifest_paths(&self) -> Vec<String> {
+        self.repositories
+            .iter()
+            .flat_map(|repo| std::iter::once(repo.path.clone()).chain(repo.extra_manifest_paths()))
+            .collect()
     }
 
+    /// Repository whose path points to the same location as `path`
+    pub fn find_repository(&self, path: &str) -> Option<&Repository> {
+        self.repositories.iter().find(|r| same_path(&r.path, path))
+    }
+
+    /// Package manager of the repository at `path`, from its `package_manager` setting,
+    /// its package.json or lockfiles, or `default_package_manager`
+    pub fn package_manager(
+        &self,
+        ctx: &crate::context::Context,
+        path: &str,
+    ) -> Result<ManagerDetection> {
+        let pinned = self
+            .find_repository(path)
+            .and_then(|repo| repo.package_manager.as_deref());
+        package::detect_manager(ctx, path, pinned, self.default_package_manager.as_deref())
+    }
+
+    /// Version range `manager` must have in `repo` (or in no configured repository)
+    pub fn package_manager_version<'a>(
+        &'a self,
+        repo: Option<&'a Repository>,
+        manager: &str,
+    ) -> Option<&'a str> {
+        repo.and_then(|repo| repo.package_manager_versions.get(manager))
+            .or(self.package_manager_versions.get(manager))
+            .map(String::as_str)
+    }
+
+    /// Check settings that would otherwise only fail in the middle of a run
+    pub fn validate(&self) -> Result<()> {
+        if let Some(manager) = &self.default_package_manager {
+            if !PACKAGE_MANAGERS.contains(&manager.as_str()) {
+                anyhow::bail!(
+                    "Unknown default_package_manager '{}'. Must be one of: {}",
+                    manager,
+                    PACKAGE_MANAGERS.join(", ")
+                );
+            }
+        }
+
+        for repo in &self.repositories {
+            if let Some(manager) = &repo.package_manager {
+                if !PACKAGE_MANAGERS.contains(&manager.as_str()) {
+                    anyhow::bail!(
+                        "Unknown package_manager '{}' for {}. Must be one of: {}",
+                        manager,
+                        repo.path,
+                        PACKAGE_MANAGERS.join(", ")
+                    );
+                }
+            }
+        }
+
+        for repo in &self.repositories {
+            for dir in &repo.extra_manifests {
+                let inside = !dir.is_empty()
+                    && !Path::new(dir).is_absolute()
+                    && !dir.split('/').any(|part| part == ".." || part.is_empty());
+                if !inside {
+                    anyhow::bail!(
+                        "Invalid extra_manifests entry '{}' for {}: must be a directory inside the repository, such as 'functions'",
+                        dir,
+                        repo.path
+                    );
+                }
+            }
+        }
+
+        let versions = std::iter::once((None, &self.package_manager_versions)).chain(
+            self.repositories
+                .iter()
+                .map(|repo| (Some(&repo.path), &repo.package_manager_versions)),
+        );
+        for (path, versions) in versions {
+            for manager in versions.keys() {
+                if !PACKAGE_MANAGERS.contains(&manager.as_str()) {
+                    anyhow::bail!(
+                        "Unknown package manager '{}' in package_manager_versions{}. Must be one of: {}",
+                        manager,
+                        path.map(|p| format!(" for {}", p)).unwrap_or_default(),
+                        PACKAGE_MANAGERS.join(", ")
+                    );
+                }
+            }
+        }
+
+        self.notifications.validate()?;
+        crate::order::waves(&self.repositories)?;
+
+        for (i, policy) in self.policies.iter().enumerate() {
+            policy.validate()?;
+            if self.policies[..i].iter().any(|p| p.name == policy.name) {
+                anyhow::bail!("Duplicate policy name '{}'", policy.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the config back to the file it came from. With a project-local config,
+    /// the local file gets its own repositories and policies plus any setting changed
+    /// since loading, and the global file is left alone.
     pub fn save(&self) -> Result<()> {
-        let config_path = get_config_path()?;
-        let config_dir = config_path.parent().unwrap();
+        if let Some(layer) = &self.local_file {
+            let mut table = settings(&layer.local)?;
+            let loaded = settings(&layer.loaded)?;
+            for (key, value) in settings(self)? {
+                if loaded.get(&key) != Some(&value) {
+                    table.insert(key, value);
+                }
+            }
+            let entries = Config {
+                repositories: self
+                    .repositories
+                    .iter()
+                    .filter(|r| r.local)
+                    .cloned()
+                    .collect(),
+                policies: self.policies.iter().filter(|p| p.local).cloned().collect(),
+                ..Default::default()
+            };
+
+            let toml = format!(
+                "{}\n{}",
+                toml::to_string(&table)?,
+                toml::to_string(&entries)?
+            );
+            fs::write(&layer.path, toml.trim_start())
+                .with_context(|| format!("Failed to write {}", layer.path.display()))?;
+            return Ok(());
+        }
 
-        fs::create_dir_all(config_dir)?;
+        let config_path = match &self.file {
+            Some(file) => file.clone(),
+            None => get_config_path()?,
+        };
+        if let Some(config_dir) = config_path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(config_dir)?;
+        }
 
         let toml = toml::to_string(self)?;
         fs::write(&config_path, toml)?;
@@ -62,38 +613,67 @@ impl Config {
         Ok(())
     }
 
-    pub fn add_repository(&mut self, path: String) -> Result<()> {
-        // Check for duplicates (after tilde expansion)
-        let expanded_path = expand_tilde(&path)?;
+    pub fn github_host(&self) -> &str {
+        self.github_host.as_deref().unwrap_or("github.com")
+    }
 
-        for repo in &self.repositories {
-            let repo_expanded_path = expand_tilde(&repo.path)?;
-            if repo_expanded_path == expanded_path {
-                anyhow::bail!("Repository already exists in config");
+    /// Branch patterns of the pull requests update bots open
+    pub fn bot_branches(&self) -> Vec<String> {
+        if self.bot_branches.is_empty() {
+            crate::github::DEFAULT_BOT_BRANCHES
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        } else {
+            self.bot_branches.clone()
+        }
+    }
+
+    /// Refuse to change an entry of the global config while a local one is in effect,
+    /// since only the local file is written
+    pub fn check_writable(&self, local: bool, what: &str) -> Result<()> {
+        match &self.local_file {
+            Some(layer) if !local => anyhow::bail!(
+                "{} is defined in the global config, but changes are saved to {}. \
+                 Edit the global config, or run mru outside this project",
+                what,
+                layer.path.display()
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_repository(&mut self, mut repository: Repository) -> Result<()> {
+        // Check for duplicates (after tilde expansion and canonicalization)
+        if let Some(existing) = self.find_repository(&repository.path) {
+            anyhow::bail!("Repository already exists in config: {}", existing.path);
+        }
+        if let Some(alias) = &repository.alias {
+            if let Some(existing) = self
+                .repositories
+                .iter()
+                .find(|r| r.alias.as_ref() == Some(alias))
+            {
+                anyhow::bail!("Alias '{}' is already used by {}", alias, existing.path);
             }
         }
 
         // Save original path (with tilde)
-        self.repositories.push(Repository { path });
+        repository.local = self.local_file.is_some();
+        self.repositories.push(repository);
         self.save()?;
 
         Ok(())
     }
 
     pub fn remove_repository(&mut self, path: &str) -> Result<()> {
-        let expanded_path = expand_tilde(path)?;
+        if let Some(repo) = self.find_repository(path) {
+            self.check_writable(repo.local, &format!("Repository {}", repo.path))?;
+        }
         let initial_len = self.repositories.len();
 
-        // Remove by comparing expanded paths
-        let mut i = 0;
-        while i < self.repositories.len() {
-            let repo_expanded_path = expand_tilde(&self.repositories[i].path)?;
-            if repo_expanded_path == expanded_path {
-                self.repositories.remove(i);
-            } else {
-                i += 1;
-            }
-        }
+        // Remove by comparing canonical paths
+        self.repositories.retain(|r| !same_path(&r.path, path));
 
         if self.repositories.len() == initial_len {
             anyhow::bail!("Repository not found: {}", path);
@@ -104,25 +684,331 @@ impl Config {
     }
 }
 
+pub fn read_file(path: &Path) -> Result<Config> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+}
+
+/// Top-level settings of a config, without its repositories and policies
+pub fn settings(config: &Config) -> Result<toml::map::Map<String, toml::Value>> {
+    let mut table = match toml::Value::try_from(config)? {
+        toml::Value::Table(table) => table,
+        _ => unreachable!("config serializes to a table"),
+    };
+    table.remove("repositories");
+    table.remove("policies");
+    Ok(table)
+}
+
+/// Nearest `.mru.toml` in `dir` or one of its ancestors
+pub fn find_local_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(LOCAL_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Config file of the active profile, else ~/.config/mru/config.toml
 pub fn get_config_path() -> Result<PathBuf> {
-    // Get home directory
-    let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
+    match active_profile() {
+        Some(name) => profile_path(name),
+        None => default_config_path(),
+    }
+}
 
-    // Create ~/.config/mru/config.toml path
-    let config_path = home.join(".config").join("mru").join("config.toml");
+/// ~/.config/mru/config.toml, whatever profile is active
+pub fn default_config_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("config.toml"))
+}
 
-    Ok(config_path)
+/// Directory of the campaign state, history and lock: the config directory, or the
+/// active profile's own, so runs of different profiles don't mix
+pub fn state_dir() -> Result<PathBuf> {
+    match active_profile() {
+        Some(name) => Ok(profiles_dir()?.join(name)),
+        None => get_config_dir(),
+    }
+}
+
+pub fn profiles_dir() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("profiles"))
+}
+
+/// e.g. ~/.config/mru/profiles/work.toml
+pub fn profile_path(name: &str) -> Result<PathBuf> {
+    Ok(profiles_dir()?.join(format!("{}.toml", name)))
+}
+
+/// Fail unless `name` can be a profile: letters, digits, `-` and `_`
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid || name == "default" {
+        anyhow::bail!(
+            "Invalid profile name '{}': use letters, digits, '-' and '_' (and not 'default')",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Profile named by `--profile`, else `MRU_PROFILE`, else `default_profile` of the
+/// default config. `default` names the default config itself.
+pub fn select_profile(flag: Option<&str>) -> Result<Option<String>> {
+    let name = match flag {
+        Some(name) => Some(name.to_string()),
+        None => match std::env::var(PROFILE_ENV).ok().filter(|n| !n.is_empty()) {
+            Some(name) => Some(name),
+            None => default_profile()?,
+        },
+    };
+    Ok(name.filter(|name| name != "default"))
+}
+
+/// `default_profile` of the default config
+pub fn default_profile() -> Result<Option<String>> {
+    let path = default_config_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(read_file(&path)?.default_profile)
+}
+
+/// Use the profile `name` for the rest of the invocation
+pub fn set_profile(name: &str) -> Result<()> {
+    validate_profile_name(name)?;
+    if PROFILE.set(name.to_string()).is_err() {
+        anyhow::bail!("A profile was already selected");
+    }
+    Ok(())
 }
 
+pub fn active_profile() -> Option<&'static str> {
+    PROFILE.get().map(String::as_str)
+}
+
+pub fn get_config_dir() -> Result<PathBuf> {
+    // %APPDATA%\mru on Windows
+    #[cfg(windows)]
+    let base = dirs::config_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find configuration directory"))?;
+
+    // ~/.config/mru everywhere else
+    #[cfg(not(windows))]
+    let base = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
+        .join(".config");
+
+    Ok(base.join("mru"))
+}
+
+/// Expand `~`, `~/path` and `~user/path` to the corresponding home directory
 pub fn expand_tilde(path: &str) -> Result<String> {
-    if path.starts_with("~/") {
-        let home =
-            dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
+    let Some(after_tilde) = path.strip_prefix('~') else {
+        return Ok(path.to_string());
+    };
 
-        // Remove ~/ and combine with home directory
-        let path_without_tilde = &path[2..];
-        Ok(home.join(path_without_tilde).to_string_lossy().to_string())
+    // Split `~user/rest` into the user name and the rest of the path
+    let is_separator = |c: char| c == '/' || (cfg!(windows) && c == '\\');
+    let (user, rest) = match after_tilde.find(is_separator) {
+        Some(pos) => (&after_tilde[..pos], &after_tilde[pos + 1..]),
+        None => (after_tilde, ""),
+    };
+
+    let home = if user.is_empty() {
+        dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
     } else {
-        Ok(path.to_string())
+        user_home(user)
+            .ok_or_else(|| anyhow::anyhow!("Unknown user '{}' in path: {}", user, path))?
+    };
+
+    if rest.is_empty() {
+        return Ok(home.to_string_lossy().to_string());
+    }
+
+    // Remove ~/ and combine with home directory
+    Ok(home.join(rest).to_string_lossy().to_string())
+}
+
+/// Home directory of another user, from the password database
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<PathBuf> {
+    use std::ffi::{CStr, CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = CString::new(user).ok()?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = std::ptr::null_mut();
+
+    // SAFETY: all pointers are valid for the duration of the call and `buf` outlives `passwd`
+    let rc = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+
+    // SAFETY: `pw_dir` points into `buf`, which is still alive
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+/// Home directory of another user, assumed to be a sibling of the current user's
+#[cfg(not(unix))]
+fn user_home(user: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?.parent()?.join(user);
+    home.is_dir().then_some(home)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_are_plain_file_names() {
+        assert!(validate_profile_name("work-2").is_ok());
+        assert!(validate_profile_name("../work").is_err());
+        assert!(validate_profile_name("default").is_err());
+        assert!(validate_profile_name("").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_package_manager() {
+        let config = Config {
+            default_package_manager: Some("pnmp".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            default_package_manager: Some("pnpm".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn repositories_without_github_url_still_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            default_commit_message = "chore: update dependencies"
+
+            [[repositories]]
+            path = "~/work/app"
+
+            [[repositories]]
+            path = "~/work/api"
+            github_url = "https://github.com/acme/api"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.repositories[0].github_url, None);
+        assert_eq!(
+            config.repositories[1].github_url.as_deref(),
+            Some("https://github.com/acme/api")
+        );
+    }
+
+    #[test]
+    fn nearest_local_config_is_found_from_nested_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let project = root.path().join("project");
+        let nested = project.join("packages/web/src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(LOCAL_CONFIG_FILE), "").unwrap();
+        assert_eq!(
+            find_local_config(&nested),
+            Some(root.path().join(LOCAL_CONFIG_FILE))
+        );
+
+        fs::write(project.join(LOCAL_CONFIG_FILE), "").unwrap();
+        assert_eq!(
+            find_local_config(&nested),
+            Some(project.join(LOCAL_CONFIG_FILE))
+        );
+    }
+
+    #[test]
+    fn local_config_wins_conflicts_and_receives_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_path = dir.path().join(LOCAL_CONFIG_FILE);
+        let global: Config = toml::from_str(
+            r#"
+            default_commit_message = "chore: update dependencies"
+            default_package_manager = "npm"
+            retries = 5
+
+            [[repositories]]
+            path = "/work/shared"
+
+            [[repositories]]
+            path = "/work/app"
+            alias = "global-app"
+            "#,
+        )
+        .unwrap();
+        let local: Config = toml::from_str(
+            r#"
+            default_package_manager = "pnpm"
+
+            [[repositories]]
+            path = "/work/app"
+            alias = "local-app"
+            "#,
+        )
+        .unwrap();
+
+        let mut config = global
+            .clone()
+            .merge_local(local.clone(), local_path.clone());
+        assert_eq!(config.default_package_manager.as_deref(), Some("pnpm"));
+        assert_eq!(config.retries, Some(5));
+        assert_eq!(config.default_commit_message, "chore: update dependencies");
+        let aliases: Vec<_> = config
+            .repositories
+            .iter()
+            .map(|r| (r.path.as_str(), r.alias.as_deref(), r.local))
+            .collect();
+        assert_eq!(
+            aliases,
+            vec![
+                ("/work/shared", None, false),
+                ("/work/app", Some("local-app"), true)
+            ]
+        );
+        assert!(config.remove_repository("/work/shared").is_err());
+
+        config.gh_timeout = Some(30);
+        config
+            .add_repository(Repository {
+                path: "/work/new".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let saved: Config = toml::from_str(&fs::read_to_string(&local_path).unwrap()).unwrap();
+        assert_eq!(saved.gh_timeout, Some(30));
+        assert_eq!(saved.retries, None);
+        let paths: Vec<_> = saved.repositories.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["/work/app", "/work/new"]);
+
+        let replacing = Config {
+            replace_repositories: true,
+            ..local
+        };
+        let config = global.merge_local(replacing, local_path);
+        assert_eq!(config.repositories.len(), 1);
     }
 }
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::github::GithubContext;
+use crate::log::{Logger, LoggingRunner};
+use crate::manifest::ManifestCache;
+use crate::package::RegistryCache;
+use crate::preflight::ToolCache;
+use crate::retry::{self, RetryPolicy};
+use crate::runner::{CommandOutput, CommandRunner, CommandSpec, SystemRunner};
+
+/// Time limits for spawned processes
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub install: Duration,
+    pub git: Duration,
+    pub gh: Duration,
+    pub registry: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            install: Duration::from_secs(600),
+            git: Duration::from_secs(120),
+            gh: Duration::from_secs(120),
+            registry: Duration::from_secs(120),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Defaults overridden by the `*_timeout` settings (in seconds) from the config
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = Timeouts::default();
+        Timeouts {
+            install: config
+                .install_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.install),
+            git: config
+                .git_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.git),
+            gh: config
+                .gh_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.gh),
+            registry: config
+                .registry_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.registry),
+        }
+    }
+}
+
+/// Shared state for a single mru invocation
+pub struct Context {
+    pub runner: Arc<dyn CommandRunner>,
+    pub retry: RetryPolicy,
+    pub log: Arc<Logger>,
+    pub timeouts: Timeouts,
+    /// Suppress streamed output of child processes
+    pub quiet: bool,
+    /// Send the configured notifications when a campaign finishes
+    pub notify: bool,
+    pub manifests: ManifestCache,
+    pub tools: ToolCache,
+    pub registry: RegistryCache,
+    pub github: GithubContext,
+    /// Answer read-only git queries with libgit2 instead of the git CLI
+    pub native_git: bool,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        let mut ctx = Self::with_runner(Arc::new(SystemRunner));
+        ctx.native_git = crate::gitlib::AVAILABLE;
+        ctx
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Context {
+            runner,
+            retry: RetryPolicy::default(),
+            log: Arc::new(Logger::disabled()),
+            timeouts: Timeouts::default(),
+            quiet: false,
+            notify: true,
+            manifests: ManifestCache::new(),
+            tools: ToolCache::new(),
+            registry: RegistryCache::new(),
+            github: GithubContext::default(),
+            native_git: false,
+        }
+    }
+
+    /// Write every command and decision point to `log`
+    pub fn with_log(mut self, log: Logger) -> Self {
+        let log = Arc::new(log);
+        self.runner = Arc::new(LoggingRunner::new(self.runner, log.clone()));
+        self.log = log;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_github(mut self, github: GithubContext) -> Self {
+        self.github = github;
+        self
+    }
+
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn with_notify(mut self, notify: bool) -> Self {
+        self.notify = notify;
+        self
+    }
+
+    /// Accept comments and trailing commas in package.json
+    pub fn with_jsonc(mut self, jsonc: bool) -> Self {
+        self.manifests = ManifestCache::new().with_jsonc(jsonc);
+        self
+    }
+
+    /// Run a command in a repository, streaming its output prefixed with the repository name
+    pub fn stream(&self, cmd: &CommandSpec, repo_path: &str) -> Result<CommandOutput> {
+        self.runner.stream(cmd, &repo_name(repo_path), self.quiet)
+    }
+
+    /// Run a network command, retrying transient failures.
+    /// Permanent failures are returned as a failed output for the caller to report.
+    pub fn output_with_retry(&self, cmd: &CommandSpec) -> Result<CommandOutput> {
+        self.retrying(cmd, || self.runner.output(cmd))
+    }
+
+    /// Like `output_with_retry`, but streams the output like `stream`
+    pub fn stream_with_retry(&self, cmd: &CommandSpec, repo_path: &str) -> Result<CommandOutput> {
+        self.retrying(cmd, || self.stream(cmd, repo_path))
+    }
+
+    fn retrying(
+        &self,
+        cmd: &CommandSpec,
+        run: impl Fn() -> Result<CommandOutput>,
+    ) -> Result<CommandOutput> {
+        self.retry.run(&cmd.display(), || {
+            let output = run()?;
+            if !output.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if retry::is_transient(&stderr) {
+                    anyhow::bail!("{}", stderr.trim());
+                }
+            }
+            Ok(output)
+        })
+    }
+}
+
+/// Short name of a repository used to prefix its output
+pub fn repo_name(repo_path: &str) -> String {
+    Path::new(repo_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| repo_path.to_string())
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
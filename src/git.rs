@@ -1,40 +1,147 @@
-use anyhow::{Context, Result};
-use std::error::Error;
-use std::path::PathBuf;
-use std::process::Command;
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
+use crate::codeowners::CodeOwners;
 use crate::config::Config;
 use crate::config::Repository;
+use crate::context::Context;
+use crate::github::{CreatedPr, PrMetadata};
+use crate::gitlib;
+use crate::hooks::{self, HookEnv, HookStage};
+use crate::lockfile::LockfileShape;
+use crate::package::{matches_glob, ManagerCheck, SpecifierChange, UpdateOutcome};
+use crate::provider::{NewPr, Provider};
 use crate::repo::expand_path;
+use crate::runner::{self, CommandOutput, CommandSpec};
+use crate::template::TemplateContext;
+use crate::timing::StepTimer;
 
-/// Get current branch name
-pub fn get_current_branch(repo_path: &str) -> Result<String> {
+/// git command with output that doesn't depend on the user's locale and
+/// that fails instead of prompting for credentials
+pub fn git_command() -> CommandSpec {
+    CommandSpec::new("git")
+        .env("LC_ALL", "C")
+        .env("GIT_TERMINAL_PROMPT", "0")
+}
+
+/// Answer a read-only query with libgit2 when the context allows it. `None` falls back
+/// to the git CLI, also when libgit2 fails, e.g. on a repository format it can't read.
+fn native<T>(ctx: &Context, path: &Path, query: impl FnOnce(&Path) -> Result<T>) -> Option<T> {
+    if !ctx.native_git {
+        return None;
+    }
+    query(path).ok()
+}
+
+/// Name of a branch as git reports it. Ref names may hold bytes that aren't UTF-8,
+/// so the name is kept as is to check the branch out again; `Display` escapes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchName(OsString);
+
+impl BranchName {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        #[cfg(unix)]
+        let name = std::os::unix::ffi::OsStringExt::from_vec(bytes);
+        #[cfg(not(unix))]
+        let name = OsString::from(String::from_utf8_lossy(&bytes).into_owned());
+        BranchName(name)
+    }
+
+    /// The name as UTF-8, for uses that put it into text such as a refspec
+    pub fn to_str(&self) -> Result<&str> {
+        self.0
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Branch name '{}' is not valid UTF-8", self))
+    }
+}
+
+impl From<&str> for BranchName {
+    fn from(name: &str) -> Self {
+        BranchName(name.into())
+    }
+}
+
+impl fmt::Display for BranchName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&runner::escape_os_str(&self.0))
+    }
+}
+
+impl AsRef<OsStr> for BranchName {
+    fn as_ref(&self) -> &OsStr {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for BranchName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for BranchName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+/// Branch checked out in the repository, or `None` if HEAD is detached
+pub fn current_branch(ctx: &Context, repo_path: &str) -> Result<Option<BranchName>> {
     let path = expand_path(repo_path)?;
+    if let Some(branch) = native(ctx, &path, gitlib::current_branch) {
+        return Ok(branch.map(BranchName::from_bytes));
+    }
 
-    let output = Command::new("git")
-        .current_dir(path)
-        .args(["branch", "--show-current"])
-        .output()
+    // Exits with 1 and no output when HEAD isn't a branch
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(path).args([
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "HEAD",
+        ]))
         .context("Failed to get current branch")?;
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to get current branch for repository: {}", repo_path);
+    match output.code {
+        Some(0) => Ok(Some(BranchName::from_bytes(
+            output.stdout.trim_ascii_end().to_vec(),
+        ))),
+        Some(1) if output.stderr.is_empty() => Ok(None),
+        _ => anyhow::bail!(
+            "Failed to get current branch for repository {}: {}",
+            repo_path,
+            output.tail()
+        ),
     }
+}
 
-    let branch = String::from_utf8(output.stdout)
-        .context("Invalid UTF-8 in branch name")?
-        .trim()
-        .to_string();
-
-    Ok(branch)
+/// Get current branch name. A detached HEAD is an error, since there is no branch to return to.
+pub fn get_current_branch(ctx: &Context, repo_path: &str) -> Result<BranchName> {
+    current_branch(ctx, repo_path)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "HEAD is detached in {}; check out a branch first",
+            repo_path
+        )
+    })
 }
 
 /// Create branch
-pub fn create_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Result<String> {
+pub fn create_branch(
+    ctx: &Context,
+    repo_path: &str,
+    branch_name: &str,
+    start: Option<&str>,
+    dry_run: bool,
+) -> Result<BranchName> {
     let path = expand_path(repo_path)?;
 
     // Save current branch (for restoration in case of failure)
-    let original_branch = get_current_branch(repo_path)?;
+    let original_branch = get_current_branch(ctx, repo_path)?;
 
     if dry_run {
         println!("Would create branch '{}' in {}", branch_name, repo_path);
@@ -43,32 +150,42 @@ pub fn create_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Resul
 
     println!("Creating branch '{}' in {}", branch_name, repo_path);
 
-    // Check if branch already exists
-    let output = Command::new("git")
-        .current_dir(&path)
-        .args(["branch", "--list", branch_name])
-        .output()
-        .context("Failed to list branches")?;
-
-    let branch_exists = !output.stdout.is_empty();
+    if local_branch_exists(ctx, repo_path, branch_name)? {
+        ctx.log.event(
+            "branch_exists",
+            &[("repo", repo_path), ("branch", branch_name)],
+        );
 
-    if branch_exists {
         // If branch exists, check out
-        let status = Command::new("git")
-            .current_dir(&path)
-            .args(["checkout", branch_name])
-            .status()
+        let status = ctx
+            .stream(
+                &git_command()
+                    .current_dir(&path)
+                    .arg("checkout")
+                    .arg(branch_name),
+                repo_path,
+            )
             .context("Failed to checkout existing branch")?;
 
         if !status.success() {
             anyhow::bail!("Failed to checkout existing branch: {}", branch_name);
         }
     } else {
-        // If branch does not exist, create new branch
-        let status = Command::new("git")
-            .current_dir(&path)
-            .args(["checkout", "-b", branch_name])
-            .status()
+        // If branch does not exist, create new branch, from `start` if given. It isn't
+        // set to track `start`, so pushing it goes to a branch of its own name.
+        let status = ctx
+            .stream(
+                &git_command()
+                    .current_dir(&path)
+                    .args(["checkout", "-b", branch_name])
+                    .args(
+                        start
+                            .map(|start| ["--no-track", start])
+                            .into_iter()
+                            .flatten(),
+                    ),
+                repo_path,
+            )
             .context("Failed to create new branch")?;
 
         if !status.success() {
@@ -79,244 +196,3978 @@ pub fn create_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Resul
     Ok(original_branch)
 }
 
-/// Stage changes
-pub fn stage_changes(repo_path: &PathBuf, _files: &[&str], dry_run: bool) -> Result<()> {
+/// Branch exists in the local repository
+pub fn local_branch_exists(ctx: &Context, repo_path: &str, branch_name: &str) -> Result<bool> {
+    let path = expand_path(repo_path)?;
+    if let Some(exists) = native(ctx, &path, |p| gitlib::local_branch_exists(p, branch_name)) {
+        return Ok(exists);
+    }
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "for-each-ref",
+            "--format=%(refname)",
+            &format!("refs/heads/{}", branch_name),
+        ]))
+        .context("Failed to list branches")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to list branches: {}", output.tail());
+    }
+
+    Ok(!output.stdout.is_empty())
+}
+
+/// What a directory is to git, to tell whether it can be configured as a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepositoryLayout {
+    /// A repository without a working tree
+    Bare,
+    /// Inside the `.git` directory of a repository
+    GitDir,
+    /// Inside a working tree: a clone, a linked worktree or a submodule checkout
+    WorkTree { toplevel: String },
+}
+
+/// Ask git what `repo_path` is, rather than looking for a `.git` directory, which
+/// linked worktrees and submodules have as a file instead
+pub fn repository_layout(ctx: &Context, repo_path: &str) -> Result<RepositoryLayout> {
+    let path = expand_path(repo_path)?;
+    if !path.is_dir() {
+        anyhow::bail!("{} is not a directory", repo_path);
+    }
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "rev-parse",
+            "--is-bare-repository",
+            "--is-inside-work-tree",
+        ]))
+        .context("Failed to inspect repository")?;
+    if !output.success() {
+        anyhow::bail!("{} is not a git repository: {}", repo_path, output.tail());
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut answers = text.lines();
+    match (answers.next(), answers.next()) {
+        (Some("true"), _) => return Ok(RepositoryLayout::Bare),
+        (_, Some("true")) => {}
+        _ => return Ok(RepositoryLayout::GitDir),
+    }
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["rev-parse", "--show-toplevel"]),
+        )
+        .context("Failed to find the top level of the repository")?;
+    if !output.success() {
+        anyhow::bail!(
+            "Failed to find the top level of the repository at {}: {}",
+            repo_path,
+            output.tail()
+        );
+    }
+    let toplevel = String::from_utf8(output.stdout)
+        .context("Repository path is not valid UTF-8")?
+        .trim_end_matches(['\r', '\n'])
+        .to_string();
+    Ok(RepositoryLayout::WorkTree { toplevel })
+}
+
+/// Default branch of origin (e.g. `main`), falling back to `main` if origin/HEAD isn't set
+pub fn default_branch(ctx: &Context, repo_path: &str) -> Result<String> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "symbolic-ref",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ]))
+        .context("Failed to get default branch")?;
+
+    let head = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match head.strip_prefix("origin/") {
+        Some(branch) if output.success() => Ok(branch.to_string()),
+        _ => Ok("main".to_string()),
+    }
+}
+
+/// Date (`YYYY-MM-DD`) and subject of the last commit on the default branch, or on
+/// HEAD without a local default branch. `None` for a repository without commits.
+pub fn last_commit(ctx: &Context, repo_path: &str) -> Result<Option<(String, String)>> {
+    let path = expand_path(repo_path)?;
+    let base = default_branch(ctx, repo_path)?;
+
+    for rev in [base.as_str(), "HEAD"] {
+        let output = ctx
+            .runner
+            .output(&git_command().current_dir(&path).args([
+                "log",
+                "-1",
+                "--format=%cs|%s",
+                rev,
+                "--",
+            ]))
+            .context("Failed to read the last commit")?;
+        if output.success() {
+            let line = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            return Ok(line
+                .split_once('|')
+                .map(|(date, subject)| (date.to_string(), subject.to_string())));
+        }
+    }
+    Ok(None)
+}
+
+/// Refuse to go on unless `branch_name` is checked out and, without `allow_protected`,
+/// it is neither the default branch nor one matching `protected` (globs like `release/*`)
+pub fn ensure_safe_branch(
+    ctx: &Context,
+    repo_path: &str,
+    branch_name: &str,
+    protected: &[String],
+    allow_protected: bool,
+) -> Result<()> {
+    let current = get_current_branch(ctx, repo_path)?;
+    if current != branch_name {
+        ctx.log.event(
+            "unexpected_branch",
+            &[
+                ("repo", repo_path),
+                ("expected", branch_name),
+                ("current", &current.to_string()),
+            ],
+        );
+        anyhow::bail!(
+            "Expected branch '{}' to be checked out in {}, but '{}' is; refusing to commit",
+            branch_name,
+            repo_path,
+            current
+        );
+    }
+
+    if allow_protected {
+        return Ok(());
+    }
+
+    let default = default_branch(ctx, repo_path)?;
+    if branch_name == default || protected.iter().any(|p| matches_glob(p, branch_name)) {
+        ctx.log.event(
+            "protected_branch",
+            &[("repo", repo_path), ("branch", branch_name)],
+        );
+        anyhow::bail!(
+            "Refusing to commit to protected branch '{}' in {} (pass --allow-protected to override)",
+            branch_name,
+            repo_path
+        );
+    }
+
+    Ok(())
+}
+
+/// Delete a local branch, merged or not
+pub fn delete_local_branch(ctx: &Context, repo_path: &str, branch_name: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["branch", "-D", branch_name]),
+        )
+        .context("Failed to delete branch")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to delete branch {}: {}", branch_name, output.tail());
+    }
+
+    Ok(())
+}
+
+/// Delete a branch on origin. A branch that is already gone is not an error.
+pub fn delete_remote_branch(ctx: &Context, repo_path: &str, branch_name: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .output_with_retry(
+            &git_command()
+                .timeout(ctx.timeouts.git)
+                .current_dir(&path)
+                .args(["push", "origin", "--delete", branch_name]),
+        )
+        .context("Failed to delete remote branch")?;
+
+    if !output.success() && !output.tail().contains("remote ref does not exist") {
+        anyhow::bail!(
+            "Failed to delete remote branch {}: {}",
+            branch_name,
+            output.tail()
+        );
+    }
+
+    Ok(())
+}
+
+/// Stage the changed files of the repository that match [`DEFAULT_ALLOWED_PATHS`] or
+/// `files` (globs relative to the repository). Other changes, such as a node_modules
+/// that isn't ignored, are left out of the commit with a warning.
+pub fn stage_changes(
+    ctx: &Context,
+    repo_path: &PathBuf,
+    files: &[String],
+    dry_run: bool,
+) -> Result<()> {
     if dry_run {
-        println!("Would stage all changes in {}", repo_path.display());
+        println!("Would stage the changed files in {}", repo_path.display());
         return Ok(());
     }
 
-    // git add .
-    let output = Command::new("git")
-        .current_dir(repo_path)
-        .args(["add", "."])
-        .output()
-        .context("Failed to execute git add")?;
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(repo_path).args([
+            "ls-files",
+            "-z",
+            "--modified",
+            "--deleted",
+            "--others",
+            "--exclude-standard",
+        ]))
+        .context("Failed to list changed files")?;
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Failed to stage changes: {}", error);
+    if !output.success() {
+        anyhow::bail!("Failed to list changed files: {}", output.tail());
     }
 
-    // git add parent directory
-    if let Some(parent) = repo_path.parent() {
-        let output = Command::new("git")
-            .current_dir(parent)
-            .args(["add", "."])
-            .output()
-            .context("Failed to stage changes in parent directory")?;
+    let mut changed: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect();
+    // Deleted files are also listed as modified
+    changed.sort();
+    changed.dedup();
+    let (paths, skipped): (Vec<String>, Vec<String>) = changed.into_iter().partition(|path| {
+        DEFAULT_ALLOWED_PATHS
+            .iter()
+            .copied()
+            .chain(files.iter().map(String::as_str))
+            .any(|pattern| matches_glob(pattern, path))
+    });
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Failed to stage changes in parent directory: {}", error);
-        }
+    if !skipped.is_empty() {
+        eprintln!(
+            "Warning: leaving {} changed files out of the commit in {} (allow them with allowed_paths): {}{}",
+            skipped.len(),
+            repo_path.display(),
+            skipped.iter().take(5).cloned().collect::<Vec<_>>().join(", "),
+            if skipped.len() > 5 { ", ..." } else { "" }
+        );
+    }
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(repo_path)
+                .args(["add", "--all", "--"])
+                .args(&paths),
+        )
+        .context("Failed to execute git add")?;
+
+    if !output.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to stage changes: {}", error);
     }
 
     Ok(())
 }
 
 /// Commit changes
-pub fn commit_changes(repo_path: &str, message: &str, dry_run: bool) -> Result<()> {
+/// Returns whether a commit was made; nothing is committed without staged changes.
+pub fn commit_changes(
+    ctx: &Context,
+    repo_path: &str,
+    message: &str,
+    dry_run: bool,
+) -> Result<bool> {
     let path = expand_path(repo_path)?;
 
     if dry_run {
         println!("Would commit changes with message: '{}'", message);
-        return Ok(());
+        return Ok(true);
     }
 
     println!("Committing changes with message: '{}'", message);
 
     // Check if there are staged changes
-    let output = Command::new("git")
-        .current_dir(&path)
-        .args(["diff", "--staged", "--name-only"])
-        .output()
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["diff", "--staged", "--name-only"]),
+        )
         .context("Failed to check staged changes")?;
 
     if output.stdout.is_empty() {
         println!("No staged changes to commit");
-        return Ok(());
+        return Ok(false);
     }
 
     // Commit changes
-    let status = Command::new("git")
-        .current_dir(&path)
-        .args(["commit", "-m", message])
-        .status()
+    let status = ctx
+        .stream(
+            &git_command()
+                .current_dir(&path)
+                .args(["commit", "-m", message]),
+            repo_path,
+        )
         .context("Failed to commit changes")?;
 
     if !status.success() {
-        anyhow::bail!("Failed to commit changes");
+        return Err(GitRejection::new("Commit".to_string(), &status).into());
     }
 
-    Ok(())
+    Ok(true)
 }
 
-/// Push branch
-pub fn push_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Result<()> {
-    let path = expand_path(repo_path)?;
+/// Trailer naming the campaign that made a commit
+pub const CAMPAIGN_TRAILER: &str = "Mru-Campaign";
+
+/// Trailer naming the package and version a commit updated to, e.g. `lodash@4.17.21`
+pub const PACKAGE_TRAILER: &str = "Mru-Package";
+
+/// Notes ref the pull request URL of a campaign commit is kept in
+pub const NOTES_REF: &str = "refs/notes/mru";
+
+/// `message` with `trailers` appended the way `git interpret-trailers` expects: in the
+/// last paragraph, after a blank line unless that paragraph is a trailer block already
+pub fn with_trailers(message: &str, trailers: &[(&str, &str)]) -> String {
+    let message = message.trim_end();
+    let mut paragraphs = message.rsplitn(2, "\n\n");
+    let last = paragraphs.next().unwrap_or_default();
+    let in_block = paragraphs.next().is_some()
+        && last.lines().all(|line| {
+            line.split_once(':').is_some_and(|(key, _)| {
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+        });
+    let mut out = message.to_string();
+    out.push_str(if in_block { "\n" } else { "\n\n" });
+    let lines: Vec<String> = trailers
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    out.push_str(&lines.join("\n"));
+    out
+}
 
+/// Attach `note` to `rev` in `refs/notes/mru`, replacing an earlier one, and push the notes
+/// so other clones can see them. Failing to push only warns.
+pub fn add_note(
+    ctx: &Context,
+    repo_path: &str,
+    rev: &str,
+    note: &str,
+    dry_run: bool,
+) -> Result<()> {
     if dry_run {
-        println!("Would push branch '{}' to origin", branch_name);
+        println!("Would add a note to {} in {}", rev, NOTES_REF);
         return Ok(());
     }
+    let path = expand_path(repo_path)?;
+    let ref_arg = format!("--ref={}", NOTES_REF);
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["notes", &ref_arg, "add", "-f", "-m", note, rev]),
+        )
+        .context("Failed to add a git note")?;
+    if !output.success() {
+        anyhow::bail!("Failed to add a git note: {}", output.tail());
+    }
 
-    println!("Pushing branch '{}' to origin", branch_name);
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .timeout(ctx.timeouts.git)
+                .current_dir(&path)
+                .args(["push", "origin", NOTES_REF]),
+        )
+        .context("Failed to push git notes")?;
+    if !output.success() {
+        eprintln!(
+            "Warning: {}: failed to push {}: {}",
+            repo_path,
+            NOTES_REF,
+            output.tail()
+        );
+    }
+    Ok(())
+}
 
-    let status = Command::new("git")
-        .current_dir(&path)
-        .args(["push", "--set-upstream", "origin", branch_name])
-        .status()
-        .context("Failed to push branch")?;
+/// A commit a campaign made, found by its trailers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CampaignCommit {
+    pub sha: String,
+    pub campaign: String,
+    /// Value of the `Mru-Package` trailer, e.g. `lodash@4.17.21`
+    pub package: Option<String>,
+    /// From the commit's note in `refs/notes/mru`
+    pub pr_url: Option<String>,
+    /// Local and remote branches containing the commit
+    pub branches: Vec<String>,
+}
 
-    if !status.success() {
-        anyhow::bail!("Failed to push branch: {}", branch_name);
+/// Commits on any ref of the repository with an `Mru-Campaign` trailer, only those of
+/// `campaign_id` if given, newest first. This works without the campaign's state file.
+pub fn campaign_commits(
+    ctx: &Context,
+    repo_path: &str,
+    campaign_id: Option<&str>,
+) -> Result<Vec<CampaignCommit>> {
+    let path = expand_path(repo_path)?;
+    let grep = format!("--grep={}: {}", CAMPAIGN_TRAILER, campaign_id.unwrap_or(""));
+    let notes = format!("--notes={}", NOTES_REF);
+    let format = format!(
+        "--format=%H%x1f%(trailers:key={},valueonly,separator=%x2C)%x1f\
+         %(trailers:key={},valueonly,separator=%x2C)%x1f%N%x1e",
+        CAMPAIGN_TRAILER, PACKAGE_TRAILER
+    );
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "log",
+            "--all",
+            "--fixed-strings",
+            &grep,
+            &notes,
+            &format,
+        ]))
+        .context("Failed to search the git log")?;
+    if !output.success() {
+        anyhow::bail!("Failed to search the git log: {}", output.tail());
     }
 
-    Ok(())
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut commits = Vec::new();
+    for record in stdout.split('\x1e') {
+        let fields: Vec<&str> = record.trim_start_matches('\n').split('\x1f').collect();
+        let [sha, campaign, package, note] = fields.as_slice() else {
+            continue;
+        };
+        // --grep matches the text anywhere, not only the trailer's whole value
+        let campaign = campaign.trim();
+        if campaign.is_empty() || campaign_id.is_some_and(|id| id != campaign) {
+            continue;
+        }
+        let package = package.trim();
+        let note = note.trim();
+        commits.push(CampaignCommit {
+            sha: sha.to_string(),
+            campaign: campaign.to_string(),
+            package: (!package.is_empty()).then(|| package.to_string()),
+            pr_url: note.lines().next().map(str::to_string),
+            branches: branches_containing(ctx, repo_path, sha)?,
+        });
+    }
+    Ok(commits)
 }
 
-/// Return to original branch
-pub fn checkout_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Result<()> {
+/// Short names of the local and remote branches that contain `sha`
+fn branches_containing(ctx: &Context, repo_path: &str, sha: &str) -> Result<Vec<String>> {
     let path = expand_path(repo_path)?;
+    let contains = format!("--contains={}", sha);
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "for-each-ref",
+            &contains,
+            "--format=%(refname:short)",
+            "refs/heads",
+            "refs/remotes",
+        ]))
+        .context("Failed to list branches")?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
 
-    if dry_run {
-        println!("Would checkout branch '{}' in {}", branch_name, repo_path);
-        return Ok(());
+/// Files an update may always stage: the manifest, lockfiles, changelog and changesets
+pub const DEFAULT_ALLOWED_PATHS: [&str; 7] = [
+    "package.json",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "CHANGELOG.md",
+    ".changeset/*",
+];
+
+/// Most files an update may stage unless `max_staged_files` says otherwise
+pub const DEFAULT_MAX_STAGED_FILES: usize = 50;
+
+/// Paths in the index that differ from HEAD
+pub fn staged_paths(ctx: &Context, repo_path: &str) -> Result<Vec<String>> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["diff", "--staged", "--name-only", "-z"]),
+        )
+        .context("Failed to check staged changes")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to check staged changes: {}", output.tail());
     }
 
-    println!("Checking out branch '{}' in {}", branch_name, repo_path);
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect())
+}
 
-    let status = Command::new("git")
-        .current_dir(&path)
-        .args(["checkout", branch_name])
-        .status()
-        .context("Failed to checkout branch")?;
+/// Refuse to commit staged paths that match neither [`DEFAULT_ALLOWED_PATHS`] nor
+/// `allowed` (globs), or more than `max_files` of them, e.g. a node_modules that
+/// isn't ignored and was just filled by the install
+pub fn check_staged_paths(
+    ctx: &Context,
+    repo_path: &str,
+    allowed: &[String],
+    max_files: usize,
+) -> Result<()> {
+    let staged = staged_paths(ctx, repo_path)?;
+    let unexpected: Vec<&String> = staged
+        .iter()
+        .filter(|path| {
+            !DEFAULT_ALLOWED_PATHS
+                .iter()
+                .copied()
+                .chain(allowed.iter().map(String::as_str))
+                .any(|pattern| matches_glob(pattern, path))
+        })
+        .collect();
 
-    if !status.success() {
-        anyhow::bail!("Failed to checkout branch: {}", branch_name);
+    if !unexpected.is_empty() {
+        let mut listing: Vec<String> = unexpected
+            .iter()
+            .take(20)
+            .map(|path| format!("  {}", path))
+            .collect();
+        if unexpected.len() > 20 {
+            listing.push(format!("  ... and {} more", unexpected.len() - 20));
+        }
+        ctx.log.event(
+            "unexpected_paths",
+            &[
+                ("repo", repo_path),
+                ("count", &unexpected.len().to_string()),
+            ],
+        );
+        anyhow::bail!(
+            "Refusing to commit {} unexpected files in {}:\n{}\n\
+             Add them to .gitignore, or allow them with allowed_paths in the config or --allow-extra-paths",
+            unexpected.len(),
+            repo_path,
+            listing.join("\n")
+        );
+    }
+
+    if staged.len() > max_files {
+        anyhow::bail!(
+            "Refusing to commit {} files in {} (limit {}); raise max_staged_files in the config to allow it",
+            staged.len(),
+            repo_path,
+            max_files
+        );
     }
 
     Ok(())
 }
 
-/// Check repository status
-pub fn check_status(repo_path: &str) -> Result<bool> {
+/// Files and line counts changed by a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diffstat {
+    pub files: Vec<String>,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl Diffstat {
+    /// Parse `git show --numstat` output; binary files count as changed without lines
+    pub fn parse(numstat: &str) -> Self {
+        let mut stat = Diffstat {
+            files: Vec::new(),
+            insertions: 0,
+            deletions: 0,
+        };
+        for line in numstat.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let (Some(added), Some(deleted), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            stat.insertions += added.parse::<usize>().unwrap_or(0);
+            stat.deletions += deleted.parse::<usize>().unwrap_or(0);
+            stat.files.push(path.to_string());
+        }
+        stat
+    }
+
+    /// Summary in git's words, e.g. "2 files changed, 6 insertions(+), 6 deletions(-)"
+    pub fn summary(&self) -> String {
+        let plural =
+            |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        let mut parts = vec![plural(self.files.len(), "file changed", "files changed")];
+        if self.insertions > 0 {
+            parts.push(plural(self.insertions, "insertion(+)", "insertions(+)"));
+        }
+        if self.deletions > 0 {
+            parts.push(plural(self.deletions, "deletion(-)", "deletions(-)"));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Diffstat of the HEAD commit
+pub fn head_diffstat(ctx: &Context, repo_path: &str) -> Result<Diffstat> {
     let path = expand_path(repo_path)?;
 
-    let output = Command::new("git")
-        .current_dir(&path)
-        .args(["status", "--porcelain"])
-        .output()
-        .context("Failed to check git status")?;
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["show", "--format=", "--numstat", "HEAD"]),
+        )
+        .context("Failed to read the commit's diffstat")?;
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to check git status");
+    if !output.success() {
+        anyhow::bail!("Failed to read the commit's diffstat: {}", output.tail());
     }
 
-    // Check if there are changes (non-empty output means changes)
-    let has_changes = !output.stdout.is_empty();
+    Ok(Diffstat::parse(&String::from_utf8_lossy(&output.stdout)))
+}
 
-    Ok(has_changes)
+/// Contents of `file`, relative to `dir`, as committed at `git_ref`, e.g. `origin/main`
+pub fn show_file(ctx: &Context, dir: &str, git_ref: &str, file: &str) -> Result<String> {
+    let path = expand_path(dir)?;
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .arg("show")
+                .arg(format!("{}:./{}", git_ref, file)),
+        )
+        .with_context(|| format!("Failed to read {} at {}", file, git_ref))?;
+
+    if !output.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("invalid object name") || stderr.contains("Invalid object name") {
+            anyhow::bail!("ref '{}' doesn't exist in {}", git_ref, dir);
+        }
+        if stderr.contains("does not exist in") || stderr.contains("but not in") {
+            anyhow::bail!("{} isn't committed at {} in {}", file, git_ref, dir);
+        }
+        anyhow::bail!("Failed to read {} at {}: {}", file, git_ref, output.tail());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-/// Pull repository
-pub fn pull_repository(repo_path: &str, dry_run: bool) -> Result<()> {
+/// The package.json diff and the lockfile diffstat of the commit at the tip of `branch`
+pub fn manifest_diff(ctx: &Context, repo_path: &str, branch: &str) -> Result<(String, String)> {
     let path = expand_path(repo_path)?;
+    let show = |args: Vec<String>| -> Result<String> {
+        let output = ctx
+            .runner
+            .output(&git_command().current_dir(&path).args(&args))
+            .context("Failed to read the commit's diff")?;
+        if !output.success() {
+            anyhow::bail!("Failed to read the commit's diff: {}", output.tail());
+        }
+        Ok(runner::escape_bytes(&output.stdout).trim_end().to_string())
+    };
 
-    if dry_run {
-        println!("Would pull latest changes in {}", repo_path);
-        return Ok(());
+    let args = |extra: &[&str], files: &[&str]| {
+        ["show", "--format="]
+            .iter()
+            .chain(extra)
+            .chain(&[branch, "--"])
+            .map(|arg| arg.to_string())
+            .chain(files.iter().map(|file| format!(":(glob)**/{}", file)))
+            .collect()
+    };
+    let diff = show(args(&[], &["package.json"]))?;
+    let stat = show(args(&["--stat"], &LOCKFILES))?;
+    Ok((diff, stat))
+}
+
+/// Why git refused a commit or push, from the signature of its output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionKind {
+    ProtectedBranch,
+    NonFastForward,
+    Authentication,
+    HookRejected,
+    Other,
+}
+
+impl RejectionKind {
+    pub fn classify(output: &str) -> Self {
+        let output = output.to_lowercase();
+        let has = |markers: &[&str]| markers.iter().any(|m| output.contains(m));
+
+        if has(&["protected branch", "gh006"]) {
+            RejectionKind::ProtectedBranch
+        } else if has(&[
+            "authentication failed",
+            "permission denied",
+            "could not read username",
+            "invalid username or password",
+            "terminal prompts disabled",
+            "the requested url returned error: 403",
+        ]) {
+            RejectionKind::Authentication
+        } else if has(&[
+            "non-fast-forward",
+            "fetch first",
+            "tip of your current branch",
+        ]) {
+            RejectionKind::NonFastForward
+        } else if has(&["hook"]) {
+            RejectionKind::HookRejected
+        } else {
+            RejectionKind::Other
+        }
     }
 
-    println!("Pulling latest changes in {}", repo_path);
+    fn label(self) -> &'static str {
+        match self {
+            RejectionKind::ProtectedBranch => "the branch is protected",
+            RejectionKind::NonFastForward => "non-fast-forward",
+            RejectionKind::Authentication => "authentication failed",
+            RejectionKind::HookRejected => "rejected by a git hook",
+            RejectionKind::Other => "failed",
+        }
+    }
+
+    fn advice(self) -> Option<&'static str> {
+        match self {
+            RejectionKind::ProtectedBranch => Some(
+                "The remote doesn't accept pushes to this branch; use another branch name \
+                 (e.g. with --type) or ask for the protection rule to be changed",
+            ),
+            RejectionKind::NonFastForward => Some(
+                "The remote branch has commits the local one doesn't, likely from an earlier run; \
+                 delete the remote branch or push it with `git push --force-with-lease`",
+            ),
+            RejectionKind::Authentication => Some(
+                "Re-authenticate with `gh auth login`, or check your SSH key or credential helper",
+            ),
+            RejectionKind::HookRejected => {
+                Some("Fix what the hook reported (see its output), then run `mru resume`")
+            }
+            RejectionKind::Other => None,
+        }
+    }
+}
 
-    let status = Command::new("git")
-        .current_dir(&path)
-        .args(["pull"])
-        .status()
-        .context("Failed to pull repository")?;
+/// A commit or push that git refused, with its captured output
+#[derive(Debug)]
+pub struct GitRejection {
+    /// What was attempted, e.g. `Push of branch 'update-react-18.2.0'`
+    pub action: String,
+    pub kind: RejectionKind,
+    /// Last lines of git's output
+    pub output: String,
+    /// URL of the remote that was pushed to or cloned from, without credentials
+    pub remote: Option<String>,
+}
 
-    if !status.success() {
-        anyhow::bail!("Failed to pull repository: {}", repo_path);
+impl GitRejection {
+    pub fn new(action: String, output: &CommandOutput) -> Self {
+        let output = output.tail();
+        GitRejection {
+            action,
+            kind: RejectionKind::classify(&output),
+            output,
+            remote: None,
+        }
     }
 
-    Ok(())
+    pub fn with_remote(mut self, remote: Option<String>) -> Self {
+        self.remote = remote
+            .filter(|url| !url.is_empty())
+            .map(|url| crate::log::redact(&url));
+        self
+    }
 }
 
-/// Execute package update workflow
-pub fn update_package_workflow(
-    repo: &Repository,
-    package_name: &str,
-    version: &str,
-    commit_message: &str,
-    create_pr: bool,
-    dry_run: bool,
-    config: &Config,
-) -> Result<()> {
-    println!("\n=== Processing repository: {} ===", repo.path);
+/// What to do about a remote that wants credentials, by how it is reached
+fn authentication_advice(remote: &str) -> &'static str {
+    if remote.starts_with("http://") || remote.starts_with("https://") {
+        "git can't prompt for a password while mru runs; set up a credential helper \
+         (`gh auth setup-git`), or switch the remote to SSH"
+    } else {
+        "Check that your SSH key is loaded (`ssh-add -l`) and has access to the repository"
+    }
+}
 
-    // 1. Save current branch
-    let original_branch = get_current_branch(&repo.path)?;
+impl std::fmt::Display for GitRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let (RejectionKind::Authentication, Some(remote)) = (self.kind, &self.remote) {
+            return write!(
+                f,
+                "{}: authentication required for {}. {}",
+                self.action,
+                remote,
+                authentication_advice(remote)
+            );
+        }
+        write!(f, "{}: {}", self.action, self.kind.label())?;
+        match self.kind.advice() {
+            Some(advice) => write!(f, ". {}", advice),
+            // Without a known cause, git's own message is the best explanation
+            None => match self.output.lines().last() {
+                Some(line) => write!(f, ": {}", line.trim()),
+                None => Ok(()),
+            },
+        }
+    }
+}
 
-    // 2. Create branch
-    let branch_name = format!(
-        "update-{}-{}",
-        package_name,
-        version.replace("^", "").replace("~", "")
-    );
-    create_branch(&repo.path, &branch_name, dry_run)?;
+impl std::error::Error for GitRejection {}
 
-    // 3. Update package.json (this function is in package.rs)
-    let updated = crate::package::update_package(&repo.path, package_name, version, dry_run)?;
+/// Push branch
+pub fn push_branch(ctx: &Context, repo_path: &str, branch_name: &str, dry_run: bool) -> Result<()> {
+    let path = expand_path(repo_path)?;
 
-    if !updated {
-        println!(
-            "Package '{}' is already at version '{}', skipping",
-            package_name, version
-        );
-        // Return to original branch
-        checkout_branch(&repo.path, &original_branch, dry_run)?;
+    if dry_run {
+        println!("Would push branch '{}' to origin", branch_name);
         return Ok(());
     }
 
-    // 4. Run package install with default package manager
-    let pkg_manager = match crate::package::detect_package_manager(&repo.path) {
-        Ok(manager) => manager,
-        Err(_) => config.default_package_manager.clone().unwrap(),
-    };
-    crate::package::run_install_with_manager(&repo.path, &pkg_manager, dry_run)?;
+    println!("Pushing branch '{}' to origin", branch_name);
 
-    // 5. Stage changes
-    stage_changes(&PathBuf::from(&repo.path), &[], dry_run)?;
+    let output = ctx
+        .stream_with_retry(
+            &git_command()
+                .timeout(ctx.timeouts.git)
+                .current_dir(&path)
+                .args(["push", "--set-upstream", "origin", branch_name]),
+            repo_path,
+        )
+        .context("Failed to push branch")?;
 
-    // 6. Commit changes
-    commit_changes(&repo.path, commit_message, dry_run)?;
+    if !output.success() {
+        let remote = crate::github::origin_url(ctx, repo_path);
+        let rejection = GitRejection::new(format!("Push of branch '{}'", branch_name), &output);
+        return Err(rejection.with_remote(remote).into());
+    }
 
-    // 7. Push to GitHub
-    push_branch(&repo.path, &branch_name, dry_run)?;
+    Ok(())
+}
 
-    // 8. Create PR (optional) - this function will be implemented in github.rs
-    if create_pr {
-        if let Err(e) = crate::github::create_pr(
-            &repo.path,
-            &branch_name,
-            commit_message,
-            dry_run,
-            true, // draft by default
-            None, // use default body
-        ) {
-            eprintln!("Warning: Failed to create PR: {}", e);
-        }
-    }
+/// Number of commits on HEAD that are not on `origin/<base>`,
+/// or `None` if they cannot be compared, e.g. because the remote branch was never fetched
+pub fn commits_ahead(ctx: &Context, repo_path: &str, base: &str) -> Result<Option<u64>> {
+    let path = expand_path(repo_path)?;
 
-    println!(
-        "✅ Successfully updated {} to {} in {}",
-        package_name, version, repo.path
-    );
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "rev-list",
+            "--count",
+            &format!("origin/{}..HEAD", base),
+        ]))
+        .context("Failed to count commits")?;
 
-    // 9. Return to original branch
-    checkout_branch(&repo.path, &original_branch, dry_run)?;
+    if !output.success() {
+        return Ok(None);
+    }
 
-    Ok(())
+    Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+}
+
+/// Create an annotated tag on HEAD
+pub fn create_tag(ctx: &Context, repo_path: &str, tag: &str, message: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    println!("Tagging {} as {}", repo_path, tag);
+    run_git(ctx, &path, &["tag", "-a", tag, "-m", message])
+}
+
+/// Push a tag to origin
+pub fn push_tag(ctx: &Context, repo_path: &str, tag: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    println!("Pushing tag '{}' to origin", tag);
+    let output = ctx
+        .stream_with_retry(
+            &git_command()
+                .timeout(ctx.timeouts.git)
+                .current_dir(&path)
+                .args(["push", "origin", &format!("refs/tags/{}", tag)]),
+            repo_path,
+        )
+        .context("Failed to push tag")?;
+
+    if !output.success() {
+        let remote = crate::github::origin_url(ctx, repo_path);
+        let rejection = GitRejection::new(format!("Push of tag '{}'", tag), &output);
+        return Err(rejection.with_remote(remote).into());
+    }
+
+    Ok(())
+}
+
+/// Return to original branch
+pub fn checkout_branch(
+    ctx: &Context,
+    repo_path: &str,
+    branch_name: impl AsRef<OsStr>,
+    dry_run: bool,
+) -> Result<()> {
+    let path = expand_path(repo_path)?;
+    let branch_name = branch_name.as_ref();
+    let shown = runner::escape_os_str(branch_name);
+
+    if dry_run {
+        println!("Would checkout branch '{}' in {}", shown, repo_path);
+        return Ok(());
+    }
+
+    println!("Checking out branch '{}' in {}", shown, repo_path);
+
+    let status = ctx
+        .stream(
+            &git_command()
+                .current_dir(&path)
+                .arg("checkout")
+                .arg(branch_name),
+            repo_path,
+        )
+        .context("Failed to checkout branch")?;
+
+    if !status.success() {
+        anyhow::bail!("Failed to checkout branch: {}", shown);
+    }
+
+    Ok(())
+}
+
+/// Check repository status
+pub fn check_status(ctx: &Context, repo_path: &str) -> Result<bool> {
+    let path = expand_path(repo_path)?;
+    if let Some(dirty) = native(ctx, &path, gitlib::is_dirty) {
+        return Ok(dirty);
+    }
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["status", "--porcelain=v2"]),
+        )
+        .context("Failed to check git status")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to check git status");
+    }
+
+    // Check if there are changes (non-empty output means changes)
+    let has_changes = !output.stdout.is_empty();
+
+    Ok(has_changes)
+}
+
+/// Commits behind and ahead of the upstream branch, or `None` if there is no upstream
+pub fn ahead_behind(ctx: &Context, repo_path: &str) -> Result<Option<(usize, usize)>> {
+    let path = expand_path(repo_path)?;
+    if let Some(counts) = native(ctx, &path, gitlib::ahead_behind) {
+        return Ok(counts);
+    }
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "--untracked-files=no",
+        ]))
+        .context("Failed to compare with upstream")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to compare with upstream: {}", output.tail());
+    }
+
+    // `# branch.ab +<ahead> -<behind>` is only printed when the branch has an upstream;
+    // a detached HEAD never has one
+    let text = String::from_utf8_lossy(&output.stdout);
+    let Some(line) = text.lines().find_map(|l| l.strip_prefix("# branch.ab ")) else {
+        return Ok(None);
+    };
+    let mut counts = line
+        .split_whitespace()
+        .map(|n| n.trim_start_matches(['+', '-']).parse::<usize>());
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some((behind, ahead))),
+        _ => anyhow::bail!("Unexpected output from git status: {}", line),
+    }
+}
+
+/// Local branches matching a pattern such as `update-*`
+pub fn list_branches(ctx: &Context, repo_path: &str, pattern: &str) -> Result<Vec<String>> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "for-each-ref",
+            "--format=%(refname:short)",
+            &format!("refs/heads/{}", pattern),
+        ]))
+        .context("Failed to list branches")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to list branches: {}", output.tail());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+/// URL of a remote, or `None` if the repository has no such remote
+pub fn remote_url(ctx: &Context, repo_path: &str, remote: &str) -> Option<String> {
+    let path = expand_path(repo_path).ok()?;
+    if let Some(url) = native(ctx, &path, |p| gitlib::remote_url(p, remote)) {
+        return url;
+    }
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["remote", "get-url", remote]),
+        )
+        .ok()?;
+
+    output
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Point a remote at `url`, adding it if it doesn't exist
+pub fn set_remote(ctx: &Context, repo_path: &str, remote: &str, url: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+    let action = if remote_url(ctx, repo_path, remote).is_some() {
+        "set-url"
+    } else {
+        "add"
+    };
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["remote", action, remote, url]),
+        )
+        .context("Failed to configure remote")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to configure remote {}: {}", remote, output.tail());
+    }
+
+    Ok(())
+}
+
+/// Rename a remote, e.g. `origin` to `upstream`
+pub fn rename_remote(ctx: &Context, repo_path: &str, from: &str, to: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["remote", "rename", from, to]),
+        )
+        .context("Failed to rename remote")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to rename remote {}: {}", from, output.tail());
+    }
+
+    Ok(())
+}
+
+/// Fetch remote refs from origin
+pub fn fetch(ctx: &Context, repo_path: &str) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .output_with_retry(
+            &git_command()
+                .timeout(ctx.timeouts.git)
+                .current_dir(&path)
+                .args(["fetch", "--quiet", "origin"]),
+        )
+        .context("Failed to fetch")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to fetch: {}", output.tail());
+    }
+
+    Ok(())
+}
+
+/// Checked-out base branch compared with its upstream, see [`sync_base`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseSync {
+    /// Up to date with its upstream, or without one
+    UpToDate,
+    /// Was behind and has been fast-forwarded by this many commits
+    FastForwarded(usize),
+    /// Behind, but uncommitted changes keep it from being fast-forwarded
+    Stale { behind: usize },
+    /// Has commits origin doesn't have
+    Diverged { ahead: usize, behind: usize },
+}
+
+impl BaseSync {
+    /// Why an update branched from this base would be wrong, if it would
+    pub fn problem(self) -> Option<String> {
+        match self {
+            BaseSync::UpToDate | BaseSync::FastForwarded(_) => None,
+            BaseSync::Stale { behind } => Some(format!(
+                "local base is {} behind origin and has uncommitted changes",
+                behind
+            )),
+            BaseSync::Diverged { ahead, behind } => Some(format!(
+                "local base has diverged from origin ({} ahead, {} behind)",
+                ahead, behind
+            )),
+        }
+    }
+}
+
+/// Fetch origin and fast-forward the checked-out branch to its upstream when that is safe:
+/// it has no commits of its own and no uncommitted changes
+pub fn sync_base(ctx: &Context, repo_path: &str) -> Result<BaseSync> {
+    fetch(ctx, repo_path)?;
+
+    let (behind, ahead) = match ahead_behind(ctx, repo_path)? {
+        Some(counts) => counts,
+        None => return Ok(BaseSync::UpToDate),
+    };
+    if ahead > 0 {
+        return Ok(BaseSync::Diverged { ahead, behind });
+    }
+    if behind == 0 {
+        return Ok(BaseSync::UpToDate);
+    }
+    if check_status(ctx, repo_path)? {
+        return Ok(BaseSync::Stale { behind });
+    }
+
+    println!("Fast-forwarding {} by {} commits", repo_path, behind);
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(expand_path(repo_path)?).args([
+            "merge",
+            "--ff-only",
+            "--quiet",
+            "@{upstream}",
+        ]))
+        .context("Failed to fast-forward")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to fast-forward {}: {}", repo_path, output.tail());
+    }
+
+    Ok(BaseSync::FastForwarded(behind))
+}
+
+/// What the update workflow does with an extra manifest inside a git submodule, whose
+/// files belong to the submodule's repository rather than the configured one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubmoduleMode {
+    /// Leave it alone with a warning
+    #[default]
+    Skip,
+    /// Run the workflow in the submodule's repository: its own branch, commit, push and PR
+    Recurse,
+    /// Like `Recurse`, then commit the submodule's new commit in the parent repository too
+    RecurseAndCommit,
+}
+
+/// Paths of the submodules listed in `.gitmodules`, relative to the repository
+pub fn submodule_paths(ctx: &Context, repo_path: &str) -> Result<Vec<String>> {
+    let path = expand_path(repo_path)?;
+    if !path.join(".gitmodules").is_file() {
+        return Ok(Vec::new());
+    }
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "config",
+            "--file",
+            ".gitmodules",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ]))
+        .context("Failed to read .gitmodules")?;
+    // Exits with 1 when no submodule has a path
+    if !output.success() && output.code != Some(1) {
+        anyhow::bail!("Failed to read .gitmodules: {}", output.tail());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(_, sub)| sub.trim().trim_end_matches('/').to_string())
+        .collect())
+}
+
+/// Submodule of `submodules` that the directory `dir` is in, and `dir` relative to it
+fn in_submodule<'a, 'b>(dir: &'b str, submodules: &'a [String]) -> Option<(&'a str, &'b str)> {
+    let dir = dir.trim_start_matches("./").trim_end_matches('/');
+    submodules
+        .iter()
+        .find_map(|sub| match dir.strip_prefix(sub.as_str())? {
+            "" => Some((sub.as_str(), "")),
+            rest => Some((sub.as_str(), rest.strip_prefix('/')?)),
+        })
+}
+
+/// Result of running the update workflow in one repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkflowOutcome {
+    /// Package was updated and pushed
+    Updated {
+        changes: Vec<UpdateOutcome>,
+        pr_url: Option<String>,
+        /// Summary of the commit's diffstat
+        diffstat: Option<String>,
+        /// Branch checked out before the update branch was created
+        original_branch: BranchName,
+        /// Install and pre-update hook commands that ran, with the last lines they printed
+        outputs: Vec<(String, String)>,
+        /// Problems worth a look that didn't stop the update, e.g. unrequested code owners
+        warnings: Vec<String>,
+        /// Milestone and project the PR was given
+        milestone: Option<String>,
+        project: Option<String>,
+        /// Package managers the installs ran with, e.g. `pnpm 9.1.0 (corepack)`
+        package_managers: Vec<String>,
+    },
+    /// Package was already at the requested version or not found
+    Skipped,
+    /// The manifest was edited, but the branch ended up with nothing over the base branch
+    NoChanges,
+    /// The commit was made, but its push was declined after reviewing the diffstat
+    PushDeclined { diffstat: String },
+}
+
+/// Branch name used for a package update
+pub fn update_branch_name(package_name: &str, version: &str) -> String {
+    format!(
+        "update-{}-{}",
+        package_name,
+        version.replace("^", "").replace("~", "")
+    )
+}
+
+/// Open a draft PR for a pushed branch, against `base` or the default branch, with
+/// `metadata`; an existing PR for the branch is given its milestone and project.
+/// `metadata` is left with the milestone and project that were actually set.
+/// Repositories without a GitHub remote are skipped with a warning, as are PR failures.
+#[allow(clippy::too_many_arguments)]
+fn open_pull_request(
+    ctx: &Context,
+    repo: &Repository,
+    branch_name: &str,
+    title: &str,
+    body: Option<&str>,
+    base: Option<&str>,
+    metadata: &mut PrMetadata,
+    dry_run: bool,
+) -> Option<String> {
+    match crate::provider::for_repo(ctx, repo) {
+        Ok(Some(provider)) => {
+            return open_provider_pull_request(
+                ctx,
+                provider.as_ref(),
+                repo,
+                branch_name,
+                title,
+                body,
+                base,
+                metadata,
+                dry_run,
+            )
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Warning: Skipping PR for {}: {:#}", repo.path, e);
+            return None;
+        }
+    }
+
+    let github_url = repo
+        .github_url
+        .clone()
+        .or_else(|| crate::github::detect_github_url(ctx, &repo.path));
+    let Some(github_url) = github_url else {
+        ctx.log.event("pr_skipped", &[("repo", &repo.path)]);
+        let remote = crate::github::origin_url(ctx, &repo.path);
+        match remote.as_deref().and_then(crate::provider::unsupported_host) {
+            Some(service) => eprintln!(
+                "Warning: Skipping PR for {}: its remote is on {}, where pull requests can't be opened",
+                repo.path, service
+            ),
+            None => eprintln!(
+                "Warning: Skipping PR for {}: no GitHub remote found. Set one with 'mru add-repo --url'",
+                repo.path
+            ),
+        }
+        return None;
+    };
+
+    // gh is told the repository explicitly rather than inferring it from the working directory
+    let origin = match crate::github::CloneSource::parse(&github_url, &ctx.github.host) {
+        Ok(origin) => origin,
+        Err(e) => {
+            eprintln!("Warning: Skipping PR for {}: {}", repo.path, e);
+            return None;
+        }
+    };
+
+    // Pull requests from a fork go to the repository it was forked from
+    let (head, base_repo) = match repo
+        .upstream
+        .as_deref()
+        .map(|url| crate::github::CloneSource::parse(url, &ctx.github.host))
+    {
+        Some(Ok(upstream)) => (
+            format!("{}:{}", origin.owner, branch_name),
+            upstream.repo_arg(),
+        ),
+        _ => (branch_name.to_string(), origin.repo_arg()),
+    };
+
+    // Milestones belong to each repository, and gh fails the PR over a missing one
+    if let Some(milestone) = metadata.milestone.clone().filter(|_| !dry_run) {
+        let create = metadata.create_missing_milestone;
+        match crate::github::ensure_milestone(ctx, &base_repo, &milestone, create) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!(
+                    "Warning: {} has no milestone '{}'; pass --create-missing-milestone to create it",
+                    base_repo, milestone
+                );
+                metadata.milestone = None;
+            }
+            Err(e) => {
+                eprintln!("Warning: {:#}", e);
+                metadata.milestone = None;
+            }
+        }
+    }
+
+    match crate::github::create_pr(
+        ctx, &repo.path, &head, title, dry_run, true, // draft by default
+        body, &base_repo, base, metadata,
+    ) {
+        Ok(CreatedPr::New(url)) => Some(url),
+        Ok(CreatedPr::Existing(url)) => {
+            if metadata.milestone.is_some() || metadata.project.is_some() {
+                if let Err(e) = crate::github::edit_pr_metadata(
+                    ctx,
+                    &url,
+                    metadata.milestone.as_deref(),
+                    metadata.project.as_deref(),
+                ) {
+                    eprintln!("Warning: {:#}", e);
+                    metadata.milestone = None;
+                    metadata.project = None;
+                }
+            }
+            Some(url)
+        }
+        Err(e) => {
+            eprintln!("Warning: Failed to create PR: {}", e);
+            None
+        }
+    }
+}
+
+/// Open a PR on Bitbucket or Azure DevOps. Reviewers, milestones and projects are only
+/// set on GitHub, so they are dropped with a warning
+#[allow(clippy::too_many_arguments)]
+fn open_provider_pull_request(
+    ctx: &Context,
+    provider: &dyn Provider,
+    repo: &Repository,
+    branch_name: &str,
+    title: &str,
+    body: Option<&str>,
+    base: Option<&str>,
+    metadata: &mut PrMetadata,
+    dry_run: bool,
+) -> Option<String> {
+    if dry_run {
+        println!(
+            "Would create {} PR for branch '{}' with title: '{}'",
+            provider.kind().label(),
+            branch_name,
+            title
+        );
+        return Some(String::from("dry-run-pr-url"));
+    }
+
+    if !metadata.reviewers.is_empty() || metadata.milestone.is_some() || metadata.project.is_some()
+    {
+        eprintln!(
+            "Warning: {}: reviewers, milestones and projects are only set on GitHub pull requests",
+            repo.path
+        );
+        metadata.milestone = None;
+        metadata.project = None;
+    }
+
+    let base = match base {
+        Some(base) => base.to_string(),
+        None => match default_branch(ctx, &repo.path) {
+            Ok(base) => base,
+            Err(e) => {
+                eprintln!("Warning: Skipping PR for {}: {:#}", repo.path, e);
+                return None;
+            }
+        },
+    };
+    let pr = NewPr {
+        branch: branch_name,
+        base: &base,
+        title,
+        body: body.unwrap_or_default(),
+        // draft by default, like on GitHub
+        draft: true,
+    };
+    match provider.create_pr(ctx, &pr) {
+        Ok(CreatedPr::New(url) | CreatedPr::Existing(url)) => Some(url),
+        Err(e) => {
+            eprintln!("Warning: Failed to create PR: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Commit the working tree changes of a repository to `branch_name`, optionally pushing it
+/// and opening a PR, then return to the original branch.
+/// Returns the PR URL if one was opened.
+pub fn commit_to_branch(
+    ctx: &Context,
+    repo: &Repository,
+    branch_name: &str,
+    message: &str,
+    push: bool,
+    create_pr: bool,
+) -> Result<Option<String>> {
+    // Uncommitted changes are carried over to the new branch, and all of them are committed
+    let original_branch = create_branch(ctx, &repo.path, branch_name, None, false)?;
+    stage_changes(ctx, &expand_path(&repo.path)?, &["*".to_string()], false)?;
+    commit_changes(ctx, &repo.path, message, false)?;
+
+    let mut pr_url = None;
+    if push || create_pr {
+        push_branch(ctx, &repo.path, branch_name, false)?;
+        if create_pr {
+            pr_url = open_pull_request(
+                ctx,
+                repo,
+                branch_name,
+                message,
+                None,
+                None,
+                &mut PrMetadata::default(),
+                false,
+            );
+        }
+    }
+
+    checkout_branch(ctx, &repo.path, &original_branch, false)?;
+    Ok(pr_url)
+}
+
+/// Full hash of the commit `rev` points to, or `None` if there is no such commit
+pub fn resolve_commit(ctx: &Context, repo_path: &str, rev: &str) -> Result<Option<String>> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ]))
+        .context("Failed to resolve commit")?;
+
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.success() && !hash.is_empty()).then_some(hash))
+}
+
+/// Newest commit on any branch whose message contains `message`
+pub fn find_commit_by_message(
+    ctx: &Context,
+    repo_path: &str,
+    message: &str,
+) -> Result<Option<String>> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(&path).args([
+            "log",
+            "--all",
+            "-n1",
+            "--format=%H",
+            "--fixed-strings",
+            &format!("--grep={}", message),
+        ]))
+        .context("Failed to search commits")?;
+
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.success() && !hash.is_empty()).then_some(hash))
+}
+
+/// First line of a commit's message
+pub fn commit_subject(ctx: &Context, repo_path: &str, commit: &str) -> Result<String> {
+    let path = expand_path(repo_path)?;
+
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["log", "-n1", "--format=%s", commit]),
+        )
+        .context("Failed to read commit message")?;
+
+    if !output.success() {
+        anyhow::bail!("Failed to read commit {}: {}", commit, output.tail());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Check out `branch_name` reset to `start`, creating it if needed
+pub fn create_branch_from(
+    ctx: &Context,
+    repo_path: &str,
+    branch_name: &str,
+    start: &str,
+) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    println!(
+        "Creating branch '{}' from '{}' in {}",
+        branch_name, start, repo_path
+    );
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["checkout", "-B", branch_name, start]),
+        )
+        .context("Failed to create branch")?;
+
+    if !output.success() {
+        anyhow::bail!(
+            "Failed to create branch {} from {}: {}",
+            branch_name,
+            start,
+            output.tail()
+        );
+    }
+
+    Ok(())
+}
+
+/// Files with unresolved conflicts
+fn conflicted_files(ctx: &Context, path: &Path) -> Result<Vec<String>> {
+    let output = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(path)
+                .args(["diff", "--name-only", "--diff-filter=U"]),
+        )
+        .context("Failed to list conflicts")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Stage `commit` as the submodule's commit at `sub`, without checking it out there
+/// (`None` in a dry run, before there is one)
+fn stage_gitlink(ctx: &Context, path: &Path, sub: &str, commit: Option<&str>) -> Result<()> {
+    let Some(commit) = commit else {
+        println!("Would stage submodule {} at its new commit", sub);
+        return Ok(());
+    };
+    println!(
+        "Staging submodule {} at {}",
+        sub,
+        &commit[..commit.len().min(7)]
+    );
+    run_git(
+        ctx,
+        path,
+        &[
+            "update-index",
+            "--cacheinfo",
+            &format!("160000,{},{}", commit, sub),
+        ],
+    )
+}
+
+/// Run a git command in `path` that must succeed
+fn run_git(ctx: &Context, path: &Path, args: &[&str]) -> Result<()> {
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(path).args(args))
+        .with_context(|| format!("Failed to run git {}", args.join(" ")))?;
+
+    if !output.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), output.tail());
+    }
+
+    Ok(())
+}
+
+/// Result of backporting a commit onto one release branch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackportOutcome {
+    /// Cherry-picked and pushed, with the PR URL if one was opened
+    Pushed { pr_url: Option<String> },
+    /// The cherry-pick conflicted in these files and was aborted
+    Conflict(Vec<String>),
+}
+
+/// Cherry-pick `commit` onto `branch_name`, created from `origin/<target>`, push it and
+/// open a PR against `target`. A conflict in package.json alone is resolved by editing
+/// the target's package.json again with `update` (package and version); any other
+/// conflict aborts the cherry-pick.
+#[allow(clippy::too_many_arguments)]
+pub fn backport_commit(
+    ctx: &Context,
+    repo: &Repository,
+    commit: &str,
+    target: &str,
+    branch_name: &str,
+    title: &str,
+    update: Option<(&str, &str)>,
+    dry_run: bool,
+) -> Result<BackportOutcome> {
+    let path = expand_path(&repo.path)?;
+
+    if dry_run {
+        println!(
+            "Would cherry-pick {} onto '{}' as '{}' in {}",
+            commit, target, branch_name, repo.path
+        );
+        return Ok(BackportOutcome::Pushed { pr_url: None });
+    }
+
+    create_branch_from(ctx, &repo.path, branch_name, &format!("origin/{}", target))?;
+
+    println!("Cherry-picking {} onto '{}'", commit, target);
+    let picked = ctx
+        .runner
+        .output(
+            &git_command()
+                .current_dir(&path)
+                .args(["cherry-pick", "-x", commit]),
+        )
+        .context("Failed to cherry-pick")?;
+
+    if !picked.success() {
+        let conflicts = conflicted_files(ctx, &path)?;
+        match update {
+            Some((package, version)) if conflicts == ["package.json"] => {
+                println!(
+                    "Resolving the package.json conflict by updating {} again",
+                    package
+                );
+                run_git(ctx, &path, &["checkout", "--ours", "--", "package.json"])?;
+                ctx.manifests.invalidate(&path.join("package.json"));
+                crate::package::update_package(ctx, &repo.path, package, version, false)?;
+                run_git(ctx, &path, &["add", "package.json"])?;
+                let output = ctx
+                    .runner
+                    .output(
+                        &git_command()
+                            .current_dir(&path)
+                            .env("GIT_EDITOR", "true")
+                            .args(["cherry-pick", "--continue"]),
+                    )
+                    .context("Failed to continue cherry-pick")?;
+                if !output.success() {
+                    run_git(ctx, &path, &["cherry-pick", "--abort"])?;
+                    anyhow::bail!("Failed to continue cherry-pick: {}", output.tail());
+                }
+            }
+            _ => {
+                run_git(ctx, &path, &["cherry-pick", "--abort"])?;
+                ctx.log.event(
+                    "backport_conflict",
+                    &[("repo", &repo.path), ("target", target)],
+                );
+                return Ok(BackportOutcome::Conflict(conflicts));
+            }
+        }
+    }
+
+    push_branch(ctx, &repo.path, branch_name, false)?;
+    let pr_url = open_pull_request(
+        ctx,
+        repo,
+        branch_name,
+        title,
+        None,
+        Some(target),
+        &mut PrMetadata::default(),
+        false,
+    );
+    Ok(BackportOutcome::Pushed { pr_url })
+}
+
+/// The global and the repository's allowed paths, and `extra` paths the command itself changes
+fn allowed_paths(repo: &Repository, config: &Config, extra: &[String]) -> Vec<String> {
+    config
+        .allowed_paths
+        .iter()
+        .chain(&repo.allowed_paths)
+        .chain(extra)
+        .cloned()
+        .collect()
+}
+
+/// Check the staged paths against [`allowed_paths`]
+fn check_staged_paths_for(
+    ctx: &Context,
+    repo: &Repository,
+    config: &Config,
+    extra: &[String],
+) -> Result<()> {
+    let max_files = config.max_staged_files.unwrap_or(DEFAULT_MAX_STAGED_FILES);
+    check_staged_paths(
+        ctx,
+        &repo.path,
+        &allowed_paths(repo, config, extra),
+        max_files,
+    )
+}
+
+/// Rewrite dependency specifiers (`pin-all`, `widen`) on a new branch, install, commit,
+/// push and optionally open a PR, then return to the original branch. Returns the PR URL.
+pub fn rewrite_specifiers_workflow(
+    ctx: &Context,
+    repo: &Repository,
+    changes: &[SpecifierChange],
+    branch_name: &str,
+    commit_message: &str,
+    create_pr: bool,
+    config: &Config,
+) -> Result<Option<String>> {
+    println!("\n=== Processing repository: {} ===", repo.path);
+
+    let pkg_manager = workflow_package_manager(ctx, config, repo, &repo.path, None)?;
+    let original_branch = get_current_branch(ctx, &repo.path)?;
+
+    create_branch(ctx, &repo.path, branch_name, None, false)?;
+    ensure_safe_branch(
+        ctx,
+        &repo.path,
+        branch_name,
+        &config.protected_branches,
+        false,
+    )?;
+    crate::package::apply_specifier_changes(ctx, &repo.path, changes)?;
+    let env = config.env_for(Some(repo))?;
+    crate::package::run_install_with_manager(ctx, &repo.path, &pkg_manager, &env, false)?;
+
+    // Workspace member manifests the changes name may be committed too
+    let members: Vec<String> = changes.iter().filter_map(|c| c.file.clone()).collect();
+    stage_changes(
+        ctx,
+        &expand_path(&repo.path)?,
+        &allowed_paths(repo, config, &members),
+        false,
+    )?;
+    check_staged_paths_for(ctx, repo, config, &members)?;
+    commit_changes(ctx, &repo.path, commit_message, false)?;
+    push_branch(ctx, &repo.path, branch_name, false)?;
+
+    let pr_url = if create_pr {
+        open_pull_request(
+            ctx,
+            repo,
+            branch_name,
+            commit_message,
+            None,
+            None,
+            &mut PrMetadata::default(),
+            false,
+        )
+    } else {
+        None
+    };
+
+    checkout_branch(ctx, &repo.path, &original_branch, false)?;
+    Ok(pr_url)
+}
+
+/// Lockfiles an install may create or rewrite
+const LOCKFILES: [&str; 4] = [
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+];
+
+/// package.json as it was before an update, to put back if the update fails before
+/// it is committed
+pub struct ManifestBackup {
+    package_json: Vec<u8>,
+    /// Lockfiles that existed before the update
+    lockfiles: Vec<&'static str>,
+}
+
+impl ManifestBackup {
+    pub fn capture(repo_path: &str) -> Result<Self> {
+        let path = expand_path(repo_path)?;
+        let package_json =
+            std::fs::read(path.join("package.json")).context("Failed to back up package.json")?;
+        let lockfiles = LOCKFILES
+            .into_iter()
+            .filter(|name| path.join(name).exists())
+            .collect();
+        Ok(ManifestBackup {
+            package_json,
+            lockfiles,
+        })
+    }
+
+    /// Put package.json back byte for byte and unstage it, check out lockfiles
+    /// committed at HEAD again, and remove lockfiles the install created
+    pub fn restore(&self, ctx: &Context, repo_path: &str) -> Result<()> {
+        let path = expand_path(repo_path)?;
+        let package_json = path.join("package.json");
+        crate::manifest::write_atomic(&package_json, &self.package_json)?;
+        ctx.manifests.invalidate(&package_json);
+        run_git(ctx, &path, &["reset", "--quiet", "--", "package.json"])?;
+
+        for name in LOCKFILES {
+            let tracked = ctx
+                .runner
+                .output(&git_command().current_dir(&path).args([
+                    "cat-file",
+                    "-e",
+                    // Relative to the current directory, for extra manifests
+                    &format!("HEAD:./{}", name),
+                ]))
+                .is_ok_and(|output| output.success());
+            if tracked {
+                run_git(ctx, &path, &["checkout", "HEAD", "--", name])?;
+            } else if !self.lockfiles.contains(&name) && path.join(name).exists() {
+                std::fs::remove_file(path.join(name))
+                    .with_context(|| format!("Failed to remove {}", name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Package manager for a workflow, printed and logged with where it came from.
+/// `manifest` names an extra manifest the manager is for.
+fn workflow_package_manager(
+    ctx: &Context,
+    config: &Config,
+    repo: &Repository,
+    repo_path: &str,
+    manifest: Option<&str>,
+) -> Result<ManagerCheck> {
+    let detection = config.package_manager(ctx, repo_path)?;
+    let source = detection.source.to_string();
+    if let Some(warning) = &detection.warning {
+        eprintln!("Warning: {}: {}", repo_path, warning);
+    }
+    let check = crate::package::check_manager(
+        ctx,
+        repo_path,
+        &detection.manager,
+        config.package_manager_version(Some(repo), &detection.manager),
+        &config.env_for(Some(repo))?,
+    )?;
+    match manifest {
+        Some(manifest) => println!(
+            "Package manager for {}: {} ({})",
+            manifest,
+            check.label(),
+            source
+        ),
+        None => println!("Package manager: {} ({})", check.label(), source),
+    }
+    ctx.log.event(
+        "package_manager",
+        &[
+            ("repo", repo_path),
+            ("manager", &detection.manager),
+            ("version", check.version.as_deref().unwrap_or("unknown")),
+            ("source", &source),
+        ],
+    );
+    Ok(check)
+}
+
+/// Execute package update workflow
+#[allow(clippy::too_many_arguments)]
+pub fn update_package_workflow(
+    ctx: &Context,
+    repo: &Repository,
+    package_name: &str,
+    version: &str,
+    branch_name: &str,
+    base: Option<&str>,
+    commit_message: &str,
+    create_pr: bool,
+    pr_body: Option<&str>,
+    pr_title: Option<&str>,
+    allow_protected: bool,
+    changelog: bool,
+    dedupe: bool,
+    restore_on_failure: bool,
+    allow_lockfile_format_change: bool,
+    confirm_push: Option<&dyn Fn(&str) -> bool>,
+    timer: &mut StepTimer,
+    dry_run: bool,
+    config: &Config,
+    submodules: SubmoduleMode,
+    codeowners: bool,
+    pr_metadata: &PrMetadata,
+    vars: &TemplateContext,
+) -> Result<WorkflowOutcome> {
+    println!("\n=== Processing repository: {} ===", repo.path);
+
+    // Manifests in a submodule belong to its repository; committing them here would
+    // only bump the gitlink
+    let submodule_paths = submodule_paths(ctx, &repo.path)?;
+    let mut nested: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    // Resolve the package managers up front so a missing one fails before anything changes
+    let pkg_manager = workflow_package_manager(ctx, config, repo, &repo.path, None)?;
+    let mut extras = Vec::new();
+    for (dir, path) in repo.extra_manifests.iter().zip(repo.extra_manifest_paths()) {
+        if let Some((sub, inner)) = in_submodule(dir, &submodule_paths) {
+            nested.entry(sub).or_default().push(inner.to_string());
+            continue;
+        }
+        let manifest = format!("{}/package.json", dir);
+        let manager = workflow_package_manager(ctx, config, repo, &path, Some(&manifest))?;
+        extras.push((dir.as_str(), path, manager));
+    }
+
+    // Submodules are updated first, so their new commits can be committed here
+    let mut gitlinks = Vec::new();
+    let mut submodule_changes = Vec::new();
+    let mut submodule_outcome = None;
+    for (sub, dirs) in &nested {
+        let manifests: Vec<String> = dirs
+            .iter()
+            .map(|dir| match dir.as_str() {
+                "" => "package.json".to_string(),
+                dir => format!("{}/package.json", dir),
+            })
+            .collect();
+        let verb = if dry_run { "Would" } else { "Will" };
+        if submodules == SubmoduleMode::Skip {
+            ctx.log.event(
+                "submodule_skipped",
+                &[("repo", &repo.path), ("submodule", sub)],
+            );
+            println!(
+                "⚠ {} skip {} in submodule {}; pass --recurse-submodules to update it in the submodule's repository",
+                verb,
+                manifests.join(", "),
+                sub
+            );
+            continue;
+        }
+        println!(
+            "{} update {} in submodule {} in the submodule's repository{}",
+            verb,
+            manifests.join(", "),
+            sub,
+            if submodules == SubmoduleMode::RecurseAndCommit {
+                ", then commit its new commit here"
+            } else {
+                ""
+            }
+        );
+
+        let sub_path = format!("{}/{}", repo.path.trim_end_matches('/'), sub);
+        let sub_repo = Repository {
+            github_url: crate::github::detect_github_url(ctx, &sub_path),
+            extra_manifests: dirs.iter().filter(|d| !d.is_empty()).cloned().collect(),
+            env: repo.env.clone(),
+            path: sub_path,
+            ..Default::default()
+        };
+        let outcome = update_package_workflow(
+            ctx,
+            &sub_repo,
+            package_name,
+            version,
+            branch_name,
+            None,
+            commit_message,
+            create_pr,
+            pr_body,
+            pr_title,
+            allow_protected,
+            false,
+            dedupe,
+            restore_on_failure,
+            allow_lockfile_format_change,
+            confirm_push,
+            timer,
+            dry_run,
+            config,
+            submodules,
+            codeowners,
+            pr_metadata,
+            vars,
+        )?;
+        if let WorkflowOutcome::Updated { changes, .. } = &outcome {
+            if submodules == SubmoduleMode::RecurseAndCommit {
+                let commit = if dry_run {
+                    None
+                } else {
+                    Some(
+                        resolve_commit(ctx, &sub_repo.path, branch_name)?.ok_or_else(|| {
+                            anyhow::anyhow!("Branch {} not found in {}", branch_name, sub)
+                        })?,
+                    )
+                };
+                gitlinks.push((*sub, commit));
+                submodule_changes.extend(changes.iter().cloned());
+            }
+            submodule_outcome.get_or_insert(outcome);
+        }
+    }
+
+    // 1. Save current branch
+    let original_branch = get_current_branch(ctx, &repo.path)?;
+
+    // 2. Create branch, and make sure nothing is committed to main if that silently failed
+    timer.start("branch");
+    create_branch(ctx, &repo.path, branch_name, base, dry_run)?;
+    if base.is_some() && !dry_run {
+        // The manifests are now those of the base, not the ones read so far
+        let root = expand_path(&repo.path)?;
+        ctx.manifests.invalidate(&root.join("package.json"));
+        for (dir, _, _) in &extras {
+            ctx.manifests
+                .invalidate(&root.join(dir).join("package.json"));
+        }
+    }
+    if !dry_run {
+        ensure_safe_branch(
+            ctx,
+            &repo.path,
+            branch_name,
+            &config.protected_branches,
+            allow_protected,
+        )?;
+    }
+
+    // 3. Update package.json and the extra manifests (this function is in package.rs)
+    timer.start("manifest");
+    let mut backups = Vec::new();
+    if restore_on_failure && !dry_run {
+        backups.push((repo.path.clone(), ManifestBackup::capture(&repo.path)?));
+        for (_, path, _) in &extras {
+            backups.push((path.clone(), ManifestBackup::capture(path)?));
+        }
+    }
+    let mut changes =
+        crate::package::update_package(ctx, &repo.path, package_name, version, dry_run)?;
+    let mut installs = Vec::new();
+    if !changes.is_empty() {
+        installs.push((repo.path.clone(), pkg_manager.clone()));
+    }
+    for (_, path, manager) in &extras {
+        let extra_changes =
+            crate::package::update_package(ctx, path, package_name, version, dry_run)?;
+        if !extra_changes.is_empty() {
+            installs.push((path.clone(), manager.clone()));
+            changes.extend(extra_changes);
+        }
+    }
+
+    changes.extend(submodule_changes);
+
+    if changes.is_empty() {
+        ctx.log.event(
+            "package_skipped",
+            &[
+                ("repo", &repo.path),
+                ("package", package_name),
+                ("version", version),
+            ],
+        );
+        println!(
+            "Package '{}' is already at version '{}', skipping",
+            package_name, version
+        );
+        // Return to original branch
+        checkout_branch(ctx, &repo.path, &original_branch, dry_run)?;
+        // Only the submodule needed the update, so its pull request is the outcome
+        return Ok(submodule_outcome.unwrap_or(WorkflowOutcome::Skipped));
+    }
+
+    // `{old}` is the version this repository was at
+    let vars = vars
+        .clone()
+        .with("old", &crate::version::normalize(&changes[0].old_version))
+        .with(
+            "base",
+            &base.map_or_else(|| original_branch.to_string(), str::to_string),
+        );
+    let commit_message = vars.render(commit_message);
+
+    let mut hook_env = HookEnv {
+        package: package_name,
+        old_version: &changes[0].old_version,
+        new_version: version,
+        branch: branch_name,
+        pr_url: None,
+    };
+
+    // 4-7 change the working tree; if any of them fails, put package.json and the
+    // lockfiles back so the repository isn't left with a half-applied update
+    let mut outputs = Vec::new();
+    let mut lockfile_warnings = Vec::new();
+    let mut prepare = || -> Result<bool> {
+        // 4. Run package install with detected or default package manager, next to
+        // each changed manifest
+        timer.start("install");
+        let env = config.env_for(Some(repo))?;
+        for (path, manager) in &installs {
+            let before = LockfileShape::read(path)?;
+            let output =
+                crate::package::run_install_with_manager(ctx, path, manager, &env, dry_run)?;
+            outputs.push((format!("{} install in {}", manager.manager, path), output));
+            // An older or different package manager rewrites the whole lockfile
+            let after = LockfileShape::read(path)?;
+            if let Some(problem) = before
+                .zip(after)
+                .and_then(|(before, after)| after.problem_since(&before))
+            {
+                let problem = format!("{} in {} ({} install)", problem, path, manager.label());
+                if !allow_lockfile_format_change {
+                    anyhow::bail!(
+                        "{}; install with the package manager version the lockfile was made with, \
+                         or pass --allow-lockfile-format-change",
+                        problem
+                    );
+                }
+                eprintln!("Warning: {}", problem);
+                lockfile_warnings.push(problem);
+            }
+            if dedupe {
+                crate::package::run_dedupe(ctx, path, &manager.manager, &env, dry_run)?;
+            }
+        }
+
+        // 5. Run pre-update hooks, e.g. to regenerate files that depend on the package
+        timer.start("hooks");
+        outputs.extend(hooks::run_hooks(
+            ctx,
+            config,
+            repo,
+            HookStage::PreUpdate,
+            &hook_env,
+            dry_run,
+        )?);
+        if changelog {
+            crate::changelog::record_update(repo, &vars, dry_run)?;
+        }
+
+        // 6. Stage changes (including the changelog), and make sure nothing else slipped in
+        timer.start("commit");
+        let path = expand_path(&repo.path)?;
+        let extra_paths: Vec<String> = extras
+            .iter()
+            .flat_map(|(dir, _, _)| {
+                std::iter::once("package.json")
+                    .chain(LOCKFILES)
+                    .map(move |file| format!("{}/{}", dir, file))
+            })
+            .chain(gitlinks.iter().map(|(sub, _)| sub.to_string()))
+            .collect();
+        stage_changes(
+            ctx,
+            &path,
+            &allowed_paths(repo, config, &extra_paths),
+            dry_run,
+        )?;
+        for (sub, commit) in &gitlinks {
+            stage_gitlink(ctx, &path, sub, commit.as_deref())?;
+        }
+        if !dry_run {
+            check_staged_paths_for(ctx, repo, config, &extra_paths)?;
+        }
+
+        // 7. Commit changes
+        commit_changes(ctx, &repo.path, &commit_message, dry_run)
+    };
+    let committed = match prepare() {
+        Ok(committed) => committed,
+        Err(e) => {
+            for (path, backup) in &backups {
+                match backup.restore(ctx, path) {
+                    Ok(()) => println!(
+                        "Restored package.json and lockfiles in {} after the failure",
+                        path
+                    ),
+                    Err(restore_err) => {
+                        eprintln!("Warning: failed to restore package.json: {}", restore_err)
+                    }
+                }
+            }
+            return_to_original_branch(ctx, &repo.path, &original_branch, dry_run);
+            return Err(e);
+        }
+    };
+
+    // 8. Push to GitHub, unless the branch has nothing to propose: pushing it would
+    // only leave an empty branch behind, and gh refuses a PR without commits
+    // The branch the PR targets: the one the update branched from, else the default branch
+    let target = base.and_then(|base| pr_base(ctx, &repo.path, base));
+    if !dry_run {
+        let base = match &target {
+            Some(target) => target.clone(),
+            None => default_branch(ctx, &repo.path)?,
+        };
+        if !committed || commits_ahead(ctx, &repo.path, &base)? == Some(0) {
+            ctx.log.event(
+                "no_changes",
+                &[("repo", &repo.path), ("branch", branch_name)],
+            );
+            println!(
+                "No changes between '{}' and '{}', skipping push and PR",
+                base, branch_name
+            );
+            if config.delete_empty_branches.unwrap_or(false) {
+                if let Err(e) = delete_remote_branch(ctx, &repo.path, branch_name) {
+                    eprintln!("Warning: {}", e);
+                }
+            }
+            checkout_branch(ctx, &repo.path, &original_branch, dry_run)?;
+            return Ok(WorkflowOutcome::NoChanges);
+        }
+    }
+
+    // A last look at what is about to leave the machine (untimed, it may wait for an answer)
+    timer.stop();
+    let mut diffstat = None;
+    let mut changed_files: Vec<String> = std::iter::once("package.json".to_string())
+        .chain(
+            extras
+                .iter()
+                .map(|(dir, _, _)| format!("{}/package.json", dir)),
+        )
+        .collect();
+    if !dry_run {
+        let stat = head_diffstat(ctx, &repo.path)?;
+        println!("{}: {}", repo.path, stat.summary());
+        for file in &stat.files {
+            println!("    {}", file);
+        }
+        if confirm_push.is_some_and(|confirm| !confirm(&repo.path)) {
+            ctx.log.event(
+                "push_declined",
+                &[("repo", &repo.path), ("branch", branch_name)],
+            );
+            checkout_branch(ctx, &repo.path, &original_branch, dry_run)?;
+            return Ok(WorkflowOutcome::PushDeclined {
+                diffstat: stat.summary(),
+            });
+        }
+        diffstat = Some(stat.summary());
+        changed_files = stat.files;
+    }
+    timer.start("push");
+    if let Err(e) = push_branch(ctx, &repo.path, branch_name, dry_run) {
+        return_to_original_branch(ctx, &repo.path, &original_branch, dry_run);
+        return Err(e);
+    }
+
+    // 9. Create PR (optional)
+    timer.start("pr");
+    let mut warnings = lockfile_warnings;
+    let mut metadata = pr_metadata.clone();
+    let pr_url = if create_pr {
+        let title = pr_title.map_or_else(|| commit_message.clone(), |t| vars.render(t));
+        let body = pr_body.map(|body| vars.render(body));
+        if let Some((reviewers, codeowner_warnings)) =
+            codeowners_reviewers(repo, codeowners, &changed_files)?
+        {
+            for warning in &codeowner_warnings {
+                eprintln!("Warning: {}: {}", repo.path, warning);
+            }
+            warnings.extend(codeowner_warnings);
+            metadata.reviewers = reviewers;
+        }
+        let pr_url = open_pull_request(
+            ctx,
+            repo,
+            branch_name,
+            &title,
+            body.as_deref(),
+            target.as_deref(),
+            &mut metadata,
+            dry_run,
+        );
+        if pr_url.is_some() {
+            if let (Some(milestone), None) = (&pr_metadata.milestone, &metadata.milestone) {
+                warnings.push(format!("milestone '{}' was not set on the PR", milestone));
+            }
+            if let (Some(project), None) = (&pr_metadata.project, &metadata.project) {
+                warnings.push(format!("project '{}' was not set on the PR", project));
+            }
+        }
+        pr_url
+    } else {
+        None
+    };
+    let (milestone, project) = match pr_url {
+        Some(_) => (metadata.milestone, metadata.project),
+        None => (None, None),
+    };
+
+    // 10. Run post-update hooks, e.g. to announce the change
+    hook_env.pr_url = pr_url.as_deref();
+    timer.start("hooks");
+    hooks::run_hooks(ctx, config, repo, HookStage::PostUpdate, &hook_env, dry_run)?;
+
+    println!(
+        "✅ Successfully updated {} from {} to {} in {}",
+        package_name, changes[0].old_version, version, repo.path
+    );
+
+    // 11. Return to original branch
+    timer.stop();
+    checkout_branch(ctx, &repo.path, &original_branch, dry_run)?;
+
+    let mut package_managers: Vec<String> = installs.iter().map(|(_, m)| m.label()).collect();
+    package_managers.sort();
+    package_managers.dedup();
+    Ok(WorkflowOutcome::Updated {
+        changes,
+        pr_url,
+        diffstat,
+        original_branch,
+        outputs,
+        warnings,
+        milestone,
+        project,
+        package_managers,
+    })
+}
+
+/// Branch a PR should target when the update branched from `base`: the branch a
+/// remote-tracking ref such as `origin/release/2.x` follows, or a local branch itself.
+/// `None` for a tag or commit, so the PR goes to the default branch.
+fn pr_base(ctx: &Context, repo_path: &str, base: &str) -> Option<String> {
+    if let Some(branch) = base.strip_prefix("origin/") {
+        return Some(branch.to_string());
+    }
+    local_branch_exists(ctx, repo_path, base)
+        .unwrap_or(false)
+        .then(|| base.to_string())
+}
+
+/// Check out the branch the workflow started from after a step failed, so the
+/// repository isn't left on the campaign branch
+fn return_to_original_branch(
+    ctx: &Context,
+    repo_path: &str,
+    original_branch: &BranchName,
+    dry_run: bool,
+) {
+    match checkout_branch(ctx, repo_path, original_branch, dry_run) {
+        Ok(()) => println!("Returned {} to '{}'", repo_path, original_branch),
+        Err(e) => eprintln!("Warning: failed to return to the original branch: {}", e),
+    }
+}
+
+/// Reviewers from the code owners of `files` and warnings about owners that can't be
+/// requested, or `None` when `codeowners` is off or the repository has no CODEOWNERS file
+fn codeowners_reviewers(
+    repo: &Repository,
+    codeowners: bool,
+    files: &[String],
+) -> Result<Option<(Vec<String>, Vec<String>)>> {
+    if !codeowners {
+        return Ok(None);
+    }
+    let Some(owners) = CodeOwners::load(&expand_path(&repo.path)?)? else {
+        return Ok(None);
+    };
+    Ok(Some(owners.reviewers(files)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, MockRunner};
+    use std::fs;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn setup_repo(version: &str) -> (TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            format!(r#"{{"dependencies": {{"react": "{}"}}}}"#, version),
+        )
+        .unwrap();
+        fs::write(dir.path().join("package-lock.json"), "{}").unwrap();
+
+        let repo = Repository {
+            path: dir.path().to_string_lossy().to_string(),
+            github_url: Some("https://github.com/o/r".to_string()),
+            ..Default::default()
+        };
+        (dir, repo)
+    }
+
+    /// Runner for a repository on `main` that is on the update branch once it was created
+    fn runner_on_main() -> MockRunner {
+        MockRunner::new()
+            .respond_once(
+                "git symbolic-ref --quiet --short HEAD",
+                CommandOutput::ok("main\n"),
+            )
+            .respond_once(
+                "git symbolic-ref --quiet --short HEAD",
+                CommandOutput::ok("main\n"),
+            )
+            .respond(
+                "git symbolic-ref --quiet --short HEAD",
+                CommandOutput::ok("update-react-18.2.0\n"),
+            )
+    }
+
+    fn test_config() -> Config {
+        Config {
+            default_commit_message: "chore: update dependencies".to_string(),
+            default_package_manager: Some("npm".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn run_workflow(
+        runner: &Arc<MockRunner>,
+        repo: &Repository,
+        create_pr: bool,
+    ) -> Result<WorkflowOutcome> {
+        let ctx = Context::with_runner(runner.clone());
+        update_package_workflow(
+            &ctx,
+            repo,
+            "react",
+            "^18.2.0",
+            "update-react-18.2.0",
+            None,
+            "chore: update react to ^18.2.0",
+            create_pr,
+            None,
+            None,
+            false,
+            false,
+            false,
+            true,
+            false,
+            None,
+            &mut StepTimer::default(),
+            false,
+            &test_config(),
+            SubmoduleMode::Skip,
+            false,
+            &PrMetadata::default(),
+            &TemplateContext::default(),
+        )
+    }
+
+    #[test]
+    fn workflow_checks_out_existing_branch() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            runner_on_main()
+                .respond(
+                    "git for-each-ref --format=%(refname) refs/heads/",
+                    CommandOutput::ok("  update-react-18.2.0\n"),
+                )
+                .respond("git ls-files", CommandOutput::ok("package.json\0"))
+                .respond("git diff --staged", CommandOutput::ok("package.json\0")),
+        );
+
+        run_workflow(&runner, &repo, false).unwrap();
+
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "npm --version",
+                "git symbolic-ref --quiet --short HEAD",
+                "git symbolic-ref --quiet --short HEAD",
+                "git for-each-ref --format=%(refname) refs/heads/update-react-18.2.0",
+                "git checkout update-react-18.2.0",
+                "git symbolic-ref --quiet --short HEAD",
+                "git symbolic-ref --short refs/remotes/origin/HEAD",
+                "npm install",
+                "git ls-files -z --modified --deleted --others --exclude-standard",
+                "git add --all -- package.json",
+                "git diff --staged --name-only -z",
+                "git diff --staged --name-only",
+                "git commit -m chore: update react to ^18.2.0",
+                "git symbolic-ref --short refs/remotes/origin/HEAD",
+                "git rev-list --count origin/main..HEAD",
+                "git show --format= --numstat HEAD",
+                "git push --set-upstream origin update-react-18.2.0",
+                "git checkout main",
+            ]
+        );
+    }
+
+    #[test]
+    fn workflow_runs_hooks_around_commit() {
+        let (dir, mut repo) = setup_repo("^17.0.0");
+        repo.pre_update_hooks = vec!["npm run codegen".to_string()];
+        let mut config = test_config();
+        config.post_update_hooks = vec!["./notify.sh".to_string()];
+        let run_with = |runner: &Arc<MockRunner>, config: &Config| {
+            let ctx = Context::with_runner(runner.clone());
+            let result = update_package_workflow(
+                &ctx,
+                &repo,
+                "react",
+                "^18.2.0",
+                "update-react-18.2.0",
+                None,
+                "chore: update react to ^18.2.0",
+                false,
+                None,
+                None,
+                false,
+                false,
+                false,
+                true,
+                false,
+                None,
+                &mut StepTimer::default(),
+                false,
+                config,
+                SubmoduleMode::Skip,
+                false,
+                &PrMetadata::default(),
+                &TemplateContext::default(),
+            );
+            (result, runner.calls())
+        };
+
+        let runner = Arc::new(
+            runner_on_main().respond("git diff --staged", CommandOutput::ok("package.json\0")),
+        );
+        let (result, calls) = run_with(&runner, &config);
+        result.unwrap();
+        let position = |line: &str| calls.iter().position(|c| c == line).unwrap();
+        assert!(position("npm install") < position("sh -c npm run codegen"));
+        assert!(
+            position("sh -c npm run codegen")
+                < position("git ls-files -z --modified --deleted --others --exclude-standard")
+        );
+        assert!(
+            position("git push --set-upstream origin update-react-18.2.0")
+                < position("sh -c ./notify.sh")
+        );
+
+        // A failing pre-update hook fails the repository before anything is committed
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"dependencies": {"react": "^17.0.0"}}"#,
+        )
+        .unwrap();
+        let runner = Arc::new(
+            runner_on_main().respond("sh -c npm run codegen", CommandOutput::failed("boom")),
+        );
+        let (result, calls) = run_with(&runner, &config);
+        assert!(result.unwrap_err().to_string().contains("pre-update hook"));
+        assert!(!calls.iter().any(|c| c.starts_with("git commit")));
+    }
+
+    #[test]
+    fn workflow_restores_manifest_when_install_fails() {
+        let (dir, repo) = setup_repo("^17.0.0");
+        let original = "{\n\t\"dependencies\": { \"react\": \"^17.0.0\" }  \n}";
+        fs::write(dir.path().join("package.json"), original).unwrap();
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("npm install", CommandOutput::failed("ERESOLVE"))
+                .respond(
+                    "git cat-file -e HEAD:./package-lock.json",
+                    CommandOutput::ok(""),
+                )
+                .respond("git cat-file", CommandOutput::failed("")),
+        );
+
+        assert!(run_workflow(&runner, &repo, false).is_err());
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            original
+        );
+        let calls = runner.calls();
+        assert!(calls.contains(&"git reset --quiet -- package.json".to_string()));
+        assert!(calls.contains(&"git checkout HEAD -- package-lock.json".to_string()));
+        assert!(!calls.contains(&"git checkout HEAD -- yarn.lock".to_string()));
+        assert_eq!(calls.last().unwrap(), "git checkout main");
+    }
+
+    #[test]
+    fn workflow_refuses_to_commit_to_protected_branches() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let mut config = test_config();
+        config.protected_branches = vec!["release/*".to_string()];
+        let run = |runner: &Arc<MockRunner>, branch: &str, allow_protected: bool| {
+            let ctx = Context::with_runner(runner.clone());
+            update_package_workflow(
+                &ctx,
+                &repo,
+                "react",
+                "^18.2.0",
+                branch,
+                None,
+                "chore: update react to ^18.2.0",
+                false,
+                None,
+                None,
+                allow_protected,
+                false,
+                false,
+                true,
+                false,
+                None,
+                &mut StepTimer::default(),
+                false,
+                &config,
+                SubmoduleMode::Skip,
+                false,
+                &PrMetadata::default(),
+                &TemplateContext::default(),
+            )
+        };
+
+        // Branch creation "succeeds" but leaves the repository on main
+        let runner = Arc::new(MockRunner::new().respond(
+            "git symbolic-ref --quiet --short HEAD",
+            CommandOutput::ok("main\n"),
+        ));
+        let err = run(&runner, "update-react-18.2.0", false).unwrap_err();
+        assert!(err.to_string().contains("refusing to commit"));
+        assert!(!runner.calls().iter().any(|c| c.starts_with("git add")
+            || c.starts_with("git commit")
+            || c.starts_with("git push")));
+
+        // A branch name that is the default branch, or matches a protected pattern
+        for branch in ["develop", "release/1.x"] {
+            let runner = Arc::new(
+                MockRunner::new()
+                    .respond(
+                        "git symbolic-ref --quiet --short HEAD",
+                        CommandOutput::ok(&format!("{}\n", branch)),
+                    )
+                    .respond(
+                        "git symbolic-ref --short refs/remotes",
+                        CommandOutput::ok("origin/develop\n"),
+                    ),
+            );
+            let err = run(&runner, branch, false).unwrap_err();
+            assert!(err.to_string().contains("protected branch"));
+            assert!(!runner.calls().iter().any(|c| c.starts_with("git commit")));
+        }
+
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "git symbolic-ref --quiet --short HEAD",
+                    CommandOutput::ok("release/1.x\n"),
+                )
+                .respond("git diff --staged", CommandOutput::ok("package.json\0")),
+        );
+        run(&runner, "release/1.x", true).unwrap();
+        assert!(runner.calls().iter().any(|c| c.starts_with("git commit")));
+    }
+
+    #[test]
+    fn rejected_push_is_classified_with_its_output() {
+        for (stderr, kind) in [
+            (
+                "remote: error: GH006: Protected branch update failed for refs/heads/main.",
+                RejectionKind::ProtectedBranch,
+            ),
+            (
+                " ! [rejected]  update-foo -> update-foo (non-fast-forward)",
+                RejectionKind::NonFastForward,
+            ),
+            (
+                "fatal: Authentication failed for 'https://github.com/o/r.git/'",
+                RejectionKind::Authentication,
+            ),
+            (
+                "error: failed to push some refs\nhusky - pre-push hook exited with code 1",
+                RejectionKind::HookRejected,
+            ),
+        ] {
+            assert_eq!(RejectionKind::classify(stderr), kind, "{}", stderr);
+        }
+
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let stderr = " ! [rejected]  update-react-18.2.0 -> update-react-18.2.0 (fetch first)";
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond("git push", CommandOutput::failed(stderr)),
+        );
+
+        let err = run_workflow(&runner, &repo, false).unwrap_err();
+        let rejection = err.downcast_ref::<GitRejection>().unwrap();
+        assert_eq!(rejection.kind, RejectionKind::NonFastForward);
+        assert_eq!(rejection.output, stderr);
+        assert!(err.to_string().contains("--force-with-lease"));
+    }
+
+    /// Push over HTTP to a local server that answers every request with 401
+    #[test]
+    fn push_to_a_remote_that_wants_credentials_fails_fast() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/o/r.git",
+            listener.local_addr().unwrap().port()
+        );
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"git\"\r\n\
+                      Content-Length: 0\r\nConnection: close\r\n\r\n",
+                );
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        for args in [
+            &["init", "--quiet", "--initial-branch=main"][..],
+            &[
+                "-c",
+                "user.name=mru",
+                "-c",
+                "user.email=mru@example.com",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "init",
+            ],
+            &["remote", "add", "origin", &url],
+        ] {
+            run_git(&ctx, dir.path(), args).unwrap();
+        }
+
+        let start = std::time::Instant::now();
+        let err = push_branch(&ctx, &dir.path().to_string_lossy(), "main", false).unwrap_err();
+        assert!(start.elapsed() < std::time::Duration::from_secs(30));
+        let rejection = err.downcast_ref::<GitRejection>().unwrap();
+        assert_eq!(rejection.kind, RejectionKind::Authentication);
+        assert!(err
+            .to_string()
+            .contains(&format!("authentication required for {}", url)));
+        assert!(err.to_string().contains("gh auth setup-git"));
+    }
+
+    #[test]
+    fn campaign_commits_are_found_by_their_trailers_and_notes() {
+        let trailers = [
+            (CAMPAIGN_TRAILER, "update-lodash-1"),
+            (PACKAGE_TRAILER, "lodash@4.17.21"),
+        ];
+        let message = with_trailers("chore: update lodash to 4.17.21\n", &trailers);
+        assert_eq!(
+            message,
+            "chore: update lodash to 4.17.21\n\n\
+             Mru-Campaign: update-lodash-1\nMru-Package: lodash@4.17.21"
+        );
+        // Joins a trailer block the template already has
+        assert_eq!(
+            with_trailers("Update\n\nRefs: ABC-1", &trailers[..1]),
+            "Update\n\nRefs: ABC-1\nMru-Campaign: update-lodash-1"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let ctx = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        for args in [
+            &["init", "--quiet", "--initial-branch=main"][..],
+            // Notes are commits too
+            &["config", "user.name", "mru"],
+            &["config", "user.email", "mru@example.com"],
+            &["commit", "--quiet", "--allow-empty", "-m", "init"],
+            &["commit", "--quiet", "--allow-empty", "-m", &message],
+        ] {
+            run_git(&ctx, dir.path(), args).unwrap();
+        }
+
+        // git itself parses them
+        fs::write(dir.path().join("message"), &message).unwrap();
+        let parsed = ctx
+            .runner
+            .output(&git_command().current_dir(dir.path()).args([
+                "interpret-trailers",
+                "--parse",
+                "message",
+            ]))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&parsed.stdout),
+            "Mru-Campaign: update-lodash-1\nMru-Package: lodash@4.17.21\n"
+        );
+
+        // No origin to push the notes to: only a warning
+        let url = "https://github.com/o/r/pull/7";
+        add_note(&ctx, &path, "main", url, false).unwrap();
+
+        let commits = campaign_commits(&ctx, &path, Some("update-lodash-1")).unwrap();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].campaign, "update-lodash-1");
+        assert_eq!(commits[0].package.as_deref(), Some("lodash@4.17.21"));
+        assert_eq!(commits[0].pr_url.as_deref(), Some(url));
+        assert_eq!(commits[0].branches, vec!["main"]);
+        // Ids are matched whole
+        assert!(campaign_commits(&ctx, &path, Some("update-lodash"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(campaign_commits(&ctx, &path, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn manifests_are_read_as_committed_at_a_ref() {
+        let (dir, repo) = setup_repo("^17.0.2");
+        let ctx = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        for args in [
+            &["init", "--quiet", "--initial-branch=main"][..],
+            &["add", "package.json"],
+            &[
+                "-c",
+                "user.name=mru",
+                "-c",
+                "user.email=mru@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                "init",
+            ],
+        ] {
+            run_git(&ctx, dir.path(), args).unwrap();
+        }
+        // A stale or edited working tree doesn't change what the ref has
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"dependencies": {"react": "^18.2.0"}}"#,
+        )
+        .unwrap();
+
+        let at_head = crate::package::list_packages_at(&ctx, &repo.path, "main").unwrap();
+        assert_eq!(at_head[0].specifier, "^17.0.2");
+        assert_eq!(
+            crate::package::get_package_version_at(&ctx, &repo.path, "react", "main").unwrap(),
+            Some("^17.0.2".to_string())
+        );
+        let versions =
+            crate::package::dependency_versions(&ctx, &[&repo.path], 1, Some("origin/main"));
+        let err = versions[0].1.as_ref().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("ref 'origin/main' doesn't exist in {}", repo.path)
+        );
+    }
+
+    #[test]
+    fn git_output_is_read_from_plumbing_in_the_c_locale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+
+        // Detached HEAD: symbolic-ref exits with 1 and prints nothing
+        let runner = Arc::new(MockRunner::new().respond(
+            "git symbolic-ref --quiet --short HEAD",
+            CommandOutput {
+                code: Some(1),
+                ..Default::default()
+            },
+        ));
+        let ctx = Context::with_runner(runner.clone());
+        assert_eq!(current_branch(&ctx, &path).unwrap(), None);
+        assert!(get_current_branch(&ctx, &path)
+            .unwrap_err()
+            .to_string()
+            .contains("HEAD is detached"));
+
+        let runner = Arc::new(MockRunner::new().respond(
+            "git status --porcelain=v2 --branch",
+            CommandOutput::ok(
+                "# branch.oid 1234\n# branch.head main\n\
+                     # branch.upstream origin/main\n# branch.ab +2 -5\n",
+            ),
+        ));
+        let ctx = Context::with_runner(runner.clone());
+        assert_eq!(ahead_behind(&ctx, &path).unwrap(), Some((5, 2)));
+
+        // No upstream: git leaves out the branch.upstream and branch.ab lines
+        let runner = Arc::new(MockRunner::new().respond(
+            "git status --porcelain=v2 --branch",
+            CommandOutput::ok("# branch.oid 1234\n# branch.head topic\n"),
+        ));
+        let ctx = Context::with_runner(runner.clone());
+        assert_eq!(ahead_behind(&ctx, &path).unwrap(), None);
+        list_branches(&ctx, &path, "update-*").unwrap();
+        check_status(&ctx, &path).unwrap();
+
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "git status --porcelain=v2 --branch --untracked-files=no",
+                "git for-each-ref --format=%(refname:short) refs/heads/update-*",
+                "git status --porcelain=v2",
+            ]
+        );
+        for cmd in runner.commands() {
+            assert!(cmd.envs.contains(&("LC_ALL".to_string(), "C".to_string())));
+            assert!(cmd
+                .envs
+                .contains(&("GIT_TERMINAL_PROMPT".to_string(), "0".to_string())));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_branch_is_checked_out_again_byte_for_byte() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        // "café" in Latin-1, as an old checkout might have named a branch
+        let name = b"feature/caf\xe9";
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "git symbolic-ref --quiet --short HEAD",
+                    CommandOutput {
+                        code: Some(0),
+                        stdout: [&name[..], b"\n"].concat(),
+                        ..Default::default()
+                    },
+                )
+                .respond("git checkout", CommandOutput::ok("")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+
+        let branch = get_current_branch(&ctx, &path).unwrap();
+        assert_eq!(branch.to_string(), "feature/caf\\xe9");
+        assert!(branch.to_str().is_err());
+        checkout_branch(&ctx, &path, &branch, false).unwrap();
+
+        let checkout = runner.commands().pop().unwrap();
+        assert_eq!(checkout.args[1].as_bytes(), name);
+        assert_eq!(checkout.display(), "git checkout feature/caf\\xe9");
+    }
+
+    #[test]
+    fn last_commit_falls_back_to_head_and_tolerates_empty_repositories() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let missing_main = || {
+            MockRunner::new()
+                .respond(
+                    "git symbolic-ref --short refs/remotes/origin/HEAD",
+                    CommandOutput::failed(
+                        "fatal: ref refs/remotes/origin/HEAD is not a symbolic ref",
+                    ),
+                )
+                .respond(
+                    "git log -1 --format=%cs|%s main",
+                    CommandOutput::failed("fatal: ambiguous argument 'main': unknown revision"),
+                )
+        };
+
+        let runner = Arc::new(missing_main().respond(
+            "git log -1 --format=%cs|%s HEAD",
+            CommandOutput::ok("2024-03-01|fix: handle a|b in subjects\n"),
+        ));
+        let ctx = Context::with_runner(runner);
+        assert_eq!(
+            last_commit(&ctx, &repo.path).unwrap(),
+            Some((
+                "2024-03-01".to_string(),
+                "fix: handle a|b in subjects".to_string()
+            ))
+        );
+
+        let runner = Arc::new(missing_main().respond(
+            "git log -1 --format=%cs|%s HEAD",
+            CommandOutput::failed(
+                "fatal: your current branch 'main' does not have any commits yet",
+            ),
+        ));
+        let ctx = Context::with_runner(runner);
+        assert_eq!(last_commit(&ctx, &repo.path).unwrap(), None);
+    }
+
+    #[test]
+    fn sync_base_fast_forwards_only_a_clean_branch_that_is_behind() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let sync = |ab: &str, dirty: &str| {
+            let runner = Arc::new(
+                MockRunner::new()
+                    .respond(
+                        "git status --porcelain=v2 --branch",
+                        CommandOutput::ok(&format!("# branch.head main\n# branch.ab {}\n", ab)),
+                    )
+                    .respond("git status --porcelain=v2", CommandOutput::ok(dirty)),
+            );
+            let ctx = Context::with_runner(runner.clone());
+            (sync_base(&ctx, &repo.path).unwrap(), runner.calls())
+        };
+
+        let (outcome, calls) = sync("+0 -12", "");
+        assert_eq!(outcome, BaseSync::FastForwarded(12));
+        assert_eq!(calls[0], "git fetch --quiet origin");
+        assert_eq!(
+            calls.last().unwrap(),
+            "git merge --ff-only --quiet @{upstream}"
+        );
+
+        let (outcome, calls) = sync("+3 -12", "");
+        assert_eq!(
+            outcome.problem().unwrap(),
+            "local base has diverged from origin (3 ahead, 12 behind)"
+        );
+        assert!(!calls.iter().any(|c| c.starts_with("git merge")));
+
+        let (outcome, _) = sync("+0 -2", "1 .M N... 100644 100644 100644 a b package.json\n");
+        assert_eq!(outcome, BaseSync::Stale { behind: 2 });
+        assert_eq!(sync("+0 -0", "").0, BaseSync::UpToDate);
+    }
+
+    #[test]
+    fn backport_resolves_only_package_json_conflicts() {
+        let (dir, repo) = setup_repo("^17.0.0");
+        let backport = |conflicts: &str| {
+            let runner = Arc::new(
+                MockRunner::new()
+                    .respond("git cherry-pick -x", CommandOutput::failed("CONFLICT"))
+                    .respond("git diff --name-only", CommandOutput::ok(conflicts))
+                    .respond(
+                        "gh pr create",
+                        CommandOutput::ok("https://github.com/o/r/pull/9\n"),
+                    ),
+            );
+            let ctx = Context::with_runner(runner.clone());
+            let outcome = backport_commit(
+                &ctx,
+                &repo,
+                "abc1234",
+                "release/3.x",
+                "update-react-18.2.0-release-3-x",
+                "[release/3.x] chore: update react to ^18.2.0",
+                Some(("react", "^18.2.0")),
+                false,
+            )
+            .unwrap();
+            (outcome, runner.calls())
+        };
+
+        let (outcome, calls) = backport("package-lock.json\npackage.json\n");
+        assert_eq!(
+            outcome,
+            BackportOutcome::Conflict(vec![
+                "package-lock.json".to_string(),
+                "package.json".to_string()
+            ])
+        );
+        assert_eq!(calls.last().unwrap(), "git cherry-pick --abort");
+
+        let (outcome, calls) = backport("package.json\n");
+        assert_eq!(
+            outcome,
+            BackportOutcome::Pushed {
+                pr_url: Some("https://github.com/o/r/pull/9".to_string())
+            }
+        );
+        assert_eq!(
+            calls[..6],
+            [
+                "git checkout -B update-react-18.2.0-release-3-x origin/release/3.x",
+                "git cherry-pick -x abc1234",
+                "git diff --name-only --diff-filter=U",
+                "git checkout --ours -- package.json",
+                "git add package.json",
+                "git cherry-pick --continue",
+            ]
+        );
+        assert!(calls
+            .iter()
+            .any(|c| c.starts_with("gh pr create") && c.contains("--draft --base release/3.x")));
+        let manifest = fs::read_to_string(dir.path().join("package.json")).unwrap();
+        assert!(manifest.contains("^18.2.0"));
+    }
+
+    #[test]
+    fn workflow_refuses_to_commit_unexpected_paths() {
+        let (_dir, mut repo) = setup_repo("^17.0.0");
+        let staged =
+            "package.json\0package-lock.json\0node_modules/react/index.js\0src/gen/api.ts\0";
+        let runner = Arc::new(runner_on_main().respond(
+            "git diff --staged --name-only -z",
+            CommandOutput::ok(staged),
+        ));
+
+        let err = run_workflow(&runner, &repo, false).unwrap_err().to_string();
+        assert!(
+            err.starts_with("Refusing to commit 2 unexpected files"),
+            "{}",
+            err
+        );
+        assert!(err.contains("\n  node_modules/react/index.js\n  src/gen/api.ts\n"));
+        assert!(!runner.calls().iter().any(|c| c.starts_with("git commit")));
+
+        repo.allowed_paths = vec!["src/gen/*".to_string(), "node_modules/*".to_string()];
+        fs::write(
+            Path::new(&repo.path).join("package.json"),
+            r#"{"dependencies": {"react": "^17.0.0"}}"#,
+        )
+        .unwrap();
+        run_workflow(&runner, &repo, false).unwrap();
+    }
+
+    #[test]
+    fn only_allowed_changes_are_staged() {
+        let dir = tempfile::tempdir().unwrap();
+        let changed = "package.json\0package-lock.json\0node_modules/react/index.js\0\
+                       src/gen/api.ts\0package-lock.json\0";
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond("git ls-files", CommandOutput::ok(changed))
+                .respond(
+                    "git diff --staged",
+                    CommandOutput::ok("package.json\0odd\nname.json\0"),
+                ),
+        );
+        let ctx = Context::with_runner(runner.clone());
+
+        stage_changes(
+            &ctx,
+            &dir.path().to_path_buf(),
+            &["src/gen/*".to_string()],
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "git ls-files -z --modified --deleted --others --exclude-standard",
+                "git add --all -- package-lock.json package.json src/gen/api.ts",
+            ]
+        );
+
+        // Paths are separated by NUL only, a newline is part of the name
+        assert_eq!(
+            staged_paths(&ctx, &dir.path().to_string_lossy()).unwrap(),
+            vec!["package.json", "odd\nname.json"]
+        );
+    }
+
+    #[test]
+    fn workflow_updates_and_installs_extra_manifests() {
+        let (dir, mut repo) = setup_repo("^17.0.0");
+        let functions = dir.path().join("functions");
+        fs::create_dir(&functions).unwrap();
+        fs::write(
+            functions.join("package.json"),
+            r#"{"dependencies": {"react": "^17.0.1"}}"#,
+        )
+        .unwrap();
+        fs::write(functions.join("yarn.lock"), "").unwrap();
+        repo.extra_manifests = vec!["functions".to_string()];
+        let staged =
+            "package.json\0package-lock.json\0functions/package.json\0functions/yarn.lock\0";
+        let runner = Arc::new(runner_on_main().respond(
+            "git diff --staged --name-only -z",
+            CommandOutput::ok(staged),
+        ));
+
+        // The member's package.json and lockfile pass the staged paths check
+        run_workflow(&runner, &repo, false).unwrap();
+
+        assert!(fs::read_to_string(functions.join("package.json"))
+            .unwrap()
+            .contains(r#""react": "^18.2.0""#));
+        let calls = runner.calls();
+        assert!(calls.iter().any(|c| c == "npm install"));
+        assert!(calls.iter().any(|c| c.starts_with("yarn install")));
+    }
+
+    #[test]
+    fn workflow_skips_or_recurses_into_submodules() {
+        let (dir, mut repo) = setup_repo("^17.0.0");
+        let web = dir.path().join("vendor/web");
+        fs::create_dir_all(&web).unwrap();
+        fs::write(web.join(".git"), "gitdir: ../../.git/modules/web\n").unwrap();
+        let manifest = r#"{"dependencies": {"react": "^17.0.1"}}"#;
+        fs::write(web.join("package.json"), manifest).unwrap();
+        fs::write(
+            dir.path().join(".gitmodules"),
+            "[submodule \"web\"]\n\tpath = vendor/web\n",
+        )
+        .unwrap();
+        repo.extra_manifests = vec!["vendor/web".to_string()];
+        let with_submodule = |runner: MockRunner| {
+            runner.respond(
+                "git config --file .gitmodules --get-regexp",
+                CommandOutput::ok("submodule.web.path vendor/web\n"),
+            )
+        };
+
+        // By default the submodule's package.json is left alone
+        let runner = Arc::new(with_submodule(runner_on_main()));
+        run_workflow(&runner, &repo, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(web.join("package.json")).unwrap(),
+            manifest
+        );
+        assert_eq!(
+            runner
+                .calls()
+                .iter()
+                .filter(|c| *c == "npm install")
+                .count(),
+            1
+        );
+
+        // The submodule runs the workflow first, then the parent commits its new commit
+        let branch = |name: &str| CommandOutput::ok(&format!("{}\n", name));
+        let runner = Arc::new(
+            with_submodule(MockRunner::new())
+                .respond_once("git symbolic-ref --quiet", branch("main"))
+                .respond_once("git symbolic-ref --quiet", branch("main"))
+                .respond_once("git symbolic-ref --quiet", branch("update-react-18.2.0"))
+                .respond_once("git symbolic-ref --quiet", branch("main"))
+                .respond_once("git symbolic-ref --quiet", branch("main"))
+                .respond("git symbolic-ref --quiet", branch("update-react-18.2.0"))
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(
+                    "git rev-parse --verify --quiet update-react-18.2.0",
+                    CommandOutput::ok("abc1234\n"),
+                ),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        update_package_workflow(
+            &ctx,
+            &repo,
+            "react",
+            "^18.2.0",
+            "update-react-18.2.0",
+            None,
+            "chore: update react to ^18.2.0",
+            false,
+            None,
+            None,
+            false,
+            false,
+            false,
+            true,
+            false,
+            None,
+            &mut StepTimer::default(),
+            false,
+            &test_config(),
+            SubmoduleMode::RecurseAndCommit,
+            false,
+            &PrMetadata::default(),
+            &TemplateContext::default(),
+        )
+        .unwrap();
+
+        assert!(fs::read_to_string(web.join("package.json"))
+            .unwrap()
+            .contains(r#""react": "^18.2.0""#));
+        let calls = runner.calls();
+        let position = |call: &str| calls.iter().position(|c| c == call).unwrap();
+        // The parent's package.json was updated by the first run: it only commits the gitlink
+        assert_eq!(calls.iter().filter(|c| *c == "npm install").count(), 1);
+        assert_eq!(
+            calls.iter().filter(|c| c.starts_with("git commit")).count(),
+            2
+        );
+        assert!(
+            position("git rev-parse --verify --quiet update-react-18.2.0^{commit}")
+                < position("git update-index --cacheinfo 160000,abc1234,vendor/web")
+        );
+    }
+
+    #[test]
+    fn workflow_passes_configured_env_to_install_and_hooks() {
+        std::env::set_var("MRU_TEST_NPM_TOKEN", "s3cret");
+        let (_dir, mut repo) = setup_repo("^17.0.0");
+        repo.pre_update_hooks = vec!["npm run codegen".to_string()];
+        repo.env = [
+            ("NPM_TOKEN", "${MRU_TEST_NPM_TOKEN}"),
+            ("PUPPETEER_SKIP_DOWNLOAD", "1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let runner = Arc::new(runner_on_main());
+
+        run_workflow(&runner, &repo, false).unwrap();
+
+        let expected = [
+            ("NPM_TOKEN".to_string(), "s3cret".to_string()),
+            ("PUPPETEER_SKIP_DOWNLOAD".to_string(), "1".to_string()),
+        ];
+        for line in ["npm install", "sh -c npm run codegen"] {
+            let command = runner
+                .commands()
+                .into_iter()
+                .find(|c| c.display() == line)
+                .unwrap();
+            assert!(
+                expected.iter().all(|var| command.envs.contains(var)),
+                "{}",
+                line
+            );
+        }
+        assert!(runner
+            .commands()
+            .iter()
+            .filter(|c| c.program == "git")
+            .all(|c| c.envs.iter().all(|(name, _)| name != "NPM_TOKEN")));
+    }
+
+    /// Times the status queries over synthetic repositories with the git CLI and with
+    /// libgit2, checking both give the same answers. Run with
+    /// `cargo test --release git::tests::bench_read_only_queries -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_read_only_queries() {
+        const REPOS: usize = 30;
+        let dirs: Vec<TempDir> = (0..REPOS).map(|_| tempfile::tempdir().unwrap()).collect();
+        let cli = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        for (i, dir) in dirs.iter().enumerate() {
+            let path = dir.path();
+            for args in [
+                &["init", "--quiet", "--initial-branch=main"][..],
+                &["remote", "add", "origin", "https://github.com/o/r"],
+            ] {
+                run_git(&cli, path, args).unwrap();
+            }
+            fs::write(path.join("package.json"), "{}").unwrap();
+            if i % 2 == 0 {
+                run_git(&cli, path, &["add", "."]).unwrap();
+            }
+        }
+        let paths: Vec<String> = dirs
+            .iter()
+            .map(|d| d.path().to_string_lossy().to_string())
+            .collect();
+
+        let query = |ctx: &Context| {
+            let start = std::time::Instant::now();
+            let answers: Vec<_> = paths
+                .iter()
+                .map(|path| {
+                    (
+                        current_branch(ctx, path).unwrap(),
+                        check_status(ctx, path).unwrap(),
+                        ahead_behind(ctx, path).unwrap(),
+                        local_branch_exists(ctx, path, "main").unwrap(),
+                        remote_url(ctx, path, "origin"),
+                    )
+                })
+                .collect();
+            (start.elapsed(), answers)
+        };
+        let (cli_time, cli_answers) = query(&cli);
+        let mut native = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        native.native_git = true;
+        let (native_time, native_answers) = query(&native);
+
+        assert_eq!(cli_answers, native_answers);
+        println!(
+            "{} repositories: git CLI {:?}, libgit2 {:?} ({:.1}x)",
+            REPOS,
+            cli_time,
+            native_time,
+            cli_time.as_secs_f64() / native_time.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn workflow_skips_commit_without_staged_changes() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(runner_on_main());
+
+        let outcome = run_workflow(&runner, &repo, true).unwrap();
+        assert_eq!(outcome, WorkflowOutcome::NoChanges);
+
+        let calls = runner.calls();
+        assert!(calls.contains(&"git checkout -b update-react-18.2.0".to_string()));
+        assert!(calls.contains(&"git diff --staged --name-only".to_string()));
+        assert!(!calls
+            .iter()
+            .any(|c| c.starts_with("git commit") || c.starts_with("git push")));
+        assert!(!calls.iter().any(|c| c.starts_with("gh ")));
+        assert_eq!(calls.last().unwrap(), "git checkout main");
+    }
+
+    #[test]
+    fn workflow_skips_branch_without_commits_over_base() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond("git rev-list --count", CommandOutput::ok("0\n")),
+        );
+        let config = Config {
+            delete_empty_branches: Some(true),
+            ..test_config()
+        };
+
+        let ctx = Context::with_runner(runner.clone());
+        let outcome = update_package_workflow(
+            &ctx,
+            &repo,
+            "react",
+            "^18.2.0",
+            "update-react-18.2.0",
+            None,
+            "chore: update react to ^18.2.0",
+            true,
+            None,
+            None,
+            false,
+            false,
+            false,
+            true,
+            false,
+            None,
+            &mut StepTimer::default(),
+            false,
+            &config,
+            SubmoduleMode::Skip,
+            false,
+            &PrMetadata::default(),
+            &TemplateContext::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, WorkflowOutcome::NoChanges);
+
+        let calls = runner.calls();
+        let tail = &calls[calls.len() - 4..];
+        assert_eq!(
+            tail,
+            [
+                "git symbolic-ref --short refs/remotes/origin/HEAD",
+                "git rev-list --count origin/main..HEAD",
+                "git push origin --delete update-react-18.2.0",
+                "git checkout main",
+            ]
+        );
+        assert!(!calls.iter().any(|c| c.starts_with("gh ")));
+
+        // With --base, the commits are counted over the branch the PR would target
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond("git rev-list --count", CommandOutput::ok("0\n")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let outcome = update_package_workflow(
+            &ctx,
+            &repo,
+            "react",
+            "^18.2.0",
+            "update-react-18.2.0",
+            Some("origin/release/2.x"),
+            "chore: update react to ^18.2.0",
+            true,
+            None,
+            None,
+            false,
+            false,
+            false,
+            true,
+            false,
+            None,
+            &mut StepTimer::default(),
+            false,
+            &test_config(),
+            SubmoduleMode::Skip,
+            false,
+            &PrMetadata::default(),
+            &TemplateContext::default(),
+        )
+        .unwrap();
+        assert_eq!(outcome, WorkflowOutcome::NoChanges);
+        let calls = runner.calls();
+        assert!(calls.contains(&"git rev-list --count origin/release/2.x..HEAD".to_string()));
+        assert!(!calls.contains(&"git rev-list --count origin/main..HEAD".to_string()));
+    }
+
+    #[test]
+    fn workflow_reuses_existing_pr() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(
+                    "git show --format= --numstat HEAD",
+                    CommandOutput::ok("1\t1\tpackage.json\n"),
+                )
+                .respond(
+                    "gh pr create",
+                    CommandOutput::failed("a pull request for branch already exists"),
+                )
+                .respond(
+                    "gh pr list",
+                    CommandOutput::ok(
+                        r#"[{"number":1,"url":"https://github.com/o/r/pull/1","state":"OPEN"}]"#,
+                    ),
+                ),
+        );
+
+        let outcome = run_workflow(&runner, &repo, true).unwrap();
+        assert_eq!(
+            outcome,
+            WorkflowOutcome::Updated {
+                changes: vec![UpdateOutcome {
+                    section: "dependencies".to_string(),
+                    old_version: "^17.0.0".to_string(),
+                    new_version: "^18.2.0".to_string(),
+                }],
+                pr_url: Some("https://github.com/o/r/pull/1".to_string()),
+                diffstat: Some("1 file changed, 1 insertion(+), 1 deletion(-)".to_string()),
+                original_branch: "main".into(),
+                outputs: vec![(format!("npm install in {}", repo.path), String::new())],
+                warnings: Vec::new(),
+                milestone: None,
+                project: None,
+                package_managers: vec!["npm".to_string()],
+            }
+        );
+
+        let calls = runner.calls();
+        let gh_calls: Vec<&String> = calls.iter().filter(|c| c.starts_with("gh ")).collect();
+        assert_eq!(gh_calls.len(), 3);
+        assert_eq!(gh_calls[0], "gh auth status --hostname github.com");
+        assert!(gh_calls[1].starts_with("gh pr create --title chore: update react to ^18.2.0"));
+        assert!(gh_calls[1].contains("--head update-react-18.2.0 --repo o/r "));
+        assert_eq!(
+            gh_calls[2],
+            "gh pr list --repo o/r --head update-react-18.2.0 --state all --json number,url,state"
+        );
+        assert_eq!(calls.last().unwrap(), "git checkout main");
+    }
+
+    #[test]
+    fn workflow_requests_reviews_from_code_owners() {
+        let (dir, repo) = setup_repo("^17.0.0");
+        fs::create_dir(dir.path().join(".github")).unwrap();
+        fs::write(
+            dir.path().join(".github/CODEOWNERS"),
+            "* @acme/platform\n/package.json @acme/deps @me @octocat\n/vendor/\n",
+        )
+        .unwrap();
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(
+                    "git show --format= --numstat HEAD",
+                    CommandOutput::ok("1\t1\tpackage.json\n1\t1\tvendor/package-lock.json\n"),
+                )
+                .respond(
+                    "gh api --hostname github.com user",
+                    CommandOutput::ok("me\n"),
+                )
+                .respond(
+                    "gh pr create",
+                    CommandOutput::ok("https://github.com/o/r/pull/1\n"),
+                ),
+        );
+        let config = test_config();
+        let pr_metadata = PrMetadata {
+            reviewers: vec!["static".to_string()],
+            ..Default::default()
+        };
+
+        let ctx = Context::with_runner(runner.clone());
+        let workflow = |codeowners| {
+            update_package_workflow(
+                &ctx,
+                &repo,
+                "react",
+                "^18.2.0",
+                "update-react-18.2.0",
+                None,
+                "chore: update react to ^18.2.0",
+                true,
+                None,
+                None,
+                false,
+                false,
+                false,
+                true,
+                false,
+                None,
+                &mut StepTimer::default(),
+                false,
+                &config,
+                SubmoduleMode::Skip,
+                codeowners,
+                &pr_metadata,
+                &TemplateContext::default(),
+            )
+        };
+        let pr_create = || {
+            runner
+                .calls()
+                .into_iter()
+                .rfind(|c| c.starts_with("gh pr create"))
+                .unwrap()
+        };
+
+        // The author is left out: GitHub refuses to request their review
+        let outcome = workflow(true).unwrap();
+        assert!(pr_create().ends_with(" --reviewer acme/deps,octocat"));
+        let WorkflowOutcome::Updated { warnings, .. } = outcome else {
+            panic!("expected an update, got {:?}", outcome);
+        };
+        assert_eq!(warnings, ["no code owners for vendor/package-lock.json"]);
+
+        // Without --codeowners the configured reviewers are requested
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"dependencies": {"react": "^17.0.0"}}"#,
+        )
+        .unwrap();
+        workflow(false).unwrap();
+        assert!(pr_create().ends_with(" --reviewer static"));
+    }
+
+    #[test]
+    fn workflow_sets_milestone_and_project() {
+        let milestones = "gh api --hostname github.com --paginate repos/o/r/milestones?state=all";
+        let workflow = |runner: &Arc<MockRunner>, create_missing_milestone| {
+            let (_dir, repo) = setup_repo("^17.0.0");
+            let ctx = Context::with_runner(runner.clone());
+            update_package_workflow(
+                &ctx,
+                &repo,
+                "react",
+                "^18.2.0",
+                "update-react-18.2.0",
+                None,
+                "chore: update react to ^18.2.0",
+                true,
+                None,
+                None,
+                false,
+                false,
+                false,
+                true,
+                false,
+                None,
+                &mut StepTimer::default(),
+                false,
+                &test_config(),
+                SubmoduleMode::Skip,
+                false,
+                &PrMetadata {
+                    milestone: Some("2026-10".to_string()),
+                    project: Some("Dependencies".to_string()),
+                    create_missing_milestone,
+                    ..Default::default()
+                },
+                &TemplateContext::default(),
+            )
+            .unwrap()
+        };
+
+        // A missing milestone is left off with a warning rather than failing the PR
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(milestones, CommandOutput::ok("2026-09\n"))
+                .respond(
+                    "gh pr create",
+                    CommandOutput::ok("https://github.com/o/r/pull/1\n"),
+                ),
+        );
+        let WorkflowOutcome::Updated {
+            warnings,
+            milestone,
+            project,
+            ..
+        } = workflow(&runner, false)
+        else {
+            panic!("expected an update");
+        };
+        assert_eq!(warnings, ["milestone '2026-10' was not set on the PR"]);
+        assert_eq!(
+            (milestone, project.as_deref()),
+            (None, Some("Dependencies"))
+        );
+        let calls = runner.calls();
+        let create = calls
+            .iter()
+            .find(|c| c.starts_with("gh pr create"))
+            .unwrap();
+        assert!(create.ends_with(" --project Dependencies"));
+
+        // Created where missing, and set on a PR that already existed with `gh pr edit`
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(milestones, CommandOutput::ok("2026-09\n"))
+                .respond(
+                    "gh pr create",
+                    CommandOutput::failed("a pull request for branch already exists"),
+                )
+                .respond(
+                    "gh pr list",
+                    CommandOutput::ok(
+                        r#"[{"number":1,"url":"https://github.com/o/r/pull/1","state":"OPEN"}]"#,
+                    ),
+                ),
+        );
+        let WorkflowOutcome::Updated {
+            warnings,
+            milestone,
+            ..
+        } = workflow(&runner, true)
+        else {
+            panic!("expected an update");
+        };
+        assert!(warnings.is_empty());
+        assert_eq!(milestone.as_deref(), Some("2026-10"));
+        let calls = runner.calls();
+        assert!(calls
+            .iter()
+            .any(|c| c == "gh api --hostname github.com repos/o/r/milestones -f title=2026-10"));
+        assert!(calls.iter().any(|c| c
+            == "gh pr edit https://github.com/o/r/pull/1 --milestone 2026-10 --add-project Dependencies"));
+    }
+
+    #[test]
+    fn workflow_declined_push_returns_to_the_original_branch() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(
+                    "git show --format= --numstat HEAD",
+                    CommandOutput::ok("2\t1\tpackage.json\n-\t-\tlogo.png\n"),
+                ),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let decline = |_: &str| false;
+
+        let outcome = update_package_workflow(
+            &ctx,
+            &repo,
+            "react",
+            "^18.2.0",
+            "update-react-18.2.0",
+            None,
+            "chore: update react to ^18.2.0",
+            true,
+            None,
+            None,
+            false,
+            false,
+            false,
+            true,
+            false,
+            Some(&decline),
+            &mut StepTimer::default(),
+            false,
+            &test_config(),
+            SubmoduleMode::Skip,
+            false,
+            &PrMetadata::default(),
+            &TemplateContext::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            outcome,
+            WorkflowOutcome::PushDeclined {
+                diffstat: "2 files changed, 2 insertions(+), 1 deletion(-)".to_string()
+            }
+        );
+
+        let calls = runner.calls();
+        assert!(!calls.iter().any(|c| c.starts_with("git push")));
+        assert_eq!(calls.last().unwrap(), "git checkout main");
+    }
+
+    #[test]
+    fn workflow_skips_pr_without_github_remote() {
+        let (_dir, mut repo) = setup_repo("^17.0.0");
+        repo.github_url = None;
+        let runner = Arc::new(
+            runner_on_main()
+                .respond("git diff --staged", CommandOutput::ok("package.json\0"))
+                .respond(
+                    "git remote get-url origin",
+                    CommandOutput::ok("git@gitlab.com:o/r.git\n"),
+                ),
+        );
+
+        let outcome = run_workflow(&runner, &repo, true).unwrap();
+        assert!(matches!(
+            outcome,
+            WorkflowOutcome::Updated { pr_url: None, .. }
+        ));
+        assert!(!runner.calls().iter().any(|c| c.starts_with("gh ")));
+    }
+
+    #[test]
+    fn commit_to_branch_pushes_and_returns_to_original_branch() {
+        let (_dir, repo) = setup_repo("^17.0.0");
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "git symbolic-ref --quiet --short HEAD",
+                    CommandOutput::ok("main\n"),
+                )
+                .respond(
+                    "git ls-files",
+                    CommandOutput::ok("tsconfig.json\0src/index.ts\0"),
+                )
+                .respond("git diff --staged", CommandOutput::ok("tsconfig.json\0")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+
+        commit_to_branch(
+            &ctx,
+            &repo,
+            "mru-exec-codemod",
+            "chore: codemod",
+            true,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "git symbolic-ref --quiet --short HEAD",
+                "git for-each-ref --format=%(refname) refs/heads/mru-exec-codemod",
+                "git checkout -b mru-exec-codemod",
+                "git ls-files -z --modified --deleted --others --exclude-standard",
+                "git add --all -- src/index.ts tsconfig.json",
+                "git diff --staged --name-only",
+                "git commit -m chore: codemod",
+                "git push --set-upstream origin mru-exec-codemod",
+                "git checkout main",
+            ]
+        );
+    }
+
+    #[test]
+    fn workflow_skips_repo_already_at_version() {
+        let (_dir, repo) = setup_repo("^18.2.0");
+        let runner = Arc::new(runner_on_main());
+
+        run_workflow(&runner, &repo, false).unwrap();
+
+        assert_eq!(
+            runner.calls(),
+            vec![
+                "npm --version",
+                "git symbolic-ref --quiet --short HEAD",
+                "git symbolic-ref --quiet --short HEAD",
+                "git for-each-ref --format=%(refname) refs/heads/update-react-18.2.0",
+                "git checkout -b update-react-18.2.0",
+                "git symbolic-ref --quiet --short HEAD",
+                "git symbolic-ref --short refs/remotes/origin/HEAD",
+                "git checkout main",
+            ]
+        );
+    }
 }
@@ -0,0 +1,167 @@
+// Read-only git queries answered with libgit2, without spawning a git process.
+// Mutations (checkout, commit, push) always go through the git CLI, where hooks and
+// credential helpers apply. Built without the `libgit2` feature, every query fails
+// and callers fall back to the CLI.
+
+pub use imp::*;
+
+#[cfg(feature = "libgit2")]
+mod imp {
+    use anyhow::Result;
+    use git2::{BranchType, ErrorCode, Repository, StatusOptions};
+    use std::path::Path;
+
+    /// libgit2 is available
+    pub const AVAILABLE: bool = true;
+
+    /// Like the CLI, a path inside a larger work tree opens that work tree
+    fn open(path: &Path) -> Result<Repository> {
+        Ok(Repository::discover(path)?)
+    }
+
+    /// Branch HEAD points to, also before its first commit, or `None` if detached.
+    /// The name is raw bytes, as ref names needn't be UTF-8.
+    pub fn current_branch(path: &Path) -> Result<Option<Vec<u8>>> {
+        let repo = open(path)?;
+        let head = repo.find_reference("HEAD")?;
+        Ok(head
+            .symbolic_target_bytes()
+            .and_then(|target| target.strip_prefix(b"refs/heads/"))
+            .map(<[u8]>::to_vec))
+    }
+
+    pub fn local_branch_exists(path: &Path, branch_name: &str) -> Result<bool> {
+        match open(path)?.find_branch(branch_name, BranchType::Local) {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether anything is modified, staged or untracked, like `git status --porcelain`
+    pub fn is_dirty(path: &Path) -> Result<bool> {
+        let repo = open(path)?;
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(false)
+            .include_ignored(false);
+        let statuses = repo.statuses(Some(&mut options))?;
+        Ok(!statuses.is_empty())
+    }
+
+    /// Commits behind and ahead of the upstream branch, or `None` without one
+    pub fn ahead_behind(path: &Path) -> Result<Option<(usize, usize)>> {
+        let repo = open(path)?;
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if !head.is_branch() {
+            return Ok(None);
+        }
+        let upstream = match git2::Branch::wrap(head).upstream() {
+            Ok(upstream) => upstream,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let (Some(local), Some(upstream)) = (repo.head()?.target(), upstream.get().target()) else {
+            return Ok(None);
+        };
+        let (ahead, behind) = repo.graph_ahead_behind(local, upstream)?;
+        Ok(Some((behind, ahead)))
+    }
+
+    /// URL of a remote, or `None` if there is no such remote
+    pub fn remote_url(path: &Path, remote: &str) -> Result<Option<String>> {
+        let repo = open(path)?;
+        let found = match repo.find_remote(remote) {
+            Ok(found) => found,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(found.url().map(str::to_string))
+    }
+}
+
+#[cfg(not(feature = "libgit2"))]
+mod imp {
+    use anyhow::Result;
+    use std::path::Path;
+
+    /// libgit2 is available
+    pub const AVAILABLE: bool = false;
+
+    fn unavailable<T>() -> Result<T> {
+        anyhow::bail!("mru was built without the libgit2 feature")
+    }
+
+    pub fn current_branch(_path: &Path) -> Result<Option<Vec<u8>>> {
+        unavailable()
+    }
+
+    pub fn local_branch_exists(_path: &Path, _branch_name: &str) -> Result<bool> {
+        unavailable()
+    }
+
+    pub fn is_dirty(_path: &Path) -> Result<bool> {
+        unavailable()
+    }
+
+    pub fn ahead_behind(_path: &Path) -> Result<Option<(usize, usize)>> {
+        unavailable()
+    }
+
+    pub fn remote_url(_path: &Path, _remote: &str) -> Result<Option<String>> {
+        unavailable()
+    }
+}
+
+#[cfg(all(test, feature = "libgit2"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_read_branch_status_and_remotes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        assert_eq!(
+            current_branch(dir.path()).unwrap().as_deref(),
+            Some(&b"main"[..])
+        );
+        assert!(!is_dirty(dir.path()).unwrap());
+        assert_eq!(ahead_behind(dir.path()).unwrap(), None);
+
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert!(is_dirty(dir.path()).unwrap());
+
+        let signature = git2::Signature::now("mru", "mru@example.com").unwrap();
+        let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap());
+        let commit = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "init",
+                &tree.unwrap(),
+                &[],
+            )
+            .unwrap();
+        repo.branch("update-react", &repo.find_commit(commit).unwrap(), false)
+            .unwrap();
+        assert!(local_branch_exists(dir.path(), "update-react").unwrap());
+        assert!(!local_branch_exists(dir.path(), "update-vue").unwrap());
+
+        repo.remote("origin", "https://github.com/o/r").unwrap();
+        assert_eq!(
+            remote_url(dir.path(), "origin").unwrap().as_deref(),
+            Some("https://github.com/o/r")
+        );
+        assert_eq!(remote_url(dir.path(), "upstream").unwrap(), None);
+
+        repo.set_head_detached(commit).unwrap();
+        assert_eq!(current_branch(dir.path()).unwrap(), None);
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::campaign::{self, Campaign, OnError, RepoState, RepoStatus};
+use crate::config::state_dir;
+use crate::provenance::ProvenanceCheck;
+
+/// Size at which the history log is rotated
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// One mru run, as recorded in the history log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub id: String,
+    pub command: String,
+    pub timestamp: u64,
+    pub package: String,
+    pub version: String,
+    pub repos: Vec<RepoState>,
+    /// Wall-clock time of the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Provenance and integrity of the version, with `--verify-provenance`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ProvenanceCheck>,
+    /// Profile the run used (`--profile`), if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// What the run did after a repository failed (`--on-error`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<OnError>,
+    /// Why the run stopped before the remaining repositories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<String>,
+}
+
+impl HistoryRecord {
+    pub fn from_campaign(command: &str, campaign: &Campaign) -> Self {
+        HistoryRecord {
+            id: campaign.id.clone(),
+            command: command.to_string(),
+            timestamp: campaign::now(),
+            package: campaign.package.clone(),
+            version: campaign.target_version.clone(),
+            repos: campaign.repos.clone(),
+            duration_ms: None,
+            provenance: campaign.provenance.clone(),
+            profile: crate::config::active_profile().map(str::to_string),
+            on_error: Some(campaign.on_error),
+            stopped: campaign.stopped.clone(),
+        }
+    }
+
+    pub fn count(&self, status: RepoStatus) -> usize {
+        self.repos.iter().filter(|r| r.status == status).count()
+    }
+
+    pub fn pr_count(&self) -> usize {
+        self.repos.iter().filter(|r| r.pr_url.is_some()).count()
+    }
+
+    /// URLs of the run's pull requests, in repository order
+    pub fn pr_urls(&self) -> Vec<&str> {
+        self.repos
+            .iter()
+            .filter_map(|r| r.pr_url.as_deref())
+            .collect()
+    }
+
+    /// Overall outcome of the run
+    pub fn outcome(&self) -> &'static str {
+        if self.count(RepoStatus::Interrupted) > 0 {
+            "interrupted"
+        } else if self.count(RepoStatus::Pending) > 0 {
+            "aborted"
+        } else if self.count(RepoStatus::Failed) > 0 {
+            "partial"
+        } else {
+            "success"
+        }
+    }
+}
+
+/// The latest run in `records` (oldest first) that included `repo_path`, with the
+/// repository's state in it
+pub fn last_run<'a>(
+    records: &'a [HistoryRecord],
+    repo_path: &str,
+) -> Option<(&'a HistoryRecord, &'a RepoState)> {
+    records.iter().rev().find_map(|record| {
+        let repo = record.repos.iter().find(|r| r.path == repo_path)?;
+        Some((record, repo))
+    })
+}
+
+fn log_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("history.jsonl"))
+}
+
+fn rotated_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("history.jsonl.1"))
+}
+
+/// Append a record to the history log.
+/// Failures are reported as a warning so they never abort the workflow.
+pub fn record(record: &HistoryRecord) {
+    if let Err(e) = append(record) {
+        eprintln!("Warning: Failed to write history log: {:#}", e);
+    }
+}
+
+fn append(record: &HistoryRecord) -> Result<()> {
+    let path = log_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Keep one rotated log so history never grows unbounded
+    if fs::metadata(&path).map(|m| m.len()).unwrap_or(0) > MAX_LOG_SIZE {
+        fs::rename(&path, rotated_path()?).context("Failed to rotate history log")?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+
+    Ok(())
+}
+
+/// All records in the history log, oldest first.
+/// Lines that cannot be parsed are skipped with a warning.
+pub fn load() -> Result<Vec<HistoryRecord>> {
+    let mut records = Vec::new();
+
+    for path in [rotated_path()?, log_path()?] {
+        if !path.exists() {
+            continue;
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(e) => eprintln!(
+                    "Warning: Skipping invalid history entry at {}:{}: {}",
+                    path.display(),
+                    i + 1,
+                    e
+                ),
+            }
+        }
+    }
+
+    Ok(records)
+}
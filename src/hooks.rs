@@ -0,0 +1,151 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Repository};
+use crate::context::Context;
+use crate::repo::expand_path;
+use crate::runner::CommandSpec;
+
+/// Point in the update workflow at which hooks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// After the manifest is edited and dependencies are installed, before staging
+    PreUpdate,
+    /// After the branch is pushed and the pull request is created
+    PostUpdate,
+}
+
+impl HookStage {
+    fn label(self) -> &'static str {
+        match self {
+            HookStage::PreUpdate => "pre-update",
+            HookStage::PostUpdate => "post-update",
+        }
+    }
+}
+
+/// What a failing hook does to its repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Fail the repository
+    Fail,
+    /// Print a warning and carry on
+    Warn,
+}
+
+/// Details of the update exposed to hooks as `MRU_*` environment variables
+#[derive(Debug, Clone, Copy)]
+pub struct HookEnv<'a> {
+    pub package: &'a str,
+    pub old_version: &'a str,
+    pub new_version: &'a str,
+    pub branch: &'a str,
+    pub pr_url: Option<&'a str>,
+}
+
+/// Global hooks followed by the repository's own hooks for `stage`
+pub fn hooks_for<'a>(config: &'a Config, repo: &'a Repository, stage: HookStage) -> Vec<&'a str> {
+    let (global, own) = match stage {
+        HookStage::PreUpdate => (&config.pre_update_hooks, &repo.pre_update_hooks),
+        HookStage::PostUpdate => (&config.post_update_hooks, &repo.post_update_hooks),
+    };
+    global.iter().chain(own).map(String::as_str).collect()
+}
+
+/// Shell command that runs `hook` in the repository with the update details in its environment
+pub fn hook_command(hook: &str, repo_path: &str, env: &HookEnv) -> Result<CommandSpec> {
+    let spec = if cfg!(windows) {
+        CommandSpec::new("cmd").args(["/C", hook])
+    } else {
+        CommandSpec::new("sh").args(["-c", hook])
+    };
+    Ok(spec
+        .current_dir(expand_path(repo_path)?)
+        .env("MRU_REPO", repo_path)
+        .env("MRU_PACKAGE", env.package)
+        .env("MRU_OLD_VERSION", env.old_version)
+        .env("MRU_NEW_VERSION", env.new_version)
+        .env("MRU_BRANCH", env.branch)
+        .env("MRU_PR_URL", env.pr_url.unwrap_or_default()))
+}
+
+/// Run the hooks for `stage` in order. A failing hook stops the remaining ones and,
+/// depending on the configured failure mode, fails the repository or prints a warning.
+/// Returns each hook that ran with the last lines it printed.
+pub fn run_hooks(
+    ctx: &Context,
+    config: &Config,
+    repo: &Repository,
+    stage: HookStage,
+    env: &HookEnv,
+    dry_run: bool,
+) -> Result<Vec<(String, String)>> {
+    let hooks = hooks_for(config, repo, stage);
+    let mut outputs = Vec::new();
+    if hooks.is_empty() {
+        return Ok(outputs);
+    }
+
+    if dry_run {
+        for hook in hooks {
+            println!("[DRY RUN] Would run {} hook: {}", stage.label(), hook);
+        }
+        return Ok(outputs);
+    }
+
+    let vars = config.env_for(Some(repo))?;
+    for hook in hooks {
+        println!("Running {} hook: {}", stage.label(), hook);
+        let cmd = hook_command(hook, &repo.path, env)?
+            .envs(&vars)
+            .timeout(ctx.timeouts.install);
+        let result = ctx.stream(&cmd, &repo.path);
+
+        let (code, output) = match &result {
+            Ok(output) => (
+                output.code.map(|c| c.to_string()).unwrap_or_default(),
+                output.combined_tail(),
+            ),
+            Err(e) => (String::new(), format!("{:#}", e)),
+        };
+        ctx.log.event(
+            "hook_finished",
+            &[
+                ("repo", &repo.path),
+                ("stage", stage.label()),
+                ("hook", hook),
+                ("exit_code", &code),
+                ("output", &output),
+            ],
+        );
+        outputs.push((hook.to_string(), output));
+
+        let error = match result {
+            Ok(output) if output.success() => continue,
+            Ok(output) => match output.code {
+                Some(code) => format!("{} hook '{}' exited with {}", stage.label(), hook, code),
+                None => format!("{} hook '{}' was killed", stage.label(), hook),
+            },
+            Err(e) => format!("{} hook '{}' failed: {:#}", stage.label(), hook, e),
+        };
+
+        match failure_mode(config, stage) {
+            HookFailure::Fail => anyhow::bail!("{}", error),
+            HookFailure::Warn => {
+                eprintln!("⚠️  Warning: {}", error);
+                return Ok(outputs);
+            }
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// Configured failure mode; pre-update hooks fail the repository and post-update hooks warn by default
+fn failure_mode(config: &Config, stage: HookStage) -> HookFailure {
+    match stage {
+        HookStage::PreUpdate => config.pre_update_hook_failure.unwrap_or(HookFailure::Fail),
+        HookStage::PostUpdate => config.post_update_hook_failure.unwrap_or(HookFailure::Warn),
+    }
+}
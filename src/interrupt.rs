@@ -0,0 +1,54 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use crate::runner;
+
+/// Exit code of a run stopped by Ctrl-C (128 + SIGINT, as shells report it)
+pub const EXIT_CODE: u8 = 130;
+
+/// Ctrl-C presses since the handler was installed
+static PRESSES: AtomicUsize = AtomicUsize::new(0);
+static INSTALL: Once = Once::new();
+
+/// The run was stopped by Ctrl-C after saving what it had done
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Take over Ctrl-C while a campaign runs. The first press lets the repository being
+/// updated finish, and no further one is started; the second kills the commands
+/// running in it, after which the workflow still restores the repository.
+pub fn install() {
+    INSTALL.call_once(|| {
+        let result = ctrlc::set_handler(|| match PRESSES.fetch_add(1, Ordering::SeqCst) {
+            0 => eprintln!(
+                "\nInterrupted: finishing the current repository, then stopping. Press Ctrl-C again to stop now"
+            ),
+            _ => {
+                eprintln!("\nStopping the running commands");
+                runner::kill_running();
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: Failed to handle Ctrl-C: {}", e);
+        }
+    });
+}
+
+/// Ctrl-C was pressed: start no further repository
+pub fn requested() -> bool {
+    PRESSES.load(Ordering::SeqCst) > 0
+}
+
+/// Ctrl-C was pressed twice, so the commands of the current repository were killed
+pub fn forced() -> bool {
+    PRESSES.load(Ordering::SeqCst) > 1
+}
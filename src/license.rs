@@ -0,0 +1,199 @@
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use std::fs;
+
+use crate::context::Context;
+use crate::lockfile::Lockfile;
+use crate::package::{matches_glob, npm_view};
+use crate::repo::expand_path;
+use crate::version;
+
+/// License reported for packages whose metadata doesn't name one
+pub const UNKNOWN: &str = "unknown";
+
+/// The license a repository uses of a package, and the one it would update to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LicenseChange {
+    pub old: String,
+    pub new: String,
+}
+
+impl LicenseChange {
+    /// Why the change breaks the license policy: a changed license, or a new license on
+    /// the deny-list (globs such as `AGPL-*`). Empty if it doesn't.
+    pub fn problems(&self, denylist: &[String]) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.old != self.new {
+            problems.push(format!("license changes from {} to {}", self.old, self.new));
+        }
+        if let Some(pattern) = denylist.iter().find(|p| matches_glob(p, &self.new)) {
+            problems.push(format!(
+                "license {} is on the deny-list ({})",
+                self.new, pattern
+            ));
+        }
+        problems
+    }
+
+    /// e.g. `MIT → Apache-2.0`
+    pub fn describe(&self) -> String {
+        format!("{} → {}", self.old, self.new)
+    }
+}
+
+/// License of `package@version` in the registry the repository at `repo_path` installs
+/// from; `unknown` if its metadata has none
+pub fn published(ctx: &Context, repo_path: &str, package: &str, version: &str) -> Result<String> {
+    let spec = format!("{}@{}", package, version);
+    // A range matching several versions lists the license of each; the last is the newest
+    let license = match npm_view(ctx, repo_path, &spec, "license")? {
+        Some(Value::Array(licenses)) => licenses.last().and_then(spdx),
+        Some(license) => spdx(&license),
+        None => None,
+    };
+    Ok(license.unwrap_or_else(|| UNKNOWN.to_string()))
+}
+
+/// License of the version of `package` the repository uses: from the package's metadata
+/// in node_modules when it is installed, else from the registry, for the version the
+/// lockfile resolved `specifier` to or the version `specifier` pins.
+/// `unknown` if none of them tells.
+pub fn current(
+    ctx: &Context,
+    repo_path: &str,
+    package: &str,
+    specifier: &str,
+    lockfile: Option<&Lockfile>,
+) -> Result<String> {
+    if let Some(metadata) = installed_metadata(repo_path, package)? {
+        return Ok(from_metadata(&metadata).unwrap_or_else(|| UNKNOWN.to_string()));
+    }
+
+    let range = version::range_of(specifier);
+    let resolved = lockfile.and_then(|lock| lock.resolved(package, specifier));
+    let version = match resolved {
+        Some(resolved) => Some(resolved),
+        None if version::is_exact(range) => Some(range.to_string()),
+        None => version::pin(range, None),
+    };
+    match version {
+        Some(version) => published(ctx, repo_path, package, &version),
+        None => Ok(UNKNOWN.to_string()),
+    }
+}
+
+/// package.json of `package` in the repository's node_modules, if it is installed
+fn installed_metadata(repo_path: &str, package: &str) -> Result<Option<Value>> {
+    let path = expand_path(repo_path)?
+        .join("node_modules")
+        .join(package)
+        .join("package.json");
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let metadata = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(metadata))
+}
+
+/// License named by package metadata: the `license` field, or the deprecated `licenses`
+/// list, joined as an SPDX `OR` expression
+fn from_metadata(metadata: &Value) -> Option<String> {
+    spdx(&metadata["license"]).or_else(|| {
+        let licenses: Vec<String> = metadata["licenses"]
+            .as_array()?
+            .iter()
+            .filter_map(spdx)
+            .collect();
+        match licenses.len() {
+            0 => None,
+            1 => licenses.into_iter().next(),
+            _ => Some(format!("({})", licenses.join(" OR "))),
+        }
+    })
+}
+
+/// A license given as an SPDX expression, or as the legacy `{"type": "MIT"}` object
+fn spdx(value: &Value) -> Option<String> {
+    let license = value.as_str().or_else(|| value["type"].as_str())?.trim();
+    (!license.is_empty()).then(|| license.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, MockRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn current_prefers_installed_metadata_over_the_registry() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "npm view left-pad@1.3.0 license",
+                    CommandOutput::ok(r#""WTFPL""#),
+                )
+                .respond("npm view left-pad@1.1.0 license", CommandOutput::ok("")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+
+        assert_eq!(
+            current(&ctx, &repo, "left-pad", "^1.3.0", None).unwrap(),
+            "WTFPL"
+        );
+        // No license field is reported, not treated as an error
+        assert_eq!(
+            current(&ctx, &repo, "left-pad", "1.1.0", None).unwrap(),
+            UNKNOWN
+        );
+        assert_eq!(
+            current(&ctx, &repo, "left-pad", "*", None).unwrap(),
+            UNKNOWN
+        );
+
+        let installed = dir.path().join("node_modules/left-pad");
+        fs::create_dir_all(&installed).unwrap();
+        fs::write(
+            installed.join("package.json"),
+            r#"{"licenses": [{"type": "MIT"}, {"type": "Apache-2.0"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            current(&ctx, &repo, "left-pad", "^1.3.0", None).unwrap(),
+            "(MIT OR Apache-2.0)"
+        );
+
+        // The registry was asked once per version
+        assert_eq!(runner.calls().len(), 2);
+        assert_eq!(
+            published(&ctx, &repo, "left-pad", "1.3.0").unwrap(),
+            "WTFPL"
+        );
+        assert_eq!(runner.calls().len(), 2);
+    }
+
+    #[test]
+    fn changed_and_denied_licenses_are_problems() {
+        let denylist = vec!["GPL-3.0-only".to_string(), "AGPL-*".to_string()];
+        let same = LicenseChange {
+            old: "MIT".to_string(),
+            new: "MIT".to_string(),
+        };
+        assert!(same.problems(&denylist).is_empty());
+        assert_eq!(same.describe(), "MIT → MIT");
+
+        let relicensed = LicenseChange {
+            old: "MIT".to_string(),
+            new: "AGPL-3.0-or-later".to_string(),
+        };
+        assert_eq!(
+            relicensed.problems(&denylist),
+            [
+                "license changes from MIT to AGPL-3.0-or-later",
+                "license AGPL-3.0-or-later is on the deny-list (AGPL-*)",
+            ]
+        );
+    }
+}
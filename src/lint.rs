@@ -0,0 +1,320 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::context::Context;
+use crate::package::{self, DependencyEntry, SpecifierChange};
+use crate::repo::expand_path;
+use crate::version;
+
+/// Sections a package is installed from; peerDependencies are left out, as they
+/// usually repeat a devDependency with a wider range on purpose
+const INSTALLED_SECTIONS: [&str; 3] = ["dependencies", "devDependencies", "optionalDependencies"];
+
+/// Protocols that link a workspace package rather than install it from the registry
+const LOCAL_PROTOCOLS: [&str; 4] = ["workspace:", "file:", "link:", "portal:"];
+
+/// One package.json of a repository: the root one or a workspace member's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Path relative to the repository, e.g. `packages/ui/package.json`
+    pub file: String,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub entries: Vec<DependencyEntry>,
+}
+
+impl Manifest {
+    pub fn from_json(file: &str, package_json: &Value) -> Self {
+        Manifest {
+            file: file.to_string(),
+            name: package_json["name"].as_str().map(str::to_string),
+            version: package_json["version"].as_str().map(str::to_string),
+            entries: package::dependency_entries(package_json),
+        }
+    }
+}
+
+/// The root package.json of a repository, then those of its workspace members
+pub fn load(ctx: &Context, repo_path: &str) -> Result<Vec<Manifest>> {
+    let root = expand_path(repo_path)?;
+    let members = package::workspace_manifests(ctx, repo_path)?;
+    std::iter::once("package.json".to_string())
+        .chain(members)
+        .map(|file| {
+            let package_json = ctx.manifests.get(&root.join(&file))?;
+            Ok(Manifest::from_json(&file, &package_json))
+        })
+        .collect()
+}
+
+/// Section whose specifier `--fix` keeps when a package is in both
+/// dependencies and devDependencies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Prefer {
+    #[default]
+    Dependencies,
+    DevDependencies,
+}
+
+impl Prefer {
+    fn sections(self) -> (&'static str, &'static str) {
+        match self {
+            Prefer::Dependencies => ("dependencies", "devDependencies"),
+            Prefer::DevDependencies => ("devDependencies", "dependencies"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// Declared in more than one section of the same package.json
+    SeveralSections,
+    /// Declared with different specifiers by several workspace members
+    MemberConflict,
+    /// A specifier that is not a valid semver range
+    InvalidSpecifier,
+    /// A registry specifier for the name of a workspace package, which it doesn't match
+    ShadowsMember,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IssueKind::SeveralSections => "in several sections",
+            IssueKind::MemberConflict => "different versions across workspace members",
+            IssueKind::InvalidSpecifier => "not a valid semver range",
+            IssueKind::ShadowsMember => "shadows a workspace package",
+        })
+    }
+}
+
+/// Where a package is declared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub section: &'static str,
+    pub specifier: String,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.file, self.section, self.specifier)
+    }
+}
+
+/// A problem with how one package is declared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub package: String,
+    pub locations: Vec<Location>,
+    pub note: Option<String>,
+    /// Change that resolves the issue, when it is safe to make
+    pub fix: Option<SpecifierChange>,
+}
+
+fn location(manifest: &Manifest, entry: &DependencyEntry) -> Location {
+    Location {
+        file: manifest.file.clone(),
+        section: entry.section,
+        specifier: entry.specifier.clone(),
+    }
+}
+
+/// Issues in the manifests of one repository, as loaded by [`load`]
+pub fn check(manifests: &[Manifest], prefer: Prefer) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let members: BTreeMap<&str, &Manifest> = manifests
+        .iter()
+        .skip(1)
+        .filter_map(|m| Some((m.name.as_deref()?, m)))
+        .collect();
+
+    for manifest in manifests {
+        let mut by_name: BTreeMap<&str, Vec<&DependencyEntry>> = BTreeMap::new();
+        for entry in &manifest.entries {
+            if INSTALLED_SECTIONS.contains(&entry.section) {
+                by_name.entry(&entry.name).or_default().push(entry);
+            }
+        }
+        for (name, entries) in by_name.into_iter().filter(|(_, e)| e.len() > 1) {
+            issues.push(Issue {
+                kind: IssueKind::SeveralSections,
+                package: name.to_string(),
+                locations: entries.iter().map(|e| location(manifest, e)).collect(),
+                note: None,
+                fix: align_sections(manifest, &entries, prefer),
+            });
+        }
+    }
+
+    if manifests.len() > 1 {
+        let mut by_name: BTreeMap<&str, Vec<Location>> = BTreeMap::new();
+        for manifest in manifests {
+            for entry in &manifest.entries {
+                if INSTALLED_SECTIONS.contains(&entry.section)
+                    && package::is_registry_specifier(&entry.specifier)
+                    && !members.contains_key(entry.name.as_str())
+                {
+                    by_name
+                        .entry(&entry.name)
+                        .or_default()
+                        .push(location(manifest, entry));
+                }
+            }
+        }
+        for (name, locations) in by_name {
+            let files: BTreeSet<&str> = locations.iter().map(|l| l.file.as_str()).collect();
+            let specifiers: BTreeSet<&str> =
+                locations.iter().map(|l| l.specifier.as_str()).collect();
+            if files.len() > 1 && specifiers.len() > 1 {
+                issues.push(Issue {
+                    kind: IssueKind::MemberConflict,
+                    package: name.to_string(),
+                    locations,
+                    note: None,
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    for manifest in manifests {
+        for entry in &manifest.entries {
+            let range = version::range_of(&entry.specifier);
+            let checked = package::is_registry_specifier(&entry.specifier)
+                || version::npm_alias(&entry.specifier).is_some();
+            // An empty specifier means any version to npm
+            if checked && !range.is_empty() && version::validate(range).is_err() {
+                issues.push(Issue {
+                    kind: IssueKind::InvalidSpecifier,
+                    package: entry.name.clone(),
+                    locations: vec![location(manifest, entry)],
+                    note: None,
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    for manifest in manifests {
+        for entry in &manifest.entries {
+            let Some(member) = members.get(entry.name.as_str()) else {
+                continue;
+            };
+            if member.file == manifest.file
+                || LOCAL_PROTOCOLS
+                    .iter()
+                    .any(|p| entry.specifier.starts_with(p))
+            {
+                continue;
+            }
+            let linked = package::is_registry_specifier(&entry.specifier)
+                && member
+                    .version
+                    .as_deref()
+                    .is_some_and(|v| version::satisfies(&entry.specifier, v));
+            if !linked {
+                issues.push(Issue {
+                    kind: IssueKind::ShadowsMember,
+                    package: entry.name.clone(),
+                    locations: vec![location(manifest, entry)],
+                    note: Some(format!(
+                        "the workspace package in {} is {}",
+                        member.file,
+                        member.version.as_deref().unwrap_or("unversioned")
+                    )),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+/// For a package in exactly dependencies and devDependencies with different registry
+/// specifiers, the change that gives the section `prefer` doesn't name the other's
+fn align_sections(
+    manifest: &Manifest,
+    entries: &[&DependencyEntry],
+    prefer: Prefer,
+) -> Option<SpecifierChange> {
+    let (keep, change) = prefer.sections();
+    let [a, b] = entries else { return None };
+    let (kept, changed) = if a.section == keep { (a, b) } else { (b, a) };
+    let safe = kept.section == keep
+        && changed.section == change
+        && kept.specifier != changed.specifier
+        && package::is_registry_specifier(&kept.specifier)
+        && package::is_registry_specifier(&changed.specifier);
+    safe.then(|| SpecifierChange {
+        file: (manifest.file != "package.json").then(|| manifest.file.clone()),
+        section: changed.section,
+        name: changed.name.clone(),
+        old: changed.specifier.clone(),
+        new: kept.specifier.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn check_finds_each_kind_of_issue() {
+        let manifests = vec![
+            Manifest::from_json(
+                "package.json",
+                &json!({
+                    "workspaces": ["packages/*"],
+                    "dependencies": {"react": "^18.2.0", "lodash": "^4.17.21"},
+                    "devDependencies": {"react": "^18.0.0", "zod": "latest"}
+                }),
+            ),
+            Manifest::from_json(
+                "packages/ui/package.json",
+                &json!({"name": "@acme/ui", "version": "2.1.0", "dependencies": {"lodash": "^4.17.0"}}),
+            ),
+            Manifest::from_json(
+                "packages/app/package.json",
+                &json!({
+                    "name": "@acme/app",
+                    "dependencies": {"@acme/ui": "^1.0.0", "lodash": "^4.17.21"},
+                    "devDependencies": {"@acme/ui-kit": "workspace:*"}
+                }),
+            ),
+        ];
+
+        let issues = check(&manifests, Prefer::Dependencies);
+        let found: Vec<(IssueKind, &str)> = issues
+            .iter()
+            .map(|i| (i.kind, i.package.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (IssueKind::SeveralSections, "react"),
+                (IssueKind::MemberConflict, "lodash"),
+                (IssueKind::InvalidSpecifier, "zod"),
+                (IssueKind::ShadowsMember, "@acme/ui"),
+            ]
+        );
+
+        let fix = issues[0].fix.as_ref().unwrap();
+        assert_eq!(
+            (fix.file.as_deref(), fix.section, fix.new.as_str()),
+            (None, "devDependencies", "^18.2.0")
+        );
+        let fix = check(&manifests, Prefer::DevDependencies)[0]
+            .fix
+            .clone()
+            .unwrap();
+        assert_eq!((fix.section, fix.new.as_str()), ("dependencies", "^18.0.0"));
+        assert!(issues[1..].iter().all(|i| i.fix.is_none()));
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::campaign;
+use crate::config::state_dir;
+
+const LOCK_FILE: &str = "mru.lock";
+
+/// How often a waiting process checks whether the lock was released
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Where we can't tell whether a process is alive, one older than this is taken to be gone
+#[cfg(not(unix))]
+const STALE_AFTER_SECS: u64 = 24 * 60 * 60;
+
+/// Process holding the lock, as recorded in the lock file
+#[derive(Debug, Serialize, Deserialize)]
+struct Owner {
+    pid: u32,
+    started: u64,
+    command: String,
+}
+
+/// Advisory lock held while a command changes the config or repositories.
+/// Released when dropped, and by the OS when the process exits, so a crashed
+/// process never leaves it behind.
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Take the lock in the config directory (the active profile's own), waiting for another process if `wait` is set
+    pub fn acquire(command: &str, wait: bool) -> Result<Self> {
+        Self::acquire_in(&state_dir()?, command, wait)
+    }
+
+    fn acquire_in(dir: &Path, command: &str, wait: bool) -> Result<Self> {
+        fs::create_dir_all(dir).context("Failed to create config directory")?;
+        let path = dir.join(LOCK_FILE);
+        // The lock is on the file itself, so it is never replaced or removed: two
+        // processes could otherwise each lock a different file
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+
+        let mut announced = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+                }
+            }
+
+            // Unreadable while the holder writes it, and on Windows while it's locked
+            let holder = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<Owner>(&content).ok());
+            if !wait {
+                let holder = match &holder {
+                    Some(holder) => format!(
+                        " (pid {}, started {} UTC, running '{}')",
+                        holder.pid,
+                        campaign::format_timestamp(holder.started),
+                        holder.command
+                    ),
+                    None => String::new(),
+                };
+                anyhow::bail!(
+                    "Another mru process{} is running.\n\
+                     Wait for it to finish, or pass --wait to wait for it",
+                    holder
+                );
+            }
+            if !announced {
+                match &holder {
+                    Some(holder) => println!(
+                        "Waiting for mru process {} ('{}') to finish...",
+                        holder.pid, holder.command
+                    ),
+                    None => println!("Waiting for another mru process to finish..."),
+                }
+                announced = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        // Replaces the record of a process that crashed holding the lock
+        let owner = serde_json::to_string(&Owner {
+            pid: std::process::id(),
+            started: campaign::now(),
+            command: command.to_string(),
+        })?;
+        file.set_len(0)
+            .and_then(|()| file.write_all(owner.as_bytes()))
+            .context("Failed to write lock file")?;
+        Ok(Lock { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Whether process `pid`, started at `started` (seconds since the epoch), has exited.
+/// A process by that pid that started later is another one the pid was reused for
+#[cfg(unix)]
+pub fn process_gone(pid: u32, started: u64) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    if pid <= 0 {
+        return true;
+    }
+
+    // SAFETY: signal 0 only checks whether the process exists
+    let alive = unsafe { libc::kill(pid, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    // `started` is taken after the process starts; boot time only has whole seconds
+    !alive || start_time(pid).is_some_and(|start| start > started + 2)
+}
+
+#[cfg(not(unix))]
+pub fn process_gone(_pid: u32, started: u64) -> bool {
+    campaign::now().saturating_sub(started) > STALE_AFTER_SECS
+}
+
+/// When process `pid` started, in seconds since the epoch, from `/proc`
+#[cfg(target_os = "linux")]
+fn start_time(pid: libc::pid_t) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces; `starttime` is the 22nd
+    // field, the 20th after it, in clock ticks since boot
+    let ticks: u64 = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()?;
+    let boot: u64 = fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    // SAFETY: sysconf only reads a configuration value
+    let hz = u64::try_from(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }).ok()?;
+    Some(boot + ticks / hz.max(1))
+}
+
+/// Without `/proc`, a live process is taken to be the one that recorded its pid
+#[cfg(all(unix, not(target_os = "linux")))]
+fn start_time(_pid: libc::pid_t) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+
+    #[test]
+    fn lock_is_exclusive_and_reusable_after_release() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let lock = Lock::acquire_in(dir.path(), "update", false).unwrap();
+        let err = Lock::acquire_in(dir.path(), "exec", false).unwrap_err();
+        #[cfg(unix)]
+        {
+            assert!(err
+                .to_string()
+                .contains(&format!("pid {}", std::process::id())));
+            assert!(err.to_string().contains("running 'update'"));
+        }
+        assert!(err.to_string().starts_with("Another mru process"));
+
+        drop(lock);
+        assert!(Lock::acquire_in(dir.path(), "exec", false).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reused_pids_are_gone() {
+        let started = campaign::now();
+        assert!(!process_gone(std::process::id(), started));
+        // A record from before this process started names an earlier process
+        assert!(process_gone(std::process::id(), 0));
+        assert!(process_gone(i32::MAX as u32, started));
+    }
+
+    /// Run in a child process by `contending_processes_share_a_stale_lock`: takes the lock
+    /// in `MRU_LOCK_TEST_DIR` and holds it for `MRU_LOCK_TEST_HOLD_MS`
+    #[test]
+    fn lock_holder() {
+        let Ok(dir) = std::env::var("MRU_LOCK_TEST_DIR") else {
+            return;
+        };
+        let hold = std::env::var("MRU_LOCK_TEST_HOLD_MS").unwrap();
+        match Lock::acquire_in(Path::new(&dir), "holder", false) {
+            Ok(_lock) => {
+                println!("acquired");
+                std::io::stdout().flush().unwrap();
+                thread::sleep(Duration::from_millis(hold.parse().unwrap()));
+            }
+            Err(_) => println!("busy"),
+        }
+    }
+
+    fn holder(dir: &Path, hold_ms: u64) -> Child {
+        Command::new(std::env::current_exe().unwrap())
+            .args(["lock::tests::lock_holder", "--exact", "--nocapture"])
+            .env("MRU_LOCK_TEST_DIR", dir)
+            .env("MRU_LOCK_TEST_HOLD_MS", hold_ms.to_string())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn contending_processes_share_a_stale_lock() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // A process killed while holding the lock leaves its record behind
+        let mut crashed = holder(dir.path(), 60_000);
+        let lines = BufReader::new(crashed.stdout.take().unwrap()).lines();
+        assert!(lines
+            .map(Result::unwrap)
+            .any(|line| line.contains("acquired")));
+        crashed.kill().unwrap();
+        crashed.wait().unwrap();
+        assert!(fs::read_to_string(dir.path().join(LOCK_FILE))
+            .unwrap()
+            .contains("\"holder\""));
+
+        let contenders = [holder(dir.path(), 2_000), holder(dir.path(), 2_000)];
+        let outcomes: Vec<String> = contenders
+            .into_iter()
+            .map(|child| String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap())
+            .collect();
+        let acquired = outcomes.iter().filter(|o| o.contains("acquired")).count();
+        let busy = outcomes.iter().filter(|o| o.contains("busy")).count();
+        assert_eq!((acquired, busy), (1, 1), "{:?}", outcomes);
+
+        assert!(Lock::acquire_in(dir.path(), "exec", false).is_ok());
+    }
+}
@@ -0,0 +1,476 @@
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::repo::expand_path;
+
+/// A repository's lockfile, read just enough to find the versions of its direct dependencies
+pub enum Lockfile {
+    /// package-lock.json
+    Npm(Value),
+    /// yarn.lock, classic or berry
+    Yarn(String),
+    /// pnpm-lock.yaml
+    Pnpm(String),
+}
+
+impl Lockfile {
+    /// The repository's lockfile, in the order package managers are detected; `None` without one
+    pub fn load(repo_path: &str) -> Result<Option<Self>> {
+        let path = expand_path(repo_path)?;
+        let read = |name: &str| {
+            let file = path.join(name);
+            fs::read_to_string(&file).with_context(|| format!("Failed to read {}", file.display()))
+        };
+
+        if path.join("pnpm-lock.yaml").exists() {
+            return Ok(Some(Lockfile::Pnpm(read("pnpm-lock.yaml")?)));
+        }
+        if path.join("yarn.lock").exists() {
+            return Ok(Some(Lockfile::Yarn(read("yarn.lock")?)));
+        }
+        if path.join("package-lock.json").exists() {
+            let text = read("package-lock.json")?;
+            let value = serde_json::from_str(&text).context("Failed to parse package-lock.json")?;
+            return Ok(Some(Lockfile::Npm(value)));
+        }
+        Ok(None)
+    }
+
+    /// Version the lockfile resolved the direct dependency `name@specifier` to
+    pub fn resolved(&self, name: &str, specifier: &str) -> Option<String> {
+        match self {
+            Lockfile::Npm(lock) => lock
+                .pointer(&format!(
+                    "/packages/node_modules~1{}/version",
+                    pointer_escape(name)
+                ))
+                .or_else(|| lock.get("dependencies")?.get(name)?.get("version"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            Lockfile::Yarn(text) => yarn_version(text, name, specifier),
+            Lockfile::Pnpm(text) => pnpm_version(text, name),
+        }
+    }
+
+    /// Every package the lockfile installs, with the versions it is resolved to
+    pub fn packages(&self) -> BTreeMap<String, BTreeSet<String>> {
+        let mut packages: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut add = |name: &str, version: String| {
+            packages
+                .entry(name.to_string())
+                .or_default()
+                .insert(version);
+        };
+        match self {
+            Lockfile::Npm(lock) => {
+                let entries = lock["packages"].as_object().into_iter().flatten();
+                for (path, entry) in entries {
+                    let Some((_, name)) = path.rsplit_once("node_modules/") else {
+                        continue;
+                    };
+                    if let Some(version) = entry["version"].as_str() {
+                        add(name, version.to_string());
+                    }
+                }
+            }
+            Lockfile::Yarn(text) => {
+                let mut name = None;
+                for line in text.lines() {
+                    if !line.starts_with([' ', '#']) && line.ends_with(':') {
+                        let descriptor = line.split(", ").next().unwrap_or(line);
+                        name = descriptor
+                            .trim_end_matches(':')
+                            .trim_matches('"')
+                            .rsplit_once('@')
+                            .map(|(name, _)| name.to_string())
+                            .filter(|name| !name.is_empty());
+                    } else if let (Some(name), Some(version)) =
+                        (&name, line.trim().strip_prefix("version"))
+                    {
+                        add(name, clean_version(version.trim_start_matches(':')));
+                    }
+                }
+            }
+            Lockfile::Pnpm(text) => {
+                let mut in_packages = false;
+                for line in text.lines() {
+                    if !line.starts_with(' ') && !line.trim().is_empty() {
+                        in_packages = line.trim() == "packages:";
+                        continue;
+                    }
+                    let indent = line.len() - line.trim_start().len();
+                    if !in_packages || indent != 2 || !line.ends_with(':') {
+                        continue;
+                    }
+                    if let Some((name, version)) = pnpm_package_key(line) {
+                        add(name, version);
+                    }
+                }
+            }
+        }
+        packages
+    }
+}
+
+/// Format marker and size of a lockfile, to tell when an install rewrote it in another
+/// format or lost most of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockfileShape {
+    pub file: &'static str,
+    /// e.g. `lockfileVersion 3`, `lockfileVersion 9.0`, `yarn v1`, `__metadata.version 8`
+    pub format: Option<String>,
+    pub lines: usize,
+}
+
+impl LockfileShape {
+    /// The lockfile in `dir`, checked in the order package managers are detected
+    pub fn read(dir: &str) -> Result<Option<Self>> {
+        let path = expand_path(dir)?;
+        for file in [
+            "pnpm-lock.yaml",
+            "yarn.lock",
+            "package-lock.json",
+            "npm-shrinkwrap.json",
+        ] {
+            let Ok(text) = fs::read_to_string(path.join(file)) else {
+                continue;
+            };
+            return Ok(Some(LockfileShape {
+                file,
+                format: lockfile_format(file, &text),
+                lines: text.lines().count(),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// What is wrong with the lockfile after an install, compared with `before`: another
+    /// format, or less than half the lines
+    pub fn problem_since(&self, before: &LockfileShape) -> Option<String> {
+        if self.file != before.file {
+            return None;
+        }
+        if self.format != before.format {
+            let describe = |format: &Option<String>| {
+                format
+                    .clone()
+                    .unwrap_or_else(|| "no format marker".to_string())
+            };
+            return Some(format!(
+                "{} format changed from {} to {}",
+                self.file,
+                describe(&before.format),
+                describe(&self.format)
+            ));
+        }
+        if self.lines * 2 < before.lines {
+            return Some(format!(
+                "{} shrank from {} to {} lines, which usually means the wrong package manager ran",
+                self.file, before.lines, self.lines
+            ));
+        }
+        None
+    }
+}
+
+/// `lockfileVersion` of npm and pnpm lockfiles, `__metadata.version` of yarn berry's, and
+/// the `# yarn lockfile v1` header of yarn classic's
+fn lockfile_format(file: &str, text: &str) -> Option<String> {
+    match file {
+        "package-lock.json" | "npm-shrinkwrap.json" => {
+            let value: Value = serde_json::from_str(text).ok()?;
+            Some(format!("lockfileVersion {}", value.get("lockfileVersion")?))
+        }
+        "pnpm-lock.yaml" => text.lines().find_map(|line| {
+            let version = line.strip_prefix("lockfileVersion:")?;
+            Some(format!(
+                "lockfileVersion {}",
+                version.trim().trim_matches(['\'', '"'])
+            ))
+        }),
+        _ => {
+            if let Some(version) = text
+                .lines()
+                .find_map(|line| line.strip_prefix("# yarn lockfile "))
+            {
+                return Some(format!("yarn {}", version.trim()));
+            }
+            let mut lines = text.lines().skip_while(|line| *line != "__metadata:");
+            lines.next()?;
+            lines
+                .take_while(|line| line.starts_with(' '))
+                .find_map(|line| line.trim().strip_prefix("version:"))
+                .map(|version| format!("__metadata.version {}", version.trim()))
+        }
+    }
+}
+
+/// Name and version of a `packages:` key: `/lodash/4.17.21` (v5), `/lodash@4.17.21` (v6)
+/// or `lodash@4.17.21` (v9), with a peer dependency suffix on any of them
+fn pnpm_package_key(line: &str) -> Option<(&str, String)> {
+    let key = line.trim().trim_end_matches(':').trim_matches(['"', '\'']);
+    let key = key.strip_prefix('/').unwrap_or(key);
+    let key = key.split('(').next().unwrap_or(key);
+    let (name, version) = match key.rsplit_once('@') {
+        Some((name, version)) if !name.is_empty() => (name, version),
+        _ => key.rsplit_once('/')?,
+    };
+    Some((name, clean_version(version)))
+}
+
+fn pointer_escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+/// Version of a lockfile value without quotes or pnpm's peer dependency suffix
+fn clean_version(value: &str) -> String {
+    let value = value.trim().trim_matches(['"', '\'']);
+    let value = value.split('(').next().unwrap_or(value);
+    value.split('_').next().unwrap_or(value).to_string()
+}
+
+/// Entries look like `"lodash@^4.17.0", lodash@^4.17.20:` (berry: `"lodash@npm:^4.17.20":`)
+/// followed by an indented `version "4.17.21"` (berry: `version: 4.17.21`)
+fn yarn_version(text: &str, name: &str, specifier: &str) -> Option<String> {
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        if line.starts_with([' ', '#']) || !line.ends_with(':') {
+            continue;
+        }
+        let matches = line.trim_end_matches(':').split(", ").any(|descriptor| {
+            let descriptor = descriptor.trim_matches('"');
+            descriptor.rsplit_once('@').is_some_and(|(n, range)| {
+                n == name && range.strip_prefix("npm:").unwrap_or(range) == specifier
+            })
+        });
+        if !matches {
+            continue;
+        }
+        return lines
+            .take_while(|line| line.starts_with(' '))
+            .find_map(|line| line.trim().strip_prefix("version"))
+            .map(|value| clean_version(value.trim_start_matches(':')));
+    }
+    None
+}
+
+/// Direct dependencies are listed under the root importer (`importers: .: dependencies:`),
+/// or at the top level in lockfiles without importers, either with a nested `version:`
+/// or, in lockfile v5, as `name: version`
+fn pnpm_version(text: &str, name: &str) -> Option<String> {
+    const SECTIONS: [&str; 3] = ["dependencies:", "devDependencies:", "optionalDependencies:"];
+
+    let mut in_importers = false;
+    let mut in_root_importer = false;
+    let mut section_indent = None;
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+
+        if indent == 0 {
+            in_importers = trimmed == "importers:";
+            section_indent = SECTIONS.contains(&trimmed).then_some(0);
+            continue;
+        }
+        if in_importers && indent == 2 {
+            in_root_importer = trimmed == ".:";
+            section_indent = None;
+            continue;
+        }
+        if in_importers && in_root_importer && indent == 4 {
+            section_indent = SECTIONS.contains(&trimmed).then_some(4);
+            continue;
+        }
+
+        let Some(section) = section_indent else {
+            continue;
+        };
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        if indent != section + 2 || key.trim_matches(['"', '\'']) != name {
+            continue;
+        }
+        if !value.trim().is_empty() {
+            return Some(clean_version(value));
+        }
+        return lines
+            .take_while(|line| line.len() - line.trim_start().len() > indent)
+            .find_map(|line| line.trim().strip_prefix("version:"))
+            .map(clean_version);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolved_reads_npm_and_yarn_lockfiles() {
+        let npm = Lockfile::Npm(serde_json::json!({
+            "lockfileVersion": 3,
+            "packages": {
+                "": {},
+                "node_modules/@babel/core": {"version": "7.24.0"},
+            }
+        }));
+        assert_eq!(
+            npm.resolved("@babel/core", "^7.0.0").as_deref(),
+            Some("7.24.0")
+        );
+        let npm_v1 = Lockfile::Npm(serde_json::json!({
+            "dependencies": {"lodash": {"version": "4.17.21"}}
+        }));
+        assert_eq!(
+            npm_v1.resolved("lodash", "^4.17.0").as_deref(),
+            Some("4.17.21")
+        );
+
+        let classic = Lockfile::Yarn(
+            "# yarn lockfile v1\n\n\"@babel/core@^7.0.0\", \"@babel/core@^7.1.0\":\n  version \"7.2.0\"\n  resolved \"https://registry\"\n\nlodash@^4.17.20:\n  version \"4.17.21\"\n"
+                .to_string(),
+        );
+        assert_eq!(
+            classic.resolved("@babel/core", "^7.1.0").as_deref(),
+            Some("7.2.0")
+        );
+        assert_eq!(
+            classic.resolved("lodash", "^4.17.20").as_deref(),
+            Some("4.17.21")
+        );
+        assert_eq!(classic.resolved("lodash", "^4.0.0"), None);
+
+        let berry = Lockfile::Yarn(
+            "__metadata:\n  version: 8\n\n\"lodash@npm:^4.17.20\":\n  version: 4.17.21\n"
+                .to_string(),
+        );
+        assert_eq!(
+            berry.resolved("lodash", "^4.17.20").as_deref(),
+            Some("4.17.21")
+        );
+    }
+
+    #[test]
+    fn packages_lists_every_resolved_version() {
+        let names = |lockfile: Lockfile| -> Vec<String> {
+            lockfile
+                .packages()
+                .into_iter()
+                .map(|(name, versions)| {
+                    let versions: Vec<String> = versions.into_iter().collect();
+                    format!("{}@{}", name, versions.join("+"))
+                })
+                .collect()
+        };
+        let npm = Lockfile::Npm(serde_json::json!({
+            "packages": {
+                "": {"name": "app"},
+                "node_modules/lodash": {"version": "4.17.21"},
+                "node_modules/a/node_modules/lodash": {"version": "3.10.1"},
+                "node_modules/@babel/core": {"version": "7.24.0"},
+            }
+        }));
+        assert_eq!(
+            names(npm),
+            vec!["@babel/core@7.24.0", "lodash@3.10.1+4.17.21"]
+        );
+
+        let classic = Lockfile::Yarn(
+            "# yarn lockfile v1\n\n\"@babel/core@^7.0.0\", \"@babel/core@^7.1.0\":\n  version \"7.2.0\"\n\nlodash@^4.17.20:\n  version \"4.17.21\"\n"
+                .to_string(),
+        );
+        assert_eq!(names(classic), vec!["@babel/core@7.2.0", "lodash@4.17.21"]);
+
+        let pnpm = Lockfile::Pnpm(
+            "lockfileVersion: '9.0'\n\nimporters:\n\n  .:\n    dependencies:\n      react:\n        version: 18.3.1\n\npackages:\n\n  '@types/react@18.3.3(@types/prop-types@15.7.12)':\n    resolution: {integrity: sha512}\n\n  react@18.3.1:\n    resolution: {integrity: sha512}\n\nsnapshots:\n\n  react@18.3.1: {}\n"
+                .to_string(),
+        );
+        assert_eq!(names(pnpm), vec!["@types/react@18.3.3", "react@18.3.1"]);
+        let v5 = Lockfile::Pnpm("packages:\n\n  /lodash/4.17.21:\n    dev: false\n".to_string());
+        assert_eq!(names(v5), vec!["lodash@4.17.21"]);
+    }
+
+    #[test]
+    fn lockfile_shape_catches_format_changes_and_shrinking() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let shape = |file: &str, text: &str| {
+            fs::write(dir.path().join(file), text).unwrap();
+            let shape = LockfileShape::read(&path).unwrap().unwrap();
+            fs::remove_file(dir.path().join(file)).unwrap();
+            shape
+        };
+
+        let v3 = shape(
+            "package-lock.json",
+            "{\n  \"lockfileVersion\": 3,\n  \"packages\": {}\n}\n",
+        );
+        assert_eq!(v3.format.as_deref(), Some("lockfileVersion 3"));
+        let v2 = shape(
+            "package-lock.json",
+            "{\n  \"lockfileVersion\": 2,\n  \"packages\": {}\n}\n",
+        );
+        assert_eq!(
+            v2.problem_since(&v3).as_deref(),
+            Some("package-lock.json format changed from lockfileVersion 3 to lockfileVersion 2")
+        );
+        assert_eq!(v3.problem_since(&v3), None);
+
+        let pnpm = shape(
+            "pnpm-lock.yaml",
+            "lockfileVersion: '9.0'\n\nimporters: {}\n",
+        );
+        assert_eq!(pnpm.format.as_deref(), Some("lockfileVersion 9.0"));
+        let classic = shape(
+            "yarn.lock",
+            "# THIS IS AN AUTOGENERATED FILE\n# yarn lockfile v1\n",
+        );
+        assert_eq!(classic.format.as_deref(), Some("yarn v1"));
+        let berry = shape("yarn.lock", "__metadata:\n  version: 8\n  cacheKey: 10\n");
+        assert_eq!(berry.format.as_deref(), Some("__metadata.version 8"));
+        assert_eq!(
+            berry.problem_since(&classic).as_deref(),
+            Some("yarn.lock format changed from yarn v1 to __metadata.version 8")
+        );
+
+        let big = LockfileShape {
+            lines: 1000,
+            ..classic.clone()
+        };
+        let small = LockfileShape {
+            lines: 400,
+            ..classic
+        };
+        assert_eq!(
+            small.problem_since(&big).as_deref(),
+            Some("yarn.lock shrank from 1000 to 400 lines, which usually means the wrong package manager ran")
+        );
+        assert_eq!(big.problem_since(&small), None);
+    }
+
+    #[test]
+    fn resolved_reads_pnpm_lockfiles() {
+        let v9 = Lockfile::Pnpm(
+            "lockfileVersion: '9.0'\n\nimporters:\n\n  .:\n    dependencies:\n      react:\n        specifier: ^18.2.0\n        version: 18.3.1\n    devDependencies:\n      '@types/react':\n        specifier: ^18.0.0\n        version: 18.3.3(@types/prop-types@15.7.12)\n\n  packages/ui:\n    dependencies:\n      vue:\n        specifier: ^3.0.0\n        version: 3.4.0\n\npackages:\n\n  react@18.3.1:\n    resolution: {integrity: sha512}\n"
+                .to_string(),
+        );
+        assert_eq!(v9.resolved("react", "^18.2.0").as_deref(), Some("18.3.1"));
+        assert_eq!(
+            v9.resolved("@types/react", "^18.0.0").as_deref(),
+            Some("18.3.3")
+        );
+        assert_eq!(v9.resolved("vue", "^3.0.0"), None);
+
+        let v5 = Lockfile::Pnpm(
+            "lockfileVersion: 5.4\n\nspecifiers:\n  react: ^18.2.0\n\ndependencies:\n  react: 18.2.0_zpnidt7m3osuk7shl3s4oenomq\n".to_string(),
+        );
+        assert_eq!(v5.resolved("react", "^18.2.0").as_deref(), Some("18.2.0"));
+    }
+}
@@ -1,37 +1,252 @@
+mod azure;
+mod bitbucket;
+mod bumps;
+mod campaign;
+mod changelog;
 mod cli;
+mod codeowners;
 mod config;
+mod context;
 mod git;
 mod github;
+mod gitlib;
+mod history;
+mod hooks;
+mod http;
+mod interrupt;
+mod license;
+mod lint;
+mod lock;
+mod lockfile;
+mod log;
+mod manifest;
+mod message;
+mod node;
+mod notify;
+mod order;
 mod package;
+mod parallel;
+mod policy;
+mod preflight;
+mod preview;
+mod provenance;
+mod provider;
+mod ratelimit;
 mod repo;
+mod report;
+mod retry;
+mod runner;
+mod scratch;
+mod share;
+mod suggest;
+mod summary;
+mod table;
+mod template;
+mod timing;
+mod ui;
+mod version;
 
 use anyhow::Result;
 use clap::Parser;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
+    match run() {
+        Err(e) if e.is::<interrupt::Interrupted>() => {
+            eprintln!("{}", e);
+            Ok(ExitCode::from(interrupt::EXIT_CODE))
+        }
+        result => result.map(|()| ExitCode::SUCCESS),
+    }
+}
+
+fn run() -> Result<()> {
     let cli = cli::Cli::parse();
-    let mut config = config::Config::load()?;
+    // The profile decides where the config, lock, campaigns and history are
+    if cli.config.is_none() {
+        if let Some(name) = config::select_profile(cli.profile.as_deref())? {
+            config::set_profile(&name)?;
+            if !cli.quiet {
+                eprintln!("Using profile '{}'", name);
+            }
+        }
+    }
+    let _lock = cli
+        .command
+        .lock_name()
+        .map(|name| lock::Lock::acquire(name, cli.wait))
+        .transpose()?;
+    let config_file = cli.config.as_deref().map(repo::expand_path).transpose()?;
+    let mut config = config::Config::load(config_file.as_deref())?;
+    let attempts = cli
+        .retries
+        .or(config.retries)
+        .unwrap_or(retry::DEFAULT_ATTEMPTS);
+    let mut ctx = context::Context::new()
+        .with_retry(retry::RetryPolicy::new(attempts))
+        .with_timeouts(context::Timeouts::from_config(&config))
+        .with_github(
+            github::GithubContext::new(config.github_host()).with_concurrency(
+                config
+                    .github_concurrency
+                    .unwrap_or(github::DEFAULT_CONCURRENCY),
+            ),
+        )
+        .with_quiet(cli.quiet)
+        .with_notify(!cli.no_notify)
+        .with_jsonc(cli.jsonc);
+
+    if let Some(log_file) = cli.log_file.as_ref().or(config.log_file.as_ref()) {
+        let path = repo::expand_path(log_file)?;
+        ctx = ctx.with_log(log::Logger::open(&path)?);
+    }
 
     match &cli.command {
         cli::Commands::Update {
             package,
             version,
+            stdin,
+            wanted: _,
+            preserve_prefix,
             message,
             pull_request,
             dry_run,
+            no_validate,
+            commit_type,
+            scope,
+            ticket,
+            policy,
+            allow_protected,
+            force_base,
+            base,
+            changelog,
+            allow_extra_paths,
+            confirm_push,
+            yes,
+            timings,
+            wait_between_waves,
+            dedupe,
+            no_dedupe,
+            restore_on_failure: _,
+            no_restore_on_failure,
+            recurse_submodules,
+            commit_submodules,
+            ready,
+            comment_details,
+            resolve_preview,
+            skip_if_bot_pr,
+            ignore_bot_prs,
+            allow_lockfile_format_change,
+            repo_path,
+            trailers: _,
+            no_trailers,
+            pr_note,
+            codeowners,
+            milestone,
+            project,
+            create_missing_milestone,
+            verify_provenance,
+            provenance,
+            license_policy,
+            limit,
+            batch,
+            web,
+            web_limit,
+            report,
+            report_format,
+            summary,
+            on_error,
         } => {
-            cli::handle_update(
-                &config,
-                package,
-                version,
-                message.as_deref(),
-                *pull_request,
-                *dry_run,
-            )?;
+            let config = cli::ad_hoc_config(&ctx, &config, repo_path.as_deref())?;
+            let update = |config: &config::Config, package: &str, version: Option<&str>| {
+                cli::handle_update(
+                    &ctx,
+                    config,
+                    package,
+                    version,
+                    *preserve_prefix,
+                    message.as_deref(),
+                    *pull_request,
+                    *dry_run,
+                    *no_validate,
+                    &message::CommitStyle {
+                        kind: commit_type.clone(),
+                        scope: scope.clone(),
+                        ticket: ticket.clone(),
+                    },
+                    *policy,
+                    *allow_protected,
+                    *force_base,
+                    base.as_deref(),
+                    *changelog,
+                    allow_extra_paths,
+                    *confirm_push && !*yes,
+                    *timings,
+                    *wait_between_waves,
+                    !*no_dedupe && (*dedupe || config.dedupe.unwrap_or(false)),
+                    !*no_restore_on_failure,
+                    match (*recurse_submodules, *commit_submodules) {
+                        (false, _) => git::SubmoduleMode::Skip,
+                        (true, false) => git::SubmoduleMode::Recurse,
+                        (true, true) => git::SubmoduleMode::RecurseAndCommit,
+                    },
+                    *ready,
+                    *comment_details,
+                    *resolve_preview,
+                    match (*skip_if_bot_pr, *ignore_bot_prs) {
+                        (true, _) => campaign::BotPrs::Skip,
+                        (_, true) => campaign::BotPrs::Ignore,
+                        _ => campaign::BotPrs::NoPr,
+                    },
+                    !*no_trailers,
+                    *pr_note,
+                    *allow_lockfile_format_change,
+                    summary.or(config.summary).unwrap_or_default(),
+                    on_error
+                        .or(config.on_error)
+                        .unwrap_or_else(campaign::OnError::detect),
+                    *codeowners || config.codeowners.unwrap_or(false),
+                    milestone.as_deref().or(config.milestone.as_deref()),
+                    project.as_deref().or(config.project.as_deref()),
+                    *create_missing_milestone,
+                    verify_provenance.then_some(*provenance),
+                    license_policy
+                        .or((!config.license_denylist.is_empty()).then_some(cli::PolicyMode::Warn)),
+                    limit.map(campaign::Batching::Limit).or(*batch),
+                    web.then_some(*web_limit),
+                    report.as_deref().map(|path| (path, *report_format)),
+                )
+            };
+            if *stdin {
+                cli::handle_update_stdin(&ctx, &config, *yes, update)?;
+            } else {
+                let package = package.as_deref().unwrap_or_default();
+                update(&config, package, version.as_deref())?;
+            }
         }
 
-        cli::Commands::AddRepo { path } => {
-            cli::handle_add_repo(&mut config, path)?;
+        cli::Commands::Ui => {
+            cli::handle_ui(&ctx, &config)?;
+        }
+
+        cli::Commands::AddRepo {
+            path,
+            url,
+            alias,
+            use_toplevel,
+            strict,
+            force,
+        } => {
+            cli::handle_add_repo(
+                &ctx,
+                &mut config,
+                path,
+                url.as_deref(),
+                alias.as_deref(),
+                *use_toplevel,
+                *strict,
+                *force,
+            )?;
         }
 
         cli::Commands::RemoveRepo { path } => {
@@ -39,28 +254,204 @@ fn main() -> Result<()> {
         }
 
         cli::Commands::ListRepos => {
-            cli::handle_list_repos(&config)?;
+            cli::handle_list_repos(&ctx, &config)?;
         }
 
-        cli::Commands::Compare { package } => {
-            cli::handle_compare(&config, package)?;
+        cli::Commands::Status {
+            fetch,
+            json,
+            prs,
+            include_bots,
+        } => {
+            cli::handle_status(&ctx, &config, *fetch, *json, *prs, *include_bots)?;
         }
 
-        cli::Commands::ListPackages { repo } => {
-            cli::handle_list_packages(&config, repo.as_deref())?;
+        cli::Commands::Doctor { repo } => {
+            cli::handle_doctor(&ctx, &config, repo.as_deref())?;
         }
 
-        cli::Commands::Clone {
-            github_url,
-            output,
-            add,
-        } => {
-            cli::handle_clone(&mut config, github_url, output.as_deref(), *add)?;
+        cli::Commands::Compare(args) => {
+            let config = cli::ad_hoc_config(&ctx, &config, args.repo_path.as_deref())?;
+            cli::handle_compare(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Check(args) => {
+            cli::handle_check(&ctx, &config, args)?;
+        }
+
+        cli::Commands::ListPackages(args) => {
+            let config = cli::ad_hoc_config(&ctx, &config, args.repo_path.as_deref())?;
+            cli::handle_list_packages(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Clone(args) => {
+            cli::handle_clone(&ctx, &mut config, args)?;
+        }
+
+        cli::Commands::Fork(args) => {
+            cli::handle_fork(&ctx, &mut config, args)?;
+        }
+
+        cli::Commands::Bootstrap(args) => {
+            cli::handle_bootstrap(&ctx, &mut config, args)?;
         }
 
         cli::Commands::SetPackageManager { name } => {
             cli::handle_set_package_manager(&mut config, name)?;
         }
+
+        cli::Commands::Resume {
+            campaign_id,
+            next_batch,
+            on_error,
+        } => {
+            cli::handle_resume(
+                &ctx,
+                &config,
+                campaign_id.as_deref(),
+                *next_batch,
+                *on_error,
+            )?;
+        }
+
+        cli::Commands::Undo {
+            campaign_id,
+            dry_run,
+            yes,
+        } => {
+            cli::handle_undo(&ctx, &config, campaign_id.as_deref(), *dry_run, *yes)?;
+        }
+
+        cli::Commands::Ready(args) => {
+            cli::handle_ready(&ctx, &config, args)?;
+        }
+
+        cli::Commands::History { command, json } => match command {
+            Some(cli::HistoryCommands::Show { id, markdown }) => {
+                cli::handle_history_show(&ctx, &config, id, *json, *markdown)?
+            }
+            None => cli::handle_history(*json)?,
+        },
+
+        cli::Commands::Campaigns { command } => match command {
+            cli::CampaignCommands::List => cli::handle_campaigns_list()?,
+        },
+
+        cli::Commands::Exec(args) => {
+            let config = cli::ad_hoc_config(&ctx, &config, args.repo_path.as_deref())?;
+            cli::handle_exec(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Merge(args) => {
+            cli::handle_merge(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Backport(args) => {
+            cli::handle_backport(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Version(args) => {
+            cli::handle_version(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Propagate(args) => {
+            cli::handle_propagate(&ctx, &config, args)?;
+        }
+
+        cli::Commands::PinAll(args) => {
+            cli::handle_pin_all(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Widen(args) => {
+            cli::handle_widen(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Engines(args) => {
+            cli::handle_engines(&ctx, &config, args)?;
+        }
+
+        cli::Commands::LintManifests(args) => {
+            cli::handle_lint_manifests(&ctx, &config, args)?;
+        }
+
+        cli::Commands::Notify { report } => {
+            cli::handle_notify(&config, report)?;
+        }
+
+        cli::Commands::Config { command } => match command {
+            cli::ConfigCommands::Which => cli::handle_config_which(&config)?,
+            cli::ConfigCommands::Export {
+                out,
+                workspace,
+                strip_local,
+            } => cli::handle_config_export(
+                &config,
+                out.as_deref(),
+                workspace.as_deref(),
+                *strip_local,
+            )?,
+            cli::ConfigCommands::Import {
+                file,
+                merge: _,
+                replace,
+                root,
+                prefer,
+                clone,
+                dry_run,
+            } => cli::handle_config_import(
+                &ctx,
+                &mut config,
+                file,
+                *replace,
+                root.as_deref(),
+                *prefer,
+                *clone,
+                *dry_run,
+            )?,
+        },
+
+        cli::Commands::Profile { command } => match command {
+            cli::ProfileCommands::List => cli::handle_profile_list(&config)?,
+            cli::ProfileCommands::Create { name } => cli::handle_profile_create(name)?,
+            cli::ProfileCommands::Delete { name, yes } => cli::handle_profile_delete(name, *yes)?,
+            cli::ProfileCommands::Copy { from, to } => cli::handle_profile_copy(from, to)?,
+        },
+
+        cli::Commands::Templates { command } => match command {
+            cli::TemplateCommands::Test { repo } => {
+                cli::handle_templates_test(&config, repo.as_deref())?
+            }
+        },
+
+        cli::Commands::Cache { command } => match command {
+            cli::CacheCommands::Clean { tmp: _, dry_run } => {
+                cli::handle_cache_clean(&config, *dry_run)?
+            }
+        },
+
+        cli::Commands::Policy { command } => match command {
+            cli::PolicyCommands::List => cli::handle_policy_list(&config)?,
+            cli::PolicyCommands::Add {
+                name,
+                package,
+                repos,
+                ignore,
+                allowed,
+                reason,
+            } => cli::handle_policy_add(
+                &mut config,
+                policy::Policy {
+                    name: name.clone(),
+                    package: package.clone(),
+                    repos: repos.clone(),
+                    ignore: *ignore,
+                    allowed: allowed.clone(),
+                    reason: reason.clone(),
+                    ..Default::default()
+                },
+            )?,
+            cli::PolicyCommands::Remove { name } => cli::handle_policy_remove(&mut config, name)?,
+        },
     }
 
     Ok(())
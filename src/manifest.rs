@@ -0,0 +1,466 @@
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Manifest as read from disk, with the modification time it was read at
+struct Entry {
+    mtime: Option<SystemTime>,
+    text: Arc<String>,
+    value: Arc<Value>,
+}
+
+/// Parsed package.json files, read at most once per invocation.
+/// An entry is re-read if the file's modification time changes.
+#[derive(Default)]
+pub struct ManifestCache {
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    reads: AtomicUsize,
+    /// Accept comments and trailing commas
+    jsonc: bool,
+}
+
+impl ManifestCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_jsonc(mut self, jsonc: bool) -> Self {
+        self.jsonc = jsonc;
+        self
+    }
+
+    /// Parsed contents of the manifest at `path`
+    pub fn get(&self, path: &Path) -> Result<Arc<Value>> {
+        self.load(path)?;
+        Ok(self.entries.lock().unwrap()[path].value.clone())
+    }
+
+    /// Raw text of the manifest at `path`, exactly as it is on disk
+    pub fn get_text(&self, path: &Path) -> Result<Arc<String>> {
+        self.load(path)?;
+        Ok(self.entries.lock().unwrap()[path].text.clone())
+    }
+
+    fn load(&self, path: &Path) -> Result<()> {
+        let mtime = fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        if let Some(entry) = self.entries.lock().unwrap().get(path) {
+            if entry.mtime == mtime {
+                return Ok(());
+            }
+        }
+
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let value = self.parse(path, &text)?;
+
+        self.entries.lock().unwrap().insert(
+            path.to_path_buf(),
+            Entry {
+                mtime,
+                text: Arc::new(text),
+                value: Arc::new(value),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Parse manifest text read from somewhere other than `path` on disk, e.g. a
+    /// commit, the way a cached manifest is parsed. `path` names it in errors.
+    pub fn parse(&self, path: &Path, text: &str) -> Result<Value> {
+        // A UTF-8 BOM is replaced rather than removed so error positions match the file
+        let mut source = text.replacen('\u{feff}', " ", 1);
+        if self.jsonc {
+            source = blank_jsonc(&source);
+        }
+        serde_json::from_str(&source).map_err(|e| parse_error(path, text, e, self.jsonc))
+    }
+
+    /// Drop the cached entry for a manifest that is about to be modified
+    pub fn invalidate(&self, path: &Path) {
+        self.entries.lock().unwrap().remove(path);
+    }
+
+    /// Number of times a manifest was read from disk
+    #[cfg(test)]
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+/// Replace a file by writing a temporary file next to it and renaming it over the original,
+/// so an interruption leaves either the old or the new content. Permissions are kept.
+pub fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    use std::io::Write;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+    temp.write_all(content.as_ref())
+        .and_then(|_| temp.as_file().sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp.path(), metadata.permissions())?;
+    }
+    temp.persist(path)
+        .map_err(|e| e.error)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Parse error with the line, column and a snippet of the offending line
+fn parse_error(path: &Path, text: &str, err: serde_json::Error, jsonc: bool) -> anyhow::Error {
+    let line = err.line();
+    let column = err.column();
+    let snippet = text.lines().nth(line.saturating_sub(1)).unwrap_or("");
+    let snippet = snippet.trim_start_matches('\u{feff}');
+    // Keep tabs so the caret lines up with the snippet
+    let padding: String = snippet
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let mut message = format!(
+        "Failed to parse {} at line {}, column {}: {}\n    {}\n    {}^",
+        path.display(),
+        line,
+        column,
+        err,
+        snippet,
+        padding
+    );
+    if !jsonc && (text.contains("//") || text.contains("/*")) {
+        message.push_str("\nIf the file contains comments, rerun with --jsonc");
+    }
+    anyhow::anyhow!(message)
+}
+
+/// Copy of a JSONC document with comments and trailing commas replaced by spaces.
+/// Byte offsets (and so line and column numbers) are the same as in the original.
+pub fn blank_jsonc(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = bytes.to_vec();
+
+    // Comments
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'"', _) => {
+                i = skip_string(text, i).unwrap_or(bytes.len());
+                continue;
+            }
+            (b'/', Some(b'/')) => {
+                while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r' {
+                    out[i] = b' ';
+                    i += 1;
+                }
+                continue;
+            }
+            (b'/', Some(b'*')) => {
+                let end = text[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |pos| i + 2 + pos + 2);
+                for byte in &mut out[i..end] {
+                    if *byte != b'\n' && *byte != b'\r' {
+                        *byte = b' ';
+                    }
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    // Trailing commas, now that comments between a comma and the closing bracket are gone
+    let blanked = String::from_utf8(out).expect("only ASCII bytes were replaced");
+    let bytes = blanked.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = skip_string(&blanked, i).unwrap_or(bytes.len());
+                continue;
+            }
+            b',' => {
+                let next = skip_ws(&blanked, i + 1);
+                if matches!(bytes.get(next), Some(b'}') | Some(b']')) {
+                    out[i] = b' ';
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    String::from_utf8(out).expect("only ASCII bytes were replaced")
+}
+
+/// Replace the value of `name` inside the top-level `section` object of a JSON document,
+/// leaving every other byte (indentation, key order, line endings, final newline) untouched.
+/// Comments and trailing commas are skipped over and kept as they are.
+/// Returns `None` if the section or entry does not exist or the value is not a string.
+pub fn replace_entry_value(
+    text: &str,
+    section: &str,
+    name: &str,
+    new_value: &str,
+) -> Option<String> {
+    // Locate the entry in a copy without comments; offsets are the same in `text`
+    let blanked = blank_jsonc(text);
+    let root = find_root(&blanked)?;
+    let section_span = find_member(&blanked, root, section)?;
+    let entry_span = find_member(&blanked, section_span.start, name)?;
+
+    if !text[entry_span.clone()].starts_with('"') {
+        return None;
+    }
+
+    let escaped = serde_json::to_string(new_value).ok()?;
+    let mut result = String::with_capacity(text.len() + escaped.len());
+    result.push_str(&text[..entry_span.start]);
+    result.push_str(&escaped);
+    result.push_str(&text[entry_span.end..]);
+    Some(result)
+}
+
+/// Replace the string value of the top-level member `name`, e.g. `"version"`,
+/// preserving the rest of the document like [`replace_entry_value`]
+pub fn replace_top_level_value(text: &str, name: &str, new_value: &str) -> Option<String> {
+    let blanked = blank_jsonc(text);
+    let root = find_root(&blanked)?;
+    let span = find_member(&blanked, root, name)?;
+
+    if !text[span.clone()].starts_with('"') {
+        return None;
+    }
+
+    let escaped = serde_json::to_string(new_value).ok()?;
+    Some(format!(
+        "{}{}{}",
+        &text[..span.start],
+        escaped,
+        &text[span.end..]
+    ))
+}
+
+/// Index of the opening brace of the top-level object
+fn find_root(text: &str) -> Option<usize> {
+    let start = text
+        .strip_prefix('\u{feff}')
+        .map_or(0, |_| '\u{feff}'.len_utf8());
+    let start = skip_ws(text, start);
+    (text.as_bytes().get(start) == Some(&b'{')).then_some(start)
+}
+
+/// Span of the value of member `key` in the object whose `{` is at `object_start`
+fn find_member(text: &str, object_start: usize, key: &str) -> Option<Range<usize>> {
+    let bytes = text.as_bytes();
+    if bytes.get(object_start) != Some(&b'{') {
+        return None;
+    }
+
+    let mut pos = object_start + 1;
+    loop {
+        pos = skip_ws(text, pos);
+        match bytes.get(pos)? {
+            b'}' => return None,
+            b',' => {
+                pos += 1;
+                continue;
+            }
+            b'"' => {}
+            _ => return None,
+        }
+
+        let key_end = skip_string(text, pos)?;
+        let member_key: String = serde_json::from_str(&text[pos..key_end]).ok()?;
+
+        pos = skip_ws(text, key_end);
+        if bytes.get(pos) != Some(&b':') {
+            return None;
+        }
+        let value_start = skip_ws(text, pos + 1);
+        let value_end = skip_value(text, value_start)?;
+
+        if member_key == key {
+            return Some(value_start..value_end);
+        }
+        pos = value_end;
+    }
+}
+
+fn skip_ws(text: &str, mut pos: usize) -> usize {
+    let bytes = text.as_bytes();
+    while pos < bytes.len() && matches!(bytes[pos], b' ' | b'\t' | b'\n' | b'\r') {
+        pos += 1;
+    }
+    pos
+}
+
+/// End (exclusive) of the string literal starting at `pos`
+fn skip_string(text: &str, pos: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = pos + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// End (exclusive) of the JSON value starting at `pos`
+fn skip_value(text: &str, pos: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    match bytes.get(pos)? {
+        b'"' => skip_string(text, pos),
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut i = pos;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'"' => {
+                        i = skip_string(text, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            None
+        }
+        _ => {
+            let mut i = pos;
+            while i < bytes.len()
+                && !matches!(bytes[i], b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r')
+            {
+                i += 1;
+            }
+            Some(i)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed_lines(before: &str, after: &str) -> usize {
+        assert_eq!(before.split('\n').count(), after.split('\n').count());
+        before
+            .split('\n')
+            .zip(after.split('\n'))
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+
+    fn fixture(indent: &str, newline: &str) -> String {
+        [
+            "{".to_string(),
+            format!("{}\"name\": \"app\",", indent),
+            format!("{}\"dependencies\": {{", indent),
+            format!("{0}{0}\"zod\": \"^3.0.0\",", indent),
+            format!("{0}{0}\"react\": \"^17.0.2\"", indent),
+            format!("{}}},", indent),
+            format!("{}\"devDependencies\": {{", indent),
+            format!("{0}{0}\"react\": \"^17.0.2\"", indent),
+            format!("{}}}", indent),
+            "}".to_string(),
+            String::new(),
+        ]
+        .join(newline)
+    }
+
+    #[test]
+    fn replace_touches_one_line_for_each_indent_style() {
+        for (indent, newline) in [("  ", "\n"), ("    ", "\n"), ("\t", "\n"), ("  ", "\r\n")] {
+            let before = fixture(indent, newline);
+            let after = replace_entry_value(&before, "dependencies", "react", "^18.2.0").unwrap();
+
+            assert_eq!(changed_lines(&before, &after), 1);
+            assert!(after.contains(&format!("{0}{0}\"react\": \"^18.2.0\"{1}", indent, newline)));
+            assert!(after.ends_with(newline));
+            // The same package in another section is left alone
+            assert!(after.contains(&format!(
+                "{0}{0}\"react\": \"^17.0.2\"{1}{0}}}{1}}}",
+                indent, newline
+            )));
+        }
+    }
+
+    #[test]
+    fn cache_accepts_bom_and_reports_parse_errors_with_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package.json");
+        let cache = ManifestCache::new();
+
+        fs::write(
+            &path,
+            "\u{feff}{\"dependencies\": {\"react\": \"^17.0.2\"}}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cache.get(&path).unwrap()["dependencies"]["react"],
+            "^17.0.2"
+        );
+
+        fs::write(
+            &path,
+            "{\n  \"name\": \"app\"\n  \"version\": \"1.0.0\"\n}\n",
+        )
+        .unwrap();
+        cache.invalidate(&path);
+        let err = cache.get(&path).unwrap_err().to_string();
+        assert!(err.contains("line 3, column 3"), "{}", err);
+        assert!(
+            err.contains("\n      \"version\": \"1.0.0\"\n      ^"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn jsonc_is_read_with_flag_and_edited_without_losing_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package.json");
+        let text = "{\n  // runtime deps\n  \"dependencies\": {\n    \"react\": \"^17.0.2\", /* pinned */\n  },\n}\n";
+        fs::write(&path, text).unwrap();
+
+        let err = ManifestCache::new().get(&path).unwrap_err().to_string();
+        assert!(err.contains("--jsonc"), "{}", err);
+
+        let value = ManifestCache::new().with_jsonc(true).get(&path).unwrap();
+        assert_eq!(value["dependencies"]["react"], "^17.0.2");
+
+        let after = replace_entry_value(text, "dependencies", "react", "^18.2.0").unwrap();
+        assert_eq!(after, text.replace("^17.0.2", "^18.2.0"));
+    }
+
+    #[test]
+    fn replace_returns_none_for_missing_entries() {
+        let text = fixture("  ", "\n");
+        assert!(replace_entry_value(&text, "dependencies", "vue", "^3.0.0").is_none());
+        assert!(replace_entry_value(&text, "peerDependencies", "react", "^18.0.0").is_none());
+    }
+}
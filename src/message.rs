@@ -0,0 +1,115 @@
+use anyhow::Result;
+
+/// Commit types accepted when the config has no `commit_types` list
+pub const DEFAULT_COMMIT_TYPES: [&str; 11] = [
+    "build", "chore", "ci", "docs", "feat", "fix", "perf", "refactor", "revert", "style", "test",
+];
+
+/// Conventional-commit type, scope and ticket used in generated commit messages,
+/// PR titles and branch names
+#[derive(Debug, Default, Clone)]
+pub struct CommitStyle {
+    pub kind: Option<String>,
+    pub scope: Option<String>,
+    pub ticket: Option<String>,
+}
+
+impl CommitStyle {
+    /// Check the type against `allowed`, or the conventional types if none are configured
+    pub fn validate(&self, allowed: Option<&[String]>) -> Result<()> {
+        let Some(kind) = &self.kind else {
+            return Ok(());
+        };
+
+        let allowed: Vec<&str> = match allowed {
+            Some(types) => types.iter().map(|t| t.as_str()).collect(),
+            None => DEFAULT_COMMIT_TYPES.to_vec(),
+        };
+        if !allowed.contains(&kind.as_str()) {
+            anyhow::bail!(
+                "Commit type '{}' is not allowed. Must be one of: {}",
+                kind,
+                allowed.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether any part was given, so generated names should follow the style
+    pub fn is_set(&self) -> bool {
+        self.kind.is_some() || self.scope.is_some() || self.ticket.is_some()
+    }
+
+    /// Commit type, `chore` unless one was given
+    pub fn kind(&self) -> &str {
+        self.kind.as_deref().unwrap_or("chore")
+    }
+
+    /// Commit subject, e.g. `fix(deps): SEC-42 update lodash to 4.17.21`
+    pub fn subject(&self, description: &str) -> String {
+        let mut subject = self.kind().to_string();
+        if let Some(scope) = &self.scope {
+            subject.push_str(&format!("({})", scope));
+        }
+        subject.push_str(": ");
+        if let Some(ticket) = &self.ticket {
+            subject.push_str(ticket);
+            subject.push(' ');
+        }
+        subject.push_str(description);
+        subject
+    }
+
+    /// Branch for a package update, e.g. `fix/sec-42-lodash-4.17.21`.
+    /// `None` if no part was given, leaving the default `update-*` name.
+    pub fn branch_name(&self, package: &str, version: &str) -> Option<String> {
+        if !self.is_set() {
+            return None;
+        }
+
+        let mut name = format!("{}/", self.kind());
+        if let Some(ticket) = &self.ticket {
+            name.push_str(&ticket.to_lowercase());
+            name.push('-');
+        }
+        name.push_str(&format!("{}-{}", package, version.replace(['^', '~'], "")));
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn style_shapes_subject_and_branch() {
+        let style = CommitStyle {
+            kind: Some("fix".to_string()),
+            scope: Some("deps".to_string()),
+            ticket: Some("SEC-42".to_string()),
+        };
+
+        assert_eq!(
+            style.subject("update lodash to 4.17.21"),
+            "fix(deps): SEC-42 update lodash to 4.17.21"
+        );
+        assert_eq!(
+            style.branch_name("lodash", "^4.17.21").as_deref(),
+            Some("fix/sec-42-lodash-4.17.21")
+        );
+        assert_eq!(
+            crate::template::TemplateContext::new("lodash", "4.17.21")
+                .with_style(&style)
+                .render("{type}: bump {package} ({ticket})"),
+            "fix: bump lodash (SEC-42)"
+        );
+        assert_eq!(
+            CommitStyle::default().branch_name("lodash", "4.17.21"),
+            None
+        );
+
+        assert!(style.validate(None).is_ok());
+        assert!(style.validate(Some(&["build".to_string()])).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::http::HttpSetup;
+use crate::version;
+
+/// Index of all Node.js releases, newest first
+const INDEX_URL: &str = "https://nodejs.org/dist/index.json";
+
+/// How long fetching the release index may take
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One entry of the Node.js release index
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NodeRelease {
+    /// Version with a `v` prefix, e.g. `v20.11.0`
+    pub version: String,
+    /// Version of the npm bundled with the release; missing for the oldest ones
+    #[serde(default)]
+    pub npm: Option<String>,
+}
+
+impl NodeRelease {
+    /// Version without the `v` prefix
+    pub fn number(&self) -> &str {
+        self.version.trim_start_matches('v')
+    }
+}
+
+/// Releases from the Node.js release index, newest first
+pub fn releases() -> Result<Vec<NodeRelease>> {
+    let setup = HttpSetup::detect();
+    let response = setup
+        .client(TIMEOUT)?
+        .get(INDEX_URL)
+        .send()
+        .with_context(|| {
+            format!(
+                "Failed to fetch the Node.js release index via {}",
+                setup.describe(INDEX_URL)
+            )
+        })?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!(
+            "Failed to fetch the Node.js release index: server responded with {}",
+            status
+        );
+    }
+    response
+        .json()
+        .context("Failed to read the Node.js release index")
+}
+
+/// Node.js version to pin for the engines `range`: `explicit` if given, which must
+/// be in the range, or else the newest release in it. The release is looked up in
+/// `releases` for its bundled npm; an explicit version missing there has none.
+pub fn pick(releases: &[NodeRelease], range: &str, explicit: Option<&str>) -> Result<NodeRelease> {
+    if let Some(explicit) = explicit {
+        let explicit = explicit.trim_start_matches('v');
+        if !version::satisfies(range, explicit) {
+            anyhow::bail!("Node.js {} is not in the engines range {}", explicit, range);
+        }
+        return Ok(releases
+            .iter()
+            .find(|release| release.number() == explicit)
+            .cloned()
+            .unwrap_or_else(|| NodeRelease {
+                version: format!("v{}", explicit),
+                npm: None,
+            }));
+    }
+
+    releases
+        .iter()
+        .find(|release| version::satisfies(range, release.number()))
+        .cloned()
+        .with_context(|| format!("No Node.js release matches the engines range {}", range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_takes_the_newest_release_in_range_or_the_explicit_one() {
+        let releases: Vec<NodeRelease> = serde_json::from_str(
+            r#"[
+                {"version": "v22.1.0", "npm": "10.7.0", "lts": false},
+                {"version": "v20.12.2", "npm": "10.5.0", "lts": "Iron"},
+                {"version": "v20.11.1", "npm": "10.2.4", "lts": "Iron"},
+                {"version": "v18.20.2", "npm": "10.5.0", "lts": "Hydrogen"}
+            ]"#,
+        )
+        .unwrap();
+
+        let release = pick(&releases, ">=18 <21", None).unwrap();
+        assert_eq!(
+            (release.number(), release.npm.as_deref()),
+            ("20.12.2", Some("10.5.0"))
+        );
+
+        let release = pick(&releases, "^20.0.0", Some("v20.11.1")).unwrap();
+        assert_eq!(release.npm.as_deref(), Some("10.2.4"));
+        assert_eq!(pick(&[], "^20.0.0", Some("20.5.0")).unwrap().npm, None);
+
+        assert!(pick(&releases, "^20.0.0", Some("18.20.2")).is_err());
+        assert!(pick(&releases, ">=24", None).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::campaign::RepoStatus;
+use crate::config::TemplateConfig;
+use crate::context::repo_name;
+use crate::history::HistoryRecord;
+use crate::http::HttpSetup;
+use crate::template::{format_date, TemplateContext};
+
+/// Time limit for delivering one notification
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to announce finished campaigns. URLs carry secrets, so they are given as
+/// references to environment variables, e.g. `"$SLACK_WEBHOOK_URL"`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Receives the run report as a JSON POST
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// Slack incoming webhook; receives a summary with a link to each pull request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slack_webhook_url: Option<String>,
+}
+
+impl NotificationConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (key, value) in self.targets() {
+            if env_reference(value).is_none() {
+                anyhow::bail!(
+                    "notifications.{} must reference an environment variable, e.g. \"${}\", \
+                     so the secret isn't stored in the config",
+                    key,
+                    key.to_ascii_uppercase()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets().is_empty()
+    }
+
+    fn targets(&self) -> Vec<(&'static str, &str)> {
+        let mut targets = Vec::new();
+        if let Some(url) = &self.webhook_url {
+            targets.push(("webhook_url", url.as_str()));
+        }
+        if let Some(url) = &self.slack_webhook_url {
+            targets.push(("slack_webhook_url", url.as_str()));
+        }
+        targets
+    }
+}
+
+/// Name of the variable in `$NAME` or `${NAME}`
+fn env_reference(value: &str) -> Option<&str> {
+    let name = value.strip_prefix('$')?;
+    let name = match name.strip_prefix('{') {
+        Some(braced) => braced.strip_suffix('}')?,
+        None => name,
+    };
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+/// Notification message unless `templates.notification` is set
+const DEFAULT_MESSAGE: &str = "mru updated {package} to {version} — {summary}";
+
+/// One-line summary, e.g. "mru updated lodash to 4.17.21 — 12 PRs opened, 2 skipped, 1 failed"
+pub fn summary(record: &HistoryRecord) -> String {
+    message(record, &TemplateConfig::default())
+}
+
+/// The summary as `templates.notification` words it
+pub fn message(record: &HistoryRecord, templates: &TemplateConfig) -> String {
+    let vars = TemplateContext::new(&record.package, &record.version)
+        .with("campaign", &record.id)
+        .with(
+            "date",
+            &format_date(record.timestamp, templates.date_format()),
+        )
+        .with("summary", &outcome_counts(record));
+    vars.render(templates.notification.as_deref().unwrap_or(DEFAULT_MESSAGE))
+}
+
+/// e.g. "12 PRs opened, 2 skipped, 1 failed"
+fn outcome_counts(record: &HistoryRecord) -> String {
+    let mut parts = vec![format!(
+        "{} PR{} opened",
+        record.pr_count(),
+        if record.pr_count() == 1 { "" } else { "s" }
+    )];
+    for (status, label) in [
+        (RepoStatus::Completed, "updated without PR"),
+        (RepoStatus::Skipped, "skipped"),
+        (RepoStatus::NoChanges, "without changes"),
+        (RepoStatus::Blocked, "blocked by policy"),
+        (RepoStatus::Failed, "failed"),
+        (RepoStatus::Interrupted, "interrupted"),
+        (RepoStatus::Pending, "pending"),
+        (RepoStatus::Deferred, "deferred to later batches"),
+    ] {
+        let mut count = record.count(status);
+        if status == RepoStatus::Completed {
+            count = count.saturating_sub(record.pr_count());
+        }
+        if count > 0 {
+            parts.push(format!("{} {}", count, label));
+        }
+    }
+
+    parts.join(", ")
+}
+
+/// Slack message with the summary and one line per repository
+pub fn slack_payload(record: &HistoryRecord, templates: &TemplateConfig) -> Value {
+    let lines: Vec<String> = record
+        .repos
+        .iter()
+        .map(|repo| {
+            let name = repo_name(&repo.path);
+            match (&repo.pr_url, repo.status) {
+                (Some(url), _) => format!("• <{}|{}>", url, name),
+                (None, RepoStatus::Failed) => format!(
+                    "• {}: failed{}",
+                    name,
+                    repo.error
+                        .as_deref()
+                        .map(|e| format!(" ({})", e))
+                        .unwrap_or_default()
+                ),
+                (None, status) => format!("• {}: {}", name, status.label()),
+            }
+        })
+        .collect();
+
+    let summary = message(record, templates);
+    let mut blocks = vec![json!({
+        "type": "section",
+        "text": {"type": "mrkdwn", "text": format!("*{}*", summary)},
+    })];
+    if !lines.is_empty() {
+        blocks.push(json!({
+            "type": "section",
+            "text": {"type": "mrkdwn", "text": lines.join("\n")},
+        }));
+    }
+
+    json!({"text": summary, "blocks": blocks})
+}
+
+/// Deliver the report to every configured target. Failures are printed as
+/// warnings so they never fail the run.
+pub fn send(config: &NotificationConfig, templates: &TemplateConfig, record: &HistoryRecord) {
+    for (key, value) in config.targets() {
+        let Some(var) = env_reference(value) else {
+            continue;
+        };
+        let Some(url) = std::env::var(var).ok().filter(|u| !u.is_empty()) else {
+            eprintln!(
+                "Warning: {} is not set; skipping notification to notifications.{}",
+                var, key
+            );
+            continue;
+        };
+
+        let payload = match key {
+            "slack_webhook_url" => slack_payload(record, templates),
+            _ => match serde_json::to_value(record) {
+                Ok(value) => value,
+                Err(e) => {
+                    eprintln!("Warning: Failed to serialize run report: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        match post(&url, &payload) {
+            Ok(()) => println!("Sent notification to notifications.{}", key),
+            Err(e) => eprintln!(
+                "Warning: Failed to send notification to notifications.{}: {:#}",
+                key, e
+            ),
+        }
+    }
+}
+
+fn post(url: &str, payload: &Value) -> Result<()> {
+    let setup = HttpSetup::detect();
+    let response = setup
+        .client(TIMEOUT)?
+        .post(url)
+        .json(payload)
+        .send()
+        // The URL holds the secret, so keep it out of the error
+        .map_err(|e| {
+            anyhow::Error::new(e.without_url())
+                .context(format!("request failed via {}", setup.describe(url)))
+        })?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("server responded with {}", status);
+    }
+    Ok(())
+}
+
+/// Read a run report: a history record, or a campaign state file from the campaigns directory
+pub fn load_report(path: &std::path::Path) -> Result<HistoryRecord> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read report: {}", path.display()))?;
+    if let Ok(record) = serde_json::from_str::<HistoryRecord>(&content) {
+        return Ok(record);
+    }
+    let campaign: crate::campaign::Campaign =
+        serde_json::from_str(&content).with_context(|| {
+            format!(
+                "Not a run report or campaign state file: {}",
+                path.display()
+            )
+        })?;
+    Ok(HistoryRecord::from_campaign("notify", &campaign))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::RepoState;
+
+    fn repo(path: &str, status: RepoStatus, pr_url: Option<&str>) -> RepoState {
+        RepoState {
+            path: path.to_string(),
+            status,
+            error: None,
+            output: None,
+            pr_url: pr_url.map(str::to_string),
+            old_version: None,
+            original_branch: None,
+            fast_forwarded: None,
+            diffstat: None,
+            duration_ms: None,
+            steps: Vec::new(),
+            batch: None,
+            warnings: Vec::new(),
+            milestone: None,
+            project: None,
+            license: None,
+            target_version: None,
+            resolve_preview: None,
+            package_managers: Vec::new(),
+            ad_hoc: false,
+            scanned_version: None,
+        }
+    }
+
+    #[test]
+    fn summary_counts_prs_and_slack_links_them() {
+        let record = HistoryRecord {
+            id: "update-lodash-1".to_string(),
+            command: "update".to_string(),
+            timestamp: 0,
+            package: "lodash".to_string(),
+            version: "4.17.21".to_string(),
+            repos: vec![
+                repo(
+                    "~/a",
+                    RepoStatus::Completed,
+                    Some("https://github.com/o/a/pull/1"),
+                ),
+                repo("~/b", RepoStatus::Skipped, None),
+                repo("~/c", RepoStatus::Failed, None),
+            ],
+            duration_ms: None,
+            provenance: None,
+            profile: None,
+            on_error: None,
+            stopped: None,
+        };
+
+        assert_eq!(
+            summary(&record),
+            "mru updated lodash to 4.17.21 — 1 PR opened, 1 skipped, 1 failed"
+        );
+        let text =
+            slack_payload(&record, &TemplateConfig::default())["blocks"][1]["text"]["text"].clone();
+        assert_eq!(
+            text,
+            "• <https://github.com/o/a/pull/1|a>\n• b: skipped\n• c: failed"
+        );
+        let templates = TemplateConfig {
+            notification: Some("[{date}] {package}@{version}: {summary}".to_string()),
+            date_format: Some("%d/%m".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            slack_payload(&record, &templates)["text"],
+            "[01/01] lodash@4.17.21: 1 PR opened, 1 skipped, 1 failed"
+        );
+
+        assert_eq!(env_reference("${SLACK_URL}"), Some("SLACK_URL"));
+        assert_eq!(env_reference("$HOOK"), Some("HOOK"));
+        assert!(NotificationConfig {
+            webhook_url: Some("https://hooks.example.com/secret".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+
+use crate::config::Repository;
+use crate::context::repo_name;
+
+/// Short name of a repository for messages: its alias, or else its directory name
+pub fn display_name(repo: &Repository) -> String {
+    repo.alias.clone().unwrap_or_else(|| repo_name(&repo.path))
+}
+
+/// Group repositories into waves that can be updated in turn: every repository comes
+/// after the ones it `depends_on`, and within a wave higher `priority` goes first
+/// (ties keep the config order).
+pub fn waves(repos: &[Repository]) -> Result<Vec<Vec<&Repository>>> {
+    let deps = dependencies(repos)?;
+
+    let mut wave_of: Vec<Option<usize>> = vec![None; repos.len()];
+    let mut waves: Vec<Vec<usize>> = Vec::new();
+    while wave_of.iter().any(Option::is_none) {
+        let wave: Vec<usize> = (0..repos.len())
+            .filter(|&i| wave_of[i].is_none())
+            .filter(|&i| deps[i].iter().all(|&d| wave_of[d].is_some()))
+            .collect();
+        if wave.is_empty() {
+            let cycle = find_cycle(&deps, &wave_of);
+            let names: Vec<String> = cycle.iter().map(|&i| display_name(&repos[i])).collect();
+            anyhow::bail!("Repositories depend on each other: {}", names.join(" -> "));
+        }
+        for &i in &wave {
+            wave_of[i] = Some(waves.len());
+        }
+        waves.push(wave);
+    }
+
+    Ok(waves
+        .into_iter()
+        .map(|mut wave| {
+            // Stable, so equal priorities stay in config order
+            wave.sort_by_key(|&i| std::cmp::Reverse(repos[i].priority));
+            wave.into_iter().map(|i| &repos[i]).collect()
+        })
+        .collect())
+}
+
+/// Indices of the repositories each one depends on
+fn dependencies(repos: &[Repository]) -> Result<Vec<Vec<usize>>> {
+    repos
+        .iter()
+        .map(|repo| {
+            repo.depends_on
+                .iter()
+                .map(|name| {
+                    repos.iter().position(|r| r.is_named(name)).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "{} depends on '{}', which is not a configured repository",
+                            repo.path,
+                            name
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A cycle among the repositories not placed in a wave, starting and ending at the same one
+fn find_cycle(deps: &[Vec<usize>], placed: &[Option<usize>]) -> Vec<usize> {
+    let start = (0..deps.len()).find(|&i| placed[i].is_none()).unwrap_or(0);
+    let mut path = vec![start];
+    loop {
+        let current = *path.last().unwrap();
+        let Some(&next) = deps[current].iter().find(|&&d| placed[d].is_none()) else {
+            return path;
+        };
+        if let Some(at) = path.iter().position(|&i| i == next) {
+            let mut cycle = path.split_off(at);
+            cycle.push(next);
+            return cycle;
+        }
+        path.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(path: &str, priority: i32, depends_on: &[&str]) -> Repository {
+        Repository {
+            path: path.to_string(),
+            priority,
+            depends_on: depends_on.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(waves: &[Vec<&Repository>]) -> Vec<Vec<String>> {
+        waves
+            .iter()
+            .map(|wave| wave.iter().map(|r| display_name(r)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn waves_put_dependencies_first_and_sort_by_priority() {
+        let repos = vec![
+            repo("/w/web", 0, &["ui"]),
+            repo("/w/admin", 5, &["ui", "tokens"]),
+            repo("/w/tokens", 0, &[]),
+            repo("/w/ui", 0, &["tokens"]),
+            repo("/w/docs", 1, &[]),
+        ];
+        assert_eq!(
+            names(&waves(&repos).unwrap()),
+            vec![vec!["docs", "tokens"], vec!["ui"], vec!["admin", "web"]]
+        );
+    }
+
+    #[test]
+    fn waves_reject_cycles_and_unknown_dependencies() {
+        let repos = vec![
+            repo("/w/docs", 0, &[]),
+            repo("/w/a", 0, &["c"]),
+            repo("/w/b", 0, &["a"]),
+            repo("/w/c", 0, &["b"]),
+        ];
+        assert_eq!(
+            waves(&repos).unwrap_err().to_string(),
+            "Repositories depend on each other: a -> c -> b -> a"
+        );
+
+        let repos = vec![repo("/w/a", 0, &["missing"])];
+        assert_eq!(
+            waves(&repos).unwrap_err().to_string(),
+            "/w/a depends on 'missing', which is not a configured repository"
+        );
+    }
+}
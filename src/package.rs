@@ -1,18 +1,84 @@
-use anyhow::{Context, Result};
-use serde_json::{json, Value, Map};
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+use crate::context::Context;
+use crate::manifest::{replace_entry_value, replace_top_level_value, write_atomic};
 use crate::repo::expand_path;
+use crate::runner::CommandSpec;
+use crate::version;
 
-/// Update specific package version in package.json
-pub fn update_package(
-    repo_path: &str,
-    package_name: &str,
-    version: &str,
-    dry_run: bool,
-) -> Result<bool> {
+/// Dependency sections that mru reads and updates
+pub const DEPENDENCY_SECTIONS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+];
+
+/// A package declared in one dependency section of package.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyEntry {
+    pub section: &'static str,
+    pub name: String,
+    pub specifier: String,
+    /// License of the version in use, when looked up (`list-packages --licenses`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+impl DependencyEntry {
+    /// Whether this entry installs `package`: under its own name, or as the target
+    /// of an npm alias such as `"lodash-es": "npm:lodash@^4.17.21"`
+    pub fn installs(&self, package: &str) -> bool {
+        self.name == package
+            || version::npm_alias(&self.specifier).is_some_and(|(target, _)| target == package)
+    }
+}
+
+/// Name and specifier under which `versions` (as from `dependency_versions`) declares
+/// `package`: its own entry, or else an npm alias of it
+pub fn declared<'a>(
+    versions: &'a BTreeMap<String, String>,
+    package: &str,
+) -> Option<(&'a str, &'a str)> {
+    versions
+        .get_key_value(package)
+        .or_else(|| {
+            versions.iter().find(|(_, spec)| {
+                version::npm_alias(spec).is_some_and(|(target, _)| target == package)
+            })
+        })
+        .map(|(name, spec)| (name.as_str(), spec.as_str()))
+}
+
+/// All string-valued entries of every dependency section, in section order
+pub fn dependency_entries(package_json: &Value) -> Vec<DependencyEntry> {
+    let mut entries = Vec::new();
+    for section in DEPENDENCY_SECTIONS {
+        let Some(deps) = package_json.get(section).and_then(|d| d.as_object()) else {
+            continue;
+        };
+        for (name, specifier) in deps {
+            if let Some(specifier) = specifier.as_str() {
+                entries.push(DependencyEntry {
+                    section,
+                    name: name.clone(),
+                    specifier: specifier.to_string(),
+                    license: None,
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Path and parsed contents of a repository's package.json
+fn load_manifest(ctx: &Context, repo_path: &str) -> Result<(PathBuf, Arc<Value>)> {
     let path = expand_path(repo_path)?;
     let package_json_path = path.join("package.json");
 
@@ -20,245 +86,1388 @@ pub fn update_package(
         anyhow::bail!("package.json not found in repository: {}", repo_path);
     }
 
-    let content = fs::read_to_string(&package_json_path).context("Failed to read package.json")?;
-    let mut package_json: Value = serde_json::from_str(&content).context("Failed to parse package.json")?;
-    let mut updated = false;
+    let package_json = ctx.manifests.get(&package_json_path)?;
+    Ok((package_json_path, package_json))
+}
 
-    // Update dependencies
-    if let Some(deps) = package_json.get_mut("dependencies") {
-        if let Some(pkg) = deps.get_mut(package_name) {
-            if pkg.as_str().unwrap_or("") != version {
-                if !dry_run {
-                    *pkg = json!(version);
-                }
-                updated = true;
-                println!(
-                    "Updated {} in dependencies from {} to {}",
-                    package_name,
-                    pkg.as_str().unwrap_or("unknown"),
-                    version
-                );
-            }
+/// package.json of each workspace member, relative to the repository, from the
+/// `workspaces` field of package.json (a list, or `{ "packages": [...] }`) or else the
+/// `packages` list of pnpm-workspace.yaml. A pattern segment may be `*` or `**`, and
+/// patterns starting with `!` exclude members.
+pub fn workspace_manifests(ctx: &Context, repo_path: &str) -> Result<Vec<String>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    let root = expand_path(repo_path)?;
+    let workspaces = &package_json["workspaces"];
+    let mut patterns: Vec<String> = workspaces
+        .as_array()
+        .or_else(|| workspaces["packages"].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|pattern| pattern.as_str().map(str::to_string))
+        .collect();
+    if patterns.is_empty() {
+        if let Ok(yaml) = fs::read_to_string(root.join("pnpm-workspace.yaml")) {
+            patterns = pnpm_workspace_packages(&yaml);
         }
     }
 
-    // Update devDependencies
-    if let Some(dev_deps) = package_json.get_mut("devDependencies") {
-        if let Some(pkg) = dev_deps.get_mut(package_name) {
-            if pkg.as_str().unwrap_or("") != version {
-                if !dry_run {
-                    *pkg = json!(version);
-                }
-                updated = true;
-                println!(
-                    "Updated {} in devDependencies from {} to {}",
-                    package_name,
-                    pkg.as_str().unwrap_or("unknown"),
-                    version
-                );
-            }
+    let (excluded, included): (Vec<&String>, Vec<&String>) =
+        patterns.iter().partition(|p| p.starts_with('!'));
+    let clean = |pattern: &str| -> String {
+        pattern
+            .trim_start_matches('!')
+            .trim_start_matches("./")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let members: std::collections::BTreeSet<String> = included
+        .iter()
+        .flat_map(|pattern| expand_workspace_pattern(&root, &clean(pattern)))
+        .filter(|dir| !dir.is_empty())
+        .filter(|dir| !excluded.iter().any(|p| matches_glob(&clean(p), dir)))
+        .filter(|dir| root.join(dir).join("package.json").is_file())
+        .collect();
+    Ok(members
+        .into_iter()
+        .map(|dir| format!("{}/package.json", dir))
+        .collect())
+}
+
+/// Entries of the `packages` list in pnpm-workspace.yaml
+fn pnpm_workspace_packages(yaml: &str) -> Vec<String> {
+    yaml.lines()
+        .skip_while(|line| line.trim_end() != "packages:")
+        .skip(1)
+        .take_while(|line| line.trim().is_empty() || line.trim_start().starts_with(['-', '#']))
+        .filter_map(|line| line.trim().strip_prefix('-'))
+        .map(|pattern| pattern.trim().trim_matches(['"', '\'']).to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect()
+}
+
+/// Directories, relative to `root`, that a workspace pattern such as `packages/*`
+/// matches. node_modules and hidden directories are never matched by a wildcard.
+fn expand_workspace_pattern(root: &Path, pattern: &str) -> Vec<String> {
+    let join = |dir: &str, name: &str| {
+        if dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", dir, name)
         }
-    }
+    };
+    let subdirectories = |dir: &str| -> Vec<String> {
+        let Ok(entries) = fs::read_dir(root.join(dir)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name != "node_modules" && !name.starts_with('.'))
+            .collect();
+        names.sort();
+        names.into_iter().map(|name| join(dir, &name)).collect()
+    };
 
-    // Update peerDependencies
-    if let Some(peer_deps) = package_json.get_mut("peerDependencies") {
-        if let Some(pkg) = peer_deps.get_mut(package_name) {
-            if pkg.as_str().unwrap_or("") != version {
-                if !dry_run {
-                    *pkg = json!(version);
+    let mut dirs = vec![String::new()];
+    for segment in pattern.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        dirs = dirs
+            .iter()
+            .flat_map(|dir| match segment {
+                "**" => {
+                    let mut all = vec![dir.clone()];
+                    let mut i = 0;
+                    while i < all.len() {
+                        all.extend(subdirectories(&all[i]));
+                        i += 1;
+                    }
+                    all
                 }
-                updated = true;
-                println!(
-                    "Updated {} in peerDependencies from {} to {}",
-                    package_name,
-                    pkg.as_str().unwrap_or("unknown"),
-                    version
-                );
-            }
+                _ if segment.contains(['*', '?']) => subdirectories(dir)
+                    .into_iter()
+                    .filter(|sub| matches_glob(segment, sub.rsplit('/').next().unwrap_or(sub)))
+                    .collect(),
+                _ => {
+                    let sub = join(dir, segment);
+                    if root.join(&sub).is_dir() {
+                        vec![sub]
+                    } else {
+                        Vec::new()
+                    }
+                }
+            })
+            .collect();
+    }
+    dirs
+}
+
+/// Version change of a package in one dependency section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateOutcome {
+    pub section: String,
+    pub old_version: String,
+    pub new_version: String,
+}
+
+/// Update specific package version in package.json.
+/// Returns the change made in each section (empty if nothing needed updating).
+pub fn update_package(
+    ctx: &Context,
+    repo_path: &str,
+    package_name: &str,
+    version: &str,
+    dry_run: bool,
+) -> Result<Vec<UpdateOutcome>> {
+    let (package_json_path, package_json) = load_manifest(ctx, repo_path)?;
+    let mut content = ctx.manifests.get_text(&package_json_path)?.as_ref().clone();
+    let mut outcomes = Vec::new();
+
+    for entry in dependency_entries(&package_json) {
+        // An npm alias keeps its `npm:<name>@` and only gets the new range
+        let new_version = crate::version::replace_range(&entry.specifier, version);
+        if !entry.installs(package_name) || entry.specifier == new_version {
+            continue;
+        }
+        let section = entry.section;
+        let old_version = entry.specifier;
+
+        if dry_run {
+            println!(
+                "Would update {} in {} of {} from {} to {}",
+                entry.name,
+                section,
+                package_json_path.display(),
+                old_version,
+                new_version
+            );
+        } else {
+            content = replace_entry_value(&content, section, &entry.name, &new_version)
+                .with_context(|| format!("Failed to locate {} entry in package.json", section))?;
+            println!(
+                "Updated {} in {} of {} from {} to {}",
+                entry.name,
+                section,
+                package_json_path.display(),
+                old_version,
+                new_version
+            );
         }
+
+        outcomes.push(UpdateOutcome {
+            section: section.to_string(),
+            old_version,
+            new_version,
+        });
     }
 
-    if updated && !dry_run {
-        let formatted = serde_json::to_string_pretty(&package_json)?;
-        fs::write(package_json_path, formatted)?;
+    if !outcomes.is_empty() && !dry_run {
+        // Edit the file in place so indentation, key order and line endings are preserved
+        ctx.manifests.invalidate(&package_json_path);
+        write_atomic(&package_json_path, content)?;
         println!("Saved changes to package.json in {}", repo_path);
-    } else if !updated {
+    } else if outcomes.is_empty() {
         println!(
             "Package '{}' is already at version '{}' or not found",
             package_name, version
         );
     }
 
-    Ok(updated)
+    Ok(outcomes)
 }
 
-/// Detect package manager (pnpm, yarn, npm)
-pub fn detect_package_manager(repo_path: &str) -> Result<String> {
-    let path = expand_path(repo_path)?;
+/// A specifier rewritten by `pin-all` or `widen`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpecifierChange {
+    /// package.json to change, relative to the repository; `None` for the root one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub section: &'static str,
+    pub name: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Whether a specifier refers to a registry version rather than e.g. `workspace:*`,
+/// `file:../lib`, `npm:other@1` or a git URL or `owner/repo` shorthand
+pub fn is_registry_specifier(specifier: &str) -> bool {
+    !specifier.contains([':', '/']) && !specifier.starts_with('.')
+}
+
+/// Specifiers in `sections` of packages matching `filter` that `rewrite` changes,
+/// skipping specifiers that don't come from the registry
+pub fn plan_specifier_changes(
+    ctx: &Context,
+    repo_path: &str,
+    sections: &[String],
+    filter: Option<&str>,
+    rewrite: impl Fn(&DependencyEntry) -> Option<String>,
+) -> Result<Vec<SpecifierChange>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    Ok(dependency_entries(&package_json)
+        .into_iter()
+        .filter(|entry| sections.iter().any(|s| s == entry.section))
+        .filter(|entry| filter.is_none_or(|glob| matches_glob(glob, &entry.name)))
+        .filter(|entry| is_registry_specifier(&entry.specifier))
+        .filter_map(|entry| {
+            let new = rewrite(&entry)?;
+            Some(SpecifierChange {
+                file: None,
+                section: entry.section,
+                name: entry.name,
+                old: entry.specifier,
+                new,
+            })
+        })
+        .collect())
+}
+
+/// Versions to pin in the `volta` block of package.json; `None` leaves a key alone
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoltaPins {
+    pub node: Option<String>,
+    pub npm: Option<String>,
+    pub yarn: Option<String>,
+}
+
+/// Tools pinned in the `volta` block of a repository's package.json
+pub fn volta_tools(ctx: &Context, repo_path: &str) -> Result<Vec<String>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    Ok(package_json["volta"]
+        .as_object()
+        .map(|volta| volta.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// Set `engines.node` to `range` and the tools already pinned in the `volta` block to
+/// `pins`. Entries that don't exist are not added, so repositories without an
+/// `engines.node` or a `volta` block are left alone.
+pub fn plan_engine_changes(
+    ctx: &Context,
+    repo_path: &str,
+    range: &str,
+    pins: &VoltaPins,
+) -> Result<Vec<SpecifierChange>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    let wanted = [
+        ("engines", "node", Some(range)),
+        ("volta", "node", pins.node.as_deref()),
+        ("volta", "npm", pins.npm.as_deref()),
+        ("volta", "yarn", pins.yarn.as_deref()),
+    ];
+
+    Ok(wanted
+        .into_iter()
+        .filter_map(|(section, name, new)| {
+            let old = package_json[section][name].as_str()?;
+            let new = new.filter(|new| *new != old)?;
+            Some(SpecifierChange {
+                file: None,
+                section,
+                name: name.to_string(),
+                old: old.to_string(),
+                new: new.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Write specifier changes to a repository's package.json, or to the workspace
+/// member manifests the changes name
+pub fn apply_specifier_changes(
+    ctx: &Context,
+    repo_path: &str,
+    changes: &[SpecifierChange],
+) -> Result<()> {
+    let root = expand_path(repo_path)?;
+    let mut files: Vec<&str> = changes
+        .iter()
+        .map(|c| c.file.as_deref().unwrap_or("package.json"))
+        .collect();
+    files.sort_unstable();
+    files.dedup();
 
-    // Check for pnpm-lock.yaml
-    if path.join("pnpm-lock.yaml").exists() {
-        return Ok("pnpm".to_string());
+    for file in files {
+        let path = root.join(file);
+        let mut content = ctx.manifests.get_text(&path)?.as_ref().clone();
+        let in_file = changes
+            .iter()
+            .filter(|c| c.file.as_deref().unwrap_or("package.json") == file);
+        for change in in_file {
+            content = replace_entry_value(&content, change.section, &change.name, &change.new)
+                .with_context(|| {
+                    format!(
+                        "Failed to locate {} in {} of {}",
+                        change.name, change.section, file
+                    )
+                })?;
+        }
+
+        ctx.manifests.invalidate(&path);
+        write_atomic(&path, content)?;
     }
+    Ok(())
+}
+
+/// The repository's own version, from the top-level `version` field of its package.json
+pub fn own_version(ctx: &Context, repo_path: &str) -> Result<Option<String>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    Ok(package_json["version"].as_str().map(str::to_string))
+}
+
+/// The repository's own package name, from the top-level `name` field of its package.json
+pub fn own_name(ctx: &Context, repo_path: &str) -> Result<Option<String>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    Ok(package_json["name"].as_str().map(str::to_string))
+}
 
-    // Check for yarn.lock
-    if path.join("yarn.lock").exists() {
-        return Ok("yarn".to_string());
+/// Whether `name@version` is published, asking the registry the repository's npm
+/// configuration (e.g. a scoped registry in `.npmrc`) points to
+pub fn is_published(ctx: &Context, repo_path: &str, name: &str, version: &str) -> Result<bool> {
+    Ok(npm_view_version(ctx, repo_path, &format!("{}@{}", name, version))?.is_some())
+}
+
+/// Version the registry's `latest` tag points to, or `None` if the package isn't published
+pub fn latest_version(ctx: &Context, repo_path: &str, name: &str) -> Result<Option<String>> {
+    npm_view_version(ctx, repo_path, name)
+}
+
+/// Answers of `npm view`, by repository, spec and field, so each is asked for at most
+/// once per process
+#[derive(Default)]
+pub struct RegistryCache {
+    views: Mutex<HashMap<(String, String, String), Option<Value>>>,
+}
+
+impl RegistryCache {
+    pub fn new() -> Self {
+        Self::default()
     }
+}
 
-    // Check for package-lock.json
-    if path.join("package-lock.json").exists() {
-        return Ok("npm".to_string());
+/// `npm view <spec> <field> --json` in the repository, asking the registry its npm
+/// configuration points to; `None` if nothing matches. Answers are cached.
+pub fn npm_view(ctx: &Context, repo_path: &str, spec: &str, field: &str) -> Result<Option<Value>> {
+    let key = (repo_path.to_string(), spec.to_string(), field.to_string());
+    if let Some(value) = ctx.registry.views.lock().unwrap().get(&key) {
+        return Ok(value.clone());
     }
 
-    // No lock file found
-    anyhow::bail!("No package manager lock file found")
+    let output = ctx
+        .output_with_retry(
+            &CommandSpec::new("npm")
+                .timeout(ctx.timeouts.registry)
+                .current_dir(expand_path(repo_path)?)
+                .args(["view", spec, field, "--json"]),
+        )
+        .with_context(|| format!("Failed to look up {} in the registry", spec))?;
+
+    let value = if output.success() {
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "" => None,
+            text => Some(serde_json::from_str(text).with_context(|| {
+                format!("Failed to parse the registry's {} of {}", field, spec)
+            })?),
+        }
+    } else if String::from_utf8_lossy(&output.stderr).contains("E404") {
+        None
+    } else {
+        anyhow::bail!(
+            "Failed to look up {} in the registry: {}",
+            spec,
+            output.tail()
+        )
+    };
+
+    ctx.registry
+        .views
+        .lock()
+        .unwrap()
+        .insert(key, value.clone());
+    Ok(value)
+}
+
+/// Newest published version of `name` within `range`, leaving out prereleases;
+/// `None` if no published version is in it
+pub fn newest_in_range(
+    ctx: &Context,
+    repo_path: &str,
+    name: &str,
+    range: &str,
+) -> Result<Option<String>> {
+    // A package with a single version gives it as a string rather than a list
+    let versions = match npm_view(ctx, repo_path, name, "versions")? {
+        Some(Value::Array(versions)) => versions,
+        Some(version) => vec![version],
+        None => Vec::new(),
+    };
+    Ok(versions
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|v| version::is_exact(v) && !v.contains('-') && version::satisfies(range, v))
+        .max_by(|a, b| version::compare_specifiers(a, b))
+        .map(str::to_string))
 }
 
-/// Run package install with specified package manager
-pub fn run_install_with_manager(repo_path: &str, pkg_manager: &str, dry_run: bool) -> Result<()> {
+/// `npm view <spec> version` in the repository; `None` if nothing matches
+fn npm_view_version(ctx: &Context, repo_path: &str, spec: &str) -> Result<Option<String>> {
     let path = expand_path(repo_path)?;
 
-    if dry_run {
-        println!("Would run {} install in {}", pkg_manager, repo_path);
-        return Ok(());
+    let output = ctx
+        .output_with_retry(
+            &CommandSpec::new("npm")
+                .timeout(ctx.timeouts.registry)
+                .current_dir(&path)
+                .args(["view", spec, "version"]),
+        )
+        .with_context(|| format!("Failed to look up {} in the registry", spec))?;
+
+    // A missing version prints nothing; a package the registry doesn't know is E404
+    if output.success() {
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Ok((!version.is_empty()).then_some(version));
+    }
+    if String::from_utf8_lossy(&output.stderr).contains("E404") {
+        return Ok(None);
     }
+    anyhow::bail!(
+        "Failed to look up {} in the registry: {}",
+        spec,
+        output.tail()
+    )
+}
 
-    println!("Running {} install in {}", pkg_manager, repo_path);
+/// Set the top-level `version` field of a repository's package.json
+pub fn set_own_version(ctx: &Context, repo_path: &str, version: &str) -> Result<()> {
+    let (package_json_path, _) = load_manifest(ctx, repo_path)?;
+    let content = ctx.manifests.get_text(&package_json_path)?;
+    let content = replace_top_level_value(&content, "version", version)
+        .context("Failed to locate the version field in package.json")?;
 
-    let status = Command::new(pkg_manager)
-        .current_dir(&path)
-        .arg("install")
-        .status()
-        .context(format!("Failed to run {} install", pkg_manager))?;
+    ctx.manifests.invalidate(&package_json_path);
+    write_atomic(&package_json_path, content)?;
+    Ok(())
+}
 
-    if !status.success() {
-        anyhow::bail!("{} install failed", pkg_manager);
+/// Set the repository's version with the package manager's own command, which also
+/// updates its lockfile, without letting it commit or tag
+pub fn run_version_command(
+    ctx: &Context,
+    repo_path: &str,
+    pkg_manager: &str,
+    version: &str,
+) -> Result<()> {
+    let path = expand_path(repo_path)?;
+
+    let args: &[&str] = match pkg_manager {
+        "npm" | "pnpm" => &["version", version, "--no-git-tag-version"],
+        "yarn" => &["version", "--new-version", version, "--no-git-tag-version"],
+        _ => anyhow::bail!("{} has no version command", pkg_manager),
+    };
+
+    let output = ctx
+        .runner
+        .output(
+            &CommandSpec::new(pkg_manager)
+                .timeout(ctx.timeouts.install)
+                .current_dir(&path)
+                .args(args),
+        )
+        .with_context(|| format!("Failed to run {} version", pkg_manager))?;
+
+    if !output.success() {
+        anyhow::bail!("{} version failed: {}", pkg_manager, output.tail());
     }
 
+    ctx.manifests.invalidate(&path.join("package.json"));
     Ok(())
 }
 
-/// Check package version
-pub fn get_package_version(repo_path: &str, package_name: &str) -> Result<Option<String>> {
+/// Lockfiles and their package manager, in the order they win when several exist
+const LOCKFILES: [(&str, &str); 3] = [
+    ("pnpm-lock.yaml", "pnpm"),
+    ("yarn.lock", "yarn"),
+    ("package-lock.json", "npm"),
+];
+
+/// Package managers mru knows how to drive
+pub const PACKAGE_MANAGERS: [&str; 3] = ["npm", "yarn", "pnpm"];
+
+/// Where the package manager of a repository was taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManagerSource {
+    /// The repository's `package_manager` setting
+    Config,
+    /// The `packageManager` field of package.json, e.g. `yarn@4.2.2`
+    PackageManagerField,
+    Lockfile(&'static str),
+    /// `default_package_manager`
+    Default,
+}
+
+impl std::fmt::Display for ManagerSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManagerSource::Config => write!(f, "from repository config"),
+            ManagerSource::PackageManagerField => write!(f, "from packageManager field"),
+            ManagerSource::Lockfile(file) => write!(f, "from {}", file),
+            ManagerSource::Default => write!(f, "from default_package_manager"),
+        }
+    }
+}
+
+/// Package manager of a repository, where it came from, and a warning when the
+/// repository has more than one lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerDetection {
+    pub manager: String,
+    pub source: ManagerSource,
+    pub warning: Option<String>,
+}
+
+/// Package manager for a repository, taken from, in order: the repository's
+/// `package_manager` setting (`pinned`), the `packageManager` field of package.json,
+/// its lockfile, and the configured default
+pub fn detect_manager(
+    ctx: &Context,
+    repo_path: &str,
+    pinned: Option<&str>,
+    default: Option<&str>,
+) -> Result<ManagerDetection> {
     let path = expand_path(repo_path)?;
-    let package_json_path = path.join("package.json");
+    let field = package_manager_field(ctx, &path)?;
+    let lockfiles: Vec<&'static str> = LOCKFILES
+        .iter()
+        .map(|(file, _)| *file)
+        .filter(|file| path.join(file).exists())
+        .collect();
 
-    if !package_json_path.exists() {
-        anyhow::bail!("package.json not found in repository: {}", repo_path);
+    choose_manager(pinned, field.as_deref(), &lockfiles, default).with_context(|| {
+        format!(
+            "No lockfile found in {} and no default package manager is configured. \
+             Run 'mru set-package-manager <npm|yarn|pnpm>' to set one",
+            repo_path
+        )
+    })
+}
+
+/// The `packageManager` field of the package.json in `dir`, if there is one.
+/// A package.json that can't be parsed is an error rather than no field.
+fn package_manager_field(ctx: &Context, dir: &Path) -> Result<Option<String>> {
+    let package_json = dir.join("package.json");
+    if !package_json.is_file() {
+        return Ok(None);
     }
+    let manifest = ctx.manifests.get(&package_json)?;
+    Ok(manifest["packageManager"].as_str().map(str::to_string))
+}
 
-    let content = fs::read_to_string(&package_json_path).context("Failed to read package.json")?;
+/// The first of `pinned`, the `packageManager` field, the highest-priority lockfile
+/// and `default` that names a package manager
+fn choose_manager(
+    pinned: Option<&str>,
+    field: Option<&str>,
+    lockfiles: &[&'static str],
+    default: Option<&str>,
+) -> Option<ManagerDetection> {
+    // `yarn@4.2.2+sha512.abc` names yarn
+    let field = field
+        .and_then(|field| field.split('@').next())
+        .filter(|name| PACKAGE_MANAGERS.contains(name));
+    let lockfile = lockfiles.first().and_then(|first| {
+        LOCKFILES
+            .iter()
+            .find(|(file, _)| file == first)
+            .map(|(file, manager)| (*manager, ManagerSource::Lockfile(file)))
+    });
 
-    let package_json: Value =
-        serde_json::from_str(&content).context("Failed to parse package.json")?;
+    let (manager, source) = pinned
+        .map(|manager| (manager, ManagerSource::Config))
+        .or(field.map(|manager| (manager, ManagerSource::PackageManagerField)))
+        .or(lockfile)
+        .or(default.map(|manager| (manager, ManagerSource::Default)))?;
 
-    // dependencies 확인
-    if let Some(deps) = package_json.get("dependencies") {
-        if let Some(version) = deps.get(package_name) {
-            if let Some(version_str) = version.as_str() {
-                return Ok(Some(version_str.to_string()));
-            }
+    let warning = (lockfiles.len() > 1).then(|| {
+        format!(
+            "several lockfiles found ({}); using {} ({})",
+            lockfiles.join(", "),
+            manager,
+            source
+        )
+    });
+    Some(ManagerDetection {
+        manager: manager.to_string(),
+        source,
+        warning,
+    })
+}
+
+/// A repository's package manager as installs run it, with the version it reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerCheck {
+    pub manager: String,
+    /// Run as `corepack <manager>`, so the version the `packageManager` field pins is used
+    pub corepack: bool,
+    pub version: Option<String>,
+}
+
+impl ManagerCheck {
+    /// e.g. `pnpm 9.1.0 (corepack)`
+    pub fn label(&self) -> String {
+        let mut label = self.manager.clone();
+        if let Some(version) = &self.version {
+            label = format!("{} {}", label, version);
         }
+        if self.corepack {
+            label.push_str(" (corepack)");
+        }
+        label
     }
 
-    // devDependencies 확인
-    if let Some(dev_deps) = package_json.get("devDependencies") {
-        if let Some(version) = dev_deps.get(package_name) {
-            if let Some(version_str) = version.as_str() {
-                return Ok(Some(version_str.to_string()));
-            }
+    fn command(&self) -> CommandSpec {
+        if self.corepack {
+            CommandSpec::new("corepack").arg(&self.manager)
+        } else {
+            CommandSpec::new(&self.manager)
         }
     }
+}
 
-    // peerDependencies 확인
-    if let Some(peer_deps) = package_json.get("peerDependencies") {
-        if let Some(version) = peer_deps.get(package_name) {
-            if let Some(version_str) = peer_deps.get(package_name).and_then(|v| v.as_str()) {
-                return Ok(Some(version_str.to_string()));
-            }
+/// Run `<manager> --version` in the repository and check it against what the repository
+/// expects: the version its `packageManager` field pins, run through corepack when it is
+/// installed, or else `constraint` (a range from `package_manager_versions`)
+pub fn check_manager(
+    ctx: &Context,
+    repo_path: &str,
+    manager: &str,
+    constraint: Option<&str>,
+    env: &[(String, String)],
+) -> Result<ManagerCheck> {
+    let path = expand_path(repo_path)?;
+    // `pnpm@9.1.0+sha512.abc` pins 9.1.0
+    let pin = package_manager_field(ctx, &path)?.and_then(|field| {
+        let (name, version) = field.split_once('@')?;
+        (name == manager).then(|| version.split('+').next().unwrap_or(version).to_string())
+    });
+    let mut check = ManagerCheck {
+        manager: manager.to_string(),
+        corepack: pin.is_some() && crate::preflight::tool_version(ctx, "corepack").is_some(),
+        version: None,
+    };
+
+    let output = ctx.runner.output(
+        &check
+            .command()
+            .timeout(ctx.timeouts.install)
+            .current_dir(&path)
+            .envs(env)
+            .arg("--version"),
+    )?;
+    if output.success() {
+        check.version = crate::preflight::parse_version(&String::from_utf8_lossy(&output.stdout));
+    } else if check.corepack {
+        anyhow::bail!(
+            "corepack {} --version failed in {}:\n{}",
+            manager,
+            repo_path,
+            output.tail()
+        );
+    }
+
+    let Some(found) = &check.version else {
+        if pin.is_some() || constraint.is_some() {
+            eprintln!(
+                "Warning: {}: couldn't read the {} version, so it isn't checked",
+                repo_path, manager
+            );
         }
+        return Ok(check);
+    };
+    match (&pin, constraint) {
+        (Some(pin), _) if found != pin => anyhow::bail!(
+            "{} pins {}@{} in packageManager, found {}; run corepack enable",
+            repo_path,
+            manager,
+            pin,
+            found
+        ),
+        (None, Some(range)) if !crate::version::satisfies(range, found) => anyhow::bail!(
+            "{} expects {} {}, found {}; run corepack enable, or install a matching {}",
+            repo_path,
+            manager,
+            range,
+            found,
+            manager
+        ),
+        _ => Ok(check),
     }
+}
+
+/// Run package install with specified package manager, with `env` (the repository's
+/// configured environment) set. Returns the last lines it printed.
+pub fn run_install_with_manager(
+    ctx: &Context,
+    repo_path: &str,
+    pkg_manager: &ManagerCheck,
+    env: &[(String, String)],
+    dry_run: bool,
+) -> Result<String> {
+    let path = expand_path(repo_path)?;
+
+    if dry_run {
+        println!("Would run {} install in {}", pkg_manager.label(), repo_path);
+        return Ok(String::new());
+    }
+
+    println!("Running {} install in {}", pkg_manager.label(), repo_path);
 
-    // 패키지를 찾지 못함
-    Ok(None)
+    let status = ctx
+        .stream(
+            &pkg_manager
+                .command()
+                .timeout(ctx.timeouts.install)
+                .current_dir(&path)
+                .envs(env)
+                .arg("install"),
+            repo_path,
+        )
+        .context(format!("Failed to run {} install", pkg_manager.manager))?;
+
+    if !status.success() {
+        anyhow::bail!("{} install failed:\n{}", pkg_manager.manager, status.tail());
+    }
+
+    Ok(status.combined_tail())
 }
 
-/// Get all package list
-pub fn list_all_packages(repo_path: &str) -> Result<Vec<(String, String, String)>> {
+/// Whether the repository uses Yarn 2 or later, which has `yarn dedupe`
+fn is_yarn_berry(ctx: &Context, repo_path: &str) -> Result<bool> {
     let path = expand_path(repo_path)?;
-    let package_json_path = path.join("package.json");
+    if path.join(".yarnrc.yml").exists() {
+        return Ok(true);
+    }
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+    if let Some(spec) = package_json["packageManager"].as_str() {
+        if let Some(version) = spec.strip_prefix("yarn@") {
+            return Ok(!version.starts_with("1."));
+        }
+    }
+    // Berry lockfiles start with a metadata entry
+    let lockfile = fs::read_to_string(path.join("yarn.lock")).unwrap_or_default();
+    Ok(lockfile.contains("__metadata:"))
+}
 
-    if !package_json_path.exists() {
-        anyhow::bail!("package.json not found in repository: {}", repo_path);
+/// Run the package manager's dedupe so an update doesn't leave duplicate versions in the
+/// lockfile. Yarn 1 has no reliable dedupe, so it is skipped with a warning.
+pub fn run_dedupe(
+    ctx: &Context,
+    repo_path: &str,
+    pkg_manager: &str,
+    env: &[(String, String)],
+    dry_run: bool,
+) -> Result<()> {
+    if pkg_manager == "yarn" && !is_yarn_berry(ctx, repo_path)? {
+        eprintln!(
+            "Warning: Yarn 1 has no reliable dedupe command, skipping dedupe in {}",
+            repo_path
+        );
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("Would run {} dedupe in {}", pkg_manager, repo_path);
+        return Ok(());
     }
 
-    let content = fs::read_to_string(&package_json_path).context("Failed to read package.json")?;
+    println!("Running {} dedupe in {}", pkg_manager, repo_path);
+    let path = expand_path(repo_path)?;
+    let status = ctx
+        .stream(
+            &CommandSpec::new(pkg_manager)
+                .timeout(ctx.timeouts.install)
+                .current_dir(&path)
+                .envs(env)
+                .arg("dedupe"),
+            repo_path,
+        )
+        .with_context(|| format!("Failed to run {} dedupe", pkg_manager))?;
+
+    if !status.success() {
+        anyhow::bail!("{} dedupe failed:\n{}", pkg_manager, status.tail());
+    }
+
+    Ok(())
+}
+
+/// Check package version. A package only installed through an npm alias gives the
+/// alias's range, e.g. `^4.17.21` for `"lodash-es": "npm:lodash@^4.17.21"`.
+pub fn get_package_version(
+    ctx: &Context,
+    repo_path: &str,
+    package_name: &str,
+) -> Result<Option<String>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
+
+    Ok(version_in(&dependency_entries(&package_json), package_name))
+}
+
+/// `get_package_version` for package.json as committed at `git_ref`
+pub fn get_package_version_at(
+    ctx: &Context,
+    repo_path: &str,
+    package_name: &str,
+    git_ref: &str,
+) -> Result<Option<String>> {
+    let entries = list_packages_at(ctx, repo_path, git_ref)?;
+    Ok(version_in(&entries, package_name))
+}
+
+fn version_in(entries: &[DependencyEntry], package_name: &str) -> Option<String> {
+    entries
+        .iter()
+        .find(|entry| entry.name == package_name)
+        .or_else(|| entries.iter().find(|entry| entry.installs(package_name)))
+        .map(|entry| version::range_of(&entry.specifier).to_string())
+}
 
-    let package_json: Value =
-        serde_json::from_str(&content).context("Failed to parse package.json")?;
+/// Get all package list
+pub fn list_all_packages(ctx: &Context, repo_path: &str) -> Result<Vec<DependencyEntry>> {
+    let (_, package_json) = load_manifest(ctx, repo_path)?;
 
-    let mut packages = Vec::new();
+    Ok(dependency_entries(&package_json))
+}
+
+/// Packages in the repository's package.json as committed at `git_ref`, regardless of
+/// what is checked out
+pub fn list_packages_at(
+    ctx: &Context,
+    repo_path: &str,
+    git_ref: &str,
+) -> Result<Vec<DependencyEntry>> {
+    let text = crate::git::show_file(ctx, repo_path, git_ref, "package.json")?;
+    let name = PathBuf::from(format!("{}:package.json", git_ref));
+    Ok(dependency_entries(&ctx.manifests.parse(&name, &text)?))
+}
 
-    // dependencies 수집
-    if let Some(deps) = package_json.get("dependencies").and_then(|d| d.as_object()) {
-        for (name, version) in deps {
-            if let Some(version_str) = version.as_str() {
-                packages.push((
-                    name.clone(),
-                    version_str.to_string(),
-                    "dependencies".to_string(),
-                ));
+/// Match a package name against a glob where `*` matches any run of characters
+/// and `?` a single one, e.g. `@babel/*`
+pub fn matches_glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Backtrack to the most recent `*` on mismatch
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
             }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
         }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-    // devDependencies 수집
-    if let Some(dev_deps) = package_json
-        .get("devDependencies")
-        .and_then(|d| d.as_object())
-    {
-        for (name, version) in dev_deps {
-            if let Some(version_str) = version.as_str() {
-                packages.push((
-                    name.clone(),
-                    version_str.to_string(),
-                    "devDependencies".to_string(),
-                ));
+/// Version specifier of every package in each repository, keyed by package name.
+/// A package declared in several sections is reported with its first specifier,
+/// like `get_package_version`. A repository whose package.json can't be read gets its own error.
+/// Manifests are read by up to `jobs` threads, as committed at `at_ref` if given.
+pub fn dependency_versions(
+    ctx: &Context,
+    repos: &[&str],
+    jobs: usize,
+    at_ref: Option<&str>,
+) -> Vec<(String, Result<BTreeMap<String, String>>)> {
+    crate::parallel::map(repos, jobs, |&repo_path| {
+        let entries = match at_ref {
+            Some(git_ref) => list_packages_at(ctx, repo_path, git_ref),
+            None => list_all_packages(ctx, repo_path),
+        };
+        let versions = entries.map(|entries| {
+            let mut versions = BTreeMap::new();
+            for entry in entries {
+                versions.entry(entry.name).or_insert(entry.specifier);
             }
+            versions
+        });
+        (repo_path.to_string(), versions)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    fn write_manifest(dir: &tempfile::TempDir, section: &str) -> String {
+        let path = dir.path().join("package.json");
+        fs::write(
+            &path,
+            format!(
+                "{{\n  \"{}\": {{\n    \"react\": \"^17.0.2\"\n  }}\n}}\n",
+                section
+            ),
+        )
+        .unwrap();
+        dir.path().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn update_reports_old_and_new_version_for_each_section() {
+        for section in DEPENDENCY_SECTIONS {
+            let dir = tempfile::tempdir().unwrap();
+            let repo = write_manifest(&dir, section);
+            let ctx = Context::new();
+
+            let outcomes = update_package(&ctx, &repo, "react", "^18.2.0", false).unwrap();
+
+            assert_eq!(
+                outcomes,
+                vec![UpdateOutcome {
+                    section: section.to_string(),
+                    old_version: "^17.0.2".to_string(),
+                    new_version: "^18.2.0".to_string(),
+                }]
+            );
+            let content = fs::read_to_string(dir.path().join("package.json")).unwrap();
+            assert!(content.contains("\"react\": \"^18.2.0\""));
         }
     }
 
-    // peerDependencies 수집
-    if let Some(peer_deps) = package_json
-        .get("peerDependencies")
-        .and_then(|d| d.as_object())
-    {
-        for (name, version) in peer_deps {
-            if let Some(version_str) = version.as_str() {
-                packages.push((
-                    name.clone(),
-                    version_str.to_string(),
-                    "peerDependencies".to_string(),
-                ));
-            }
+    #[test]
+    fn package_only_in_peer_dependencies_is_found_updated_and_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = write_manifest(&dir, "peerDependencies");
+        let ctx = Context::new();
+
+        assert_eq!(
+            get_package_version(&ctx, &repo, "react").unwrap(),
+            Some("^17.0.2".to_string())
+        );
+        assert_eq!(
+            list_all_packages(&ctx, &repo).unwrap(),
+            vec![DependencyEntry {
+                section: "peerDependencies",
+                name: "react".to_string(),
+                specifier: "^17.0.2".to_string(),
+                license: None,
+            }]
+        );
+
+        let outcomes = update_package(&ctx, &repo, "react", "^18.2.0", false).unwrap();
+        assert_eq!(outcomes[0].section, "peerDependencies");
+        assert_eq!(
+            get_package_version(&ctx, &repo, "react").unwrap(),
+            Some("^18.2.0".to_string())
+        );
+    }
+
+    #[test]
+    fn optional_dependencies_are_updated_in_place_and_keep_their_section_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = "{\n    \"dependencies\": {\"react\": \"^18.2.0\"},\n    \"optionalDependencies\": {\n        \"fsevents\": \"~2.3.2\",\n        \"@corp/native\": \"1.0.0\"\n    }\n}\n";
+        fs::write(dir.path().join("package.json"), manifest).unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let ctx = Context::new();
+
+        let outcomes = update_package(&ctx, &repo, "@corp/native", "1.1.0", false).unwrap();
+        assert_eq!(outcomes[0].section, "optionalDependencies");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            manifest.replace("\"1.0.0\"", "\"1.1.0\"")
+        );
+
+        let entries = list_all_packages(&ctx, &repo).unwrap();
+        let json = serde_json::to_value(&entries[1..]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"section": "optionalDependencies", "name": "fsevents", "specifier": "~2.3.2"},
+                {"section": "optionalDependencies", "name": "@corp/native", "specifier": "1.1.0"},
+            ])
+        );
+    }
+
+    #[test]
+    fn engine_changes_follow_existing_engines_and_volta_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"engines": {"node": ">=18"}, "volta": {"node": "18.20.2", "npm": "10.5.0", "yarn": "1.22.19"}}"#,
+        )
+        .unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let ctx = Context::new();
+        let pins = VoltaPins {
+            node: Some("20.12.2".to_string()),
+            npm: Some("10.5.0".to_string()),
+            yarn: None,
+        };
+
+        let changes = plan_engine_changes(&ctx, &repo, ">=20", &pins).unwrap();
+        let summary: Vec<String> = changes
+            .iter()
+            .map(|c| format!("{}.{}: {} -> {}", c.section, c.name, c.old, c.new))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "engines.node: >=18 -> >=20",
+                "volta.node: 18.20.2 -> 20.12.2"
+            ]
+        );
+
+        apply_specifier_changes(&ctx, &repo, &changes).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            r#"{"engines": {"node": ">=20"}, "volta": {"node": "20.12.2", "npm": "10.5.0", "yarn": "1.22.19"}}"#
+        );
+
+        fs::write(dir.path().join("package.json"), r#"{"name": "plain"}"#).unwrap();
+        ctx.manifests.invalidate(&dir.path().join("package.json"));
+        assert!(plan_engine_changes(&ctx, &repo, ">=20", &pins)
+            .unwrap()
+            .is_empty());
+        assert!(volta_tools(&ctx, &repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn manager_versions_are_checked_against_the_pin_or_the_configured_range() {
+        use crate::runner::{CommandOutput, MockRunner};
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        let old_pnpm =
+            || MockRunner::new().respond("pnpm --version", CommandOutput::ok("8.15.4\n"));
+
+        let ctx = Context::with_runner(Arc::new(old_pnpm()));
+        let err = check_manager(&ctx, &repo, "pnpm", Some(">=9"), &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} expects pnpm >=9, found 8.15.4; run corepack enable, or install a matching pnpm",
+                repo
+            )
+        );
+        let check = check_manager(&ctx, &repo, "pnpm", Some("^8.15.0"), &[]).unwrap();
+        assert_eq!(check.label(), "pnpm 8.15.4");
+
+        // The packageManager pin wins over the range, and runs through corepack
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "pnpm@9.1.0+sha512.abc"}"#,
+        )
+        .unwrap();
+        let runner = Arc::new(
+            old_pnpm()
+                .respond("corepack --version", CommandOutput::ok("0.28.0\n"))
+                .respond("corepack pnpm --version", CommandOutput::ok("9.1.0\n")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let check = check_manager(&ctx, &repo, "pnpm", Some("^8.15.0"), &[]).unwrap();
+        assert_eq!(check.label(), "pnpm 9.1.0 (corepack)");
+        run_install_with_manager(&ctx, &repo, &check, &[], false).unwrap();
+        assert_eq!(runner.calls().last().unwrap(), "corepack pnpm install");
+
+        // Without corepack, the pnpm on PATH must be the pinned one
+        let ctx = Context::with_runner(Arc::new(
+            old_pnpm().respond("corepack --version", CommandOutput::failed("not found")),
+        ));
+        let err = check_manager(&ctx, &repo, "pnpm", None, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} pins pnpm@9.1.0 in packageManager, found 8.15.4; run corepack enable",
+                repo
+            )
+        );
+
+        // A package.json that can't be read is reported, not taken for one without a pin
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "pnpm@9"#,
+        )
+        .unwrap();
+        let runner = Arc::new(old_pnpm());
+        let ctx = Context::with_runner(runner.clone());
+        assert!(check_manager(&ctx, &repo, "pnpm", Some("^8.15.0"), &[]).is_err());
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn update_rewrites_only_the_range_of_npm_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"dependencies": {"pkg-next": "npm:@scope/pkg@1.0.0", "@scope/pkg": "^0.9.0"}}"#,
+        )
+        .unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let ctx = Context::new();
+
+        assert_eq!(
+            get_package_version(&ctx, &repo, "pkg-next").unwrap(),
+            Some("1.0.0".to_string())
+        );
+        let outcomes = update_package(&ctx, &repo, "@scope/pkg", "1.1.0", false).unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            r#"{"dependencies": {"pkg-next": "npm:@scope/pkg@1.1.0", "@scope/pkg": "1.1.0"}}"#
+        );
+
+        update_package(&ctx, &repo, "pkg-next", "^1.2.0", false).unwrap();
+        assert_eq!(
+            get_package_version(&ctx, &repo, "pkg-next").unwrap(),
+            Some("^1.2.0".to_string())
+        );
+        assert!(fs::read_to_string(dir.path().join("package.json"))
+            .unwrap()
+            .contains(r#""pkg-next": "npm:@scope/pkg@^1.2.0""#));
+    }
+
+    #[test]
+    fn glob_matches_scoped_packages() {
+        assert!(matches_glob("@babel/*", "@babel/core"));
+        assert!(!matches_glob("@babel/*", "babel-loader"));
+        assert!(matches_glob("*eslint*", "@typescript-eslint/parser"));
+        assert!(matches_glob("react-do?", "react-dom"));
+        assert!(matches_glob("react", "react"));
+        assert!(!matches_glob("react", "react-dom"));
+    }
+
+    #[test]
+    fn choose_manager_prefers_config_then_field_then_lockfile_then_default() {
+        let pick = |pinned, field, lockfiles: &[&'static str], default| {
+            choose_manager(pinned, field, lockfiles, default).map(|d| (d.manager, d.source))
+        };
+        let all = ["yarn.lock", "package-lock.json"];
+
+        assert_eq!(
+            pick(Some("pnpm"), Some("yarn@4.2.2"), &all, Some("npm")),
+            Some(("pnpm".to_string(), ManagerSource::Config))
+        );
+        assert_eq!(
+            pick(None, Some("yarn@4.2.2+sha512.abc"), &all, Some("npm")),
+            Some(("yarn".to_string(), ManagerSource::PackageManagerField))
+        );
+        assert_eq!(
+            pick(None, Some("bun@1.1.0"), &all, Some("npm")),
+            Some(("yarn".to_string(), ManagerSource::Lockfile("yarn.lock")))
+        );
+        assert_eq!(
+            pick(None, None, &[], Some("npm")),
+            Some(("npm".to_string(), ManagerSource::Default))
+        );
+        assert_eq!(pick(None, None, &[], None), None);
+
+        let detection = choose_manager(None, Some("yarn@4.2.2"), &all, None).unwrap();
+        assert_eq!(
+            detection.warning.as_deref(),
+            Some("several lockfiles found (yarn.lock, package-lock.json); using yarn (from packageManager field)")
+        );
+        assert_eq!(
+            choose_manager(None, None, &all[1..], None).unwrap().warning,
+            None
+        );
+    }
+
+    #[test]
+    fn detect_manager_reads_the_package_manager_field_and_lockfiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+
+        let ctx = Context::new();
+
+        let err = detect_manager(&ctx, &repo, None, None).unwrap_err();
+        assert!(err.to_string().contains("mru set-package-manager"));
+
+        fs::write(dir.path().join("package-lock.json"), "{}").unwrap();
+        fs::write(dir.path().join("pnpm-lock.yaml"), "").unwrap();
+        let detection = detect_manager(&ctx, &repo, None, Some("yarn")).unwrap();
+        assert_eq!(detection.manager, "pnpm");
+        assert!(detection.warning.is_some());
+
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "yarn@4.2.2"}"#,
+        )
+        .unwrap();
+        let detection = detect_manager(&ctx, &repo, None, None).unwrap();
+        assert_eq!(detection.manager, "yarn");
+        assert_eq!(detection.source.to_string(), "from packageManager field");
+
+        // Read the way updates read it, BOM and all
+        fs::write(
+            dir.path().join("package.json"),
+            "\u{feff}{\"packageManager\": \"npm@10.8.1\"}",
+        )
+        .unwrap();
+        let detection = detect_manager(&Context::new(), &repo, None, None).unwrap();
+        assert_eq!(detection.manager, "npm");
+
+        fs::write(dir.path().join("package.json"), r#"{"packageManager": "#).unwrap();
+        let err = detect_manager(&Context::new(), &repo, None, None).unwrap_err();
+        assert!(format!("{:#}", err).contains("package.json"));
+    }
+
+    #[test]
+    fn dry_run_reports_update_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = write_manifest(&dir, "dependencies");
+        let before = fs::read_to_string(dir.path().join("package.json")).unwrap();
+        let ctx = Context::new();
+
+        let outcomes = update_package(&ctx, &repo, "react", "^18.2.0", true).unwrap();
+
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].old_version, "^17.0.2");
+        let after = fs::read_to_string(dir.path().join("package.json")).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn compare_reads_each_manifest_once() {
+        let repos: Vec<tempfile::TempDir> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut deps = String::new();
+        for i in 0..50 {
+            let sep = if i == 0 { "" } else { "," };
+            write!(deps, r#"{}"pkg-{}": "^1.0.{}""#, sep, i, i).unwrap();
+        }
+        for repo in &repos {
+            fs::write(
+                repo.path().join("package.json"),
+                format!(r#"{{"dependencies": {{{}}}}}"#, deps),
+            )
+            .unwrap();
+        }
+
+        let ctx = Context::new();
+        let paths: Vec<String> = repos
+            .iter()
+            .map(|r| r.path().to_string_lossy().to_string())
+            .collect();
+        let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+
+        let versions = dependency_versions(&ctx, &paths, 2, None);
+        for i in 0..50 {
+            let name = format!("pkg-{}", i);
+            assert!(versions.iter().all(|(repo, v)| {
+                let expected = format!("^1.0.{}", i);
+                v.as_ref().unwrap().get(&name) == Some(&expected)
+                    && get_package_version(&ctx, repo, &name).unwrap() == Some(expected)
+            }));
         }
+
+        // Without the cache this would be 150 reads
+        assert_eq!(ctx.manifests.reads(), repos.len());
     }
 
-    Ok(packages)
+    #[test]
+    fn newest_in_range_skips_prereleases_and_caches_the_registry() {
+        use crate::runner::{CommandOutput, MockRunner};
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let runner = std::sync::Arc::new(MockRunner::new().respond(
+            "npm view lodash versions --json",
+            CommandOutput::ok(r#"["4.17.0", "4.17.21", "4.18.0-rc.1", "5.0.0"]"#),
+        ));
+        let ctx = Context::with_runner(runner.clone());
+
+        let newest = |range: &str| newest_in_range(&ctx, &repo, "lodash", range).unwrap();
+        assert_eq!(newest("^4.17.0").as_deref(), Some("4.17.21"));
+        assert_eq!(newest(">=4").as_deref(), Some("5.0.0"));
+        assert_eq!(newest("^3.0.0"), None);
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[test]
+    fn is_published_tells_missing_versions_from_registry_errors() {
+        use crate::runner::{CommandOutput, MockRunner};
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let check = |output: CommandOutput| {
+            let runner = std::sync::Arc::new(MockRunner::new().respond("npm view", output));
+            is_published(&Context::with_runner(runner), &repo, "@corp/ds", "2.3.0")
+        };
+
+        assert!(check(CommandOutput::ok("2.3.0\n")).unwrap());
+        assert!(!check(CommandOutput::ok("")).unwrap());
+        assert!(!check(CommandOutput::failed("npm ERR! code E404")).unwrap());
+        assert!(check(CommandOutput::failed("npm ERR! code EACCES")).is_err());
+    }
+
+    #[test]
+    fn plan_specifier_changes_skips_non_registry_specifiers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{
+  "dependencies": {"react": "^18.2.0", "ui": "workspace:*", "lib": "file:../lib"},
+  "devDependencies": {"@types/react": "~18.0.1", "tool": "owner/tool"},
+  "peerDependencies": {"react-dom": "^18.0.0"}
 }
+"#,
+        )
+        .unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        let ctx = Context::new();
+        let sections = ["dependencies".to_string(), "devDependencies".to_string()];
 
-/// Compare package versions across multiple repositories
-pub fn compare_package_versions(
-    repos: &[&str],
-    package_name: &str,
-) -> Result<Vec<(String, Option<String>)>> {
-    let mut results = Vec::new();
+        let changes = plan_specifier_changes(&ctx, &repo, &sections, None, |entry| {
+            crate::version::pin(&entry.specifier, None)
+        })
+        .unwrap();
+        let pins: Vec<(&str, &str)> = changes
+            .iter()
+            .map(|c| (c.name.as_str(), c.new.as_str()))
+            .collect();
+        assert_eq!(pins, [("react", "18.2.0"), ("@types/react", "18.0.1")]);
 
-    for &repo_path in repos {
-        let version = get_package_version(repo_path, package_name)?;
-        results.push((repo_path.to_string(), version));
+        apply_specifier_changes(&ctx, &repo, &changes).unwrap();
+        let content = fs::read_to_string(dir.path().join("package.json")).unwrap();
+        assert!(content.contains(r#""react": "18.2.0", "ui": "workspace:*""#));
+        assert!(content.contains(r#""react-dom": "^18.0.0""#));
     }
 
-    Ok(results)
+    #[test]
+    fn run_dedupe_skips_yarn_classic() {
+        use crate::runner::MockRunner;
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().to_string_lossy().to_string();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        fs::write(dir.path().join("yarn.lock"), "# yarn lockfile v1\n").unwrap();
+        let runner = Arc::new(MockRunner::new());
+        let ctx = Context::with_runner(runner.clone());
+
+        run_dedupe(&ctx, &repo, "yarn", &[], false).unwrap();
+        assert!(runner.calls().is_empty());
+
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"packageManager": "yarn@4.1.0"}"#,
+        )
+        .unwrap();
+        ctx.manifests.invalidate(&dir.path().join("package.json"));
+        run_dedupe(&ctx, &repo, "yarn", &[], false).unwrap();
+        run_dedupe(&ctx, &repo, "pnpm", &[], false).unwrap();
+        assert_eq!(runner.calls(), ["yarn dedupe", "pnpm dedupe"]);
+    }
 }
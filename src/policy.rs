@@ -0,0 +1,149 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Repository;
+use crate::package::matches_glob;
+use crate::version;
+
+/// Restriction on automatic updates of a package, from `[[policies]]` in the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Policy {
+    /// Name used in messages and by `mru policy remove`
+    pub name: String,
+    /// Package name or glob, e.g. `webpack` or `@babel/*`
+    pub package: String,
+    /// Repositories the policy applies to, by name, alias or path (default: all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<String>,
+    /// Never update the package
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore: bool,
+    /// Range new versions must be in, e.g. `<5`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Defined in the project-local config file
+    #[serde(skip)]
+    pub local: bool,
+}
+
+impl Policy {
+    /// Check that the policy has exactly one rule and a valid range
+    pub fn validate(&self) -> Result<()> {
+        match (&self.allowed, self.ignore) {
+            (Some(_), true) => anyhow::bail!(
+                "Policy '{}' sets both ignore and allowed; use one",
+                self.name
+            ),
+            (None, false) => anyhow::bail!(
+                "Policy '{}' needs either ignore = true or an allowed range",
+                self.name
+            ),
+            (Some(range), false) => version::validate(range)
+                .map_err(|e| anyhow::anyhow!("Policy '{}': {}", self.name, e)),
+            (None, true) => Ok(()),
+        }
+    }
+
+    pub fn applies_to(&self, repo: &Repository, package: &str) -> bool {
+        matches_glob(&self.package, package)
+            && (self.repos.is_empty() || self.repos.iter().any(|r| repo.is_named(r)))
+    }
+
+    /// e.g. `ignore` or `allowed <5`
+    pub fn rule(&self) -> String {
+        match &self.allowed {
+            Some(range) => format!("allowed {}", range),
+            None => "ignore".to_string(),
+        }
+    }
+
+    /// Why updating `package` to `version` in `repo` breaks this policy, if it does
+    pub fn violation(&self, repo: &Repository, package: &str, version: &str) -> Option<String> {
+        if !self.applies_to(repo, package) {
+            return None;
+        }
+
+        let problem = match &self.allowed {
+            Some(range) if version::satisfies(range, version) => return None,
+            Some(range) => format!("{} must stay within {}", package, range),
+            None => format!("{} is never updated automatically", package),
+        };
+        let reason = self
+            .reason
+            .as_ref()
+            .map(|r| format!(" ({})", r))
+            .unwrap_or_default();
+        Some(format!("policy '{}': {}{}", self.name, problem, reason))
+    }
+
+    /// Why the version `repo` currently uses breaks this policy's allowed range, if it does.
+    /// `ignore` policies only restrict updates, so the current version never breaks them.
+    pub fn current_violation(
+        &self,
+        repo: &Repository,
+        package: &str,
+        version: &str,
+    ) -> Option<String> {
+        self.allowed.as_ref()?;
+        self.violation(repo, package, version)
+    }
+}
+
+/// First policy that forbids updating `package` to `version` in `repo`
+pub fn find_violation(
+    policies: &[Policy],
+    repo: &Repository,
+    package: &str,
+    version: &str,
+) -> Option<String> {
+    policies
+        .iter()
+        .find_map(|p| p.violation(repo, package, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_match_package_globs_and_repos() {
+        let policies = vec![
+            Policy {
+                name: "pinned-webpack".to_string(),
+                package: "webpack".to_string(),
+                repos: vec!["legacy".to_string()],
+                ignore: true,
+                ..Default::default()
+            },
+            Policy {
+                name: "babel-7".to_string(),
+                package: "@babel/*".to_string(),
+                allowed: Some("<8".to_string()),
+                ..Default::default()
+            },
+        ];
+        let legacy = Repository {
+            path: "/work/legacy".to_string(),
+            ..Default::default()
+        };
+        let app = Repository {
+            path: "/work/app".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            find_violation(&policies, &legacy, "webpack", "^5.0.0").as_deref(),
+            Some("policy 'pinned-webpack': webpack is never updated automatically")
+        );
+        assert_eq!(find_violation(&policies, &app, "webpack", "^5.0.0"), None);
+        assert_eq!(
+            find_violation(&policies, &app, "@babel/core", "7.24.0"),
+            None
+        );
+        assert!(find_violation(&policies, &app, "@babel/core", "^8.0.0")
+            .unwrap()
+            .contains("must stay within <8"));
+    }
+}
@@ -0,0 +1,507 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use crate::context::{repo_name, Context};
+use crate::git::git_command;
+use crate::provider::ProviderKind;
+use crate::repo::expand_path;
+use crate::runner::CommandSpec;
+
+/// Oldest git that understands every command mru runs (`status --porcelain=v2`)
+pub const MIN_GIT_VERSION: &str = "2.11";
+
+/// Outcome of running `<program> --version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Probe {
+    /// Version number, if one could be read from the output
+    Found(Option<String>),
+    Missing,
+}
+
+/// Tool probes, run at most once per process
+#[derive(Default)]
+pub struct ToolCache {
+    probes: Mutex<HashMap<String, Probe>>,
+}
+
+impl ToolCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// External programs a command is about to run
+#[derive(Debug, Default)]
+pub struct Requirements {
+    pub git: bool,
+    pub gh: bool,
+    /// Package managers, with the repositories that need each
+    pub package_managers: BTreeMap<String, BTreeSet<String>>,
+    /// Repositories that will be committed to and pushed, whose git setup is checked
+    pub pushes: BTreeSet<String>,
+}
+
+impl Requirements {
+    pub fn git() -> Self {
+        Requirements {
+            git: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_gh(mut self, gh: bool) -> Self {
+        self.gh = gh;
+        self
+    }
+
+    pub fn package_manager(&mut self, manager: &str, repo_path: &str) {
+        self.package_managers
+            .entry(manager.to_string())
+            .or_default()
+            .insert(crate::context::repo_name(repo_path));
+    }
+
+    pub fn push_to(&mut self, repo_path: &str) {
+        self.pushes.insert(repo_path.to_string());
+    }
+}
+
+/// Make sure every required program is installed and new enough, reporting
+/// everything that is missing in one error
+pub fn check(ctx: &Context, requirements: &Requirements) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if requirements.git {
+        match probe(ctx, "git") {
+            Probe::Missing => problems.push("git is not installed or not on PATH".to_string()),
+            Probe::Found(Some(version))
+                if compare_versions(&version, MIN_GIT_VERSION) == Ordering::Less =>
+            {
+                problems.push(format!(
+                    "git {} is too old; mru needs git {} or newer",
+                    version, MIN_GIT_VERSION
+                ))
+            }
+            Probe::Found(_) => {}
+        }
+    }
+
+    if requirements.gh && probe(ctx, "gh") == Probe::Missing {
+        problems.push(
+            "gh (GitHub CLI) is not installed or not on PATH; it is needed to work with pull requests"
+                .to_string(),
+        );
+    }
+
+    for (manager, repos) in &requirements.package_managers {
+        if probe(ctx, manager) == Probe::Missing {
+            problems.push(format!(
+                "{} is not installed or not on PATH; it is needed by {}",
+                manager,
+                repos.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Required tools are missing:\n{}",
+            problems
+                .iter()
+                .map(|p| format!("  - {}", p))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    if !requirements.pushes.is_empty() {
+        let setup = git_setup(ctx, &requirements.pushes);
+        for warning in &setup.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        if !setup.errors.is_empty() {
+            anyhow::bail!(
+                "Git is not set up to commit and push:\n{}",
+                setup
+                    .errors
+                    .iter()
+                    .map(|p| format!("  - {}", p))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Problems that would make commits or pushes fail, found before any repository changes
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GitSetup {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Check that commits have an author in every repository, and that pushing to an
+/// HTTPS remote won't need a password: git runs with prompts disabled, so it would
+/// fail in each repository instead of asking
+pub fn git_setup(ctx: &Context, repo_paths: &BTreeSet<String>) -> GitSetup {
+    let mut setup = GitSetup::default();
+
+    for (key, example) in [
+        ("user.name", "\"Your Name\""),
+        ("user.email", "you@example.com"),
+    ] {
+        let missing: Vec<String> = repo_paths
+            .iter()
+            .filter(|path| git_value(ctx, path, &["config", "--get", key]).is_none())
+            .map(|path| repo_name(path))
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+        setup.errors.push(if missing.len() == repo_paths.len() {
+            format!(
+                "{} is not set, so commits would fail; run: git config --global {} {}",
+                key, key, example
+            )
+        } else {
+            format!(
+                "{} is not set in {}; run: git config --global {} {} (or set it in each repository)",
+                key,
+                missing.join(", "),
+                key,
+                example
+            )
+        });
+    }
+
+    let mut prompting = Vec::new();
+    let mut unverified = Vec::new();
+    for path in repo_paths {
+        let Some(url) = git_value(ctx, path, &["remote", "get-url", "--push", "origin"]) else {
+            continue;
+        };
+        let helper = ["config", "--get-urlmatch", "credential.helper", &url];
+        if !url.starts_with("https://") && !url.starts_with("http://")
+            || git_value(ctx, path, &helper).is_some()
+        {
+            continue;
+        }
+        // Fails right away if the remote wants credentials, since prompts are disabled
+        let probe = expand_path(path).ok().and_then(|dir| {
+            ctx.runner
+                .output(
+                    &git_command()
+                        .current_dir(dir)
+                        .timeout(ctx.timeouts.git)
+                        .args(["ls-remote", "--exit-code", "origin", "HEAD"]),
+                )
+                .ok()
+        });
+        match probe {
+            Some(output) if !output.success() && needs_credentials(&output.tail()) => {
+                prompting.push(repo_name(path))
+            }
+            _ => unverified.push(repo_name(path)),
+        }
+    }
+    if !prompting.is_empty() {
+        setup.errors.push(format!(
+            "pushing {} over HTTPS asks for credentials and no credential helper is set up; run: gh auth setup-git (or switch the remote to SSH)",
+            prompting.join(", ")
+        ));
+    }
+    if !unverified.is_empty() {
+        setup.warnings.push(format!(
+            "pushing {} over HTTPS has no credential helper and fails if a password is needed; run: gh auth setup-git",
+            unverified.join(", ")
+        ));
+    }
+    setup
+}
+
+/// Problems with a repository that otherwise only show up partway through a campaign:
+/// nothing to update, nowhere to push, no upstream to sync the base branch from, no
+/// pull requests, and the git setup `check` looks at before pushing. `github_url` is
+/// the configured one, which may differ from origin.
+pub fn repository_warnings(
+    ctx: &Context,
+    repo_path: &str,
+    github_url: Option<&str>,
+) -> Vec<String> {
+    let dir = match expand_path(repo_path) {
+        Ok(dir) if dir.is_dir() => dir,
+        _ => return vec!["path does not exist".to_string()],
+    };
+    let mut warnings = Vec::new();
+    if !dir.join("package.json").is_file() {
+        warnings.push(
+            "no package.json at the top level, so updates find nothing to change".to_string(),
+        );
+    }
+
+    let Some(origin) = git_value(ctx, repo_path, &["remote", "get-url", "origin"]) else {
+        warnings.push(
+            "no origin remote, so update branches can't be pushed; run: git remote add origin <URL>"
+                .to_string(),
+        );
+        return warnings;
+    };
+    if github_url.is_none()
+        && crate::github::normalize_github_url(&origin).is_none()
+        && ProviderKind::detect(&origin).is_none()
+    {
+        let host = match crate::provider::unsupported_host(&origin) {
+            Some(service) => format!("on {}", service),
+            None => "not on GitHub".to_string(),
+        };
+        warnings.push(format!(
+            "origin is {}, so --pull-request pushes without opening a pull request; pass --url if the repository is mirrored on GitHub",
+            host
+        ));
+    }
+    let branch = crate::git::default_branch(ctx, repo_path).unwrap_or_else(|_| "main".to_string());
+    let local = format!("refs/heads/{}", branch);
+    let upstream = format!("{}@{{upstream}}", branch);
+    if git_value(
+        ctx,
+        repo_path,
+        &["rev-parse", "--verify", "--quiet", &local],
+    )
+    .is_some()
+        && git_value(ctx, repo_path, &["rev-parse", "--abbrev-ref", &upstream]).is_none()
+    {
+        warnings.push(format!(
+            "{} doesn't track a remote branch, so it can't be brought up to date before updating; run: git branch --set-upstream-to=origin/{} {}",
+            branch, branch, branch
+        ));
+    }
+
+    let setup = git_setup(ctx, &BTreeSet::from([repo_path.to_string()]));
+    warnings.extend(setup.errors);
+    warnings.extend(setup.warnings);
+    warnings
+}
+
+/// Trimmed output of a git command run in the repository, if it succeeded with any
+fn git_value(ctx: &Context, repo_path: &str, args: &[&str]) -> Option<String> {
+    let dir = expand_path(repo_path).ok()?;
+    let output = ctx
+        .runner
+        .output(&git_command().current_dir(dir).args(args))
+        .ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.success() && !value.is_empty()).then_some(value)
+}
+
+/// git's errors when it would have prompted for a username or password
+fn needs_credentials(stderr: &str) -> bool {
+    [
+        "terminal prompts disabled",
+        "could not read Username",
+        "Authentication failed",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
+
+/// Version `<program> --version` reports, if it runs and prints one
+pub fn tool_version(ctx: &Context, program: &str) -> Option<String> {
+    match probe(ctx, program) {
+        Probe::Found(version) => version,
+        Probe::Missing => None,
+    }
+}
+
+/// Run `<program> --version` unless it was already run by this process
+fn probe(ctx: &Context, program: &str) -> Probe {
+    if let Some(probe) = ctx.tools.probes.lock().unwrap().get(program) {
+        return probe.clone();
+    }
+
+    let probe = match ctx.runner.output(
+        &CommandSpec::new(program)
+            .timeout(ctx.timeouts.gh)
+            .arg("--version"),
+    ) {
+        Ok(output) if output.success() => {
+            Probe::Found(parse_version(&String::from_utf8_lossy(&output.stdout)))
+        }
+        _ => Probe::Missing,
+    };
+    ctx.log.event(
+        "tool_probe",
+        &[
+            ("program", program),
+            (
+                "version",
+                match &probe {
+                    Probe::Found(Some(version)) => version,
+                    Probe::Found(None) => "unknown",
+                    Probe::Missing => "missing",
+                },
+            ),
+        ],
+    );
+
+    ctx.tools
+        .probes
+        .lock()
+        .unwrap()
+        .insert(program.to_string(), probe.clone());
+    probe
+}
+
+/// Version number in `--version` output, e.g. `2.39.3` in `git version 2.39.3 (Apple Git-146)`
+pub fn parse_version(output: &str) -> Option<String> {
+    let token = output
+        .split_whitespace()
+        .map(|t| t.trim_start_matches('v'))
+        .find(|t| t.starts_with(|c: char| c.is_ascii_digit()))?;
+    let parts: Vec<&str> = token
+        .split('.')
+        .take_while(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("."))
+}
+
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |v: &str| -> Vec<u64> { v.split('.').filter_map(|p| p.parse().ok()).collect() };
+    let (a, b) = (numbers(a), numbers(b));
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, MockRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn check_lists_every_missing_tool_and_probes_each_once() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "git --version",
+                    CommandOutput::ok("git version 2.9.5.windows.1\n"),
+                )
+                .respond("gh --version", CommandOutput::failed("not found"))
+                .respond("pnpm --version", CommandOutput::failed("not found")),
+        );
+        let ctx = Context::with_runner(runner.clone());
+        let mut requirements = Requirements::git().with_gh(true);
+        requirements.package_manager("pnpm", "/work/web");
+        requirements.package_manager("pnpm", "/work/api");
+        requirements.package_manager("npm", "/work/cli");
+
+        let message = check(&ctx, &requirements).unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Required tools are missing:\n\
+             \x20 - git 2.9.5 is too old; mru needs git 2.11 or newer\n\
+             \x20 - gh (GitHub CLI) is not installed or not on PATH; it is needed to work with pull requests\n\
+             \x20 - pnpm is not installed or not on PATH; it is needed by api, web"
+        );
+
+        assert!(check(&ctx, &requirements).is_err());
+        assert_eq!(runner.calls().len(), 4);
+        assert_eq!(
+            parse_version("gh version 2.40.1 (2023-12-13)").as_deref(),
+            Some("2.40.1")
+        );
+    }
+
+    #[test]
+    fn repository_warnings_find_what_would_break_a_campaign() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let ctx = Context::with_runner(Arc::new(crate::runner::SystemRunner));
+        let git = |args: &[&str]| {
+            let output = ctx
+                .runner
+                .output(&git_command().current_dir(dir.path()).args(args))
+                .unwrap();
+            assert!(
+                output.success(),
+                "git {}: {}",
+                args.join(" "),
+                output.tail()
+            );
+        };
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        git(&["config", "user.name", "mru"]);
+        git(&["config", "user.email", "mru@example.com"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "init"]);
+        let warnings = |github_url: Option<&str>| repository_warnings(&ctx, &path, github_url);
+
+        assert_eq!(
+            warnings(None),
+            vec![
+                "no package.json at the top level, so updates find nothing to change",
+                "no origin remote, so update branches can't be pushed; run: git remote add origin <URL>",
+            ]
+        );
+
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        git(&["remote", "add", "origin", "git@gitlab.com:acme/app.git"]);
+        assert_eq!(
+            warnings(None),
+            vec![
+                "origin is on GitLab, so --pull-request pushes without opening a pull request; pass --url if the repository is mirrored on GitHub",
+                "main doesn't track a remote branch, so it can't be brought up to date before updating; run: git branch --set-upstream-to=origin/main main",
+            ]
+        );
+
+        git(&["update-ref", "refs/remotes/origin/main", "HEAD"]);
+        git(&["branch", "--quiet", "--set-upstream-to=origin/main", "main"]);
+        assert_eq!(warnings(None).len(), 1);
+        assert!(warnings(Some("https://github.com/acme/app")).is_empty());
+        git(&["remote", "set-url", "origin", "git@github.com:acme/app.git"]);
+        assert!(warnings(None).is_empty());
+
+        assert_eq!(
+            repository_warnings(&ctx, &format!("{}/missing", path), None),
+            vec!["path does not exist"]
+        );
+    }
+
+    #[test]
+    fn git_setup_reports_missing_identity_and_prompting_remotes_once() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond("git config --get user.name", CommandOutput::ok("Ada\n"))
+                .respond("git config --get user.email", CommandOutput::failed(""))
+                .respond(
+                    "git remote get-url --push origin",
+                    CommandOutput::ok("https://github.com/o/r.git\n"),
+                )
+                .respond("git config --get-urlmatch", CommandOutput::failed(""))
+                .respond(
+                    "git ls-remote",
+                    CommandOutput::failed(
+                        "fatal: could not read Username for 'https://github.com': terminal prompts disabled",
+                    ),
+                ),
+        );
+        let ctx = Context::with_runner(runner);
+        let repos: BTreeSet<String> = ["/w/api".to_string(), "/w/web".to_string()].into();
+
+        let setup = git_setup(&ctx, &repos);
+        assert_eq!(
+            setup.errors,
+            vec![
+                "user.email is not set, so commits would fail; run: git config --global user.email you@example.com",
+                "pushing api, web over HTTPS asks for credentials and no credential helper is set up; run: gh auth setup-git (or switch the remote to SSH)",
+            ]
+        );
+        assert!(setup.warnings.is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::lockfile::Lockfile;
+use crate::repo::expand_path;
+use crate::runner::CommandSpec;
+
+/// Files that decide how the package manager resolves, copied next to the manifest
+const RESOLUTION_FILES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    ".npmrc",
+    ".yarnrc",
+    ".yarnrc.yml",
+    ".pnpmfile.cjs",
+];
+
+const SECTIONS: &[&str] = &[
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+    "peerDependencies",
+];
+
+/// Packages besides the updated one that an update would add to, remove from or change
+/// in a repository's lockfile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvePreview {
+    /// e.g. `ms@2.1.3`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// e.g. `debug 4.3.4 → 4.3.7`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
+    /// Read from the lockfile the package manager wrote into a copy of the repository,
+    /// because it has no dry run that reports what would change
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub best_effort: bool,
+}
+
+impl ResolvePreview {
+    /// e.g. "3 added, 1 removed, 2 changed (best-effort)"
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        for (list, label) in [
+            (&self.added, "added"),
+            (&self.removed, "removed"),
+            (&self.changed, "changed"),
+        ] {
+            if !list.is_empty() {
+                parts.push(format!("{} {}", list.len(), label));
+            }
+        }
+        let summary = if parts.is_empty() {
+            "no other packages change".to_string()
+        } else {
+            parts.join(", ")
+        };
+        if self.best_effort {
+            format!("{} (best-effort)", summary)
+        } else {
+            summary
+        }
+    }
+
+    /// One line per package: `+ ms@2.1.3`, `- ms@2.0.0`, `~ debug 4.3.4 → 4.3.7`
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        lines.extend(self.added.iter().map(|p| format!("+ {}", p)));
+        lines.extend(self.removed.iter().map(|p| format!("- {}", p)));
+        lines.extend(self.changed.iter().map(|p| format!("~ {}", p)));
+        lines
+    }
+}
+
+/// Resolve the update of `package` to `version` in a temporary copy of the repository's
+/// manifest and lockfile, made in `tmp`, and report the packages that would change. npm's own dry run
+/// reports them; pnpm and yarn can't, so the lockfile they write into the copy is
+/// compared instead. The repository itself is never written to.
+pub fn resolve(
+    ctx: &Context,
+    repo_path: &str,
+    manager: &str,
+    package: &str,
+    version: &str,
+    env: &[(String, String)],
+    tmp: &Path,
+) -> Result<ResolvePreview> {
+    let root = expand_path(repo_path)?;
+    let text = fs::read_to_string(root.join("package.json"))
+        .with_context(|| format!("Failed to read package.json in {}", repo_path))?;
+    let mut manifest: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse package.json in {}", repo_path))?;
+    if manifest.get("workspaces").is_some() || root.join("pnpm-workspace.yaml").exists() {
+        anyhow::bail!("workspaces can't be resolved from a copy of the root manifest");
+    }
+    if Lockfile::load(repo_path)?.is_none() {
+        anyhow::bail!("there is no lockfile to compare against");
+    }
+    if !set_version(&mut manifest, package, version) {
+        return Ok(ResolvePreview::default());
+    }
+
+    let copy = tempfile::tempdir_in(tmp).context("Failed to create a temporary directory")?;
+    fs::write(
+        copy.path().join("package.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    for file in RESOLUTION_FILES {
+        if root.join(file).is_file() {
+            fs::copy(root.join(file), copy.path().join(file))
+                .with_context(|| format!("Failed to copy {}", file))?;
+        }
+    }
+
+    let args: &[&str] = match manager {
+        "npm" => &[
+            "install",
+            "--package-lock-only",
+            "--dry-run",
+            "--color=false",
+            "--ignore-scripts",
+            "--no-audit",
+            "--no-fund",
+        ],
+        "pnpm" => &["install", "--lockfile-only", "--ignore-scripts"],
+        "yarn" if root.join(".yarnrc.yml").exists() => &["install", "--mode=update-lockfile"],
+        "yarn" => &[
+            "install",
+            "--ignore-scripts",
+            "--ignore-engines",
+            "--non-interactive",
+        ],
+        other => anyhow::bail!("{} has no way to resolve without installing", other),
+    };
+    let before = lockfile_packages(copy.path())?;
+    let output = ctx.runner.output(
+        &CommandSpec::new(manager)
+            .args(args)
+            .current_dir(copy.path())
+            .envs(env)
+            .timeout(ctx.timeouts.install),
+    )?;
+    if !output.success() {
+        anyhow::bail!("{} failed to resolve:\n{}", manager, output.tail());
+    }
+
+    let mut preview = match manager {
+        "npm" => npm_dry_run(&String::from_utf8_lossy(&output.stdout)),
+        _ => ResolvePreview {
+            best_effort: true,
+            ..diff(&before, &lockfile_packages(copy.path())?)
+        },
+    };
+    // The package itself is in the manifest diff already
+    let other = |entry: &String| {
+        entry
+            .strip_prefix(package)
+            .is_none_or(|rest| !rest.starts_with(['@', ' ']))
+    };
+    preview.added.retain(other);
+    preview.removed.retain(other);
+    preview.changed.retain(other);
+    Ok(preview)
+}
+
+/// Set `package` to `version` in every dependency section it is in; false if it's in none
+fn set_version(manifest: &mut Value, package: &str, version: &str) -> bool {
+    let mut found = false;
+    for section in SECTIONS {
+        if let Some(entry) = manifest.get_mut(*section).and_then(|s| s.get_mut(package)) {
+            *entry = Value::String(version.to_string());
+            found = true;
+        }
+    }
+    found
+}
+
+fn lockfile_packages(dir: &Path) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let lockfile = Lockfile::load(&dir.to_string_lossy())?;
+    Ok(lockfile.map(|l| l.packages()).unwrap_or_default())
+}
+
+/// The `add`, `remove` and `change` lines `npm install --dry-run` prints
+fn npm_dry_run(stdout: &str) -> ResolvePreview {
+    let mut preview = ResolvePreview::default();
+    for line in stdout.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["add", name, version] => preview.added.push(format!("{}@{}", name, version)),
+            ["remove", name, version] => preview.removed.push(format!("{}@{}", name, version)),
+            ["change", name, old, "=>", new] => {
+                preview.changed.push(format!("{} {} → {}", name, old, new))
+            }
+            _ => {}
+        }
+    }
+    preview
+}
+
+/// Packages only in `after`, only in `before`, and in both with other versions
+fn diff(
+    before: &BTreeMap<String, BTreeSet<String>>,
+    after: &BTreeMap<String, BTreeSet<String>>,
+) -> ResolvePreview {
+    let join =
+        |versions: &BTreeSet<String>| versions.iter().cloned().collect::<Vec<_>>().join(", ");
+    let mut preview = ResolvePreview::default();
+    for (name, versions) in after {
+        match before.get(name) {
+            None => preview.added.push(format!("{}@{}", name, join(versions))),
+            Some(old) if old != versions => {
+                preview
+                    .changed
+                    .push(format!("{} {} → {}", name, join(old), join(versions)))
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, versions) in before {
+        if !after.contains_key(name) {
+            preview.removed.push(format!("{}@{}", name, join(versions)));
+        }
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, MockRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn preview_resolves_in_a_copy_and_leaves_the_repository_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = r#"{"dependencies": {"debug": "^4.3.0"}}"#;
+        let lock = r#"{"packages": {"node_modules/debug": {"version": "4.3.4"}}}"#;
+        fs::write(dir.path().join("package.json"), manifest).unwrap();
+        fs::write(dir.path().join("package-lock.json"), lock).unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+
+        let runner = Arc::new(MockRunner::new().respond(
+            "npm install --package-lock-only --dry-run",
+            CommandOutput::ok(
+                "add ms 2.1.3\nchange debug 4.3.4 => 4.3.7\nremove ms 2.0.0\n{\n  \"added\": 1\n}\n",
+            ),
+        ));
+        let ctx = Context::with_runner(runner.clone());
+        let tmp = tempfile::tempdir().unwrap();
+        let preview = resolve(&ctx, &path, "npm", "debug", "^4.3.7", &[], tmp.path()).unwrap();
+        assert_eq!(
+            preview,
+            ResolvePreview {
+                added: vec!["ms@2.1.3".to_string()],
+                removed: vec!["ms@2.0.0".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(preview.summary(), "1 added, 1 removed");
+        let cwd = runner.commands()[0].cwd.clone().unwrap();
+        assert!(cwd.starts_with(tmp.path()));
+        assert!(!cwd.exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            manifest
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package-lock.json")).unwrap(),
+            lock
+        );
+
+        // Without a dry run of their own, the lockfiles are compared
+        let before = BTreeMap::from([
+            ("debug".to_string(), BTreeSet::from(["4.3.4".to_string()])),
+            ("ms".to_string(), BTreeSet::from(["2.0.0".to_string()])),
+        ]);
+        let after = BTreeMap::from([
+            ("debug".to_string(), BTreeSet::from(["4.3.7".to_string()])),
+            ("ms".to_string(), BTreeSet::from(["2.1.3".to_string()])),
+        ]);
+        let preview = ResolvePreview {
+            best_effort: true,
+            ..diff(&before, &after)
+        };
+        assert_eq!(
+            preview.lines(),
+            vec!["~ debug 4.3.4 → 4.3.7", "~ ms 2.0.0 → 2.1.3"]
+        );
+        assert_eq!(preview.summary(), "2 changed (best-effort)");
+
+        // Not a dependency: nothing to resolve
+        let preview = resolve(&ctx, &path, "npm", "lodash", "4.17.21", &[], tmp.path()).unwrap();
+        assert_eq!(preview, ResolvePreview::default());
+    }
+}
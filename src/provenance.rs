@@ -0,0 +1,306 @@
+use anyhow::{Context as _, Result};
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::http::HttpSetup;
+use crate::repo::expand_path;
+use crate::runner::CommandSpec;
+
+/// Registry a mirror's integrity hashes are compared against
+const PUBLIC_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// How long fetching a version's attestations may take
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What `--verify-provenance` found out about the version a campaign rolls out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenanceCheck {
+    pub package: String,
+    pub version: String,
+    /// `dist.integrity` in the registry the repositories install from
+    pub integrity: Option<String>,
+    /// `dist.integrity` in the public registry, if the version is published there
+    pub public_integrity: Option<String>,
+    /// Whether the version has an npm provenance attestation
+    pub provenance: bool,
+    /// Repository and ref of the workflow that published it, e.g.
+    /// `https://github.com/acme/lib@refs/tags/v1.2.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl ProvenanceCheck {
+    /// Why the version falls short of the supply-chain policy; empty if it doesn't
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.provenance {
+            problems.push("published without a provenance attestation".to_string());
+        }
+        match (&self.integrity, &self.public_integrity) {
+            (None, _) => problems.push("the registry has no integrity hash for it".to_string()),
+            (Some(ours), Some(public)) if ours != public => problems.push(format!(
+                "its integrity {} differs from the public registry's {}",
+                ours, public
+            )),
+            _ => {}
+        }
+        problems
+    }
+
+    /// One line per finding, for the terminal and the run report
+    pub fn lines(&self) -> Vec<String> {
+        let provenance = match (&self.provenance, &self.source) {
+            (true, Some(source)) => format!("yes, published from {}", source),
+            (true, None) => "yes".to_string(),
+            (false, _) => "none".to_string(),
+        };
+        let integrity = match (&self.integrity, &self.public_integrity) {
+            (None, _) => "missing".to_string(),
+            (Some(ours), Some(public)) if ours == public => {
+                format!("{} (matches the public registry)", ours)
+            }
+            (Some(ours), Some(public)) => {
+                format!("{} (the public registry has {})", ours, public)
+            }
+            (Some(ours), None) => format!("{} (not in the public registry)", ours),
+        };
+        vec![
+            format!("Provenance: {}", provenance),
+            format!("Integrity: {}", integrity),
+        ]
+    }
+
+    /// Section for the pull request body
+    pub fn markdown(&self) -> String {
+        let lines: Vec<String> = self.lines().iter().map(|l| format!("- {}", l)).collect();
+        format!(
+            "### Provenance of {}@{}\n\n{}",
+            self.package,
+            self.version,
+            lines.join("\n")
+        )
+    }
+}
+
+/// Look up the provenance and integrity of `package@version` in the registry the
+/// repository at `repo_path` installs from, and its integrity in the public registry
+pub fn check(
+    ctx: &Context,
+    repo_path: &str,
+    package: &str,
+    version: &str,
+) -> Result<ProvenanceCheck> {
+    let dist = npm_view_dist(ctx, repo_path, package, version, None)?.ok_or_else(|| {
+        anyhow::anyhow!("{}@{} is not published to the registry", package, version)
+    })?;
+    let public = npm_view_dist(ctx, repo_path, package, version, Some(PUBLIC_REGISTRY))?;
+
+    let attestations = &dist["attestations"];
+    let provenance = attestations["provenance"].is_object();
+    // Where it was published from is a nicety; the attestation's presence is what counts
+    let source = match attestations["url"].as_str().filter(|_| provenance) {
+        Some(url) => fetch_source(url).unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}", e);
+            None
+        }),
+        None => None,
+    };
+
+    let integrity = |dist: &Value| dist["integrity"].as_str().map(str::to_string);
+    Ok(ProvenanceCheck {
+        package: package.to_string(),
+        version: version.to_string(),
+        integrity: integrity(&dist),
+        public_integrity: public.as_ref().and_then(integrity),
+        provenance,
+        source,
+    })
+}
+
+/// `npm view <package>@<version> dist --json` in the repository, against `registry` if
+/// given; `None` if the version isn't published there
+fn npm_view_dist(
+    ctx: &Context,
+    repo_path: &str,
+    package: &str,
+    version: &str,
+    registry: Option<&str>,
+) -> Result<Option<Value>> {
+    let spec = format!("{}@{}", package, version);
+    let mut command = CommandSpec::new("npm")
+        .timeout(ctx.timeouts.registry)
+        .current_dir(expand_path(repo_path)?)
+        .args(["view", &spec, "dist", "--json"]);
+    if let Some(registry) = registry {
+        command = command.args(["--registry", registry]);
+    }
+
+    let output = ctx
+        .output_with_retry(&command)
+        .with_context(|| format!("Failed to look up {} in the registry", spec))?;
+
+    // A missing version prints nothing; a package the registry doesn't know is E404
+    if output.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(None);
+        }
+        let dist = serde_json::from_str(&stdout)
+            .with_context(|| format!("Failed to parse the registry metadata of {}", spec))?;
+        return Ok(Some(dist));
+    }
+    if String::from_utf8_lossy(&output.stderr).contains("E404") {
+        return Ok(None);
+    }
+    anyhow::bail!(
+        "Failed to look up {} in the registry: {}",
+        spec,
+        output.tail()
+    )
+}
+
+/// Fetch a version's attestations from the registry and read where it was published from
+fn fetch_source(url: &str) -> Result<Option<String>> {
+    let setup = HttpSetup::detect();
+    let response = setup
+        .client(TIMEOUT)?
+        .get(url)
+        .send()
+        .with_context(|| format!("Failed to fetch attestations via {}", setup.describe(url)))?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!(
+            "Failed to fetch attestations from {}: server responded with {}",
+            url,
+            status
+        );
+    }
+    let attestations: Value = response.json().context("Failed to read the attestations")?;
+    Ok(source_from_attestations(&attestations))
+}
+
+/// Repository and ref of the publishing workflow, from the SLSA provenance statement
+/// among a registry's attestations
+fn source_from_attestations(attestations: &Value) -> Option<String> {
+    attestations["attestations"]
+        .as_array()?
+        .iter()
+        .filter(|attestation| {
+            attestation["predicateType"]
+                .as_str()
+                .is_some_and(|kind| kind.starts_with("https://slsa.dev/provenance/"))
+        })
+        .find_map(|attestation| {
+            let payload = attestation["bundle"]["dsseEnvelope"]["payload"].as_str()?;
+            let payload = base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .ok()?;
+            let statement: Value = serde_json::from_slice(&payload).ok()?;
+            let predicate = &statement["predicate"];
+
+            // SLSA v1, and v0.2 as published by older npm versions
+            let workflow = &predicate["buildDefinition"]["externalParameters"]["workflow"];
+            if let (Some(repository), Some(git_ref)) =
+                (workflow["repository"].as_str(), workflow["ref"].as_str())
+            {
+                return Some(format!("{}@{}", repository, git_ref));
+            }
+            let uri = predicate["invocation"]["configSource"]["uri"].as_str()?;
+            Some(uri.trim_start_matches("git+").to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CommandOutput, MockRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn check_compares_integrity_with_the_public_registry() {
+        let runner = Arc::new(
+            MockRunner::new()
+                .respond(
+                    "npm view left-pad@1.3.0 dist --json --registry",
+                    CommandOutput::ok(r#"{"integrity": "sha512-public"}"#),
+                )
+                .respond(
+                    "npm view left-pad@1.3.0 dist --json",
+                    CommandOutput::ok(r#"{"integrity": "sha512-mirror", "shasum": "abc"}"#),
+                ),
+        );
+        let ctx = Context::with_runner(runner);
+        let dir = tempfile::tempdir().unwrap();
+
+        let check = check(&ctx, &dir.path().to_string_lossy(), "left-pad", "1.3.0").unwrap();
+        assert!(!check.provenance);
+        assert_eq!(
+            check.problems(),
+            [
+                "published without a provenance attestation",
+                "its integrity sha512-mirror differs from the public registry's sha512-public",
+            ]
+        );
+        assert_eq!(
+            check.markdown(),
+            "### Provenance of left-pad@1.3.0\n\n\
+             - Provenance: none\n\
+             - Integrity: sha512-mirror (the public registry has sha512-public)"
+        );
+
+        let runner = Arc::new(MockRunner::new().respond(
+            "npm view",
+            CommandOutput::failed("npm error code E404\nnpm error 404 Not Found"),
+        ));
+        let ctx = Context::with_runner(runner);
+        let error = super::check(&ctx, &dir.path().to_string_lossy(), "left-pad", "9.9.9");
+        assert!(error.unwrap_err().to_string().contains("not published"));
+    }
+
+    #[test]
+    fn source_is_read_from_the_slsa_statement() {
+        let encode = |statement: Value| {
+            base64::engine::general_purpose::STANDARD.encode(statement.to_string())
+        };
+        let attestations = serde_json::json!({
+            "attestations": [
+                {
+                    "predicateType": "https://github.com/npm/attestation/tree/main/specs/publish/v0.1",
+                    "bundle": {"dsseEnvelope": {"payload": encode(serde_json::json!({}))}}
+                },
+                {
+                    "predicateType": "https://slsa.dev/provenance/v1",
+                    "bundle": {"dsseEnvelope": {"payload": encode(serde_json::json!({
+                        "predicate": {"buildDefinition": {"externalParameters": {"workflow": {
+                            "repository": "https://github.com/acme/lib",
+                            "ref": "refs/tags/v1.2.0",
+                            "path": ".github/workflows/publish.yml"
+                        }}}}
+                    }))}}
+                }
+            ]
+        });
+        assert_eq!(
+            source_from_attestations(&attestations).as_deref(),
+            Some("https://github.com/acme/lib@refs/tags/v1.2.0")
+        );
+
+        let v02 = serde_json::json!({
+            "attestations": [{
+                "predicateType": "https://slsa.dev/provenance/v0.2",
+                "bundle": {"dsseEnvelope": {"payload": encode(serde_json::json!({
+                    "predicate": {"invocation": {"configSource": {
+                        "uri": "git+https://github.com/acme/lib@refs/heads/main"
+                    }}}
+                }))}}
+            }]
+        });
+        assert_eq!(
+            source_from_attestations(&v02).as_deref(),
+            Some("https://github.com/acme/lib@refs/heads/main")
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::azure::AzureDevops;
+use crate::bitbucket::Bitbucket;
+use crate::config::Repository;
+use crate::context::Context;
+use crate::github::{CreatedPr, PullRequest};
+use crate::http::HttpSetup;
+
+/// Hosting service a repository's pull requests are opened on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderKind {
+    Github,
+    Bitbucket,
+    AzureDevops,
+}
+
+impl ProviderKind {
+    /// Service other than GitHub that a remote URL is on, from its host
+    pub fn detect(remote: &str) -> Option<Self> {
+        let (host, _) = split_remote(remote)?;
+        match host.as_str() {
+            "bitbucket.org" => Some(ProviderKind::Bitbucket),
+            "dev.azure.com" | "ssh.dev.azure.com" => Some(ProviderKind::AzureDevops),
+            host if host.ends_with(".visualstudio.com") => Some(ProviderKind::AzureDevops),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProviderKind::Github => "GitHub",
+            ProviderKind::Bitbucket => "Bitbucket",
+            ProviderKind::AzureDevops => "Azure DevOps",
+        }
+    }
+}
+
+/// Name of a hosting service of a remote URL that pull requests can't be opened on
+pub fn unsupported_host(remote: &str) -> Option<&'static str> {
+    let (host, _) = split_remote(remote)?;
+    (host == "gitlab.com" || host.starts_with("gitlab.")).then_some("GitLab")
+}
+
+/// Lowercase host and path, without `.git` and surrounding slashes, of a remote URL
+/// such as `https://user@host/path`, `ssh://git@host:22/path` or `git@host:path`
+pub fn split_remote(remote: &str) -> Option<(String, String)> {
+    let remote = remote.trim();
+    let (authority, path) = match remote.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => remote.split_once(':')?,
+    };
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    (!host.is_empty() && !host.contains('/')).then(|| (host.to_ascii_lowercase(), path.to_string()))
+}
+
+/// How a pull request's commits end up on the base branch; each service maps it onto
+/// its closest merge strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergeMethod {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MergeOptions {
+    pub method: MergeMethod,
+    /// Delete the source branch after merging
+    pub delete_branch: bool,
+    /// Merge even if branch protection requirements are not met; GitHub only
+    pub admin: bool,
+}
+
+/// Result of merging a branch's pull request
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeOutcome {
+    Merged(PullRequest),
+    AlreadyMerged(PullRequest),
+    /// The service refused the merge because of checks, reviews or branch policies
+    Blocked {
+        pr: PullRequest,
+        reason: String,
+    },
+}
+
+/// Pull request to open from `branch` into `base`
+#[derive(Debug, Clone, Copy)]
+pub struct NewPr<'a> {
+    pub branch: &'a str,
+    pub base: &'a str,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub draft: bool,
+}
+
+/// Pull request operations on a hosting service other than GitHub, which goes through gh.
+/// States are reported the way gh does: `OPEN`, `CLOSED` or `MERGED`
+pub trait Provider {
+    fn kind(&self) -> ProviderKind;
+
+    /// Open a pull request, or return the one already open for the branch
+    fn create_pr(&self, ctx: &Context, pr: &NewPr) -> Result<CreatedPr>;
+
+    /// The open pull request of `branch`, else its newest one
+    fn find_pr(&self, ctx: &Context, branch: &str) -> Result<Option<PullRequest>>;
+
+    fn pr_state(&self, ctx: &Context, pr_url: &str) -> Result<String>;
+
+    fn merge_pr(
+        &self,
+        ctx: &Context,
+        pr: &PullRequest,
+        options: &MergeOptions,
+    ) -> Result<MergeOutcome>;
+
+    /// Close a pull request without merging it, leaving `comment` on it
+    fn close_pr(&self, ctx: &Context, pr_url: &str, comment: &str) -> Result<()>;
+}
+
+/// Provider of a repository that isn't on GitHub: the configured `provider`, else the
+/// one `origin` is hosted on. `None` for GitHub and unknown hosts
+pub fn for_repo(ctx: &Context, repo: &Repository) -> Result<Option<Box<dyn Provider>>> {
+    if repo.provider == Some(ProviderKind::Github)
+        || (repo.provider.is_none() && repo.github_url.is_some())
+    {
+        return Ok(None);
+    }
+    let Some(remote) = crate::github::origin_url(ctx, &repo.path) else {
+        if let Some(kind) = repo.provider {
+            anyhow::bail!("{} has no origin remote on {}", repo.path, kind.label());
+        }
+        return Ok(None);
+    };
+    from_url(
+        repo.provider.or_else(|| ProviderKind::detect(&remote)),
+        &remote,
+    )
+}
+
+/// Provider of a pull request URL that isn't on GitHub
+pub fn for_pr_url(pr_url: &str) -> Result<Option<Box<dyn Provider>>> {
+    from_url(ProviderKind::detect(pr_url), pr_url)
+}
+
+fn from_url(kind: Option<ProviderKind>, url: &str) -> Result<Option<Box<dyn Provider>>> {
+    Ok(match kind {
+        Some(ProviderKind::Bitbucket) => Some(Box::new(Bitbucket::from_url(url)?)),
+        Some(ProviderKind::AzureDevops) => Some(Box::new(AzureDevops::from_url(url)?)),
+        Some(ProviderKind::Github) | None => None,
+    })
+}
+
+/// Username and password (an app password or personal access token) for a REST API
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// One REST call, built apart from sending it so each provider's calls can be checked
+/// in tests
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiRequest {
+    pub method: Method,
+    pub url: String,
+    pub body: Option<Value>,
+}
+
+/// Status and JSON body of a REST response
+#[derive(Debug, Clone)]
+pub struct ApiResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl ApiRequest {
+    pub fn get(url: String) -> Self {
+        ApiRequest {
+            method: Method::GET,
+            url,
+            body: None,
+        }
+    }
+
+    pub fn post(url: String, body: Value) -> Self {
+        ApiRequest {
+            method: Method::POST,
+            url,
+            body: Some(body),
+        }
+    }
+
+    pub fn patch(url: String, body: Value) -> Self {
+        ApiRequest {
+            method: Method::PATCH,
+            url,
+            body: Some(body),
+        }
+    }
+
+    /// Send with basic authentication; the response whatever its status, so callers can
+    /// tell a refused merge from a failed request
+    pub fn send(&self, ctx: &Context, credentials: &Credentials) -> Result<ApiResponse> {
+        ctx.log.event(
+            "api_request",
+            &[("method", self.method.as_str()), ("url", &self.url)],
+        );
+        let setup = HttpSetup::detect();
+        let mut request = setup
+            .client(ctx.timeouts.gh)?
+            .request(self.method.clone(), &self.url)
+            .basic_auth(&credentials.user, Some(&credentials.password))
+            .header("Accept", "application/json");
+        if let Some(body) = &self.body {
+            request = request.json(body);
+        }
+        let response = request.send().with_context(|| {
+            format!(
+                "Failed to reach {} ({})",
+                self.url,
+                setup.describe(&self.url)
+            )
+        })?;
+        let status = response.status().as_u16();
+        let text = response.text().unwrap_or_default();
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        Ok(ApiResponse { status, body })
+    }
+}
+
+impl ApiResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Error message of a failed call, as Bitbucket (`error.message`) and Azure DevOps
+    /// (`message`) report it
+    pub fn error(&self) -> String {
+        let message = self.body["error"]["message"]
+            .as_str()
+            .or_else(|| self.body["message"].as_str())
+            .or_else(|| self.body.as_str())
+            .unwrap_or_default()
+            .trim();
+        match message {
+            "" => format!("HTTP {}", self.status),
+            message => format!("HTTP {}: {}", self.status, message),
+        }
+    }
+
+    /// The body of a successful call, else an error saying what failed
+    pub fn json(self, what: &str) -> Result<Value> {
+        if !self.is_success() {
+            anyhow::bail!("Failed to {}: {}", what, self.error());
+        }
+        Ok(self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn providers_are_detected_from_the_remote_host() {
+        for (remote, kind) in [
+            (
+                "git@bitbucket.org:acme/app.git",
+                Some(ProviderKind::Bitbucket),
+            ),
+            (
+                "https://user@bitbucket.org/acme/app.git",
+                Some(ProviderKind::Bitbucket),
+            ),
+            (
+                "https://acme@dev.azure.com/acme/web/_git/app",
+                Some(ProviderKind::AzureDevops),
+            ),
+            (
+                "git@ssh.dev.azure.com:v3/acme/web/app",
+                Some(ProviderKind::AzureDevops),
+            ),
+            (
+                "https://acme.visualstudio.com/web/_git/app",
+                Some(ProviderKind::AzureDevops),
+            ),
+            ("git@github.com:acme/app.git", None),
+            ("https://git.example.com/bitbucket.org/app.git", None),
+            ("/srv/git/app.git", None),
+        ] {
+            assert_eq!(ProviderKind::detect(remote), kind, "{}", remote);
+        }
+
+        assert_eq!(
+            unsupported_host("git@gitlab.com:acme/app.git"),
+            Some("GitLab")
+        );
+        assert_eq!(
+            unsupported_host("https://gitlab.example.com/acme/app.git"),
+            Some("GitLab")
+        );
+        assert_eq!(
+            unsupported_host("https://git.example.com/gitlab-tools/app.git"),
+            None
+        );
+        assert_eq!(
+            split_remote("ssh://git@git.example.com:2222/acme/app.git"),
+            Some(("git.example.com".to_string(), "acme/app".to_string()))
+        );
+    }
+
+    #[test]
+    fn error_messages_come_from_either_service() {
+        let response = |status: u16, body: Value| ApiResponse { status, body };
+        assert_eq!(
+            response(
+                400,
+                serde_json::json!({"type": "error", "error": {"message": "Bad request"}})
+            )
+            .error(),
+            "HTTP 400: Bad request"
+        );
+        assert_eq!(
+            response(409, serde_json::json!({"message": "TF401179: exists"})).error(),
+            "HTTP 409: TF401179: exists"
+        );
+        assert_eq!(
+            response(502, Value::String(String::new())).error(),
+            "HTTP 502"
+        );
+        assert!(response(201, Value::Null).json("create").is_ok());
+    }
+}
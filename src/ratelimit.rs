@@ -0,0 +1,95 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+/// Wait when GitHub names no reset time, e.g. for the secondary rate limit
+const DEFAULT_WAIT: Duration = Duration::from_secs(60);
+
+/// Messages `gh` prints when GitHub refuses a call because of a rate limit
+const MARKERS: &[&str] = &[
+    "api rate limit exceeded",
+    "api rate limit already exceeded",
+    "secondary rate limit",
+    "was submitted too quickly",
+    "rate_limited",
+];
+
+/// How long to wait before retrying a failed `gh` call, if it failed because of a
+/// rate limit. `output` is the call's stderr and stdout; the `retry-after` and
+/// `x-ratelimit-reset` headers are used when present, e.g. with `gh api --include`.
+pub fn detect(output: &str, now: u64) -> Option<Duration> {
+    let lower = output.to_lowercase();
+    let exhausted = header(&lower, "x-ratelimit-remaining").as_deref() == Some("0");
+    if !exhausted && !MARKERS.iter().any(|m| lower.contains(m)) {
+        return None;
+    }
+
+    if let Some(seconds) = header(&lower, "retry-after").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(Duration::from_secs(seconds.max(1)));
+    }
+    if let Some(reset) = header(&lower, "x-ratelimit-reset").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(Duration::from_secs(reset.saturating_sub(now).max(1)));
+    }
+    Some(DEFAULT_WAIT)
+}
+
+/// Value of an HTTP header in lowercased `gh api --include` output
+fn header(output: &str, name: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == name).then(|| value.trim().to_string())
+    })
+}
+
+/// Sleep through `wait` with `sleep`, counting down on stderr once a second when
+/// it is a terminal, or announcing the wait once otherwise
+pub fn countdown(wait: Duration, sleep: fn(Duration)) {
+    let seconds = wait.as_secs().max(1);
+    if !io::stderr().is_terminal() {
+        eprintln!("GitHub rate limit reached; retrying in {}s", seconds);
+        sleep(wait);
+        return;
+    }
+
+    for left in (1..=seconds).rev() {
+        eprint!("\rGitHub rate limit reached; retrying in {}s ", left);
+        let _ = io::stderr().flush();
+        sleep(Duration::from_secs(1));
+    }
+    eprintln!("\rGitHub rate limit reached; retrying now     ");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_reads_rate_limit_messages_and_headers() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            detect(
+                "gh: API rate limit exceeded for user ID 42. (HTTP 403)",
+                now
+            ),
+            Some(DEFAULT_WAIT)
+        );
+        assert_eq!(
+            detect(
+                "HTTP/2.0 403 Forbidden\nX-Ratelimit-Remaining: 0\nX-Ratelimit-Reset: 1700000090\n",
+                now
+            ),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            detect(
+                "Retry-After: 30\n\ngh: You have exceeded a secondary rate limit (HTTP 403)",
+                now
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(detect("gh: Resource not accessible (HTTP 403)", now), None);
+        assert_eq!(
+            detect("X-Ratelimit-Remaining: 4999\nX-Ratelimit-Reset: 1", now),
+            None
+        );
+    }
+}
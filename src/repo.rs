@@ -1,174 +1,213 @@
-use anyhow::{Context, Result};
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use anyhow::Result;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 
-use crate::config::{expand_tilde, Repository};
+use crate::config::expand_tilde;
 
+/// Resolve a path as written by the user or stored in the config:
+/// environment variables are substituted first, then `~` is expanded
 pub fn expand_path(path: &str) -> Result<PathBuf> {
-    let expanded = expand_tilde(path)?;
+    let expanded = expand_tilde(&expand_env_vars(path)?)?;
     Ok(PathBuf::from(expanded))
 }
 
-/// Check repository status
-pub fn check_repository(repo: &Repository) -> Result<bool> {
-    let path = Path::new(&repo.path);
+/// Substitute `$VAR` and `${VAR}` with values from the environment
+pub fn expand_env_vars(path: &str) -> Result<String> {
+    substitute_env_vars(path, &format!("path: {}", path))
+}
 
-    // Check if path exists
-    if !path.exists() {
-        anyhow::bail!("Repository path does not exist: {}", repo.path);
-    }
+/// Substitute `$VAR` and `${VAR}` in `text`; errors say it is `used_in` there
+pub fn substitute_env_vars(text: &str, used_in: &str) -> Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, remaining) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated '${{' in {}", used_in))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        // A lone `$` is kept as is
+        if name.is_empty() {
+            result.push('$');
+            rest = after;
+            continue;
+        }
 
-    // Check if .git directory exists
-    let git_dir = path.join(".git");
-    if !git_dir.exists() {
-        anyhow::bail!("Not a git repository: {}", repo.path);
+        let value = std::env::var(name).map_err(|_| {
+            anyhow::anyhow!(
+                "Environment variable '{}' is not set (used in {})",
+                name,
+                used_in
+            )
+        })?;
+        result.push_str(&value);
+        rest = remaining;
     }
 
-    // Run git status to check for changes
-    let output = Command::new("git")
-        .current_dir(&repo.path)
-        .args(["status", "--porcelain"])
-        .output()
-        .context("Failed to execute git status")?;
+    result.push_str(rest);
+    Ok(result)
+}
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to get git status for repository: {}", repo.path);
+/// Absolute form of a path with symlinks resolved.
+/// Paths that don't exist are normalized lexically instead (`.` and `..` removed).
+pub fn canonical_path(path: &str) -> Result<PathBuf> {
+    let expanded = expand_path(path)?;
+    if let Ok(canonical) = fs::canonicalize(&expanded) {
+        return Ok(canonical);
     }
 
-    // Check if there are changes (non-empty output means changes)
-    let has_changes = !output.stdout.is_empty();
-
-    Ok(has_changes)
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        std::env::current_dir()?.join(expanded)
+    };
+    Ok(normalize_lexically(&absolute))
 }
 
-/// Get current branch name
-pub fn get_current_branch(repo_path: &str) -> Result<String> {
-    let output = Command::new("git")
-        .current_dir(repo_path)
-        .args(["branch", "--show-current"])
-        .output()
-        .context("Failed to get current branch")?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to get current branch for repository: {}", repo_path);
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
     }
-
-    let branch = String::from_utf8(output.stdout)
-        .context("Invalid UTF-8 in branch name")?
-        .trim()
-        .to_string();
-
-    Ok(branch)
+    result
 }
 
-/// Create branch
-pub fn create_branch(repo_path: &str, branch_name: &str, dry_run: bool) -> Result<()> {
-    if dry_run {
-        println!("Would create branch '{}' in {}", branch_name, repo_path);
-        return Ok(());
+/// Whether two paths, as typed by the user or stored in the config, point to the same location
+pub fn same_path(a: &str, b: &str) -> bool {
+    match (canonical_path(a), canonical_path(b)) {
+        (Ok(a), Ok(b)) => paths_equal(&a, &b),
+        _ => a == b,
     }
+}
 
-    println!("Creating branch '{}' in {}", branch_name, repo_path);
-
-    // Save original branch
-    let original_branch = get_current_branch(repo_path)?;
+// The default filesystems on macOS and Windows are case-insensitive
+#[cfg(any(target_os = "macos", windows))]
+fn paths_equal(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
 
-    // Check if branch already exists
-    let output = Command::new("git")
-        .current_dir(repo_path)
-        .args(["branch"])
-        .output()
-        .context("Failed to list branches")?;
+#[cfg(not(any(target_os = "macos", windows)))]
+fn paths_equal(a: &Path, b: &Path) -> bool {
+    a == b
+}
 
-    let branches = String::from_utf8(output.stdout).context("Invalid UTF-8 in branch list")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let branch_exists = branches
-        .lines()
-        .any(|line| line.trim().ends_with(branch_name));
+    #[test]
+    fn same_path_ignores_trailing_slashes_and_dot_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("app")).unwrap();
+        let app = dir.path().join("app").to_string_lossy().to_string();
 
-    if branch_exists {
-        // If branch exists, check out
-        let status = Command::new("git")
-            .current_dir(repo_path)
-            .args(["checkout", branch_name])
-            .status()
-            .context("Failed to checkout existing branch")?;
+        assert!(same_path(&app, &format!("{}/", app)));
+        assert!(same_path(&app, &format!("{}/../app/.", app)));
+        assert!(!same_path(&app, &dir.path().to_string_lossy()));
 
-        if !status.success() {
-            anyhow::bail!("Failed to checkout existing branch: {}", branch_name);
-        }
-    } else {
-        // Create new branch
-        let status = Command::new("git")
-            .current_dir(repo_path)
-            .args(["checkout", "-b", branch_name])
-            .status()
-            .context("Failed to create new branch")?;
-
-        if !status.success() {
-            // Return to original branch on failure
-            let _ = Command::new("git")
-                .current_dir(repo_path)
-                .args(["checkout", &original_branch])
-                .status();
-
-            anyhow::bail!("Failed to create branch: {}", branch_name);
-        }
+        // Paths that don't exist yet are compared lexically
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+        assert!(same_path(&missing, &format!("{}/../missing/", missing)));
     }
 
-    Ok(())
-}
+    #[cfg(unix)]
+    #[test]
+    fn same_path_resolves_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("app")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("app"), dir.path().join("link")).unwrap();
+
+        assert!(same_path(
+            &dir.path().join("app").to_string_lossy(),
+            &dir.path().join("link").to_string_lossy()
+        ));
+    }
 
-/// Return to original branch
-pub fn checkout_original_branch(
-    repo_path: &str,
-    original_branch: &str,
-    dry_run: bool,
-) -> Result<()> {
-    if dry_run {
-        println!(
-            "Would checkout original branch '{}' in {}",
-            original_branch, repo_path
-        );
-        return Ok(());
+    #[test]
+    fn same_path_expands_tilde() {
+        let home = dirs::home_dir().unwrap();
+        let absolute = home
+            .join("mru-test-missing/app")
+            .to_string_lossy()
+            .to_string();
+
+        assert!(same_path("~/mru-test-missing/app", &absolute));
+        assert!(same_path("~/mru-test-missing/app/", &absolute));
+        assert_eq!(expand_path("~").unwrap(), home);
     }
 
-    println!(
-        "Checking out original branch '{}' in {}",
-        original_branch, repo_path
-    );
+    #[test]
+    fn expand_path_substitutes_environment_variables() {
+        std::env::set_var("MRU_TEST_WORKSPACE", "/srv/work");
 
-    let status = Command::new("git")
-        .current_dir(repo_path)
-        .args(["checkout", original_branch])
-        .status()
-        .context("Failed to checkout original branch")?;
+        assert_eq!(
+            expand_path("$MRU_TEST_WORKSPACE/payments-api").unwrap(),
+            PathBuf::from("/srv/work/payments-api")
+        );
+        assert_eq!(
+            expand_path("${MRU_TEST_WORKSPACE}-old/app").unwrap(),
+            PathBuf::from("/srv/work-old/app")
+        );
+        assert_eq!(
+            expand_path("/tmp/a$/b").unwrap(),
+            PathBuf::from("/tmp/a$/b")
+        );
 
-    if !status.success() {
-        anyhow::bail!("Failed to checkout original branch: {}", original_branch);
+        let err = expand_path("$MRU_TEST_UNDEFINED/app").unwrap_err();
+        assert!(err.to_string().contains("MRU_TEST_UNDEFINED"));
+        assert!(expand_path("${MRU_TEST_WORKSPACE/app").is_err());
     }
 
-    Ok(())
-}
-
-/// Pull repository
-pub fn pull_repository(repo_path: &str, dry_run: bool) -> Result<()> {
-    if dry_run {
-        println!("Would pull latest changes in {}", repo_path);
-        return Ok(());
+    #[cfg(unix)]
+    #[test]
+    fn expand_path_resolves_other_users_home() {
+        let root_home = expand_path("~root").unwrap();
+        assert!(root_home.is_absolute());
+        assert_eq!(
+            expand_path("~root/apps/x").unwrap(),
+            root_home.join("apps/x")
+        );
+        assert!(expand_path("~mru-no-such-user/apps").is_err());
     }
 
-    println!("Pulling latest changes in {}", repo_path);
-
-    let status = Command::new("git")
-        .current_dir(repo_path)
-        .args(["pull"])
-        .status()
-        .context("Failed to pull repository")?;
-
-    if !status.success() {
-        anyhow::bail!("Failed to pull repository: {}", repo_path);
+    #[cfg(windows)]
+    #[test]
+    fn same_path_handles_drive_letters_and_backslashes() {
+        assert!(same_path(
+            r"C:\mru-test-missing\app\",
+            "C:/mru-test-missing/app"
+        ));
+        assert!(same_path(
+            r"c:\MRU-test-missing\App",
+            r"C:\mru-test-missing\app"
+        ));
+        assert!(same_path(
+            r"C:\mru-test-missing\other\..\app",
+            r"C:\mru-test-missing\app"
+        ));
+        assert!(!same_path(
+            r"C:\mru-test-missing\app",
+            r"D:\mru-test-missing\app"
+        ));
+
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path(r"~\work\app").unwrap(), home.join(r"work\app"));
     }
-
-    Ok(())
 }
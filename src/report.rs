@@ -0,0 +1,236 @@
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::campaign::{self, RepoState, RepoStatus};
+use crate::context::repo_name;
+use crate::history::HistoryRecord;
+use crate::timing::format_duration;
+
+/// Format of the run report written with `--report`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// The history record, as `mru history --json` prints it
+    #[default]
+    Json,
+    /// A document to share: metadata, a table of repositories and the failures
+    Markdown,
+}
+
+/// Render the run report in `format`
+pub fn render(record: &HistoryRecord, format: ReportFormat) -> Result<String> {
+    match format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(record)?),
+        ReportFormat::Markdown => Ok(markdown(record)),
+    }
+}
+
+/// Write the run report to `path`, or to stdout for `-`
+pub fn write(record: &HistoryRecord, format: ReportFormat, path: &str) -> Result<()> {
+    let report = render(record, format)?;
+    if path == "-" {
+        println!("{}", report);
+        return Ok(());
+    }
+    std::fs::write(path, report + "\n")
+        .with_context(|| format!("Failed to write report: {}", path))?;
+    println!("Report written to {}", path);
+    Ok(())
+}
+
+/// Markdown document of a run: metadata (with the failure policy and why the run
+/// stopped, if it did), the provenance check, one table row per repository, the error
+/// of each failed repository in a code block, the package managers the installs ran
+/// with, and the repositories' warnings
+pub fn markdown(record: &HistoryRecord) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# mru {}: {} → {}\n",
+        record.command, record.package, record.version
+    );
+    let _ = writeln!(out, "- Campaign: `{}`", record.id);
+    if let Some(profile) = &record.profile {
+        let _ = writeln!(out, "- Profile: `{}`", profile);
+    }
+    let _ = writeln!(
+        out,
+        "- Started: {}",
+        campaign::format_timestamp(record.timestamp)
+    );
+    if let Some(millis) = record.duration_ms {
+        let _ = writeln!(out, "- Duration: {}", format_duration(millis));
+    }
+    let _ = writeln!(out, "- Outcome: {}", record.outcome());
+    let _ = writeln!(out, "- Summary: {}", crate::notify::summary(record));
+    if let Some(on_error) = record.on_error {
+        let _ = writeln!(out, "- On error: {}", on_error.label());
+    }
+    if let Some(stopped) = &record.stopped {
+        let _ = writeln!(out, "- Stopped: {}", stopped);
+    }
+    if let Some(check) = &record.provenance {
+        let lines: Vec<String> = check.lines().iter().map(|l| format!("- {}", l)).collect();
+        let _ = writeln!(out, "\n## Provenance\n\n{}", lines.join("\n"));
+    }
+
+    let _ = writeln!(
+        out,
+        "\n## Repositories\n\n| Repository | Version | Pull request | Status |\n| --- | --- | --- | --- |"
+    );
+    for repo in &record.repos {
+        let name = if repo.ad_hoc {
+            format!("{} (ad hoc)", repo_name(&repo.path))
+        } else {
+            repo_name(&repo.path)
+        };
+        let version = match &repo.old_version {
+            Some(old) => format!(
+                "{} → {}",
+                old,
+                repo.target_version.as_deref().unwrap_or(&record.version)
+            ),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} |",
+            cell(&name),
+            cell(&version),
+            repo.pr_url.as_deref().map(pr_link).unwrap_or_default(),
+            repo.status.label()
+        );
+    }
+
+    let failed: Vec<&RepoState> = record
+        .repos
+        .iter()
+        .filter(|r| r.status == RepoStatus::Failed)
+        .collect();
+    if !failed.is_empty() {
+        let _ = writeln!(out, "\n## Failures");
+        for repo in failed {
+            let details: Vec<&str> = repo
+                .error
+                .iter()
+                .chain(&repo.output)
+                .map(String::as_str)
+                .collect();
+            let _ = writeln!(
+                out,
+                "\n### {}\n\n```\n{}\n```",
+                repo_name(&repo.path),
+                details.join("\n")
+            );
+        }
+    }
+
+    let managers: Vec<String> = record
+        .repos
+        .iter()
+        .filter(|repo| !repo.package_managers.is_empty())
+        .map(|repo| {
+            format!(
+                "- {}: {}",
+                repo_name(&repo.path),
+                repo.package_managers.join(", ")
+            )
+        })
+        .collect();
+    if !managers.is_empty() {
+        let _ = writeln!(out, "\n## Package managers\n\n{}", managers.join("\n"));
+    }
+
+    let warnings: Vec<String> = record
+        .repos
+        .iter()
+        .flat_map(|repo| {
+            let name = repo_name(&repo.path);
+            repo.warnings
+                .iter()
+                .map(move |warning| format!("- {}: {}", name, warning))
+        })
+        .collect();
+    if !warnings.is_empty() {
+        let _ = writeln!(out, "\n## Warnings\n\n{}", warnings.join("\n"));
+    }
+    out.trim_end().to_string()
+}
+
+/// `[#12](url)` for a GitHub pull request URL, or the bare URL otherwise
+fn pr_link(url: &str) -> String {
+    match url.rsplit_once("/pull/") {
+        Some((_, number)) if number.chars().all(|c| c.is_ascii_digit()) => {
+            format!("[#{}]({})", number, url)
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Table cell text, with characters that would break the table escaped
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::OnError;
+
+    fn repo(path: &str, status: RepoStatus) -> RepoState {
+        RepoState {
+            path: path.to_string(),
+            status,
+            error: None,
+            output: None,
+            pr_url: None,
+            old_version: None,
+            original_branch: None,
+            fast_forwarded: None,
+            diffstat: None,
+            duration_ms: None,
+            steps: Vec::new(),
+            batch: None,
+            warnings: Vec::new(),
+            milestone: None,
+            project: None,
+            license: None,
+            target_version: None,
+            resolve_preview: None,
+            package_managers: Vec::new(),
+            ad_hoc: false,
+            scanned_version: None,
+        }
+    }
+
+    #[test]
+    fn markdown_matches_golden_file() {
+        let mut web = repo("~/work/web", RepoStatus::Completed);
+        web.old_version = Some("^4.17.20".to_string());
+        web.pr_url = Some("https://github.com/acme/web/pull/12".to_string());
+        let mut api = repo("~/work/api", RepoStatus::Failed);
+        api.old_version = Some(">=4 <5 || 4.x".to_string());
+        api.error = Some("git push failed".to_string());
+        api.output = Some("! [rejected] update-lodash (fetch first)".to_string());
+        let record = HistoryRecord {
+            id: "20240301-120000".to_string(),
+            command: "update".to_string(),
+            timestamp: 1_709_294_400,
+            package: "lodash".to_string(),
+            version: "4.17.21".to_string(),
+            repos: vec![web, api, repo("~/work/docs", RepoStatus::Skipped)],
+            duration_ms: Some(125_000),
+            provenance: None,
+            profile: Some("work".to_string()),
+            on_error: Some(OnError::Abort),
+            stopped: Some("after ~/work/api failed (--on-error abort)".to_string()),
+        };
+
+        assert_eq!(
+            markdown(&record),
+            include_str!("testdata/report.md").trim_end()
+        );
+    }
+}
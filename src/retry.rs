@@ -0,0 +1,288 @@
+use anyhow::Result;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::runner::TimeoutError;
+
+/// Default number of attempts for network operations
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// Error messages that indicate a failure worth retrying
+const TRANSIENT_MARKERS: &[&str] = &[
+    "could not resolve host",
+    "connection timed out",
+    "connection reset",
+    "connection refused",
+    "operation timed out",
+    "timed out",
+    "early eof",
+    "the remote end hung up unexpectedly",
+    "rpc failed",
+    "network is unreachable",
+    "temporarily unavailable",
+    "tls handshake",
+    "bad gateway",
+    "service unavailable",
+    "gateway timeout",
+];
+
+/// Error messages that must never be retried, even if they also look transient
+const PERMANENT_MARKERS: &[&str] = &[
+    "authentication failed",
+    "permission denied",
+    "could not read username",
+    "merge conflict",
+    "non-fast-forward",
+    "[rejected]",
+];
+
+/// HTTP statuses worth retrying, and ones that must never be, as found by `http_statuses`
+const TRANSIENT_STATUSES: &[u16] = &[502, 503, 504];
+const PERMANENT_STATUSES: &[u16] = &[401, 403, 409];
+
+/// Words an HTTP status follows in git's and gh's errors, e.g. "The requested URL
+/// returned error: 503" or "HTTP 502: Bad Gateway"
+const STATUS_PREFIXES: &[&str] = &["http ", "returned error: ", "status ", "status code "];
+
+/// Longest wait between two attempts, however many there are
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Retry policy with exponential backoff and jitter
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: DEFAULT_ATTEMPTS,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(attempts: u32) -> Self {
+        RetryPolicy {
+            attempts: attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Run `op` until it succeeds, fails permanently, or runs out of attempts
+    pub fn run<T>(&self, what: &str, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    // A timed out command already waited its full budget
+                    let transient = e.downcast_ref::<TimeoutError>().is_none()
+                        && is_transient(&format!("{:#}", e));
+                    if transient && attempt < self.attempts {
+                        let delay = self.delay(attempt);
+                        eprintln!(
+                            "Attempt {}/{} of '{}' failed: {}. Retrying in {:.1}s",
+                            attempt,
+                            self.attempts,
+                            what,
+                            first_line(&format!("{:#}", e)),
+                            delay.as_secs_f32()
+                        );
+                        thread::sleep(delay);
+                        attempt += 1;
+                        continue;
+                    }
+
+                    if transient {
+                        return Err(
+                            e.context(format!("'{}' failed after {} attempt(s)", what, attempt))
+                        );
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_DELAY);
+        let jitter_range = (backoff.as_millis() as u64 / 2).max(1);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(0);
+        backoff + Duration::from_millis(nanos % jitter_range)
+    }
+}
+
+/// Whether an error message describes a transient (retryable) failure
+pub fn is_transient(message: &str) -> bool {
+    let message = message.to_lowercase();
+    let statuses = http_statuses(&message);
+    if PERMANENT_MARKERS.iter().any(|m| message.contains(m))
+        || statuses.iter().any(|s| PERMANENT_STATUSES.contains(s))
+    {
+        return false;
+    }
+    TRANSIENT_MARKERS.iter().any(|m| message.contains(m))
+        || statuses.iter().any(|s| TRANSIENT_STATUSES.contains(s))
+}
+
+/// HTTP statuses in a lowercased error message: numbers right after one of
+/// `STATUS_PREFIXES` or a status line's `http/1.1 `, or anywhere on a line the server
+/// sent (`remote: ...`). Numbers inside SHAs, URLs and branch names aren't statuses
+fn http_statuses(message: &str) -> Vec<u16> {
+    let mut statuses = Vec::new();
+    for line in message.lines() {
+        let line = line.trim();
+        if let Some(remote) = line.strip_prefix("remote:") {
+            let words = remote.split(|c: char| !c.is_ascii_alphanumeric());
+            statuses.extend(words.filter_map(status));
+            continue;
+        }
+        for prefix in STATUS_PREFIXES {
+            statuses.extend(
+                line.match_indices(prefix)
+                    .filter_map(|(i, _)| status_at(&line[i + prefix.len()..])),
+            );
+        }
+        statuses.extend(line.match_indices("http/").filter_map(|(i, _)| {
+            let version =
+                line[i + 5..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+            status_at(version.strip_prefix(' ')?)
+        }));
+    }
+    statuses
+}
+
+/// The HTTP status `text` starts with, if any
+fn status_at(text: &str) -> Option<u16> {
+    status(text.split(|c: char| !c.is_ascii_alphanumeric()).next()?)
+}
+
+/// A three-digit word as an HTTP error status
+fn status(word: &str) -> Option<u16> {
+    let code: u16 = word.parse().ok().filter(|_| word.len() == 3)?;
+    (400..600).contains(&code).then_some(code)
+}
+
+fn first_line(message: &str) -> &str {
+    message.lines().next().unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            base_delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn status_codes_count_only_where_http_reports_them() {
+        assert!(is_transient(
+            "fatal: unable to access 'https://github.com/acme/app/': The requested URL returned error: 503"
+        ));
+        assert!(is_transient(
+            "HTTP 502: Bad Gateway (https://api.github.com/graphql)"
+        ));
+        assert!(is_transient("remote: Internal error 504"));
+        assert!(!is_transient(
+            "fatal: unable to access 'https://github.com/acme/app/': The requested URL returned error: 403"
+        ));
+        assert!(!is_transient(
+            "HTTP 401: Bad credentials (https://api.github.com/user)"
+        ));
+        assert!(!is_transient(
+            "! [rejected] main -> main (non-fast-forward)"
+        ));
+
+        // Numbers in URLs, SHAs and branch names are no statuses, nor is "conflict" in a name
+        assert!(is_transient(
+            "fatal: unable to access 'https://git.example.com/team-401/app.git/': Connection reset by peer"
+        ));
+        assert!(is_transient(
+            "fatal: early EOF while fetching 9f403ab1 for fix-merge-conflict-ui"
+        ));
+        assert!(!is_transient("pushed 5034a9c to update-react-503"));
+
+        assert_eq!(
+            http_statuses("the requested url returned error: 503"),
+            [503]
+        );
+        assert_eq!(http_statuses("< http/1.1 502 bad gateway"), [502]);
+        assert_eq!(http_statuses("remote: error: 403 forbidden"), [403]);
+        assert!(http_statuses("https://x.example.com/org-503/app 4031a9c 40391").is_empty());
+    }
+
+    #[test]
+    fn only_transient_failures_are_retried() {
+        let calls = Cell::new(0);
+        let count = || calls.set(calls.get() + 1);
+
+        let result: Result<()> = policy(3).run("push", || {
+            count();
+            anyhow::bail!("HTTP 403: Forbidden")
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let err = policy(3)
+            .run("push", || -> Result<()> {
+                count();
+                anyhow::bail!("HTTP 502: Bad Gateway")
+            })
+            .unwrap_err();
+        assert_eq!(calls.get(), 3);
+        assert!(format!("{:#}", err).starts_with("'push' failed after 3 attempt(s)"));
+
+        calls.set(0);
+        let value = policy(3)
+            .run("fetch", || {
+                count();
+                if calls.get() < 2 {
+                    anyhow::bail!("Connection reset by peer");
+                }
+                Ok(7)
+            })
+            .unwrap();
+        assert_eq!((value, calls.get()), (7, 2));
+
+        // A command that timed out already used its whole budget
+        calls.set(0);
+        let result: Result<()> = policy(3).run("clone", || {
+            count();
+            Err(TimeoutError {
+                command: "git clone".to_string(),
+                cwd: None,
+                timeout: Duration::from_secs(120),
+            }
+            .into())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy {
+            attempts: 40,
+            base_delay: Duration::from_secs(1),
+        };
+        let first = policy.delay(1);
+        assert!(first >= Duration::from_secs(1) && first < Duration::from_millis(1500));
+        let last = policy.delay(40);
+        assert!(last >= MAX_DELAY && last < MAX_DELAY + MAX_DELAY / 2);
+    }
+}
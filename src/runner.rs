@@ -0,0 +1,492 @@
+use anyhow::{Context as _, Result};
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Description of an external command to run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    /// Arguments as the OS takes them, so names git reported as raw bytes round-trip
+    pub args: Vec<OsString>,
+    pub cwd: Option<PathBuf>,
+    pub envs: Vec<(String, String)>,
+    pub timeout: Option<Duration>,
+}
+
+impl CommandSpec {
+    pub fn new(program: &str) -> Self {
+        CommandSpec {
+            program: program.to_string(),
+            args: Vec::new(),
+            cwd: None,
+            envs: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.cwd = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.envs.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set each of `vars`, e.g. the repository's configured `env`
+    pub fn envs(mut self, vars: &[(String, String)]) -> Self {
+        self.envs.extend(vars.iter().cloned());
+        self
+    }
+
+    /// Kill the command (and its children) if it runs longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Command line as a single string (program followed by arguments), with bytes
+    /// that aren't UTF-8 escaped
+    pub fn display(&self) -> String {
+        let mut line = self.program.clone();
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&escape_os_str(arg));
+        }
+        line
+    }
+
+    fn to_command(&self) -> Command {
+        let mut command = Command::new(executable(&self.program));
+        command.args(&self.args);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        for (key, value) in &self.envs {
+            command.env(key, value);
+        }
+        // Run in its own process group so a timeout or a second Ctrl-C can kill the
+        // whole tree, and the first Ctrl-C at the terminal doesn't reach it
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        command
+    }
+}
+
+/// Executable to spawn for `program`. On Windows the Node package managers are
+/// `.cmd` shims, which `Command` doesn't find without the extension.
+fn executable(program: &str) -> String {
+    if cfg!(windows) && matches!(program, "npm" | "npx" | "yarn" | "pnpm") {
+        format!("{}.cmd", program)
+    } else {
+        program.to_string()
+    }
+}
+
+/// Text for `bytes` with every byte that isn't part of valid UTF-8 written as `\xNN`,
+/// so logs and error messages show exactly what a command printed
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    text
+}
+
+/// [`escape_bytes`] for an argument or path
+pub fn escape_os_str(value: &OsStr) -> String {
+    match value.to_str() {
+        Some(text) => text.to_string(),
+        #[cfg(unix)]
+        None => escape_bytes(std::os::unix::ffi::OsStrExt::as_bytes(value)),
+        #[cfg(not(unix))]
+        None => value.to_string_lossy().into_owned(),
+    }
+}
+
+/// A command exceeded its timeout and was killed
+#[derive(Debug)]
+pub struct TimeoutError {
+    pub command: String,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' timed out after {}s",
+            self.command,
+            self.timeout.as_secs()
+        )?;
+        if let Some(cwd) = &self.cwd {
+            write!(f, " in {}", cwd.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Result of running an external command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// Captured stderr, or stdout if stderr is empty, for failure reports
+    pub fn tail(&self) -> String {
+        let stream = if self.stderr.is_empty() {
+            &self.stdout
+        } else {
+            &self.stderr
+        };
+        escape_bytes(stream).trim_end().to_string()
+    }
+
+    /// Captured stdout followed by stderr, for reports of commands that succeeded
+    pub fn combined_tail(&self) -> String {
+        let stdout = escape_bytes(&self.stdout);
+        let stderr = escape_bytes(&self.stderr);
+        format!("{}\n{}", stdout.trim_end(), stderr.trim_end())
+            .trim()
+            .to_string()
+    }
+
+    #[cfg(test)]
+    pub fn ok(stdout: &str) -> Self {
+        CommandOutput {
+            code: Some(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn failed(stderr: &str) -> Self {
+        CommandOutput {
+            code: Some(1),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Runs external commands (git, gh, package managers).
+/// Runners are shared between threads when repositories are processed concurrently.
+pub trait CommandRunner: Send + Sync {
+    /// Run the command and capture its stdout and stderr
+    fn output(&self, cmd: &CommandSpec) -> Result<CommandOutput>;
+
+    /// Run the command, printing each output line prefixed with `[prefix]` unless `quiet`.
+    /// The returned output holds the last lines of stdout and stderr.
+    fn stream(&self, cmd: &CommandSpec, prefix: &str, quiet: bool) -> Result<CommandOutput>;
+}
+
+/// Number of trailing output lines kept from a streamed command for failure reports
+pub const TAIL_LINES: usize = 20;
+
+/// Runner that spawns real processes
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, cmd: &CommandSpec) -> Result<CommandOutput> {
+        let mut command = cmd.to_command();
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to execute {}", cmd.program))?;
+
+        // Drain the pipes on separate threads so a chatty child cannot block on a full pipe
+        let stdout = child.stdout.take().map(read_to_end);
+        let stderr = child.stderr.take().map(read_to_end);
+
+        let code = wait(cmd, &mut child)?;
+
+        Ok(CommandOutput {
+            code,
+            stdout: stdout.and_then(|h| h.join().ok()).unwrap_or_default(),
+            stderr: stderr.and_then(|h| h.join().ok()).unwrap_or_default(),
+        })
+    }
+
+    fn stream(&self, cmd: &CommandSpec, prefix: &str, quiet: bool) -> Result<CommandOutput> {
+        let mut command = cmd.to_command();
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to execute {}", cmd.program))?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .map(|pipe| stream_lines(pipe, prefix.to_string(), quiet, false));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|pipe| stream_lines(pipe, prefix.to_string(), quiet, true));
+
+        let code = wait(cmd, &mut child)?;
+
+        Ok(CommandOutput {
+            code,
+            stdout: stdout.and_then(|h| h.join().ok()).unwrap_or_default(),
+            stderr: stderr.and_then(|h| h.join().ok()).unwrap_or_default(),
+        })
+    }
+}
+
+/// Echo complete lines from `reader` with a `[prefix]`, returning the last `TAIL_LINES` lines
+fn stream_lines<R: Read + Send + 'static>(
+    reader: R,
+    prefix: String,
+    quiet: bool,
+    to_stderr: bool,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut tail: VecDeque<String> = VecDeque::new();
+        let mut line = Vec::new();
+
+        while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+            let text = escape_bytes(&line).trim_end().to_string();
+            line.clear();
+
+            if !quiet {
+                // A single print call keeps each line whole when streams interleave
+                if to_stderr {
+                    eprintln!("[{}] {}", prefix, text);
+                } else {
+                    println!("[{}] {}", prefix, text);
+                }
+            }
+
+            if tail.len() == TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(text);
+        }
+
+        let mut out = Vec::from(tail).join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.into_bytes()
+    })
+}
+
+fn read_to_end<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// Children being waited for, which a second Ctrl-C kills
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Kill the process group of every command running now
+pub fn kill_running() {
+    for &pid in RUNNING.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        kill_group(pid);
+    }
+}
+
+/// Wait for the child, killing its process group if the command's timeout expires
+fn wait(cmd: &CommandSpec, child: &mut Child) -> Result<Option<i32>> {
+    let pid = child.id();
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner()).push(pid);
+    let code = wait_for(cmd, child);
+    RUNNING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|&running| running != pid);
+    code
+}
+
+fn wait_for(cmd: &CommandSpec, child: &mut Child) -> Result<Option<i32>> {
+    let Some(timeout) = cmd.timeout else {
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", cmd.program))?;
+        return Ok(status.code());
+    };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("Failed to wait for {}", cmd.program))?
+        {
+            return Ok(status.code());
+        }
+
+        if Instant::now() >= deadline {
+            kill_tree(child);
+            let _ = child.wait();
+            return Err(TimeoutError {
+                command: cmd.display(),
+                cwd: cmd.cwd.clone(),
+                timeout,
+            }
+            .into());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn kill_tree(child: &mut Child) {
+    kill_group(child.id());
+    let _ = child.kill();
+}
+
+fn kill_group(pid: u32) {
+    #[cfg(unix)]
+    {
+        // The child leads its own process group (see `to_command`)
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Runner that records every command and answers with canned outputs.
+/// Commands without a matching response succeed with empty output.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockRunner {
+    calls: Mutex<Vec<CommandSpec>>,
+    responses: Vec<(String, CommandOutput)>,
+    /// Responses used up by the first matching command, in order
+    once: Mutex<Vec<(String, CommandOutput)>>,
+}
+
+#[cfg(test)]
+impl MockRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond with `output` to any command line starting with `prefix`
+    pub fn respond(mut self, prefix: &str, output: CommandOutput) -> Self {
+        self.responses.push((prefix.to_string(), output));
+        self
+    }
+
+    /// Respond with `output` to the next command line starting with `prefix` only.
+    /// One-time responses take precedence over those added with `respond`.
+    pub fn respond_once(self, prefix: &str, output: CommandOutput) -> Self {
+        self.once.lock().unwrap().push((prefix.to_string(), output));
+        self
+    }
+
+    /// Commands run so far, in order, with their environment
+    pub fn commands(&self) -> Vec<CommandSpec> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Command lines run so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.display())
+            .collect()
+    }
+
+    fn answer(&self, cmd: &CommandSpec) -> CommandOutput {
+        self.calls.lock().unwrap().push(cmd.clone());
+        let line = cmd.display();
+        let mut once = self.once.lock().unwrap();
+        if let Some(i) = once
+            .iter()
+            .position(|(prefix, _)| line.starts_with(prefix.as_str()))
+        {
+            return once.remove(i).1;
+        }
+        self.responses
+            .iter()
+            .find(|(prefix, _)| line.starts_with(prefix.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or_else(|| CommandOutput::ok(""))
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for MockRunner {
+    fn output(&self, cmd: &CommandSpec) -> Result<CommandOutput> {
+        Ok(self.answer(cmd))
+    }
+
+    fn stream(&self, cmd: &CommandSpec, _prefix: &str, _quiet: bool) -> Result<CommandOutput> {
+        Ok(self.answer(cmd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn system_runner_kills_command_after_timeout() {
+        let start = Instant::now();
+        let result = SystemRunner.output(
+            &CommandSpec::new("sh")
+                .args(["-c", "sleep 5"])
+                .timeout(Duration::from_millis(200)),
+        );
+
+        let err = result.unwrap_err();
+        assert!(err.downcast_ref::<TimeoutError>().is_some());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
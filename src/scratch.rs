@@ -0,0 +1,204 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+use crate::campaign;
+use crate::config::Config;
+use crate::lock::process_gone;
+use crate::repo::expand_path;
+
+/// Name prefix of the campaign directories in `tmp_root`
+const PREFIX: &str = "mru-";
+
+/// File in a campaign directory naming the run it belongs to
+const MARKER: &str = ".mru-campaign";
+
+/// Disk space a campaign expects to use per repository unless `tmp_budget_mb` is set
+pub const DEFAULT_BUDGET_MB: u64 = 100;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Marker {
+    campaign: String,
+    pid: u32,
+    started: u64,
+}
+
+/// Directory a campaign keeps its temporary copies of repositories in. It is removed with
+/// everything in it when dropped, so also when the run fails or is interrupted; a crash
+/// leaves it to `mru cache clean --tmp`.
+#[derive(Debug)]
+pub struct ScratchDir {
+    dir: TempDir,
+}
+
+impl ScratchDir {
+    pub fn create(root: &Path, campaign_id: &str) -> Result<Self> {
+        fs::create_dir_all(root)
+            .with_context(|| format!("Failed to create tmp_root {}", root.display()))?;
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", PREFIX, campaign_id))
+            .tempdir_in(root)
+            .with_context(|| {
+                format!(
+                    "Failed to create a temporary directory in {}",
+                    root.display()
+                )
+            })?;
+        let marker = Marker {
+            campaign: campaign_id.to_string(),
+            pid: std::process::id(),
+            started: campaign::now(),
+        };
+        fs::write(dir.path().join(MARKER), serde_json::to_string(&marker)?)
+            .context("Failed to write the campaign marker")?;
+        Ok(ScratchDir { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// `tmp_root` from the config, or the system temp directory
+pub fn root(config: &Config) -> Result<PathBuf> {
+    match &config.tmp_root {
+        Some(path) => expand_path(path),
+        None => Ok(std::env::temp_dir()),
+    }
+}
+
+/// Fail if the disk holding `root` has less free space than `repos` repositories are
+/// expected to use (`tmp_budget_mb` each), and warn if it has less than twice that
+pub fn check_space(config: &Config, root: &Path, repos: usize) -> Result<()> {
+    let budget = config.tmp_budget_mb.unwrap_or(DEFAULT_BUDGET_MB);
+    let needed = budget.saturating_mul(repos as u64);
+    // `root` is created when the campaign starts
+    let Some(available) = root
+        .ancestors()
+        .find(|dir| dir.exists())
+        .and_then(available_space)
+        .map(|bytes| bytes / MB)
+    else {
+        return Ok(());
+    };
+
+    if available < needed {
+        anyhow::bail!(
+            "Not enough disk space for temporary copies in {}: {} MB free, about {} MB needed \
+             ({} repositories × tmp_budget_mb {}). Free up space, or set tmp_root to a larger disk",
+            root.display(),
+            available,
+            needed,
+            repos,
+            budget
+        );
+    }
+    if available < needed.saturating_mul(2) {
+        eprintln!(
+            "Warning: {} MB free in {}, close to the {} MB the temporary copies may take",
+            available,
+            root.display(),
+            needed
+        );
+    }
+    Ok(())
+}
+
+/// Bytes available to this user on the file system holding `path`
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn available_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only writes into `stat`, and `path` is NUL-terminated
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Campaign directories in `root` left behind by runs that are no longer going. Only
+/// directories with a campaign marker are considered.
+pub fn leftovers(root: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX))
+        })
+        .filter(|path| {
+            fs::read_to_string(path.join(MARKER))
+                .ok()
+                .and_then(|content| serde_json::from_str::<Marker>(&content).ok())
+                .is_some_and(|marker| process_gone(marker.pid, marker.started))
+        })
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratch_dirs_are_removed_and_leftovers_found_by_their_marker() {
+        let root = tempfile::tempdir().unwrap();
+        let scratch = ScratchDir::create(root.path(), "update-lodash-1").unwrap();
+        fs::write(scratch.path().join("package.json"), "{}").unwrap();
+        let path = scratch.path().to_path_buf();
+        assert!(path.starts_with(root.path()));
+        // Still running
+        assert!(leftovers(root.path()).is_empty());
+        drop(scratch);
+        assert!(!path.exists());
+
+        // Not ours without a marker
+        fs::create_dir(root.path().join("mru-other")).unwrap();
+        #[cfg(unix)]
+        {
+            let crashed = root.path().join("mru-update-react-2-abc");
+            fs::create_dir(&crashed).unwrap();
+            let marker = Marker {
+                campaign: "update-react-2".to_string(),
+                pid: i32::MAX as u32,
+                started: 0,
+            };
+            fs::write(
+                crashed.join(MARKER),
+                serde_json::to_string(&marker).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(leftovers(root.path()), vec![crashed]);
+        }
+
+        let config = Config {
+            tmp_budget_mb: Some(u64::MAX / 2),
+            ..Default::default()
+        };
+        let result = check_space(&config, &root.path().join("new"), 3);
+        #[cfg(unix)]
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("Not enough disk space"));
+        #[cfg(not(unix))]
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,478 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{self, Config, Repository};
+use crate::context::repo_name;
+use crate::github::normalize_github_url;
+use crate::repo::{expand_path, same_path};
+
+/// Variable shared configs put in front of repository paths, e.g. `$WORKSPACE/web`
+pub const WORKSPACE_VAR: &str = "WORKSPACE";
+
+/// Side of a conflict `config import` keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Side {
+    Local,
+    Imported,
+}
+
+/// A repository, policy or setting that both configs have, with different values
+#[derive(Debug)]
+pub struct Conflict {
+    /// e.g. `repository ~/work/web` or `setting milestone`
+    pub what: String,
+    pub local: String,
+    pub imported: String,
+}
+
+/// Config to share, and the repositories that couldn't be put in it
+pub struct Export {
+    pub config: Config,
+    /// Repositories outside the workspace without a `github_url` to clone them from
+    pub skipped: Vec<String>,
+}
+
+/// What `merge` did to the local config
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub kept: Vec<String>,
+}
+
+/// Deepest directory all repositories are in, the default workspace of `config export`
+pub fn common_root(config: &Config) -> Option<PathBuf> {
+    let mut parents = config
+        .repositories
+        .iter()
+        .filter_map(|repo| Some(expand_path(&repo.path).ok()?.parent()?.to_path_buf()));
+    let mut root = parents.next()?;
+    for parent in parents {
+        while !parent.starts_with(&root) {
+            root = root.parent()?.to_path_buf();
+        }
+    }
+    Some(root)
+}
+
+/// Shareable copy of `config`: repository paths relative to `$WORKSPACE` (repositories
+/// elsewhere are placed at `$WORKSPACE/<name>` to be cloned from their `github_url`),
+/// without the log file and `tmp_root`. `strip_local` also drops what is tuned to this machine:
+/// environment variables, retries, timeouts and GitHub concurrency.
+pub fn export(config: &Config, workspace: &Path, strip_local: bool) -> Export {
+    let mut shared = config.clone();
+    shared.file = None;
+    shared.local_file = None;
+    shared.log_file = None;
+    shared.tmp_root = None;
+    shared.replace_repositories = false;
+    if strip_local {
+        shared.env.clear();
+        shared.retries = None;
+        shared.install_timeout = None;
+        shared.git_timeout = None;
+        shared.gh_timeout = None;
+        shared.registry_timeout = None;
+        shared.github_concurrency = None;
+    }
+
+    let mut skipped = Vec::new();
+    shared.repositories = Vec::new();
+    for repo in &config.repositories {
+        let relative = expand_path(&repo.path)
+            .ok()
+            .and_then(|path| relative_to(&path, workspace));
+        let relative = match (relative, &repo.github_url) {
+            (Some(relative), _) => relative,
+            (None, Some(_)) => repo_name(&repo.path),
+            (None, None) => {
+                skipped.push(repo.path.clone());
+                continue;
+            }
+        };
+        let mut repo = repo.clone();
+        repo.path = format!("${}/{}", WORKSPACE_VAR, relative);
+        repo.local = false;
+        if strip_local {
+            repo.env.clear();
+        }
+        shared.repositories.push(repo);
+    }
+    for policy in &mut shared.policies {
+        policy.local = false;
+    }
+
+    Export {
+        config: shared,
+        skipped,
+    }
+}
+
+/// `path` below `dir` with `/` separators, e.g. `libs/ui`
+fn relative_to(path: &Path, dir: &Path) -> Option<String> {
+    let parts: Vec<String> = path
+        .strip_prefix(dir)
+        .ok()?
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Put `root` in place of `$WORKSPACE` in the repository paths of a shared config.
+/// Without a root the paths are kept as written, which needs `WORKSPACE` set.
+pub fn resolve_paths(shared: &mut Config, root: Option<&str>) -> Result<()> {
+    for repo in &mut shared.repositories {
+        let Some(rest) = workspace_relative(&repo.path) else {
+            continue;
+        };
+        match root {
+            Some(root) => repo.path = format!("{}/{}", root.trim_end_matches('/'), rest),
+            None if std::env::var_os(WORKSPACE_VAR).is_some() => {}
+            None => anyhow::bail!(
+                "{} is relative to ${}; pass --root with the directory the repositories live in",
+                repo.path,
+                WORKSPACE_VAR
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn workspace_relative(path: &str) -> Option<&str> {
+    let rest = path
+        .strip_prefix('$')?
+        .strip_prefix(WORKSPACE_VAR)
+        .or_else(|| {
+            path.strip_prefix("${")?
+                .strip_prefix(WORKSPACE_VAR)?
+                .strip_prefix('}')
+        })?;
+    rest.strip_prefix('/')
+}
+
+/// Merge a shared config into `config`. New repositories and policies are added and
+/// unset settings taken over; for entries both have with different values,
+/// `resolve` picks the side. A repository keeps its local path either way.
+pub fn merge(
+    config: &mut Config,
+    shared: Config,
+    resolve: &mut dyn FnMut(&Conflict) -> Result<Side>,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let local = config.local_file.is_some();
+
+    let mut table = config::settings(config)?;
+    for (key, imported) in config::settings(&shared)? {
+        let what = format!("setting {}", key);
+        match table.get(&key) {
+            None => {
+                table.insert(key, imported);
+                summary.added.push(what);
+            }
+            Some(current) if *current == imported => {}
+            Some(current) => {
+                let conflict = Conflict {
+                    what: what.clone(),
+                    local: current.to_string(),
+                    imported: imported.to_string(),
+                };
+                if resolve(&conflict)? == Side::Imported {
+                    table.insert(key, imported);
+                    summary.updated.push(what);
+                } else {
+                    summary.kept.push(what);
+                }
+            }
+        }
+    }
+    let mut merged: Config = toml::Value::Table(table).try_into()?;
+    merged.repositories = std::mem::take(&mut config.repositories);
+    merged.policies = std::mem::take(&mut config.policies);
+    merged.file = config.file.take();
+    merged.local_file = config.local_file.take();
+    *config = merged;
+
+    for mut repo in shared.repositories {
+        let what = format!("repository {}", repo.path);
+        repo.local = local;
+        let Some(existing) = config
+            .repositories
+            .iter_mut()
+            .find(|r| same_repository(r, &repo))
+        else {
+            summary.added.push(what);
+            config.repositories.push(repo);
+            continue;
+        };
+        repo.path = existing.path.clone();
+        let (current, imported) = (render(existing)?, render(&repo)?);
+        if current == imported {
+            continue;
+        }
+        let conflict = Conflict {
+            what: format!("repository {}", existing.path),
+            local: current,
+            imported,
+        };
+        if resolve(&conflict)? == Side::Imported {
+            summary.updated.push(conflict.what);
+            *existing = repo;
+        } else {
+            summary.kept.push(conflict.what);
+        }
+    }
+
+    for mut policy in shared.policies {
+        let what = format!("policy {}", policy.name);
+        policy.local = local;
+        let Some(existing) = config.policies.iter_mut().find(|p| p.name == policy.name) else {
+            summary.added.push(what);
+            config.policies.push(policy);
+            continue;
+        };
+        let (current, imported) = (render(existing)?, render(&policy)?);
+        if current == imported {
+            continue;
+        }
+        let conflict = Conflict {
+            what,
+            local: current,
+            imported,
+        };
+        if resolve(&conflict)? == Side::Imported {
+            summary.updated.push(conflict.what);
+            *existing = policy;
+        } else {
+            summary.kept.push(conflict.what);
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Make the shared config the whole config, keeping the settings `export --strip-local`
+/// leaves out where the shared config doesn't set them
+pub fn replace(config: &mut Config, mut shared: Config) {
+    let local = config.local_file.is_some();
+    if shared.env.is_empty() {
+        shared.env = std::mem::take(&mut config.env);
+    }
+    shared.log_file = shared.log_file.or(config.log_file.take());
+    shared.tmp_root = shared.tmp_root.or(config.tmp_root.take());
+    shared.retries = shared.retries.or(config.retries);
+    shared.install_timeout = shared.install_timeout.or(config.install_timeout);
+    shared.git_timeout = shared.git_timeout.or(config.git_timeout);
+    shared.gh_timeout = shared.gh_timeout.or(config.gh_timeout);
+    shared.registry_timeout = shared.registry_timeout.or(config.registry_timeout);
+    shared.github_concurrency = shared.github_concurrency.or(config.github_concurrency);
+    for repo in &mut shared.repositories {
+        repo.local = local;
+    }
+    for policy in &mut shared.policies {
+        policy.local = local;
+    }
+    // A project-local config can't remove the global repositories, only hide them
+    shared.replace_repositories = local;
+    shared.file = config.file.take();
+    shared.local_file = config.local_file.take();
+    *config = shared;
+}
+
+/// The same repository: the same GitHub repository, or the same path
+fn same_repository(a: &Repository, b: &Repository) -> bool {
+    let url = |repo: &Repository| {
+        let url = repo.github_url.as_deref()?;
+        Some(normalize_github_url(url).unwrap_or_else(|| url.trim_end_matches('/').to_string()))
+    };
+    match (url(a), url(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => same_path(&a.path, &b.path),
+    }
+}
+
+fn render<T: Serialize>(entry: &T) -> Result<String> {
+    Ok(toml::to_string(entry)?.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Policy;
+    use std::collections::BTreeMap;
+
+    fn repository(path: &str, github_url: Option<&str>) -> Repository {
+        Repository {
+            path: path.to_string(),
+            github_url: github_url.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn team_config() -> Config {
+        Config {
+            default_package_manager: Some("pnpm".to_string()),
+            log_file: Some("/home/ana/mru.log".to_string()),
+            retries: Some(5),
+            milestone: Some("deps-2026-10".to_string()),
+            env: BTreeMap::from([("NPM_TOKEN".to_string(), "${NPM_TOKEN}".to_string())]),
+            repositories: vec![
+                Repository {
+                    groups: vec!["frontend".to_string()],
+                    alias: Some("web".to_string()),
+                    priority: 1,
+                    ..repository(
+                        "/home/ana/work/web-app",
+                        Some("https://github.com/acme/web-app"),
+                    )
+                },
+                Repository {
+                    extra_manifests: vec!["functions".to_string()],
+                    ..repository("/home/ana/work/libs/ui", None)
+                },
+                repository("/opt/legacy", Some("https://github.com/acme/legacy")),
+                repository("/tmp/scratch", None),
+            ],
+            policies: vec![Policy {
+                name: "react-18".to_string(),
+                package: "react".to_string(),
+                allowed: Some("<19".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn never(conflict: &Conflict) -> Result<Side> {
+        panic!("unexpected conflict: {:?}", conflict)
+    }
+
+    #[test]
+    fn exported_configs_import_back_to_the_same_setup() {
+        let config = team_config();
+        assert_eq!(
+            common_root(&config),
+            Some(PathBuf::from("/")),
+            "repositories outside one directory share only the root"
+        );
+
+        let exported = export(&config, Path::new("/home/ana/work"), true);
+        assert_eq!(exported.skipped, vec!["/tmp/scratch"]);
+        let shared = exported.config;
+        let paths: Vec<&str> = shared
+            .repositories
+            .iter()
+            .map(|r| r.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "$WORKSPACE/web-app",
+                "$WORKSPACE/libs/ui",
+                "$WORKSPACE/legacy"
+            ]
+        );
+        assert_eq!(shared.log_file, None);
+        assert_eq!(shared.retries, None);
+        assert!(shared.env.is_empty());
+        assert_eq!(shared.milestone.as_deref(), Some("deps-2026-10"));
+
+        // Through a file, into the empty config of a teammate who keeps clones in ~/src
+        let text = toml::to_string(&shared).unwrap();
+        let mut imported: Config = toml::from_str(&text).unwrap();
+        resolve_paths(&mut imported, Some("/home/bo/src/")).unwrap();
+        let mut teammate = Config::default();
+        let summary = merge(&mut teammate, imported, &mut never).unwrap();
+        assert!(summary
+            .added
+            .contains(&"repository /home/bo/src/web-app".to_string()));
+        assert!(summary.added.contains(&"policy react-18".to_string()));
+        assert_eq!(teammate.repositories[0].alias.as_deref(), Some("web"));
+        assert_eq!(teammate.repositories[1].extra_manifests, vec!["functions"]);
+        assert_eq!(teammate.default_package_manager.as_deref(), Some("pnpm"));
+
+        // Exporting the teammate's config gives the same file
+        let again = export(&teammate, Path::new("/home/bo/src"), true);
+        assert_eq!(toml::to_string(&again.config).unwrap(), text);
+
+        // Importing it again changes nothing
+        let mut imported = again.config;
+        resolve_paths(&mut imported, Some("/home/bo/src")).unwrap();
+        let summary = merge(&mut teammate, imported, &mut never).unwrap();
+        assert!(summary.added.is_empty() && summary.updated.is_empty());
+    }
+
+    #[test]
+    fn import_conflicts_take_the_chosen_side() {
+        let shared = Config {
+            milestone: Some("deps-2026-11".to_string()),
+            repositories: vec![
+                Repository {
+                    groups: vec!["frontend".to_string(), "team-a".to_string()],
+                    ..repository(
+                        "$WORKSPACE/web-app",
+                        Some("git@github.com:acme/web-app.git"),
+                    )
+                },
+                repository("$WORKSPACE/api", Some("https://github.com/acme/api")),
+            ],
+            ..Default::default()
+        };
+        let local = || Config {
+            milestone: Some("deps-2026-10".to_string()),
+            repositories: vec![Repository {
+                groups: vec!["frontend".to_string()],
+                ..repository("~/code/web", Some("https://github.com/acme/web-app"))
+            }],
+            ..Default::default()
+        };
+        let mut imported = shared.clone();
+        resolve_paths(&mut imported, Some("~/code")).unwrap();
+
+        let mut kept = local();
+        let mut conflicts = Vec::new();
+        let summary = merge(&mut kept, imported.clone(), &mut |c: &Conflict| {
+            conflicts.push(c.what.clone());
+            Ok(Side::Local)
+        })
+        .unwrap();
+        assert_eq!(
+            conflicts,
+            vec!["setting milestone", "repository ~/code/web"]
+        );
+        assert_eq!(summary.added, vec!["repository ~/code/api"]);
+        assert_eq!(kept.milestone.as_deref(), Some("deps-2026-10"));
+        assert_eq!(kept.repositories[0].groups, vec!["frontend"]);
+
+        let mut taken = local();
+        let summary = merge(&mut taken, imported.clone(), &mut |_: &Conflict| {
+            Ok(Side::Imported)
+        })
+        .unwrap();
+        assert_eq!(summary.updated.len(), 2);
+        assert_eq!(taken.milestone.as_deref(), Some("deps-2026-11"));
+        assert_eq!(
+            taken.repositories[0].path, "~/code/web",
+            "the clone stays where it is"
+        );
+        assert_eq!(taken.repositories[0].groups, vec!["frontend", "team-a"]);
+
+        let mut replaced = Config {
+            retries: Some(3),
+            ..local()
+        };
+        replace(&mut replaced, imported);
+        assert_eq!(replaced.repositories.len(), 2);
+        assert_eq!(
+            replaced.retries,
+            Some(3),
+            "machine settings survive a replace"
+        );
+        assert_eq!(replaced.milestone.as_deref(), Some("deps-2026-11"));
+    }
+}
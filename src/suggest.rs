@@ -0,0 +1,89 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Most names suggested for a typo
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Every dependency name in the scanned manifests
+pub fn known_names(repo_versions: &[(String, Result<BTreeMap<String, String>>)]) -> BTreeSet<&str> {
+    repo_versions
+        .iter()
+        .filter_map(|(_, versions)| versions.as_ref().ok())
+        .flat_map(|versions| versions.keys().map(String::as_str))
+        .collect()
+}
+
+/// Error for a package no repository depends on, suggesting known names close to it.
+/// A name that only differs in case is called out, since npm names are case-sensitive.
+pub fn not_found_message(package: &str, known: &BTreeSet<&str>) -> String {
+    let message = format!("Package '{}' isn't used by any repository", package);
+
+    if let Some(name) = known.iter().find(|name| name.eq_ignore_ascii_case(package)) {
+        return format!(
+            "{}; package names are case-sensitive, did you mean '{}'?",
+            message, name
+        );
+    }
+
+    let suggestions = close_matches(package, known);
+    if suggestions.is_empty() {
+        message
+    } else {
+        format!("{}; did you mean: {}?", message, suggestions.join(", "))
+    }
+}
+
+/// Known names within an edit distance of a third of the name's length (rounded up), closest first
+fn close_matches<'a>(name: &str, known: &BTreeSet<&'a str>) -> Vec<&'a str> {
+    let threshold = name.chars().count().div_ceil(3);
+    let mut matches: Vec<(usize, &str)> = known
+        .iter()
+        .map(|&candidate| (levenshtein(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= threshold)
+        .collect();
+    matches.sort();
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// Number of single-character insertions, deletions and substitutions turning `a` into `b`
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_found_message_suggests_close_names_and_case_fixes() {
+        let known: BTreeSet<&str> = ["react", "react-dom", "react-dnd", "redux", "lodash"].into();
+
+        assert_eq!(
+            not_found_message("reactdom", &known),
+            "Package 'reactdom' isn't used by any repository; did you mean: react-dom, react, react-dnd?"
+        );
+        assert_eq!(
+            not_found_message("React", &known),
+            "Package 'React' isn't used by any repository; package names are case-sensitive, did you mean 'react'?"
+        );
+        assert_eq!(
+            not_found_message("webpack", &known),
+            "Package 'webpack' isn't used by any repository"
+        );
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+}
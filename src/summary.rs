@@ -0,0 +1,300 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::campaign::{RepoState, RepoStatus};
+
+/// How much of a run the end-of-run summary shows (`--summary`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryMode {
+    /// Every outcome, warning and the timing table
+    #[default]
+    Full,
+    /// Only the failed and skipped repositories, with their reasons
+    Failures,
+    /// A count per status, then the failures
+    Compact,
+}
+
+/// What a failed repository's error points at, so systemic problems stand out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FailureClass {
+    PushRejected,
+    InstallFailed,
+    PrFailed,
+    Other,
+}
+
+impl FailureClass {
+    fn classify(error: &str) -> Self {
+        let error = error.to_lowercase();
+        if error.starts_with("push of ") {
+            FailureClass::PushRejected
+        } else if error.contains("install failed") {
+            FailureClass::InstallFailed
+        } else if error.contains(" pr ") || error.contains("pull request") {
+            FailureClass::PrFailed
+        } else {
+            FailureClass::Other
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FailureClass::PushRejected => "push rejected",
+            FailureClass::InstallFailed => "install failed",
+            FailureClass::PrFailed => "PR failed",
+            FailureClass::Other => "other errors",
+        }
+    }
+}
+
+/// Order of the per-status counts in the compact summary
+const STATUSES: [RepoStatus; 9] = [
+    RepoStatus::Completed,
+    RepoStatus::NoChanges,
+    RepoStatus::Skipped,
+    RepoStatus::Blocked,
+    RepoStatus::Failed,
+    RepoStatus::Interrupted,
+    RepoStatus::Deferred,
+    RepoStatus::Pending,
+    RepoStatus::Undone,
+];
+
+/// End-of-run summary of campaign `id`, grouped by outcome; the failures are grouped
+/// by what went wrong. The timing table isn't part of it
+pub fn render(id: &str, repos: &[RepoState], mode: SummaryMode) -> String {
+    let count = |status: RepoStatus| repos.iter().filter(|r| r.status == status).count();
+    let mut out = String::new();
+    match mode {
+        SummaryMode::Full => {
+            let _ = writeln!(
+                out,
+                "Campaign {}: {} completed, {} skipped, {} without changes, {} blocked by policy, {} failed, {} interrupted, {} pending",
+                id,
+                count(RepoStatus::Completed),
+                count(RepoStatus::Skipped),
+                count(RepoStatus::NoChanges),
+                count(RepoStatus::Blocked),
+                count(RepoStatus::Failed),
+                count(RepoStatus::Interrupted),
+                count(RepoStatus::Pending)
+            );
+        }
+        SummaryMode::Failures => {
+            let _ = writeln!(
+                out,
+                "Campaign {}: {} failed, {} skipped",
+                id,
+                count(RepoStatus::Failed),
+                count(RepoStatus::Skipped)
+            );
+        }
+        SummaryMode::Compact => {
+            let _ = writeln!(out, "Campaign {}:", id);
+            for status in STATUSES {
+                let n = count(status);
+                if n > 0 {
+                    let _ = writeln!(out, "  {:<12} {}", status.label(), n);
+                }
+            }
+        }
+    }
+
+    failures(&mut out, repos, mode != SummaryMode::Compact);
+    if mode == SummaryMode::Compact {
+        return out.trim_end().to_string();
+    }
+
+    if mode == SummaryMode::Full {
+        for repo in repos.iter().filter(|r| r.status == RepoStatus::Interrupted) {
+            let _ = writeln!(out, "  ⏹ {}: interrupted, resume redoes it", repo.path);
+        }
+    }
+    for repo in repos {
+        if let (RepoStatus::Skipped, Some(reason)) = (repo.status, &repo.error) {
+            let _ = writeln!(out, "  ⚠ {}: {}", repo.path, reason);
+        }
+    }
+    if mode == SummaryMode::Failures {
+        return out.trim_end().to_string();
+    }
+
+    let deferred: Vec<&str> = repos
+        .iter()
+        .filter(|r| r.status == RepoStatus::Deferred)
+        .map(|r| r.path.as_str())
+        .collect();
+    if !deferred.is_empty() {
+        let _ = writeln!(
+            out,
+            "  ⏸ {} deferred to later batches: {}",
+            deferred.len(),
+            deferred.join(", ")
+        );
+    }
+    for repo in repos {
+        for warning in &repo.warnings {
+            let _ = writeln!(out, "  ⚠ {}: {}", repo.path, warning);
+        }
+    }
+    for repo in repos.iter().filter(|r| r.ad_hoc) {
+        let _ = writeln!(
+            out,
+            "  ◇ {}: ad hoc (--repo-path), not in the config",
+            repo.path
+        );
+    }
+    for repo in repos {
+        if let Some(commits) = repo.fast_forwarded {
+            let _ = writeln!(
+                out,
+                "  ↻ {}: base branch fast-forwarded by {} commits from origin",
+                repo.path, commits
+            );
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// Failed repositories under a heading per failure class, with git's output when
+/// `output` is set
+fn failures(out: &mut String, repos: &[RepoState], output: bool) {
+    let mut failed: Vec<(FailureClass, &RepoState)> = repos
+        .iter()
+        .filter(|r| r.status == RepoStatus::Failed)
+        .map(|r| {
+            let class = FailureClass::classify(r.error.as_deref().unwrap_or_default());
+            (class, r)
+        })
+        .collect();
+    // Stable, so repositories keep their run order within a class
+    failed.sort_by_key(|(class, _)| *class);
+
+    let mut current = None;
+    for &(class, repo) in &failed {
+        if current != Some(class) {
+            let n = failed.iter().filter(|(c, _)| *c == class).count();
+            let _ = writeln!(out, "  {} ({}):", class.label(), n);
+            current = Some(class);
+        }
+        let _ = writeln!(
+            out,
+            "    ✗ {}: {}",
+            repo.path,
+            repo.error.as_deref().unwrap_or_default()
+        );
+        if output {
+            for line in repo.output.iter().flat_map(|o| o.lines()) {
+                let _ = writeln!(out, "      | {}", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(path: &str, status: RepoStatus, error: Option<&str>) -> RepoState {
+        RepoState {
+            path: path.to_string(),
+            status,
+            error: error.map(str::to_string),
+            output: None,
+            pr_url: None,
+            old_version: None,
+            original_branch: None,
+            fast_forwarded: None,
+            diffstat: None,
+            duration_ms: None,
+            steps: Vec::new(),
+            batch: None,
+            warnings: Vec::new(),
+            milestone: None,
+            project: None,
+            license: None,
+            target_version: None,
+            resolve_preview: None,
+            package_managers: Vec::new(),
+            ad_hoc: false,
+            scanned_version: None,
+        }
+    }
+
+    fn repos() -> Vec<RepoState> {
+        let mut web = repo("~/work/web", RepoStatus::Completed, None);
+        web.warnings = vec!["milestone not found".to_string()];
+        let mut api = repo(
+            "~/work/api",
+            RepoStatus::Failed,
+            Some("Push of branch 'update-lodash': rejected by a protected branch rule"),
+        );
+        api.output = Some("! [remote rejected] update-lodash".to_string());
+        vec![
+            web,
+            api,
+            repo(
+                "~/work/docs",
+                RepoStatus::Failed,
+                Some("npm install failed:\nERESOLVE"),
+            ),
+            repo(
+                "~/work/admin",
+                RepoStatus::Failed,
+                Some("Push of branch 'update-lodash': non-fast-forward"),
+            ),
+            repo(
+                "~/work/site",
+                RepoStatus::Skipped,
+                Some("doesn't depend on lodash"),
+            ),
+            repo("~/work/cli", RepoStatus::Completed, None),
+        ]
+    }
+
+    #[test]
+    fn failures_are_grouped_by_class() {
+        assert_eq!(
+            FailureClass::classify("Failed to mark PR https://x/pull/1 ready: gone"),
+            FailureClass::PrFailed
+        );
+        assert_eq!(
+            FailureClass::classify("lockfile format changed"),
+            FailureClass::Other
+        );
+
+        let full = render("c1", &repos(), SummaryMode::Full);
+        assert_eq!(
+            full,
+            "Campaign c1: 2 completed, 1 skipped, 0 without changes, 0 blocked by policy, 3 failed, 0 interrupted, 0 pending
+  push rejected (2):
+    ✗ ~/work/api: Push of branch 'update-lodash': rejected by a protected branch rule
+      | ! [remote rejected] update-lodash
+    ✗ ~/work/admin: Push of branch 'update-lodash': non-fast-forward
+  install failed (1):
+    ✗ ~/work/docs: npm install failed:
+ERESOLVE
+  ⚠ ~/work/site: doesn't depend on lodash
+  ⚠ ~/work/web: milestone not found"
+        );
+    }
+
+    #[test]
+    fn failures_and_compact_modes_leave_out_the_rest() {
+        let failures = render("c1", &repos(), SummaryMode::Failures);
+        assert!(failures.starts_with("Campaign c1: 3 failed, 1 skipped\n"));
+        assert!(failures.contains("      | ! [remote rejected]"));
+        assert!(failures.ends_with("  ⚠ ~/work/site: doesn't depend on lodash"));
+        assert!(!failures.contains("milestone not found"));
+
+        let compact = render("c1", &repos(), SummaryMode::Compact);
+        assert!(compact.starts_with(
+            "Campaign c1:\n  completed    2\n  skipped      1\n  failed       3\n  push rejected (2):"
+        ));
+        assert!(!compact.contains("| !"));
+        assert!(!compact.contains("~/work/site"));
+    }
+}
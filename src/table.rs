@@ -0,0 +1,43 @@
+/// Render rows as left-aligned columns separated by two spaces, with a header line
+pub fn render<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.as_ref().chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let mut out = vec![line(header.to_vec())];
+    for row in rows {
+        out.push(line(row.iter().map(|c| c.as_ref()).collect()));
+    }
+    out.join("\n")
+}
+
+/// Print a table to stdout
+pub fn print<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) {
+    println!("{}", render(header, rows));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_aligns_columns_to_widest_cell() {
+        let rows = vec![vec!["^4.17.0", "/work/app"], vec!["4.17.21", "/w"]];
+        assert_eq!(
+            render(&["VERSION", "REPOSITORY"], &rows),
+            "VERSION  REPOSITORY\n^4.17.0  /work/app\n4.17.21  /w"
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::campaign::format_timestamp;
+use crate::config::{Config, Repository};
+use crate::message::CommitStyle;
+
+/// Format of `{date}` unless `templates.date_format` is set
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Where a template is rendered, which decides the variables it may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Once per campaign: the branch name
+    Campaign,
+    /// For each repository: commit message, PR title and body, changelog entry
+    Repository,
+    /// When a run is announced: the notification message
+    Notification,
+}
+
+/// A value templates can use as `{name}`
+pub struct Variable {
+    pub name: &'static str,
+    pub description: &'static str,
+    scopes: &'static [Scope],
+}
+
+const EVERYWHERE: &[Scope] = &[Scope::Campaign, Scope::Repository, Scope::Notification];
+const CAMPAIGN: &[Scope] = &[Scope::Campaign, Scope::Repository];
+const REPOSITORY: &[Scope] = &[Scope::Repository];
+
+pub const VARIABLES: &[Variable] = &[
+    Variable {
+        name: "package",
+        description: "package being updated",
+        scopes: EVERYWHERE,
+    },
+    Variable {
+        name: "version",
+        description: "version the package is updated to",
+        scopes: EVERYWHERE,
+    },
+    Variable {
+        name: "new",
+        description: "same as {version}",
+        scopes: EVERYWHERE,
+    },
+    Variable {
+        name: "old",
+        description: "version the repository was at",
+        scopes: REPOSITORY,
+    },
+    Variable {
+        name: "repo",
+        description: "directory name of the repository",
+        scopes: REPOSITORY,
+    },
+    Variable {
+        name: "alias",
+        description: "alias of the repository, or its directory name",
+        scopes: REPOSITORY,
+    },
+    Variable {
+        name: "tags",
+        description: "groups of the repository, comma-separated",
+        scopes: REPOSITORY,
+    },
+    Variable {
+        name: "base",
+        description: "branch the update branches from",
+        scopes: REPOSITORY,
+    },
+    Variable {
+        name: "date",
+        description: "date the campaign started, in templates.date_format",
+        scopes: EVERYWHERE,
+    },
+    Variable {
+        name: "campaign",
+        description: "campaign id",
+        scopes: &[Scope::Repository, Scope::Notification],
+    },
+    Variable {
+        name: "hash",
+        description: "short hash of the campaign's package, version, start and repositories",
+        scopes: CAMPAIGN,
+    },
+    Variable {
+        name: "type",
+        description: "conventional-commit type (--type, default: chore)",
+        scopes: CAMPAIGN,
+    },
+    Variable {
+        name: "scope",
+        description: "conventional-commit scope (--scope)",
+        scopes: CAMPAIGN,
+    },
+    Variable {
+        name: "ticket",
+        description: "ticket key (--ticket)",
+        scopes: CAMPAIGN,
+    },
+    Variable {
+        name: "summary",
+        description: "outcome of the run, e.g. \"12 PRs opened, 1 failed\"",
+        scopes: &[Scope::Notification],
+    },
+];
+
+/// Names of the variables a template rendered in `scope` may use, as `{name}`
+pub fn available(scope: Scope) -> Vec<String> {
+    VARIABLES
+        .iter()
+        .filter(|v| v.scopes.contains(&scope))
+        .map(|v| format!("{{{}}}", v.name))
+        .collect()
+}
+
+/// Fail if `template`, named `label` in errors, uses a placeholder that isn't
+/// available in `scope`
+pub fn validate(label: &str, template: &str, scope: Scope) -> Result<()> {
+    let allowed = available(scope);
+    let unknown: Vec<String> = placeholders(template)
+        .into_iter()
+        .map(|name| format!("{{{}}}", name))
+        .filter(|name| !allowed.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        anyhow::bail!(
+            "Unknown placeholder {} in {}. Available: {}",
+            unknown.join(", "),
+            label,
+            allowed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Every template the config sets, with its name and where it is rendered
+pub fn configured(config: &Config) -> Vec<(String, &str, Scope)> {
+    let templates = &config.templates;
+    let mut all: Vec<(String, &str, Scope)> = [
+        ("templates.branch", &templates.branch, Scope::Campaign),
+        (
+            "templates.commit_message",
+            &templates.commit_message,
+            Scope::Repository,
+        ),
+        ("templates.pr_title", &templates.pr_title, Scope::Repository),
+        ("templates.pr_body", &templates.pr_body, Scope::Repository),
+        (
+            "templates.notification",
+            &templates.notification,
+            Scope::Notification,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(label, template, scope)| Some((label.to_string(), template.as_deref()?, scope)))
+    .collect();
+    for repo in &config.repositories {
+        if let Some(template) = &repo.changelog.template {
+            let label = format!("changelog.template of {}", repo.path);
+            all.push((label, template.as_str(), Scope::Repository));
+        }
+    }
+    all
+}
+
+/// Check every configured template and the date format, before anything is changed
+pub fn check_config(config: &Config) -> Result<()> {
+    let mut problems: Vec<String> = configured(config)
+        .into_iter()
+        .filter_map(|(label, template, scope)| validate(&label, template, scope).err())
+        .map(|e| e.to_string())
+        .collect();
+    if let Err(e) = validate_date_format(config.templates.date_format()) {
+        problems.push(e.to_string());
+    }
+    if !problems.is_empty() {
+        anyhow::bail!(problems.join("\n"));
+    }
+    Ok(())
+}
+
+/// Fail if `format` has a directive `{date}` can't render
+pub fn validate_date_format(format: &str) -> Result<()> {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' && !matches!(chars.next(), Some('Y' | 'm' | 'd' | 'H' | 'M' | '%')) {
+            anyhow::bail!(
+                "Unsupported templates.date_format '{}'. Use %Y, %m, %d, %H, %M and %%",
+                format
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `secs` since the epoch (UTC) in a strftime-like `format` of %Y, %m, %d, %H and %M
+pub fn format_date(secs: u64, format: &str) -> String {
+    // `YYYY-MM-DD HH:MM`
+    let stamp = format_timestamp(secs);
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&stamp[0..4]),
+            Some('m') => out.push_str(&stamp[5..7]),
+            Some('d') => out.push_str(&stamp[8..10]),
+            Some('H') => out.push_str(&stamp[11..13]),
+            Some('M') => out.push_str(&stamp[14..16]),
+            Some(other) => {
+                out.push('%');
+                if other != '%' {
+                    out.push(other);
+                }
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Short, stable hash of `parts`, to tell campaigns with the same package apart
+pub fn short_hash<S: AsRef<str>>(parts: &[S]) -> String {
+    // FNV-1a, so the hash doesn't change between builds
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.as_ref().bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)[..7].to_string()
+}
+
+/// `{name}` placeholders in `template`, in order. Braces around anything other than
+/// a name, e.g. JSON in a PR body, are left alone.
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if let Some(end) = rest.find('}') {
+            let name = &rest[..end];
+            if is_name(name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+/// Values of the template variables, filled in as they become known: the campaign's
+/// when it is created (and saved with it), the repository's when it is processed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TemplateContext {
+    values: BTreeMap<String, String>,
+}
+
+impl TemplateContext {
+    pub fn new(package: &str, version: &str) -> Self {
+        TemplateContext::default()
+            .with("package", package)
+            .with("version", version)
+            .with("new", version)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Set variable `name` to `value`
+    pub fn with(mut self, name: &str, value: &str) -> Self {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// `{type}`, `{scope}` and `{ticket}` from the commit style
+    pub fn with_style(self, style: &CommitStyle) -> Self {
+        self.with("type", style.kind())
+            .with("scope", style.scope.as_deref().unwrap_or_default())
+            .with("ticket", style.ticket.as_deref().unwrap_or_default())
+    }
+
+    /// `{repo}`, `{alias}` and `{tags}` of `repo`
+    pub fn with_repository(self, repo: &Repository) -> Self {
+        let name = crate::context::repo_name(&repo.path);
+        let alias = repo.alias.clone().unwrap_or_else(|| name.clone());
+        self.with("repo", &name)
+            .with("alias", &alias)
+            .with("tags", &repo.groups.join(","))
+    }
+
+    /// `{version}` and `{new}`
+    pub fn with_version(self, version: &str) -> Self {
+        self.with("version", version).with("new", version)
+    }
+
+    /// Replace every placeholder with a value in this context. Placeholders without
+    /// one are left for a later stage; `validate` rejects unknown names up front.
+    pub fn render(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let value = after
+                .find('}')
+                .and_then(|end| Some((end, self.values.get(&after[..end])?)));
+            match value {
+                Some((end, value)) => {
+                    out.push_str(value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_render_known_values_and_reject_unknown_names() {
+        let repo = Repository {
+            path: "/work/web-app".to_string(),
+            groups: vec!["frontend".to_string(), "team-a".to_string()],
+            ..Default::default()
+        };
+        let vars = TemplateContext::new("lodash", "4.17.21")
+            .with_repository(&repo)
+            .with("old", "4.17.20")
+            .with("date", &format_date(1_760_000_000, "%Y%m%d"));
+
+        assert_eq!(
+            vars.render("{alias} [{tags}]: {package} {old} → {new} on {date} {\"json\": 1}"),
+            "web-app [frontend,team-a]: lodash 4.17.20 → 4.17.21 on 20251009 {\"json\": 1}"
+        );
+        // Left for the repository stage
+        assert_eq!(vars.render("{campaign}"), "{campaign}");
+
+        assert!(validate("--message", "bump {package} in {repo}", Scope::Repository).is_ok());
+        let err = validate("templates.branch", "deps/{repo}-{pakage}", Scope::Campaign)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Unknown placeholder {repo}, {pakage} in templates.branch. Available: {package}, {version}"),
+            "{}",
+            err
+        );
+
+        assert!(validate_date_format("%Y-%m-%d %H:%M").is_ok());
+        assert!(validate_date_format("%b %d").is_err());
+        assert_eq!(short_hash(&["lodash", "4.17.21"]).len(), 7);
+        assert_ne!(
+            short_hash(&["lodash", "4.17.21"]),
+            short_hash(&["lodash4", ".17.21"])
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::campaign::RepoState;
+use crate::table;
+
+/// Workflow steps, in the order they run
+pub const STEPS: &[&str] = &[
+    "branch", "manifest", "install", "hooks", "commit", "push", "pr",
+];
+
+/// How many of the slowest repositories `--timings` points out
+const SLOWEST: usize = 3;
+
+/// Time spent in one step of the workflow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepTiming {
+    pub step: String,
+    pub millis: u64,
+}
+
+/// Times consecutive workflow steps; a step that runs twice (e.g. hooks) adds up
+#[derive(Debug, Default)]
+pub struct StepTimer {
+    current: Option<(&'static str, Instant)>,
+    steps: Vec<StepTiming>,
+}
+
+impl StepTimer {
+    /// End the running step, if any, and start `step`
+    pub fn start(&mut self, step: &'static str) {
+        self.stop();
+        self.current = Some((step, Instant::now()));
+    }
+
+    /// End the running step
+    pub fn stop(&mut self) {
+        if let Some((step, start)) = self.current.take() {
+            self.add(step, start.elapsed().as_millis() as u64);
+        }
+    }
+
+    fn add(&mut self, step: &str, millis: u64) {
+        match self.steps.iter_mut().find(|s| s.step == step) {
+            Some(timing) => timing.millis += millis,
+            None => self.steps.push(StepTiming {
+                step: step.to_string(),
+                millis,
+            }),
+        }
+    }
+
+    /// Steps timed so far, ending the running one
+    pub fn finish(mut self) -> Vec<StepTiming> {
+        self.stop();
+        self.steps
+    }
+}
+
+/// Human-readable duration, e.g. "850ms", "12.4s" or "2m 05s"
+pub fn format_duration(millis: u64) -> String {
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else if millis < 60_000 {
+        format!("{:.1}s", millis as f64 / 1000.0)
+    } else {
+        let secs = millis / 1000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Table of the time each repository took, optionally with a column per step and
+/// the slowest repositories marked. Repositories that weren't processed are left out.
+pub fn render(repos: &[RepoState], total_millis: u64, breakdown: bool) -> String {
+    let timed: Vec<&RepoState> = repos.iter().filter(|r| r.duration_ms.is_some()).collect();
+
+    let mut slowest: Vec<&RepoState> = timed.clone();
+    slowest.sort_by_key(|r| std::cmp::Reverse(r.duration_ms));
+    slowest.truncate(SLOWEST);
+    // Marking every repository as slowest says nothing
+    if !breakdown || timed.len() <= SLOWEST {
+        slowest.clear();
+    }
+
+    let mut header = vec!["REPOSITORY", "TOTAL"];
+    if breakdown {
+        header.extend(STEPS);
+    }
+    if !slowest.is_empty() {
+        header.push("");
+    }
+    let rows: Vec<Vec<String>> = timed
+        .iter()
+        .map(|repo| {
+            let mut row = vec![
+                repo.path.clone(),
+                format_duration(repo.duration_ms.unwrap_or_default()),
+            ];
+            if breakdown {
+                row.extend(STEPS.iter().map(|step| {
+                    repo.steps
+                        .iter()
+                        .find(|s| s.step == *step)
+                        .map_or("-".to_string(), |s| format_duration(s.millis))
+                }));
+            }
+            if slowest.iter().any(|s| s.path == repo.path) {
+                row.push("◀ slowest".to_string());
+            }
+            row
+        })
+        .collect();
+
+    let header: Vec<String> = header.iter().map(|h| h.to_uppercase()).collect();
+    let header: Vec<&str> = header.iter().map(String::as_str).collect();
+    format!(
+        "{}\nTotal: {}",
+        table::render(&header, &rows),
+        format_duration(total_millis)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::RepoStatus;
+
+    fn repo(path: &str, millis: u64, install: u64) -> RepoState {
+        RepoState {
+            path: path.to_string(),
+            status: RepoStatus::Completed,
+            error: None,
+            output: None,
+            pr_url: None,
+            old_version: None,
+            original_branch: None,
+            fast_forwarded: None,
+            diffstat: None,
+            duration_ms: Some(millis),
+            steps: vec![StepTiming {
+                step: "install".to_string(),
+                millis: install,
+            }],
+            batch: None,
+            warnings: Vec::new(),
+            milestone: None,
+            project: None,
+            license: None,
+            target_version: None,
+            resolve_preview: None,
+            package_managers: Vec::new(),
+            ad_hoc: false,
+            scanned_version: None,
+        }
+    }
+
+    #[test]
+    fn format_duration_picks_a_unit() {
+        assert_eq!(format_duration(850), "850ms");
+        assert_eq!(format_duration(12_400), "12.4s");
+        assert_eq!(format_duration(125_000), "2m 05s");
+    }
+
+    #[test]
+    fn timer_adds_up_repeated_steps() {
+        let mut timer = StepTimer::default();
+        timer.add("hooks", 5);
+        timer.add("push", 7);
+        timer.add("hooks", 3);
+        assert_eq!(
+            timer.finish(),
+            vec![
+                StepTiming {
+                    step: "hooks".to_string(),
+                    millis: 8
+                },
+                StepTiming {
+                    step: "push".to_string(),
+                    millis: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn render_marks_the_slowest_repositories_in_the_breakdown() {
+        let mut pending = repo("/w/pending", 0, 0);
+        pending.duration_ms = None;
+        let repos = vec![
+            repo("/w/a", 1_000, 600),
+            repo("/w/b", 9_000, 8_000),
+            pending,
+            repo("/w/c", 4_000, 3_000),
+            repo("/w/d", 2_000, 1_500),
+        ];
+
+        let summary = render(&repos, 16_500, false);
+        assert_eq!(
+            summary,
+            "REPOSITORY  TOTAL\n/w/a        1.0s\n/w/b        9.0s\n/w/c        4.0s\n/w/d        2.0s\nTotal: 16.5s"
+        );
+
+        let breakdown = render(&repos, 16_500, true);
+        let lines: Vec<&str> = breakdown.lines().collect();
+        assert!(lines[0].starts_with("REPOSITORY  TOTAL  BRANCH  MANIFEST  INSTALL"));
+        assert!(!lines[1].contains("slowest"));
+        assert!(lines[2].starts_with("/w/b        9.0s   -       -         8.0s"));
+        assert!(lines[2].ends_with("◀ slowest"));
+        assert!(lines[3].contains("slowest") && lines[4].contains("slowest"));
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use std::io::{self, IsTerminal, Write};
+
+/// Refuse to prompt when stdin or stdout isn't a terminal, e.g. in CI or a pipe
+pub fn require_terminal() -> Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        anyhow::bail!(
+            "mru ui needs an interactive terminal; use `mru update <package> <version>` and its flags instead"
+        );
+    }
+    Ok(())
+}
+
+/// Ask for a line of input; an empty answer gives `default`
+pub fn ask(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        anyhow::bail!("No input; aborting");
+    }
+    let input = input.trim();
+    Ok(match (input.is_empty(), default) {
+        (true, Some(default)) => default.to_string(),
+        _ => input.to_string(),
+    })
+}
+
+/// Ask a yes/no question; an empty answer gives `default`
+pub fn ask_yes_no(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = ask(&format!("{} [{}]", question, hint), None)?;
+        match answer.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+/// Items picked by a selection like `1,3-5` or `all`, numbered from 1 to `count`,
+/// as indices in ascending order
+pub fn parse_selection(input: &str, count: usize) -> Result<Vec<usize>> {
+    if input.trim().eq_ignore_ascii_case("all") {
+        return Ok((0..count).collect());
+    }
+
+    let number = |text: &str| -> Result<usize> {
+        match text.trim().parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => Ok(n - 1),
+            _ => anyhow::bail!("'{}' is not a number between 1 and {}", text.trim(), count),
+        }
+    };
+    let mut picked = Vec::new();
+    for part in input.split(',').filter(|p| !p.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((from, to)) => picked.extend(number(from)?..=number(to)?),
+            None => picked.push(number(part)?),
+        }
+    }
+    picked.sort_unstable();
+    picked.dedup();
+    if picked.is_empty() {
+        anyhow::bail!("Nothing selected");
+    }
+    Ok(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_selection_reads_lists_ranges_and_all() {
+        assert_eq!(parse_selection("3, 1-2,2", 4).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_selection("all", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(
+            parse_selection("2,5", 4).unwrap_err().to_string(),
+            "'5' is not a number between 1 and 4"
+        );
+        assert!(parse_selection(" ", 4).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+
+/// Operators that may prefix a version in a range
+const OPERATORS: [&str; 8] = ["~>", ">=", "<=", "^", "~", ">", "<", "="];
+
+/// Check that `spec` is an exact version or an npm-style range
+/// (`^1.2.3`, `~1.2`, `>=1.0.0 <2.0.0`, `1.x`, `1.0.0 - 2.0.0`, `^1 || ^2`)
+pub fn validate(spec: &str) -> Result<()> {
+    if is_valid_range(spec) {
+        return Ok(());
+    }
+
+    let hint = if !spec.is_empty() && spec.chars().all(|c| c.is_ascii_alphabetic() || c == '-') {
+        "Dist-tags such as 'latest' are not resolved; pass an explicit version or range. "
+    } else {
+        ""
+    };
+
+    anyhow::bail!(
+        "Invalid version '{}'. {}Examples of valid versions: 1.2.3, ^1.2.3, ~1.2.0, >=1.0.0 <2.0.0, 1.x\n\
+         Use --no-validate to write the specifier as is",
+        spec,
+        hint
+    )
+}
+
+/// Specifier without its range operator and `v` prefix, e.g. `^1.2.3` -> `1.2.3`
+pub fn normalize(spec: &str) -> String {
+    let spec = spec.trim();
+    let spec = OPERATORS
+        .iter()
+        .find_map(|op| spec.strip_prefix(op))
+        .unwrap_or(spec)
+        .trim_start();
+    spec.strip_prefix('v').unwrap_or(spec).to_string()
+}
+
+/// Target package and range of an npm alias such as `npm:@scope/pkg@^1.0.0`.
+/// An alias without a range, `npm:pkg`, has the range `*`.
+pub fn npm_alias(spec: &str) -> Option<(&str, &str)> {
+    let rest = spec.trim().strip_prefix("npm:")?;
+    // A scoped name starts with `@` itself
+    match rest.get(1..)?.find('@') {
+        Some(i) => Some((&rest[..=i], &rest[i + 2..])),
+        None => Some((rest, "*")),
+    }
+}
+
+/// Range part of a specifier: the range of an npm alias, or the specifier itself
+pub fn range_of(spec: &str) -> &str {
+    npm_alias(spec).map_or(spec, |(_, range)| range)
+}
+
+/// `spec` with its range set to `range`, keeping the `npm:<name>@` of an npm alias.
+/// A `range` that is an alias itself replaces `spec` as a whole.
+pub fn replace_range(spec: &str, range: &str) -> String {
+    match npm_alias(spec) {
+        Some((name, _)) if npm_alias(range).is_none() => format!("npm:{}@{}", name, range),
+        _ => range.to_string(),
+    }
+}
+
+/// Version after a `patch`, `minor` or `major` release of `current`, or `bump` itself
+/// if it is an explicit version. Releasing a prerelease drops its tag, as `npm version` does.
+pub fn next_release(current: &str, bump: &str) -> Result<String> {
+    if !matches!(bump, "patch" | "minor" | "major") {
+        let version = bump.strip_prefix('v').unwrap_or(bump);
+        if !is_partial(version) || numbers(version).len() != 3 {
+            anyhow::bail!(
+                "Invalid release '{}'. Expected patch, minor, major or a version such as 2.0.0",
+                bump
+            );
+        }
+        return Ok(version.to_string());
+    }
+
+    let parts = numbers(current);
+    let [major, minor, patch] = parts[..] else {
+        anyhow::bail!("Current version '{}' is not a full version", current);
+    };
+    let prerelease = current.contains('-');
+    let (major, minor, patch) = match bump {
+        "major" if prerelease && minor == 0 && patch == 0 => (major, 0, 0),
+        "major" => (major + 1, 0, 0),
+        "minor" if prerelease && patch == 0 => (major, minor, 0),
+        "minor" => (major, minor + 1, 0),
+        _ if prerelease => (major, minor, patch),
+        _ => (major, minor, patch + 1),
+    };
+    Ok(format!("{}.{}.{}", major, minor, patch))
+}
+
+/// Whether `spec` names a single version, e.g. `1.2.3` or `1.2.3-beta.1`, rather than a range
+pub fn is_exact(spec: &str) -> bool {
+    let core = spec.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Exact version to pin a range to: the version the lockfile resolved it to if that is
+/// in the range, else the range's own version when it is a `^`, `~` or `=` range.
+/// `None` for exact versions and ranges that can't be pinned without a lockfile.
+pub fn pin(spec: &str, resolved: Option<&str>) -> Option<String> {
+    if is_exact(spec) {
+        return None;
+    }
+    if let Some(version) = resolved.filter(|v| is_exact(v) && satisfies(spec, v)) {
+        return Some(version.to_string());
+    }
+    let version = ["^", "~", "="]
+        .iter()
+        .find_map(|op| spec.trim().strip_prefix(op))?;
+    let version = normalize(version);
+    is_exact(&version).then_some(version)
+}
+
+/// `prefix` applied to an exact version, e.g. `1.2.3` -> `^1.2.3`; `None` for ranges
+pub fn widen(spec: &str, prefix: &str) -> Option<String> {
+    is_exact(spec).then(|| format!("{}{}", prefix, spec))
+}
+
+/// Range that moves `spec` up to `version`: the exact version, or with `preserve_prefix`
+/// the version under the operator of a `^`, `~` or `>=` range, e.g. `^4.17.0` ->
+/// `^4.17.21`. Other ranges become the exact version either way.
+pub fn move_to(spec: &str, version: &str, preserve_prefix: bool) -> String {
+    let range = range_of(spec).trim();
+    let operator = ["^", "~", ">="]
+        .into_iter()
+        .filter(|_| preserve_prefix)
+        .find(|op| {
+            range
+                .strip_prefix(op)
+                .is_some_and(|rest| is_partial(rest.trim()))
+        })
+        .unwrap_or_default();
+    format!("{}{}", operator, version)
+}
+
+/// Order specifiers by the version they name, then alphabetically
+pub fn compare_specifiers(a: &str, b: &str) -> Ordering {
+    let numbers = |spec: &str| -> Vec<u64> {
+        normalize(spec)
+            .split(['.', '-', '+', ' '])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b)).then_with(|| a.cmp(b))
+}
+
+fn is_valid_range(spec: &str) -> bool {
+    let spec = spec.trim();
+    !spec.is_empty()
+        && spec
+            .split("||")
+            .all(|range| is_valid_comparators(range.trim()))
+}
+
+/// A space-separated set of comparators, or a hyphen range
+fn is_valid_comparators(range: &str) -> bool {
+    if range.is_empty() {
+        return false;
+    }
+
+    if let Some((low, high)) = range.split_once(" - ") {
+        return is_partial(low.trim()) && is_partial(high.trim());
+    }
+
+    comparators(range).is_some_and(|comparators| comparators.iter().all(|c| is_comparator(c)))
+}
+
+/// Split a comparator set into comparators, joining operators separated from their version.
+/// `None` if an operator has no version.
+fn comparators(range: &str) -> Option<Vec<String>> {
+    // npm allows whitespace between an operator and its version (`>= 1.0.0`)
+    let mut comparators = Vec::new();
+    let mut pending = String::new();
+    for token in range.split_whitespace() {
+        pending.push_str(token);
+        if !token
+            .chars()
+            .all(|c| matches!(c, '<' | '>' | '=' | '~' | '^'))
+        {
+            comparators.push(std::mem::take(&mut pending));
+        }
+    }
+
+    pending.is_empty().then_some(comparators)
+}
+
+type Triple = (u64, u64, u64);
+
+/// Whether the lowest version matched by `spec` (e.g. 5.1.0 for `^5.1.0`) is in `range`.
+/// Prerelease tags are ignored.
+pub fn satisfies(range: &str, spec: &str) -> bool {
+    let version = fill(&numbers(&normalize(spec)));
+    range
+        .split("||")
+        .any(|set| set_contains(set.trim(), version))
+}
+
+fn set_contains(set: &str, version: Triple) -> bool {
+    if let Some((low, high)) = set.split_once(" - ") {
+        let high = numbers(high.trim());
+        let below_high = if high.len() == 3 {
+            version <= fill(&high)
+        } else {
+            version < bump(&high)
+        };
+        return version >= fill(&numbers(low.trim())) && below_high;
+    }
+
+    comparators(set)
+        .is_some_and(|comparators| comparators.iter().all(|c| comparator_contains(c, version)))
+}
+
+fn comparator_contains(comparator: &str, version: Triple) -> bool {
+    let (op, target) = OPERATORS
+        .iter()
+        .find_map(|op| comparator.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", comparator));
+    let parts = numbers(target);
+    let full = parts.len() == 3;
+
+    match op {
+        ">" if full => version > fill(&parts),
+        ">" => !parts.is_empty() && version >= bump(&parts),
+        ">=" => version >= fill(&parts),
+        "<" => version < fill(&parts),
+        "<=" if full => version <= fill(&parts),
+        "<=" => version < bump(&parts),
+        "^" => version >= fill(&parts) && version < caret_upper(&parts),
+        "~" | "~>" => version >= fill(&parts) && version < tilde_upper(&parts),
+        _ => version >= fill(&parts) && version < bump(&parts),
+    }
+}
+
+/// Numeric parts of a version up to the first wildcard, e.g. `1.2.x` -> [1, 2]
+fn numbers(version: &str) -> Vec<u64> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    core.split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// Lowest version matching a partial version
+fn fill(parts: &[u64]) -> Triple {
+    let part = |i: usize| parts.get(i).copied().unwrap_or(0);
+    (part(0), part(1), part(2))
+}
+
+/// Lowest version above every version matching a partial version
+fn bump(parts: &[u64]) -> Triple {
+    match parts {
+        [] => (u64::MAX, 0, 0),
+        [major] => (major + 1, 0, 0),
+        [major, minor] => (*major, minor + 1, 0),
+        [major, minor, patch, ..] => (*major, *minor, patch + 1),
+    }
+}
+
+/// `^1.2.3` allows changes that don't modify the left-most non-zero part
+fn caret_upper(parts: &[u64]) -> Triple {
+    match parts {
+        [major, ..] if *major > 0 => (major + 1, 0, 0),
+        [0, minor, ..] if *minor > 0 => (0, minor + 1, 0),
+        _ => bump(parts),
+    }
+}
+
+/// `~1.2.3` allows patch-level changes
+fn tilde_upper(parts: &[u64]) -> Triple {
+    match parts {
+        [major, minor, ..] => (*major, minor + 1, 0),
+        _ => bump(parts),
+    }
+}
+
+fn is_comparator(comparator: &str) -> bool {
+    let version = OPERATORS
+        .iter()
+        .find_map(|op| comparator.strip_prefix(op))
+        .unwrap_or(comparator);
+    is_partial(version)
+}
+
+/// A full or partial version such as `1`, `1.2`, `1.2.x` or `v1.2.3-beta.1+build`
+fn is_partial(version: &str) -> bool {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let (core, qualifier) = match version.find(['-', '+']) {
+        Some(pos) => (&version[..pos], Some(&version[pos..])),
+        None => (version, None),
+    };
+
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.is_empty() || parts.len() > 3 || !parts.iter().all(|p| is_part(p)) {
+        return false;
+    }
+
+    match qualifier {
+        None => true,
+        // Prerelease and build metadata only make sense on a full version
+        Some(qualifier) => parts.len() == 3 && is_qualifier(qualifier),
+    }
+}
+
+fn is_part(part: &str) -> bool {
+    matches!(part, "x" | "X" | "*")
+        || (!part.is_empty()
+            && part.chars().all(|c| c.is_ascii_digit())
+            && (part == "0" || !part.starts_with('0')))
+}
+
+/// `-prerelease`, `+build` or `-prerelease+build`
+fn is_qualifier(qualifier: &str) -> bool {
+    let (prerelease, build) = match qualifier.split_once('+') {
+        Some((prerelease, build)) => (prerelease, Some(build)),
+        None => (qualifier, None),
+    };
+
+    let identifiers_valid = |s: &str| {
+        s.split('.')
+            .all(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    };
+
+    let prerelease_valid = match prerelease.strip_prefix('-') {
+        Some(ids) => identifiers_valid(ids),
+        None => prerelease.is_empty(),
+    };
+
+    prerelease_valid && build.is_none_or(identifiers_valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npm_alias_splits_scoped_and_plain_targets() {
+        assert_eq!(
+            npm_alias("npm:lodash@^4.17.21"),
+            Some(("lodash", "^4.17.21"))
+        );
+        assert_eq!(
+            npm_alias("npm:@scope/pkg@1.0.0"),
+            Some(("@scope/pkg", "1.0.0"))
+        );
+        assert_eq!(npm_alias("npm:@scope/pkg"), Some(("@scope/pkg", "*")));
+        assert_eq!(npm_alias("^4.17.21"), None);
+
+        assert_eq!(
+            replace_range("npm:@scope/pkg@1.0.0", "^1.1.0"),
+            "npm:@scope/pkg@^1.1.0"
+        );
+        assert_eq!(
+            replace_range("npm:@scope/pkg@^1.1.0", "npm:@scope/pkg@1.0.0"),
+            "npm:@scope/pkg@1.0.0"
+        );
+        assert_eq!(replace_range("^1.0.0", "^1.1.0"), "^1.1.0");
+        assert_eq!(range_of("npm:@scope/pkg@~2.0.0"), "~2.0.0");
+    }
+
+    #[test]
+    fn accepts_versions_and_ranges() {
+        for spec in [
+            "1.2.3",
+            "v1.2.3",
+            "^18.2.0",
+            "~5.0.4",
+            "1.x",
+            "1.2.*",
+            "*",
+            ">=1.0.0 <2.0.0",
+            ">= 1.0.0",
+            "^1.0.0 || ^2.0.0",
+            "1.0.0 - 2.0.0",
+            "2.0.0-beta.1",
+            "2.0.0-rc.1+build.5",
+            "=1.2.3",
+        ] {
+            assert!(validate(spec).is_ok(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn specifiers_are_normalized_and_ordered_by_version() {
+        assert_eq!(normalize("^1.2.3"), "1.2.3");
+        assert_eq!(normalize(">= v1.2.3"), "1.2.3");
+
+        let mut specs = vec!["^4.17.21", "4.9.0", "^4.17.0", "~4.17.0"];
+        specs.sort_by(|a, b| compare_specifiers(a, b));
+        assert_eq!(specs, vec!["4.9.0", "^4.17.0", "~4.17.0", "^4.17.21"]);
+    }
+
+    #[test]
+    fn satisfies_checks_the_lowest_matching_version() {
+        assert!(satisfies("<5", "^4.46.0"));
+        assert!(!satisfies("<5", "5.0.0"));
+        assert!(satisfies("<=4", "4.99.1"));
+        assert!(satisfies("^0.2.0", "0.2.9"));
+        assert!(!satisfies("^0.2.0", "0.3.0"));
+        assert!(satisfies("~1.2 || >=3", "^3.1.0"));
+        assert!(!satisfies("~1.2 || >=3", "1.3.0"));
+        assert!(satisfies("1.0.0 - 2", "2.5.0"));
+        assert!(satisfies(">= 1.0.0 < 2.0.0", "1.9.9"));
+        assert!(satisfies("*", "12.0.0"));
+    }
+
+    #[test]
+    fn rejects_garbage_and_dist_tags() {
+        for spec in [
+            "",
+            "lastest",
+            "latest",
+            "1.2.3.4",
+            "01.2.3",
+            "^",
+            ">=",
+            "1.2-beta",
+            "1.2.3-",
+            "^1.0.0 ||",
+            "npm:react@18",
+        ] {
+            assert!(validate(spec).is_err(), "{:?}", spec);
+        }
+
+        let err = validate("lastest").unwrap_err().to_string();
+        assert!(err.contains("'lastest'"));
+        assert!(err.contains("Dist-tags"));
+        assert!(err.contains("^1.2.3"));
+    }
+
+    #[test]
+    fn pin_prefers_the_resolved_version_within_the_range() {
+        assert_eq!(pin("^1.2.0", Some("1.4.2")), Some("1.4.2".to_string()));
+        assert_eq!(pin("^1.2.0", Some("2.0.0")), Some("1.2.0".to_string()));
+        assert_eq!(pin("~v1.2.3", None), Some("1.2.3".to_string()));
+        assert_eq!(
+            pin(">=1.0.0 <2.0.0", Some("1.9.0")),
+            Some("1.9.0".to_string())
+        );
+        assert_eq!(pin(">=1.0.0", None), None);
+        assert_eq!(pin("^1.2", None), None);
+        assert_eq!(pin("1.2.3", Some("1.2.3")), None);
+
+        assert_eq!(widen("1.2.3-rc.1", "^"), Some("^1.2.3-rc.1".to_string()));
+        assert_eq!(widen("~1.2.3", "^"), None);
+    }
+
+    #[test]
+    fn move_to_keeps_simple_operators_when_asked() {
+        assert_eq!(move_to("^4.17.0", "4.17.21", false), "4.17.21");
+        assert_eq!(move_to("^4.17.0", "4.17.21", true), "^4.17.21");
+        assert_eq!(move_to(">= 1.2", "1.9.0", true), ">=1.9.0");
+        assert_eq!(move_to(">=1.0.0 <2.0.0", "1.9.0", true), "1.9.0");
+        assert_eq!(move_to("npm:@scope/pkg@~1.1.0", "1.1.4", true), "~1.1.4");
+    }
+
+    #[test]
+    fn next_release_bumps_like_npm_version() {
+        assert_eq!(next_release("1.2.3", "patch").unwrap(), "1.2.4");
+        assert_eq!(next_release("1.2.3", "minor").unwrap(), "1.3.0");
+        assert_eq!(next_release("1.2.3", "major").unwrap(), "2.0.0");
+        assert_eq!(next_release("2.0.0-rc.1", "major").unwrap(), "2.0.0");
+        assert_eq!(next_release("1.2.4-beta.0", "patch").unwrap(), "1.2.4");
+        assert_eq!(
+            next_release("1.2.3", "v3.0.0-beta.1").unwrap(),
+            "3.0.0-beta.1"
+        );
+        assert!(next_release("1.2", "patch").is_err());
+        assert!(next_release("1.2.3", "1.x").is_err());
+    }
+}